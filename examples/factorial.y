@@ -2,26 +2,32 @@ module factorial;
 
 import "io" as io;
 
-func factorial(n: int) -> int {
-    let n1: int = n;
-    let n2: int = n;
+func factorial(n: int) -> Result<int, string> {
+    if (n < 0) {
+        return Result.Err("\(n) has no factorial");
+    }
 
-    // Future:
-    // let (n1, n2): (int, int) = (n1, n1);
+    let (n1, n2): (int, int) = (n, n);
 
     while (n1 > 1) {
         n1 -= 1;
         n2 *= n1;
     }
 
-    return n2;
+    return Result.Ok(n2);
 }
 
-func main() {
-    // Future:
-    // let num: int = io.input("").parse<int>()
+func describe(input: string) -> Result<string, string> {
+    let num: int = (input as Result<int, string>)?;
+    let result = factorial(num)?;
+    return Result.Ok("\(num)! = \(result)");
+}
 
-    let num: int = io.input("Enter a number: ") as int;
+func main() {
+    let input: string = io.input("Enter a number: ");
 
-    io.print("\(num)! = \(factorial(num))");
-}
\ No newline at end of file
+    match (describe(input)) {
+        Result.Ok(line) -> io.print(line),
+        Result.Err(error) -> io.print("Error: \(error)"),
+    }
+}
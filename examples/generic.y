@@ -4,6 +4,14 @@ struct Foo<T> {
     x: T
 }
 
+func apply<T, U>(x: T, f: (T) -> U) -> U {
+    return f(x);
+}
+
 func main() {
-    let test: Foo<Vec<int>>;
-}
\ No newline at end of file
+    let test: Foo<[int]>;
+    let inferred = Foo { x: [1, 2, 3] };
+    let first: int = inferred.x[0];
+    let doubled = apply<int, int>(first, |n: int| int { return n * 2; });
+    let text: string = apply(doubled, |n: int| string { return "done"; });
+}
@@ -0,0 +1,70 @@
+module iterator;
+
+import "io" as io;
+
+trait Stepper {
+    const STEP: int = 1;
+
+    func step(self) -> int;
+
+    func skip(self, n: int) -> int {
+        let i: int = 1;
+        while (i < n) {
+            self.step();
+            i += 1;
+        }
+        return self.step();
+    }
+}
+
+struct Counter {
+    value: int,
+}
+
+extend Stepper for Counter {
+    const STEP: int = 2;
+
+    func step(self) -> int {
+        self.value += Counter.STEP;
+        return self.value;
+    }
+}
+
+extend Iterator for Counter {
+    type Item = int;
+
+    func next(self) -> int? {
+        if (self.value >= 10) {
+            return null;
+        }
+        return self.step();
+    }
+}
+
+struct Countdown {
+    from: int,
+}
+
+extend Iterable for Countdown {
+    type Iter = Counter;
+
+    func iter(self) -> Counter {
+        return Counter { value: 10 - self.from };
+    }
+}
+
+func main() {
+    let counter = Counter { value: 0 };
+    let third: int = counter.skip(3);
+    io.println("third: \(third)");
+
+    for (value in Counter { value: 0 }) {
+        io.println("counted \(value)");
+    }
+    for (value in Countdown { from: 4 }) {
+        io.println("counted down \(value)");
+    }
+    for (i in 0..3) {
+        io.println("step \(i)");
+    }
+}
@@ -1,21 +1,24 @@
 module point;
 
+import "io" as io;
 import "math" as math;
 
-
 struct Point {
     x: float,
-    y: float
+    y: float,
 }
 
 extend Point {
-    pub func new(x: float, y: float): Point {
-        return Point {
-            x, y
-        };
+    pub func new(x: float, y: float) -> Point {
+        return Point { x: x, y: y };
     }
 
-    pub func magnitude(): float {
-        return math.sqrt(x*x + y*y);
+    pub func magnitude(self) -> float {
+        return math.sqrt(self.x * self.x + self.y * self.y);
     }
-}
\ No newline at end of file
+}
+
+func main() {
+    let p = Point.new(3.0, 4.0);
+    io.println("magnitude: \(p.magnitude())");
+}
@@ -0,0 +1,51 @@
+module shapes;
+
+import "io" as io;
+
+trait Shape {
+    func area(self) -> float;
+
+    func describe(self) -> string {
+        return "a shape of area \(self.area())";
+    }
+}
+
+struct Circle {
+    radius: float,
+}
+
+struct Square {
+    side: float,
+}
+
+extend Shape for Circle {
+    func area(self) -> float {
+        return 3.0 * self.radius * self.radius;
+    }
+}
+
+extend Shape for Square {
+    func area(self) -> float {
+        return self.side * self.side;
+    }
+
+    func describe(self) -> string {
+        return "a square of side \(self.side)";
+    }
+}
+
+func total(shapes: [dyn Shape]) -> float {
+    let sum = 0.0;
+    for (shape in shapes) {
+        sum += shape.area();
+    }
+    return sum;
+}
+
+func main() {
+    let shapes: [dyn Shape] = [Circle { radius: 1.0 }, Square { side: 2.0 }];
+    for (shape in shapes) {
+        io.println(shape.describe());
+    }
+    io.println("total: \(total(shapes))");
+}
@@ -0,0 +1,86 @@
+//! `y build` bundles a checked program into a copy of the `y` executable.
+//! The program is appended to the end of the binary followed by a small trailer,
+//! and the bundled executable runs it on startup instead of parsing its arguments.
+
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+const MAGIC: &[u8; 8] = b"YBUNDLE1";
+const TRAILER_LEN: usize = 16; // payload length (u64) + magic
+
+/// A program embedded in a bundled executable.
+pub struct EmbeddedProgram {
+    pub name: String,
+    pub source: String,
+}
+
+/// Writes an executable to `output` that runs `source` when started.
+pub fn write_bundle(output: &Path, name: &str, source: &str) -> io::Result<()> {
+    let mut binary = fs::read(std::env::current_exe()?)?;
+
+    // Bundling from a bundled executable replaces the existing payload.
+    if let Some(payload_len) = payload_len(&binary) {
+        binary.truncate(binary.len() - TRAILER_LEN - payload_len);
+    }
+
+    let mut payload = Vec::with_capacity(name.len() + 1 + source.len());
+    payload.extend_from_slice(name.as_bytes());
+    payload.push(0);
+    payload.extend_from_slice(source.as_bytes());
+
+    binary.extend_from_slice(&payload);
+    binary.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+    binary.extend_from_slice(MAGIC);
+
+    fs::write(output, binary)?;
+    make_executable(output)
+}
+
+/// Returns the program embedded in the running executable, if any.
+pub fn embedded_program() -> Option<EmbeddedProgram> {
+    let mut file = File::open(std::env::current_exe().ok()?).ok()?;
+    let len = file.metadata().ok()?.len();
+    if len < TRAILER_LEN as u64 {
+        return None;
+    }
+
+    let mut trailer = [0u8; TRAILER_LEN];
+    file.seek(SeekFrom::End(-(TRAILER_LEN as i64))).ok()?;
+    file.read_exact(&mut trailer).ok()?;
+    let payload_len = payload_len(&trailer)? as u64;
+    if payload_len > len - TRAILER_LEN as u64 {
+        return None;
+    }
+
+    let mut payload = vec![0u8; payload_len as usize];
+    file.seek(SeekFrom::End(-((TRAILER_LEN as u64 + payload_len) as i64))).ok()?;
+    file.read_exact(&mut payload).ok()?;
+
+    let split = payload.iter().position(|&b| b == 0)?;
+    let name = String::from_utf8(payload[..split].to_vec()).ok()?;
+    let source = String::from_utf8(payload[split + 1..].to_vec()).ok()?;
+    Some(EmbeddedProgram { name, source })
+}
+
+/// Reads the payload length from the trailer at the end of `bytes`.
+fn payload_len(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < TRAILER_LEN || &bytes[bytes.len() - MAGIC.len()..] != MAGIC {
+        return None;
+    }
+    let start = bytes.len() - TRAILER_LEN;
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&bytes[start..start + 8]);
+    Some(u64::from_le_bytes(len) as usize)
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
@@ -0,0 +1,229 @@
+use std::fs;
+use std::io::{BufRead, ErrorKind, Read, Write};
+use std::path::PathBuf;
+
+use y::errors::{Error, Warning};
+use y::frontend::utils::ast_json::ToJson;
+use y::interpreter::value::Value;
+use y::repl::Repl;
+use y::{CheckedModule, Compiler, Session};
+
+use crate::cli::{
+    bundle::{self, EmbeddedProgram},
+    Command, Emit, Input, Options,
+    EXIT_COMPILE_ERROR, EXIT_IO_ERROR, EXIT_NOT_FORMATTED, EXIT_RUNTIME_ERROR, EXIT_SUCCESS,
+};
+
+pub fn execute(options: &Options) -> i32 {
+    match options.command {
+        Command::Lsp => return lsp_command(),
+        Command::Repl => return repl_command(),
+        _ => {}
+    }
+
+    let source = match read_input(&options.input) {
+        Ok(source) => source,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return EXIT_IO_ERROR;
+        }
+    };
+    let mut session = Compiler::new().session(options.input.display_name(), source);
+
+    let result = match options.command {
+        Command::Lex => lex_command(&mut session),
+        Command::Parse => parse_command(&mut session, &options.emit),
+        Command::Check => check(&mut session).map(|_| ()),
+        Command::Run => run_command(&mut session),
+        Command::Build => build_command(&mut session, options),
+        Command::Fmt => fmt_command(&mut session, options),
+        Command::Lsp | Command::Repl => unreachable!("handled before reading the input"),
+    };
+
+    if options.time_passes {
+        for timing in &session.timings {
+            eprintln!("{} took: {:?}", timing.pass, timing.duration);
+        }
+    }
+
+    result.err().unwrap_or(EXIT_SUCCESS)
+}
+
+/// Runs a program embedded by `y build`.
+pub fn run_embedded(program: EmbeddedProgram) -> i32 {
+    let mut session = Compiler::new().session(program.name, program.source);
+    run_command(&mut session).err().unwrap_or(EXIT_SUCCESS)
+}
+
+fn read_input(input: &Input) -> Result<String, String> {
+    match input {
+        Input::Stdin => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| format!("Unable to read standard input: {}", e))?;
+            Ok(text)
+        }
+        Input::File(path) => fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read '{}': {}", path.display(), e)),
+    }
+}
+
+/// Prints compile errors and returns the matching exit code.
+fn report(errors: Vec<Error>) -> i32 {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    EXIT_COMPILE_ERROR
+}
+
+/// Writes to standard output. A reader that stops early, as `head` does, ends the
+/// command successfully; any other failure is an I/O error.
+pub fn print_out(write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>) -> Result<(), i32> {
+    let stdout = std::io::stdout();
+    let mut out = stdout.lock();
+    write(&mut out).and_then(|_| out.flush()).map_err(|e| match e.kind() {
+        ErrorKind::BrokenPipe => EXIT_SUCCESS,
+        _ => {
+            eprintln!("error: Unable to write to standard output: {}", e);
+            EXIT_IO_ERROR
+        }
+    })
+}
+
+fn warn(warnings: &[Warning]) {
+    for warning in warnings {
+        eprintln!("{}", warning);
+    }
+}
+
+/// Checks the session's source, reporting its warnings even when there are errors.
+fn check(session: &mut Session) -> Result<CheckedModule, i32> {
+    let result = session.check();
+    warn(&session.warnings);
+    result.map_err(report)
+}
+
+fn lex_command(session: &mut Session) -> Result<(), i32> {
+    let tokens = session.lex().map_err(report)?;
+    print_out(|out| tokens.iter().try_for_each(|token| writeln!(out, "{}", token)))
+}
+
+fn parse_command(session: &mut Session, emit: &Emit) -> Result<(), i32> {
+    let module = session.parse().map_err(report)?;
+    print_out(|out| match emit {
+        Emit::Ast => writeln!(out, "{:#?}", module),
+        Emit::Json => writeln!(out, "{}", module.to_json().pretty()),
+    })
+}
+
+fn run_command(session: &mut Session) -> Result<(), i32> {
+    let checked = check(session)?;
+
+    match session.run(&checked, Box::new(std::io::stdout())) {
+        Ok(Value::Int(0)) | Ok(Value::Void) => Ok(()),
+        Ok(Value::Int(code)) => Err(code as i32),
+        Ok(_) => Ok(()),
+        Err(errors) => {
+            report(errors);
+            Err(EXIT_RUNTIME_ERROR)
+        }
+    }
+}
+
+fn fmt_command(session: &mut Session, options: &Options) -> Result<(), i32> {
+    let formatted = session.format().map_err(report)?;
+
+    if options.check {
+        if formatted != session.source {
+            eprintln!("{} is not formatted", session.name);
+            return Err(EXIT_NOT_FORMATTED);
+        }
+        return Ok(());
+    }
+
+    match &options.input {
+        Input::Stdin => print_out(|out| write!(out, "{}", formatted))?,
+        Input::File(path) if formatted != session.source => {
+            if let Err(e) = fs::write(path, formatted) {
+                eprintln!("error: Unable to write '{}': {}", path.display(), e);
+                return Err(EXIT_IO_ERROR);
+            }
+        }
+        Input::File(_) => {}
+    }
+    Ok(())
+}
+
+fn lsp_command() -> i32 {
+    let stdin = std::io::stdin();
+    match y::lsp::Server::new().run(&mut stdin.lock(), &mut std::io::stdout()) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: Language server I/O failed: {}", e);
+            EXIT_IO_ERROR
+        }
+    }
+}
+
+fn repl_command() -> i32 {
+    let mut repl = Repl::new(Box::new(std::io::stdout()));
+    let stdin = std::io::stdin();
+    let mut input = String::new();
+
+    let banner = print_out(|out| writeln!(out, "y {}, type :help for help", env!("CARGO_PKG_VERSION")));
+    if let Err(code) = banner {
+        return code;
+    }
+    loop {
+        if let Err(code) = print_out(|out| write!(out, "{}", if input.is_empty() { "> " } else { "... " })) {
+            return code;
+        }
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => input.push_str(&line),
+            Err(e) => {
+                eprintln!("error: Unable to read standard input: {}", e);
+                return EXIT_IO_ERROR;
+            }
+        }
+        if !Repl::is_complete(&input) {
+            continue;
+        }
+
+        let entry = std::mem::take(&mut input);
+        if matches!(entry.trim(), ":quit" | ":q") {
+            break;
+        }
+        match repl.eval(&entry) {
+            Ok(Some(text)) => {
+                if let Err(code) = print_out(|out| writeln!(out, "{}", text)) {
+                    return code;
+                }
+            }
+            Ok(None) => {}
+            Err(errors) => {
+                report(errors);
+            }
+        }
+    }
+    EXIT_SUCCESS
+}
+
+fn build_command(session: &mut Session, options: &Options) -> Result<(), i32> {
+    check(session)?;
+
+    let output = match (&options.output, &options.input) {
+        (Some(output), _) => output.clone(),
+        (None, Input::File(path)) => PathBuf::from(path.file_stem().unwrap_or(path.as_os_str())),
+        (None, Input::Stdin) => PathBuf::from("main"),
+    };
+
+    if let Err(e) = bundle::write_bundle(&output, &session.name, &session.source) {
+        eprintln!("error: Unable to write '{}': {}", output.display(), e);
+        return Err(EXIT_IO_ERROR);
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+pub mod bundle;
+pub mod commands;
+
+use std::path::PathBuf;
+
+/// Everything went fine
+pub const EXIT_SUCCESS: i32 = 0;
+/// The program had lexing, parsing or semantic errors
+pub const EXIT_COMPILE_ERROR: i32 = 1;
+/// The command line could not be understood
+pub const EXIT_USAGE: i32 = 2;
+/// An input could not be read or an output could not be written
+pub const EXIT_IO_ERROR: i32 = 3;
+/// The program failed while running
+pub const EXIT_RUNTIME_ERROR: i32 = 4;
+/// `fmt --check` found a file that is not formatted
+pub const EXIT_NOT_FORMATTED: i32 = 1;
+
+pub const USAGE: &str = "\
+Usage: y <command> [options] <file | ->
+       y lsp | repl
+
+Commands:
+    lex       Print the token stream
+    parse     Parse the file and print its syntax tree
+    check     Run semantic analysis and report diagnostics
+    run       Check the file and execute its `main` function
+    build     Check the file and bundle it into an executable
+    fmt       Format the file in place, or to standard output when reading `-`
+    lsp       Start a language server on standard input and output
+    repl      Start an interactive session
+
+Options:
+    --emit=<ast|json>   Syntax tree format for `parse` (default: ast)
+    -o <path>           Output path for `build`
+    --check             Make `fmt` only report whether the file is formatted
+    --time-passes       Print how long each compiler pass took
+    -h, --help          Show this message
+
+Use `-` as the file to read the program from standard input.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Lex,
+    Parse,
+    Check,
+    Run,
+    Build,
+    Fmt,
+    Lsp,
+    Repl,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Emit {
+    Ast,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Input {
+    Stdin,
+    File(PathBuf),
+}
+
+impl Input {
+    /// The name used for the input in diagnostics.
+    pub fn display_name(&self) -> String {
+        match self {
+            Input::Stdin => "<stdin>".to_string(),
+            Input::File(path) => path.display().to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub input: Input,
+    pub emit: Emit,
+    pub output: Option<PathBuf>,
+    pub time_passes: bool,
+    pub check: bool,
+}
+
+/// Parses the command line, excluding the program name.
+/// `Ok(None)` means help was requested.
+pub fn parse_args(args: &[String]) -> Result<Option<Options>, String> {
+    let mut args = args.iter();
+
+    let command = match args.next().map(String::as_str) {
+        Some("lex") => Command::Lex,
+        Some("parse") => Command::Parse,
+        Some("check") => Command::Check,
+        Some("run") => Command::Run,
+        Some("build") => Command::Build,
+        Some("fmt") => Command::Fmt,
+        Some("lsp") => Command::Lsp,
+        Some("repl") => Command::Repl,
+        Some("-h" | "--help" | "help") => return Ok(None),
+        Some(other) => return Err(format!("Unknown command '{}'", other)),
+        None => return Err("No command given".to_string()),
+    };
+
+    let mut input = None;
+    let mut emit = Emit::Ast;
+    let mut output = None;
+    let mut time_passes = false;
+    let mut check = false;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--time-passes" => time_passes = true,
+            "--check" => check = true,
+            "-o" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return Err("Expected a path after '-o'".to_string()),
+            },
+            "--emit=ast" => emit = Emit::Ast,
+            "--emit=json" => emit = Emit::Json,
+            other if other.starts_with("--emit=") => {
+                return Err(format!("Unknown emit kind '{}', expected 'ast' or 'json'", &other["--emit=".len()..]));
+            }
+            "-" => input = Some(Input::Stdin),
+            other if other.starts_with('-') => return Err(format!("Unknown option '{}'", other)),
+            other => {
+                if input.is_some() {
+                    return Err(format!("Unexpected argument '{}', only one input file is supported", other));
+                }
+                input = Some(Input::File(PathBuf::from(other)));
+            }
+        }
+    }
+
+    // The language server and the REPL read their programs interactively
+    let input = match command {
+        Command::Lsp | Command::Repl if input.is_some() => {
+            return Err(format!("'{}' doesn't take an input file", if command == Command::Lsp { "lsp" } else { "repl" }));
+        }
+        Command::Lsp | Command::Repl => Input::Stdin,
+        _ => input.ok_or_else(|| "No input file given".to_string())?,
+    };
+
+    if output.is_some() && command != Command::Build {
+        return Err("'-o' is only supported by 'build'".to_string());
+    }
+    if check && command != Command::Fmt {
+        return Err("'--check' is only supported by 'fmt'".to_string());
+    }
+
+    Ok(Some(Options { command, input, emit, output, time_passes, check }))
+}
+
+/// Runs the command line and returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            return commands::print_out(|out| writeln!(out, "{}", USAGE)).err().unwrap_or(EXIT_SUCCESS);
+        }
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            return EXIT_USAGE;
+        }
+    };
+
+    commands::execute(&options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse_args(&args(&["parse", "--emit=json", "main.y", "--time-passes"])).unwrap().unwrap();
+        assert_eq!(options.command, Command::Parse);
+        assert_eq!(options.emit, Emit::Json);
+        assert_eq!(options.input, Input::File(PathBuf::from("main.y")));
+        assert!(options.time_passes);
+
+        let options = parse_args(&args(&["build", "-", "-o", "out"])).unwrap().unwrap();
+        assert_eq!(options.input, Input::Stdin);
+        assert_eq!(options.output, Some(PathBuf::from("out")));
+
+        assert_eq!(parse_args(&args(&["--help"])), Ok(None));
+        assert!(parse_args(&args(&["run"])).is_err());
+        assert!(parse_args(&args(&["run", "a.y", "b.y"])).is_err());
+        assert!(parse_args(&args(&["check", "-o", "out", "a.y"])).is_err());
+        assert!(parse_args(&args(&["fmt", "--check", "a.y"])).unwrap().unwrap().check);
+        assert!(parse_args(&args(&["run", "--check", "a.y"])).is_err());
+        assert!(parse_args(&args(&["parse", "--emit=llvm", "a.y"])).is_err());
+        assert_eq!(parse_args(&args(&["lsp"])).unwrap().unwrap().command, Command::Lsp);
+        assert!(parse_args(&args(&["lsp", "a.y"])).is_err());
+        assert_eq!(parse_args(&args(&["repl"])).unwrap().unwrap().command, Command::Repl);
+    }
+}
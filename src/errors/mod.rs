@@ -1,4 +1,6 @@
-use crate::frontend::utils::token::Span;
+use std::fmt;
+
+use crate::frontend::utils::token::{line_col, Span};
 
 const RED: &str = "\x1b[38;5;203m";
 const CYAN: &str = "\x1b[38;5;117m";
@@ -10,6 +12,20 @@ const CONTEXT_LINES: usize = 2;
 
 const MAX_LINE_LENGTH: usize = 80;  // Adjust this to your preferred line length
 
+/// Returns the byte columns that `span` covers on `line`, clamped to that line.
+fn line_columns(source: &str, line: usize, span: &Span) -> (usize, usize) {
+    let mut line_start = 0;
+    for (i, text) in source.split('\n').enumerate() {
+        if i + 1 == line {
+            let start = span.start.saturating_sub(line_start).min(text.len());
+            let end = span.end.saturating_sub(line_start).min(text.len()).max(start);
+            return (start, end);
+        }
+        line_start += text.len() + 1;
+    }
+    (0, 0)
+}
+
 trait Diagnostic {
     #[allow(dead_code)] fn get_line(&self) -> usize;
     fn get_span(&self) -> &Span;
@@ -18,7 +34,7 @@ trait Diagnostic {
     #[allow(dead_code)] fn get_kind(&self) -> &str;
     fn get_colour(&self) -> &str;
     
-    fn caret(&self) -> String {
+    fn caret(&self, source: &str) -> String {
         let mut caret = String::new();
         let span = self.get_span();
         
@@ -26,8 +42,9 @@ trait Diagnostic {
             return caret;
         }
 
-        caret.push_str(&" ".repeat(span.start));
-        caret.push_str(&"^".repeat(span.end - span.start));
+        let (start, end) = line_columns(source, self.get_line(), span);
+        caret.push_str(&" ".repeat(start));
+        caret.push_str(&"^".repeat(end.saturating_sub(start).max(1)));
         caret
     }
 
@@ -58,7 +75,7 @@ trait Diagnostic {
     }
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Error {
     pub message: String,
     pub line: usize,
@@ -72,7 +89,7 @@ pub struct Error {
     source: String,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Note {
     pub message: String,
     pub line: usize,
@@ -81,7 +98,7 @@ pub struct Note {
     pub filename: String,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Warning {
     pub message: String,
     pub line: usize,
@@ -95,7 +112,7 @@ pub struct Warning {
     source: String,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Help {
     pub message: String,
     pub line: usize,
@@ -165,12 +182,27 @@ impl Error {
         self.helps.push(help);
     }
 
+    /// Attaches the file an error was reported in. Errors raised by later passes only know
+    /// their span, so the line is worked out from the source if it was not given.
+    pub fn locate(mut self, filename: &str, source: &str) -> Error {
+        if self.line == 0 {
+            self.line = line_col(source, self.span.start).0;
+        }
+        for note in &mut self.notes {
+            note.locate(filename, source);
+        }
+        for help in &mut self.helps {
+            help.locate(filename, source);
+        }
+        self.filename = filename.to_string();
+        self.source = source.to_string();
+        self
+    }
+
     fn colourise(&self, content: &str) -> String {
-        // Safely clamp the span to the content bounds
-        let span = self.get_span();
+        // Spans are offsets into the whole source, so find the part on this line
         let len = content.len();
-        let start = span.start.min(len);
-        let end = span.end.min(len).max(start); // ensure end >= start
+        let (start, end) = line_columns(&self.source, self.line, self.get_span());
 
         // If content is empty or span is zero-length, just return content
         if len == 0 || start == end {
@@ -183,20 +215,18 @@ impl Error {
     }
 
     fn wrap_message(message: &str, indent: usize) -> String {
-        let available_width;
-
-        if indent > MAX_LINE_LENGTH {
-            available_width = MAX_LINE_LENGTH;
+        let available_width = if indent > MAX_LINE_LENGTH {
+            MAX_LINE_LENGTH
         } else {
-            available_width = MAX_LINE_LENGTH - indent;
-        }
+            MAX_LINE_LENGTH - indent
+        };
 
         let mut result = String::new();
         let mut current_line = String::new();
         let mut first_line = true;
 
         for word in message.split_whitespace() {
-            if current_line.len() + word.len() + 1 <= available_width {
+            if current_line.len() + word.len() < available_width {
                 if !current_line.is_empty() {
                     current_line.push(' ');
                 }
@@ -222,7 +252,7 @@ impl Error {
         result
     }
 
-    pub fn to_string(&self) -> String {
+    fn render(&self) -> String {
         let mut output = String::new();
         
         // 1) Print the standard error header
@@ -258,7 +288,7 @@ impl Error {
         }
 
         // 4) Merge overlapping/adjacent intervals to avoid duplicates
-        intervals.sort_by(|a, b| a.0.cmp(&b.0));
+        intervals.sort_by_key(|a| a.0);
         let mut merged = Vec::<(usize, usize)>::new();
         for (start, end) in intervals {
             if let Some((_, prev_end)) = merged.last_mut() {
@@ -340,7 +370,7 @@ impl Error {
                 if let Some(line_notes) = notes_by_line.get(&current_line) {
                     for note in line_notes {
                         let caret_indent = "      │ ".len();
-                        let note_caret = note.caret();
+                        let note_caret = note.caret(&self.source);
                         output.push_str(&format!("      │ {}{}{} ",
                             CYAN, note_caret, RESET
                         ));
@@ -356,7 +386,7 @@ impl Error {
                 if let Some(line_helps) = helps_by_line.get(&current_line) {
                     for help in line_helps {
                         let caret_indent = "      │ ".len();
-                        let help_caret = help.caret();
+                        let help_caret = help.caret(&self.source);
                         output.push_str(&format!("      │ {}{}{} ",
                             GREEN, help_caret, RESET
                         ));
@@ -376,6 +406,12 @@ impl Error {
     }
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
 impl Note {
     pub fn new(message: String, line: usize, span: Span, filename: String) -> Note {
         Note {
@@ -385,6 +421,13 @@ impl Note {
             filename,
         }
     }
+
+    fn locate(&mut self, filename: &str, source: &str) {
+        if self.line == 0 {
+            self.line = line_col(source, self.span.start).0;
+        }
+        self.filename = filename.to_string();
+    }
 }
 
 impl Warning {
@@ -412,6 +455,22 @@ impl Warning {
         self.source = source;
     }
 
+    /// Attaches the file a warning was reported in, like `Error::locate`.
+    pub fn locate(mut self, filename: &str, source: &str) -> Warning {
+        if self.line == 0 {
+            self.line = line_col(source, self.span.start).0;
+        }
+        for note in &mut self.notes {
+            note.locate(filename, source);
+        }
+        for help in &mut self.helps {
+            help.locate(filename, source);
+        }
+        self.filename = filename.to_string();
+        self.source = source.to_string();
+        self
+    }
+
     fn wrap_message(message: &str, indent: usize) -> String {
         let available_width = MAX_LINE_LENGTH - indent;
         let mut result = String::new();
@@ -419,7 +478,7 @@ impl Warning {
         let mut first_line = true;
 
         for word in message.split_whitespace() {
-            if current_line.len() + word.len() + 1 <= available_width {
+            if current_line.len() + word.len() < available_width {
                 if !current_line.is_empty() {
                     current_line.push(' ');
                 }
@@ -445,7 +504,7 @@ impl Warning {
         result
     }
 
-    pub fn to_string(&self) -> String {
+    fn render(&self) -> String {
         let mut output = String::new();
         
         // Header
@@ -461,7 +520,7 @@ impl Warning {
 
         let min_line = all_lines.iter().map(|&(line, _)| line).min().unwrap_or(self.line);
         let max_line = all_lines.iter().map(|&(line, _)| line).max().unwrap_or(self.line);
-        let start_line = min_line.saturating_sub(CONTEXT_LINES);
+        let start_line = min_line.saturating_sub(CONTEXT_LINES).max(1);
         let end_line = (max_line + CONTEXT_LINES).min(self.source.lines().count());
 
         // Source code section
@@ -478,7 +537,7 @@ impl Warning {
             if line_num == self.line {
                 output.push_str(&format!("      │ {}{}{}\n",
                     self.get_colour(),
-                    self.caret(),
+                    self.caret(&self.source),
                     RESET
                 ));
             }
@@ -489,12 +548,12 @@ impl Warning {
                     let caret_indent = "      │ ".len();
                     output.push_str(&format!("      │ {}{}{} ",
                         CYAN,
-                        note.caret(),
+                        note.caret(&self.source),
                         RESET
                     ));
                     
                     // Calculate the indent for wrapped lines
-                    let total_indent = caret_indent + note.caret().len() + 1;
+                    let total_indent = caret_indent + note.caret(&self.source).len() + 1;
                     let wrapped_message = Self::wrap_message(&note.message, total_indent);
                     
                     output.push_str(&format!("{}{}{}\n",
@@ -510,12 +569,12 @@ impl Warning {
                     let caret_indent = "      │ ".len();
                     output.push_str(&format!("      │ {}{}{} ",
                         GREEN,
-                        help.caret(),
+                        help.caret(&self.source),
                         RESET
                     ));
                     
                     // Calculate the indent for wrapped lines
-                    let total_indent = caret_indent + help.caret().len() + 1;
+                    let total_indent = caret_indent + help.caret(&self.source).len() + 1;
                     let wrapped_message = Self::wrap_message(&help.message, total_indent);
                     
                     output.push_str(&format!("{}{}{}\n",
@@ -531,6 +590,12 @@ impl Warning {
     }
 }
 
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
 impl Help {
     pub fn new(message: String, line: usize, span: Span, filename: String) -> Help {
         Help {
@@ -540,4 +605,11 @@ impl Help {
             filename,
         }
     }
+
+    fn locate(&mut self, filename: &str, source: &str) {
+        if self.line == 0 {
+            self.line = line_col(source, self.span.start).0;
+        }
+        self.filename = filename.to_string();
+    }
 }
\ No newline at end of file
@@ -0,0 +1,573 @@
+use crate::frontend::utils::{
+    cst::{SyntaxElement, SyntaxKind, SyntaxNode},
+    token::{Token, TokenKind, TriviaKind},
+};
+
+/// Lines longer than this are broken up where the formatter knows how to
+pub const MAX_WIDTH: usize = 100;
+const INDENT: &str = "    ";
+
+/// Formats a concrete syntax tree produced by a parse without errors.
+pub fn format(tree: &SyntaxNode) -> String {
+    let mut formatter = Formatter { printer: Printer::default() };
+    formatter.module(tree);
+    formatter.printer.finish()
+}
+
+/// Writes tokens out one at a time, deciding the whitespace between them.
+/// Line breaks are requested rather than written, so a trailing comment can still
+/// be placed on the line it came from.
+#[derive(Debug, Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+    /// Line feeds to write before the next token, two for a blank line
+    newlines: usize,
+    /// Suppresses the space before the next token
+    glue: bool,
+    /// The last token written on the current line
+    last: Option<TokenKind>,
+}
+
+impl Printer {
+    fn line(&mut self) {
+        self.newlines = self.newlines.max(1);
+    }
+
+    fn blank_line(&mut self) {
+        self.newlines = 2;
+    }
+
+    fn glue(&mut self) {
+        self.glue = true;
+    }
+
+    fn token(&mut self, token: &Token) {
+        let newlines = self.comments(token);
+        if newlines > 1 && self.newlines > 0 && token.kind != TokenKind::Rbrace {
+            self.keep_blank_line();
+        }
+        self.text(token.kind.clone(), &token.lexeme);
+    }
+
+    /// Writes the comments in front of `token`.
+    /// Returns the number of line feeds between the last comment (or previous token) and the token.
+    fn comments(&mut self, token: &Token) -> usize {
+        let mut newlines = 0;
+
+        for trivia in &token.leading_trivia {
+            match trivia.kind {
+                TriviaKind::Newline => newlines += 1,
+                TriviaKind::Whitespace => {}
+                TriviaKind::Comment => {
+                    if newlines == 0 && self.last.is_some() {
+                        // A trailing comment stays on the line it was written on
+                        self.out.push(' ');
+                    } else {
+                        if newlines > 1 && self.newlines > 0 {
+                            self.keep_blank_line();
+                        }
+                        self.line();
+                        self.break_line();
+                    }
+                    self.out.push_str(trivia.text.trim_end());
+                    self.last = Some(TokenKind::Slash);
+                    self.line();
+                    newlines = 0;
+                }
+            }
+        }
+
+        newlines
+    }
+
+    /// Keeps a blank line from the source, unless it directly follows an opening brace.
+    fn keep_blank_line(&mut self) {
+        if self.last != Some(TokenKind::Lbrace) {
+            self.blank_line();
+        }
+    }
+
+    fn text(&mut self, kind: TokenKind, text: &str) {
+        if self.newlines > 0 {
+            self.break_line();
+        } else if !self.glue && self.needs_space(&kind) {
+            self.out.push(' ');
+        }
+
+        self.out.push_str(text);
+        self.last = Some(kind);
+        self.glue = false;
+    }
+
+    /// Writes the requested line feeds and the indentation of the new line.
+    fn break_line(&mut self) {
+        if !self.out.is_empty() {
+            for _ in 0..self.newlines {
+                self.out.push('\n');
+            }
+            self.out.push_str(&INDENT.repeat(self.indent));
+        }
+        self.newlines = 0;
+        self.glue = false;
+        self.last = None;
+    }
+
+    fn needs_space(&self, next: &TokenKind) -> bool {
+        let last = match &self.last {
+            Some(last) => last,
+            None => return false,
+        };
+
+        !matches!(next, TokenKind::Comma | TokenKind::Semicolon | TokenKind::Rparen
+            | TokenKind::Rbracket | TokenKind::Dot | TokenKind::Colon | TokenKind::Question)
+            && !matches!(last, TokenKind::Lparen | TokenKind::Lbracket | TokenKind::Dot)
+    }
+
+    /// A printer that continues the current line, used to measure a layout.
+    fn scratch(&self) -> Printer {
+        let line_start = self.out.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        Printer {
+            out: self.out[line_start..].to_string(),
+            indent: self.indent,
+            newlines: self.newlines,
+            glue: self.glue,
+            last: self.last.clone(),
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let len = self.out.trim_end().len();
+        self.out.truncate(len);
+        self.out.push('\n');
+        self.out
+    }
+}
+
+struct Formatter {
+    printer: Printer,
+}
+
+impl Formatter {
+    fn module(&mut self, root: &SyntaxNode) {
+        let mut previous: Option<SyntaxKind> = None;
+
+        for child in &root.children {
+            match child {
+                SyntaxElement::Node(node) => {
+                    match previous {
+                        Some(previous) if Self::is_compact(previous) && previous == node.kind => self.printer.line(),
+                        Some(_) => self.printer.blank_line(),
+                        None => {}
+                    }
+                    self.node(node);
+                    previous = Some(node.kind);
+                }
+                // The end of file, and anything the parser skipped over
+                SyntaxElement::Token(token) => {
+                    self.printer.line();
+                    self.printer.comments(token);
+                }
+            }
+        }
+    }
+
+    /// Top level items that don't need a blank line between them.
+    fn is_compact(kind: SyntaxKind) -> bool {
+        matches!(kind, SyntaxKind::Import | SyntaxKind::LetStmt | SyntaxKind::ExprStmt)
+    }
+
+    fn node(&mut self, node: &SyntaxNode) {
+        match node.kind {
+            SyntaxKind::Block => self.block(node),
+            SyntaxKind::Struct | SyntaxKind::Enum | SyntaxKind::MatchStmt | SyntaxKind::MatchExpr => self.braced(node, true),
+            SyntaxKind::Trait | SyntaxKind::Extend => self.braced(node, false),
+            SyntaxKind::ParamList | SyntaxKind::ArgList | SyntaxKind::ArrayExpr
+            | SyntaxKind::TupleExpr | SyntaxKind::StructInit => self.list(node),
+            SyntaxKind::RangeExpr => self.range(node),
+            SyntaxKind::Function => self.function(node),
+            SyntaxKind::Attribute => {
+                // Each attribute goes on its own line, above what it applies to
+                self.inline(node);
+                self.printer.line();
+            }
+            _ => self.inline(node),
+        }
+    }
+
+    fn element(&mut self, parent: SyntaxKind, element: &SyntaxElement) {
+        if Self::glue_before(parent, element) {
+            self.printer.glue();
+        }
+
+        match element {
+            SyntaxElement::Node(node) => self.node(node),
+            SyntaxElement::Token(token) => self.printer.token(token),
+        }
+
+        if Self::glue_after(parent, element) {
+            self.printer.glue();
+        }
+    }
+
+    fn glue_before(parent: SyntaxKind, element: &SyntaxElement) -> bool {
+        match element {
+            SyntaxElement::Node(node) => match node.kind {
+                SyntaxKind::GenericList | SyntaxKind::ArgList => true,
+                SyntaxKind::ParamList => parent == SyntaxKind::Function,
+                _ => false,
+            },
+            SyntaxElement::Token(token) => matches!((parent, &token.kind),
+                (SyntaxKind::GenericList, TokenKind::Gt)
+                | (SyntaxKind::IndexExpr, TokenKind::Lbracket)
+                | (SyntaxKind::EnumVariant | SyntaxKind::VariantPattern | SyntaxKind::Attribute, TokenKind::Lparen)
+                | (SyntaxKind::RangePattern, TokenKind::Range | TokenKind::RangeEq)),
+        }
+    }
+
+    fn glue_after(parent: SyntaxKind, element: &SyntaxElement) -> bool {
+        match element {
+            SyntaxElement::Token(token) => matches!((parent, &token.kind),
+                (SyntaxKind::GenericList, TokenKind::Lt)
+                | (SyntaxKind::Attribute, TokenKind::Hash)
+                | (SyntaxKind::UnaryExpr, TokenKind::Bang | TokenKind::Minus)
+                | (SyntaxKind::Pattern | SyntaxKind::RangePattern, TokenKind::Minus)
+                | (SyntaxKind::RangePattern | SyntaxKind::RangeExpr, TokenKind::Range | TokenKind::RangeEq)),
+            SyntaxElement::Node(_) => false,
+        }
+    }
+
+    /// Writes a node on the current line.
+    fn inline(&mut self, node: &SyntaxNode) {
+        for child in &node.children {
+            self.element(node.kind, child);
+        }
+    }
+
+    /// Like `inline`, but the parameter list is also broken when the rest of the
+    /// header, through the `{` of the body, would run past the line width.
+    fn function(&mut self, node: &SyntaxNode) {
+        for (i, child) in node.children.iter().enumerate() {
+            match child {
+                SyntaxElement::Node(params) if params.kind == SyntaxKind::ParamList => {
+                    let broken = self.has_comments(params) || !self.fits(params, node.kind, &node.children[i + 1..]);
+                    self.printer.glue();
+                    self.list_with(params, broken);
+                }
+                _ => self.element(node.kind, child),
+            }
+        }
+    }
+
+    /// Like `inline`, but `..` only sticks to a start bound when there is one, as in `a..b` and `= ..b`.
+    fn range(&mut self, node: &SyntaxNode) {
+        for (i, child) in node.children.iter().enumerate() {
+            if i > 0 && matches!(child, SyntaxElement::Token(token) if matches!(token.kind, TokenKind::Range | TokenKind::RangeEq)) {
+                self.printer.glue();
+            }
+            self.element(node.kind, child);
+        }
+    }
+
+    fn block(&mut self, node: &SyntaxNode) {
+        let (open, stmts, close) = match Self::delimited(node) {
+            Some(parts) => parts,
+            None => return self.inline(node),
+        };
+
+        self.printer.token(open);
+        self.printer.indent += 1;
+        for stmt in stmts {
+            self.printer.line();
+            self.element(node.kind, stmt);
+        }
+        self.printer.comments(close);
+        self.printer.indent -= 1;
+
+        if stmts.is_empty() && self.printer.last == Some(TokenKind::Lbrace) {
+            self.printer.glue();
+        } else {
+            self.printer.line();
+        }
+        self.printer.text(close.kind.clone(), &close.lexeme);
+    }
+
+    /// Declarations and `match` put each item on its own line,
+    /// with commas after every item if `commas` is set.
+    fn braced(&mut self, node: &SyntaxNode, commas: bool) {
+        let open = node.children.iter().position(|child| Self::is_token(child, TokenKind::Lbrace));
+        let (header, body) = match open {
+            Some(open) => node.children.split_at(open),
+            None => return self.inline(node),
+        };
+        for child in header {
+            self.element(node.kind, child);
+        }
+
+        let (open, items, close) = match Self::delimited_slice(body) {
+            Some(parts) => parts,
+            None => return self.inline(node),
+        };
+
+        // Functions are separated by a blank line, everything else is kept together
+        let blank_lines = matches!(node.kind, SyntaxKind::Trait | SyntaxKind::Extend);
+        let is_function = |item: &SyntaxElement| matches!(item, SyntaxElement::Node(node) if node.kind == SyntaxKind::Function);
+
+        self.printer.token(open);
+        self.printer.indent += 1;
+        let mut first = true;
+        let mut previous_function = false;
+        for item in items {
+            if Self::is_token(item, TokenKind::Comma) {
+                if let SyntaxElement::Token(comma) = item {
+                    self.printer.comments(comma);
+                }
+                continue;
+            }
+            if blank_lines && !first && (previous_function || is_function(item)) {
+                self.printer.blank_line();
+            } else {
+                self.printer.line();
+            }
+            previous_function = is_function(item);
+            self.element(node.kind, item);
+            if commas {
+                self.printer.glue();
+                self.printer.text(TokenKind::Comma, ",");
+            }
+            first = false;
+        }
+        self.printer.comments(close);
+        self.printer.indent -= 1;
+
+        if first && self.printer.last == Some(TokenKind::Lbrace) {
+            self.printer.glue();
+        } else {
+            self.printer.line();
+        }
+        self.printer.text(close.kind.clone(), &close.lexeme);
+    }
+
+    /// Comma separated lists stay on one line if they fit, otherwise every
+    /// item goes on its own line with a trailing comma.
+    fn list(&mut self, node: &SyntaxNode) {
+        let broken = self.has_comments(node) || !self.fits(node, node.kind, &[]);
+        self.list_with(node, broken);
+    }
+
+    /// Whether the list fits on the current line unbroken, together with the
+    /// elements of `parent` that follow it up to the end of that line.
+    fn fits(&mut self, node: &SyntaxNode, parent: SyntaxKind, rest: &[SyntaxElement]) -> bool {
+        let scratch = self.printer.scratch();
+        let saved = std::mem::replace(&mut self.printer, scratch);
+        self.list_with(node, false);
+        for element in rest {
+            self.element(parent, element);
+        }
+        let scratch = std::mem::replace(&mut self.printer, saved);
+
+        let first_line = scratch.out.lines().next().unwrap_or("");
+        first_line.chars().count() <= MAX_WIDTH
+    }
+
+    /// Whether a comment appears inside the node, i.e. after its first token.
+    fn has_comments(&self, node: &SyntaxNode) -> bool {
+        node.tokens().iter().skip(1).any(|token| {
+            token.leading_trivia.iter().any(|trivia| trivia.kind == TriviaKind::Comment)
+        })
+    }
+
+    fn list_with(&mut self, node: &SyntaxNode, broken: bool) {
+        let open = node.children.iter().position(|child| {
+            matches!(child, SyntaxElement::Token(token) if matches!(token.kind,
+                TokenKind::Lparen | TokenKind::Lbracket | TokenKind::Lbrace | TokenKind::Pipe))
+        });
+        let (header, body) = match open {
+            Some(open) => node.children.split_at(open),
+            None => return self.inline(node),
+        };
+        let (open, items, close) = match Self::delimited_slice(body) {
+            Some(parts) => parts,
+            None => return self.inline(node),
+        };
+        for child in header {
+            self.element(node.kind, child);
+        }
+
+        // Struct literals keep spaces inside their braces
+        let padded = node.kind == SyntaxKind::StructInit;
+        let values: Vec<&SyntaxElement> = items.iter().filter(|item| !Self::is_token(item, TokenKind::Comma)).collect();
+        // A one element tuple needs its comma
+        let keep_comma = node.kind == SyntaxKind::TupleExpr && values.len() == 1;
+
+        self.printer.token(open);
+        if !padded || values.is_empty() {
+            self.printer.glue();
+        }
+        if broken {
+            self.printer.indent += 1;
+        }
+
+        let mut count = 0;
+        for item in items {
+            if let SyntaxElement::Token(comma) = item {
+                if comma.kind == TokenKind::Comma {
+                    self.printer.comments(comma);
+                    continue;
+                }
+            }
+
+            count += 1;
+            if broken {
+                self.printer.line();
+            }
+            self.element(node.kind, item);
+            if broken || count < values.len() || keep_comma {
+                self.printer.glue();
+                self.printer.text(TokenKind::Comma, ",");
+            }
+        }
+
+        self.printer.comments(close);
+        if broken {
+            self.printer.indent -= 1;
+            self.printer.line();
+        } else if !padded || values.is_empty() {
+            self.printer.glue();
+        }
+        self.printer.text(close.kind.clone(), &close.lexeme);
+    }
+
+    fn is_token(element: &SyntaxElement, kind: TokenKind) -> bool {
+        matches!(element, SyntaxElement::Token(token) if token.kind == kind)
+    }
+
+    fn delimited(node: &SyntaxNode) -> Option<(&Token, &[SyntaxElement], &Token)> {
+        Self::delimited_slice(&node.children)
+    }
+
+    /// Splits `children` into an opening token, the elements in between and a closing token.
+    fn delimited_slice(children: &[SyntaxElement]) -> Option<(&Token, &[SyntaxElement], &Token)> {
+        match children {
+            [SyntaxElement::Token(open), items @ .., SyntaxElement::Token(close)] => Some((open, items, close)),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frontend::{lexer::Lexer, parser::Parser};
+
+    fn format_str(source: &str) -> String {
+        let mut lexer = Lexer::new(source, "test.y".to_string());
+        lexer.scan_tokens();
+        let mut parser = Parser::new(&lexer.tokens, source, "test.y".to_string());
+        parser.parse();
+        assert_eq!(parser.errors, 0, "{:?}", parser.diagnostics);
+
+        let tree = parser.syntax_tree();
+        assert_eq!(tree.text(), source, "the syntax tree should be lossless");
+        format(&tree)
+    }
+
+    #[test]
+    fn test_format() {
+        let source = "module  test ;
+import \"io\" as io;
+struct Point{x:float,y:float}
+enum Shape { Circle(float), Square(float) }
+func main( ) ->int{
+    let p:Point=Point{x:1.0,y:2.0};
+    if(p.x>=1.0){io.println(\"\\(p.x)\");}else{return -1;}
+    let add:(int,int)->int=|a:int,b:int|int{return a+b;};
+    let xs:[int]=[1,2,3,];
+    xs[0]+=add(1,2) ;
+    return 0;
+}
+";
+        let expected = "module test;
+
+import \"io\" as io;
+
+struct Point {
+    x: float,
+    y: float,
+}
+
+enum Shape {
+    Circle(float),
+    Square(float),
+}
+
+func main() -> int {
+    let p: Point = Point { x: 1.0, y: 2.0 };
+    if (p.x >= 1.0) {
+        io.println(\"\\(p.x)\");
+    } else {
+        return -1;
+    }
+    let add: (int, int) -> int = |a: int, b: int| int {
+        return a + b;
+    };
+    let xs: [int] = [1, 2, 3];
+    xs[0] += add(1, 2);
+    return 0;
+}
+";
+        assert_eq!(format_str(source), expected);
+        assert_eq!(format_str(expected), expected);
+    }
+
+    #[test]
+    fn test_format_comments_and_long_lists() {
+        let source = "// Header
+module test;
+
+
+func configure(first_parameter: int, second_parameter: float, third_parameter: string, verbose: bool) {
+    // Leading comment
+
+    let x: int = 1; // Trailing comment
+
+
+    let y: int = 2;
+    // Last comment
+}
+
+func total(first_value: int, second_value: int, third_value: int, fourth_value: int, fifth: int) -> int {
+    return 0;
+}
+";
+        let expected = "// Header
+module test;
+
+func configure(
+    first_parameter: int,
+    second_parameter: float,
+    third_parameter: string,
+    verbose: bool,
+) {
+    // Leading comment
+
+    let x: int = 1; // Trailing comment
+
+    let y: int = 2;
+    // Last comment
+}
+
+func total(
+    first_value: int,
+    second_value: int,
+    third_value: int,
+    fourth_value: int,
+    fifth: int,
+) -> int {
+    return 0;
+}
+";
+        assert_eq!(format_str(source), expected);
+        assert_eq!(format_str(expected), expected);
+    }
+}
@@ -1,7 +1,9 @@
 use crate::frontend::utils::token::{
     Token,
     TokenKind,
-    Span
+    Span,
+    Trivia,
+    TriviaKind
 };
 
 use crate::errors::{Error, Help};
@@ -15,12 +17,15 @@ pub struct Lexer<'src> {
     pub start: usize,
     pub current: usize,
     pub line: usize,
-    pub col: usize,
-    pub col_start: usize,
-    pub col_end: usize,
+    /// Added to every span, so a substring can be lexed with spans into the enclosing source
+    pub offset: usize,
 
     pub had_error: bool,
-    pub error_tokens: Vec<Token>
+    pub error_tokens: Vec<Token>,
+    pub diagnostics: Vec<Error>,
+
+    /// Trivia seen since the last token, attached to the next one
+    trivia: Vec<Trivia>,
 }
 
 impl<'src> Lexer<'src> {
@@ -33,21 +38,21 @@ impl<'src> Lexer<'src> {
             start: 0,
             current: 0,
             line: 1,
-            col: 1,
-            col_start: 1,
-            col_end: 1,
+            offset: 0,
             had_error: false,
-            error_tokens: Vec::new()
+            error_tokens: Vec::new(),
+            diagnostics: Vec::new(),
+            trivia: Vec::new(),
         }
     }
 
     pub fn scan_tokens(&mut self) {
         while !self.is_at_end() {
             self.start = self.current;
-            self.col_start = self.col_end;
             self.scan_token();
         }
 
+        self.start = self.current;
         self.add_token(TokenKind::Eof);
 
         let error_tokens: Vec<Token> = self.error_tokens.clone();
@@ -68,7 +73,13 @@ impl<'src> Lexer<'src> {
             ']' => self.add_token(TokenKind::Rbracket),
             ',' => self.add_token(TokenKind::Comma),
             '.' => {
-                let token_kind = if self.match_token('.') { TokenKind::Range } else { TokenKind::Dot };
+                let token_kind = if !self.match_token('.') {
+                    TokenKind::Dot
+                } else if self.match_token('=') {
+                    TokenKind::RangeEq
+                } else {
+                    TokenKind::Range
+                };
                 self.add_token(token_kind);
             },
             ':' => {
@@ -104,6 +115,7 @@ impl<'src> Lexer<'src> {
             '/' => {
                 if self.match_token('/') {
                     self.scan_comment();
+                    self.add_trivia(TriviaKind::Comment);
                 } else {
                     let token_kind = if self.match_token('=') { TokenKind::SlashEq } else { TokenKind::Slash };
                     self.add_token(token_kind);
@@ -139,6 +151,7 @@ impl<'src> Lexer<'src> {
                 self.add_token(token_kind);
             },
             '#' => self.add_token(TokenKind::Hash),
+            '@' => self.add_token(TokenKind::At),
             '!' => {
                 let is_match = self.match_token('=');
                 let token_kind = if is_match { TokenKind::BangEq } else { TokenKind::Bang };
@@ -171,16 +184,15 @@ impl<'src> Lexer<'src> {
             },
             '"' => self.scan_string(c),
             '\'' => self.scan_string(c),
-            ' ' | '\r' | '\t' => (),
+            ' ' | '\r' | '\t' => self.add_trivia(TriviaKind::Whitespace),
             '\n' => {
                 self.line += 1;
-                self.col_end = 0;
-                self.col_start = 0;
+                self.add_trivia(TriviaKind::Newline);
             },
             _ => {
-                if c.is_digit(10) {
+                if c.is_ascii_digit() {
                     self.scan_number();
-                } else if c.is_alphabetic() {
+                } else if c.is_alphabetic() || c == '_' {
                     self.scan_identifier();
                 } else {
                     self.add_token(TokenKind::Error);
@@ -191,8 +203,7 @@ impl<'src> Lexer<'src> {
     
     fn advance(&mut self) -> char {
         let c = self.peek();
-        self.current += 1;
-        self.col_end += 1;
+        self.current += c.len_utf8();
         c
     }
 
@@ -200,8 +211,7 @@ impl<'src> Lexer<'src> {
         if self.is_at_end() || self.peek() != expected {
             return false;
         }
-        self.current += 1;
-        self.col_end += 1;
+        self.current += expected.len_utf8();
         true
     }
 
@@ -223,7 +233,8 @@ impl<'src> Lexer<'src> {
 
     fn add_token(&mut self, kind: TokenKind) {
         let lexeme = self.source[self.start..self.current].to_string();
-        let token = Token::new(kind.clone(), lexeme, self.line, Span::new(self.col_start, self.col_end));
+        let mut token = Token::new(kind.clone(), lexeme, self.line, Span::new(self.offset + self.start, self.offset + self.current));
+        token.leading_trivia = std::mem::take(&mut self.trivia);
         self.tokens.push(token.clone());
 
         if kind == TokenKind::Error {
@@ -231,6 +242,22 @@ impl<'src> Lexer<'src> {
         }
     }
 
+    fn add_trivia(&mut self, kind: TriviaKind) {
+        let span = Span::new(self.offset + self.start, self.offset + self.current);
+
+        // Runs of whitespace are kept as a single piece
+        if let Some(last) = self.trivia.last_mut() {
+            if kind == TriviaKind::Whitespace && last.kind == kind && last.span.end == span.start {
+                last.text.push_str(&self.source[self.start..self.current]);
+                last.span.end = span.end;
+                return;
+            }
+        }
+
+        let text = self.source[self.start..self.current].to_string();
+        self.trivia.push(Trivia { kind, text, span });
+    }
+
     fn scan_string(&mut self, delimiter: char) {
         while self.peek() != delimiter && !self.is_at_end() {
             if self.peek() == '\n' {
@@ -250,14 +277,14 @@ impl<'src> Lexer<'src> {
 
     fn scan_number(&mut self) {
         let mut is_float = false;
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             is_float = true;
             self.advance(); // Consume the '.'
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
         }
@@ -303,6 +330,9 @@ impl<'src> Lexer<'src> {
             "null" => TokenKind::Null,
             "module" => TokenKind::Module,
             "in" => TokenKind::In,
+            "where" => TokenKind::Where,
+            "const" => TokenKind::Const,
+            "dyn" => TokenKind::Dyn,
             "_" => TokenKind::Underscore,
             _ => TokenKind::Identifier,
         };
@@ -327,25 +357,21 @@ impl<'src> Lexer<'src> {
             error.add_help(h);
         }
 
-        eprintln!("{}", error.to_string());
-
+        self.diagnostics.push(error);
         self.had_error = true;
     }
 
     pub fn print_tokens(&self) {
         for token in &self.tokens {
-            println!("{}", token.to_string());
+            println!("{}", token);
         }
     }
 
-    /// Sets the start and current offset for lexing a substring, and resets column/line info.
-    /// Now also allows setting the line number.
+    /// Sets the byte offset of this substring within the enclosing source, and the line it starts on.
     pub fn set_offset(&mut self, offset: usize, line: usize) {
         self.start = 0;
         self.current = 0;
-        self.col = offset + 1;
-        self.col_start = offset + 1;
-        self.col_end = offset + 1;
+        self.offset = offset;
         self.line = line;
     }
 }
\ No newline at end of file
@@ -1,3 +1,4 @@
 pub mod lexer;
 pub mod utils;
-pub mod parser;
\ No newline at end of file
+pub mod parser;
+pub mod formatter;
\ No newline at end of file
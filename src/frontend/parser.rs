@@ -1,5 +1,8 @@
+use crate::frontend::lexer::Lexer;
 use crate::frontend::utils::{
     ast::*,
+    cst::{CstBuilder, SyntaxKind, SyntaxNode},
+    string_interpolation::InterpolatedString,
     token::{Token, TokenKind, Span}
 };
 
@@ -13,8 +16,11 @@ pub struct Parser<'src> {
     pub filename: String,
 
     pub errors: usize,
+    pub diagnostics: Vec<Error>,
 
     current_modifier: AccessModifier,
+    /// Attributes written before the declaration being parsed
+    current_attributes: Vec<Attribute>,
 
     type_stack: usize,
     generic_stack: usize,
@@ -22,6 +28,7 @@ pub struct Parser<'src> {
     type_var_only: bool,
 
     module: Module,
+    cst: CstBuilder,
 }
 
 impl<'src> Parser<'src> {
@@ -33,26 +40,42 @@ impl<'src> Parser<'src> {
             source,
             filename,
             errors: 0,
+            diagnostics: vec![],
             current_modifier: AccessModifier::None,
+            current_attributes: vec![],
             type_stack: 0,
             generic_stack: 0,
             type_var_only: false,
             module: Module {
+                attributes: vec![],
                 name: Token::new(TokenKind::Identifier, "module".to_string(), 1, Span::default()),
                 imports: vec![],
                 stmts: vec![],
                 span: Span::default(),
             },
+            cst: CstBuilder::new(),
         }
     }
 
     pub fn parse(&mut self) -> Module {
+        let checkpoint = self.cst.checkpoint();
+        let attributes = self.attributes();
         self.consume(TokenKind::Module, "Expected 'module' at the start of the file");
 
         self.module = self.parse_module();
+        self.module.attributes = attributes;
 
         self.consume(TokenKind::Semicolon, "Expected ';' after module declaration");
+        self.cst.wrap(checkpoint, SyntaxKind::ModuleDecl);
 
+        self.module.stmts = self.parse_items();
+        self.module.clone()
+    }
+
+    /// Parses declarations and statements up to the end of the input, without
+    /// a module header. This is how the REPL reads each input.
+    pub fn parse_items(&mut self) -> Vec<StatementKind> {
+        let mut items = vec![];
         while !self.is_at_end() {
             let stmt = self.declaration();
             if self.had_error {
@@ -60,13 +83,41 @@ impl<'src> Parser<'src> {
                 self.had_error = false;
                 continue;
             }
-            self.module.stmts.push(stmt);
+            items.push(stmt);
         }
-        
-        self.module.clone()
+        // The end of file token holds the trailing trivia
+        self.cst.token(self.peek());
+
+        items
+    }
+
+    /// Takes the concrete syntax tree built by `parse`.
+    pub fn syntax_tree(&mut self) -> SyntaxNode {
+        std::mem::take(&mut self.cst).finish()
     }
 
     fn declaration(&mut self) -> StatementKind {
+        let checkpoint = self.cst.checkpoint();
+        let declaration = self.parse_declaration();
+
+        let kind = match &declaration {
+            StatementKind::Function(_) => SyntaxKind::Function,
+            StatementKind::Struct(_) => SyntaxKind::Struct,
+            StatementKind::Enum(_) => SyntaxKind::Enum,
+            StatementKind::Extend(_) => SyntaxKind::Extend,
+            StatementKind::Trait(_) => SyntaxKind::Trait,
+            StatementKind::TypeAlias(_) => SyntaxKind::TypeAlias,
+            StatementKind::Import(_) => SyntaxKind::Import,
+            // Already wrapped by `parse_statement`
+            StatementKind::Statement(_) => return declaration,
+        };
+        self.cst.wrap(checkpoint, kind);
+
+        declaration
+    }
+
+    fn parse_declaration(&mut self) -> StatementKind {
+        self.current_attributes = self.attributes();
         if self.match_token(TokenKind::Pub) {
             self.current_modifier = AccessModifier::Public;
         } else if self.match_token(TokenKind::Priv) {
@@ -75,88 +126,154 @@ impl<'src> Parser<'src> {
             self.current_modifier = AccessModifier::Protected;
         }
 
+        if !self.current_attributes.is_empty() && !matches!(self.peek().kind, TokenKind::Func | TokenKind::Struct | TokenKind::Enum) {
+            // The declaration itself parses as usual, so there is nothing to recover from
+            let had_error = self.had_error;
+            self.error("Attributes can only be applied to functions, structs, enums, fields and modules");
+            self.had_error = had_error;
+            self.current_attributes.clear();
+        }
+
         if self.match_token(TokenKind::Func) {
-            return StatementKind::Function(self.parse_function("func"));
+            StatementKind::Function(self.parse_function("func"))
         } else if self.match_token(TokenKind::Struct) {
-            return StatementKind::Struct(self.parse_struct());
+            StatementKind::Struct(self.parse_struct())
         } else if self.match_token(TokenKind::Enum) {
-            return StatementKind::Enum(self.parse_enum());
+            StatementKind::Enum(self.parse_enum())
         } else if self.match_token(TokenKind::Extend) {
-            return StatementKind::Extend(self.parse_extend());
+            StatementKind::Extend(self.parse_extend())
         } else if self.match_token(TokenKind::Trait) {
-            return StatementKind::Trait(self.parse_trait());
+            StatementKind::Trait(self.parse_trait())
+        } else if self.match_token(TokenKind::Type) {
+            StatementKind::TypeAlias(self.parse_type_alias())
         } else if self.match_token(TokenKind::Import) {
-            return StatementKind::Import(self.parse_import());
+            StatementKind::Import(self.parse_import())
         } else {
             if self.current_modifier != AccessModifier::None {
                 self.error("Access modifier must be used with a declaration");
             }
             
             self.current_modifier = AccessModifier::None;
-            return StatementKind::Statement(self.parse_statement());
+            StatementKind::Statement(self.parse_statement())
         }
     }
 
     fn parse_statement(&mut self) -> Statement {
+        let checkpoint = self.cst.checkpoint();
+        let stmt = self.statement();
+        self.cst.wrap(checkpoint, SyntaxKind::of_statement(&stmt));
+        stmt
+    }
+
+    fn statement(&mut self) -> Statement {
         if self.match_token(TokenKind::Let) {
-            return self.parse_let_statement();
+            self.parse_let_statement()
         } else if self.match_token(TokenKind::If) {
-            return self.parse_if_statement();
+            self.parse_if_statement()
         } else if self.match_token(TokenKind::While) {
-            return self.parse_while_statement();
+            self.parse_while_statement()
         } else if self.match_token(TokenKind::For) {
-            return self.parse_for_statement();
+            self.parse_for_statement()
         } else if self.match_token(TokenKind::Match) {
-            return self.parse_match_statement();
+            self.parse_match_statement()
         } else if self.match_token(TokenKind::Return) {
-            return self.parse_return_statement();
+            self.parse_return_statement()
         } else if self.match_token(TokenKind::Break) {
-            return self.parse_break_statement();
+            self.parse_break_statement()
         } else if self.match_token(TokenKind::Continue) {
-            return self.parse_continue_statement();
+            self.parse_continue_statement()
         } else if self.match_token(TokenKind::Lbrace) {
-            return self.parse_block();
+            self.parse_block()
         } else {
-            return self.parse_expression_statement();
+            self.parse_expression_statement()
         }
     }
 
     fn parse_function(&mut self, kind: &str) -> Function {
-        let is_method = match kind {
-            "func" => false,
-            "method" => true,
-            _ => false,
-        };
+        let in_type = matches!(kind, "method" | "trait method");
 
         let start = self.peek().span.start;
+        let attributes = std::mem::take(&mut self.current_attributes);
         let access = self.current_modifier.clone();
 
         self.current_modifier = AccessModifier::None;
 
         let name = self.consume(TokenKind::Identifier, "Expected function name").clone();
 
+        let mut generics = vec![];
+        let mut bounds = vec![];
+        if self.match_token(TokenKind::Lt) {
+            let checkpoint = self.cst.checkpoint_before_last();
+            self.generic_stack += 1;
+            while !self.check(TokenKind::Gt) && !self.is_at_end() {
+                // For now only allow type variables as generics
+                let start = self.peek().span.start;
+                self.type_var_only = true;
+                let generic_type = self.type_expression();
+                self.type_var_only = false;
+                if let (Type::TypeVar { name, .. }, true) = (&generic_type, self.match_token(TokenKind::Colon)) {
+                    bounds.push(self.parse_bound(name.clone(), start));
+                }
+                generics.push(generic_type);
+                if !self.check(TokenKind::Gt) {
+                    self.consume(TokenKind::Comma, "Expected ',' after generic type");
+                }
+            }
+            self.consume(TokenKind::Gt, "Expected '>' after generic type");
+            self.generic_stack -= 1;
+            self.cst.wrap(checkpoint, SyntaxKind::GenericList);
+        }
+
+        let checkpoint = self.cst.checkpoint();
         self.consume(TokenKind::Lparen, "Expected '(' after function name");
+        let is_method = self.parse_receiver(in_type);
         let params = self.parse_parameters(TokenKind::Rparen);
         self.consume(TokenKind::Rparen, "Expected ')' after function parameters");
+        self.cst.wrap(checkpoint, SyntaxKind::ParamList);
 
-        let return_type: Type;
-
-        if self.match_token(TokenKind::Arrow) {
-            return_type = self.type_expression();
+        let return_type = if self.match_token(TokenKind::Arrow) {
+            self.type_expression()
         } else {
-            return_type = Type::Primitive {
+            Type::Primitive {
                 name: Token::new(TokenKind::Identifier, "void".to_string(), 1, Span::default()),
                 span: Span::default(),
             }
-        }
+        };
 
-        self.consume(TokenKind::Lbrace, "Expected '{' after function declaration");
+        if self.match_token(TokenKind::Where) {
+            let checkpoint = self.cst.checkpoint_before_last();
+            while self.check(TokenKind::Identifier) {
+                let start = self.peek().span.start;
+                let param = self.advance().clone();
+                self.consume(TokenKind::Colon, "Expected ':' after generic parameter in where clause");
+                bounds.push(self.parse_bound(param, start));
+                if !self.match_token(TokenKind::Comma) {
+                    break;
+                }
+            }
+            if bounds.is_empty() {
+                self.error("Expected a bound such as 'T: Trait' after 'where'");
+            }
+            self.cst.wrap(checkpoint, SyntaxKind::WhereClause);
+        }
 
-        let body = self.parse_block();
+        // Trait methods without a body must be provided by every implementation
+        let body = if kind == "trait method" && self.match_token(TokenKind::Semicolon) {
+            None
+        } else {
+            let checkpoint = self.cst.checkpoint();
+            self.consume(TokenKind::Lbrace, "Expected '{' after function declaration");
+            let body = self.parse_block();
+            self.cst.wrap(checkpoint, SyntaxKind::Block);
+            Some(body)
+        };
 
         Function {
+            attributes,
             access,
             name,
+            generics,
+            bounds,
             params,
             return_type,
             body,
@@ -165,18 +282,61 @@ impl<'src> Parser<'src> {
         }
     }
 
+    /// Parses the traits after `T:`, e.g. "Display + Clone".
+    fn parse_bound(&mut self, param: Token, start: usize) -> GenericBound {
+        // Trait names are never type variables, even inside a generic list
+        let generic_stack = std::mem::take(&mut self.generic_stack);
+        let mut traits = vec![self.type_expression()];
+        while self.match_token(TokenKind::Plus) {
+            traits.push(self.type_expression());
+        }
+        self.generic_stack = generic_stack;
+
+        GenericBound {
+            param,
+            traits,
+            span: Span::new(start, self.peek().span.start),
+        }
+    }
+
+    /// Parses a leading `self` parameter, which makes a function of an `extend` block
+    /// or trait a method called on a value rather than a static function of the type.
+    fn parse_receiver(&mut self, in_type: bool) -> bool {
+        let is_receiver = self.check(TokenKind::Identifier) && self.peek().lexeme == "self"
+            && self.tokens.get(self.current + 1).is_some_and(|next| matches!(next.kind, TokenKind::Comma | TokenKind::Rparen));
+        if !is_receiver {
+            return false;
+        }
+
+        if !in_type {
+            // The rest of the function parses as usual, so there is nothing to recover from
+            let had_error = self.had_error;
+            self.error("Only functions in 'extend' blocks and traits can take 'self'");
+            self.had_error = had_error;
+        }
+        let checkpoint = self.cst.checkpoint();
+        self.advance();
+        self.cst.wrap(checkpoint, SyntaxKind::Param);
+        if !self.check(TokenKind::Rparen) {
+            self.consume(TokenKind::Comma, "Expected ',' after parameter");
+        }
+        in_type
+    }
+
     fn parse_parameters(&mut self, tkn: TokenKind) -> Vec<Parameter> {
         let mut params = vec![];
 
-        while !self.check(tkn.clone()) {
+        while !self.check(tkn.clone()) && !self.is_at_end() {
             if params.len() > 255 {
                 self.error("Too many parameters, maximum is 255");
                 return params;
             }
 
+            let checkpoint = self.cst.checkpoint();
             let name = self.consume(TokenKind::Identifier, "Expected parameter name").clone();
             self.consume(TokenKind::Colon, "Expected ':' after parameter name");
             let ty = self.type_expression();
+            self.cst.wrap(checkpoint, SyntaxKind::Param);
 
             params.push(Parameter {
                 name,
@@ -193,6 +353,26 @@ impl<'src> Parser<'src> {
     }
 
     fn type_expression(&mut self) -> Type {
+        let checkpoint = self.cst.checkpoint();
+        let ty = self.parse_type();
+        self.cst.wrap(checkpoint, SyntaxKind::Type);
+        ty
+    }
+
+    fn parse_type(&mut self) -> Type {
+        let start = self.peek().span.start;
+        let mut ty = self.plain_type();
+        while self.match_token(TokenKind::Question) {
+            ty = Type::Optional {
+                inner: Box::new(ty),
+                span: Span::new(start, self.peek().span.start),
+            };
+        }
+        ty
+    }
+
+    /// A type without the `?` that makes it optional.
+    fn plain_type(&mut self) -> Type {
         let start = self.peek().span.start;
         self.type_stack += 1;
 
@@ -209,8 +389,31 @@ impl<'src> Parser<'src> {
             }
         }
 
+        if self.match_token(TokenKind::Dyn) {
+            // The trait is named in full even inside a generic list, e.g. "Box<dyn Into<T>>"
+            let generic_stack = std::mem::take(&mut self.generic_stack);
+            let trait_ = self.plain_type();
+            self.generic_stack = generic_stack;
+            self.type_stack -= 1;
+            if !matches!(trait_, Type::Named { .. } | Type::Error(_)) {
+                self.error("Expected a trait name after 'dyn'");
+            }
+            return Type::Dyn {
+                trait_: Box::new(trait_),
+                span: Span::new(start, self.peek().span.start),
+            };
+        }
+
         if self.match_token(TokenKind::Identifier) {
-            let name = self.previous().clone();
+            let mut name = self.previous().clone();
+
+            // An associated type of another type, e.g. "Self.Item"
+            if self.check(TokenKind::Dot) && self.tokens.get(self.current + 1).is_some_and(|next| next.kind == TokenKind::Identifier) {
+                self.advance();
+                let member = self.advance().clone();
+                name.lexeme = format!("{}.{}", name.lexeme, member.lexeme);
+                name.span = Span::new(name.span.start, member.span.end);
+            }
 
             // Check if the type is a primitive type
             match name.lexeme.clone().as_str() {
@@ -228,6 +431,7 @@ impl<'src> Parser<'src> {
             let mut generics: Vec<Type> = vec![];
 
             if self.match_token(TokenKind::Lt) {
+                let checkpoint = self.cst.checkpoint_before_last();
                 self.generic_stack += 1;
                 loop {
                     generics.push(self.type_expression());
@@ -239,17 +443,12 @@ impl<'src> Parser<'src> {
 
                 self.consume(TokenKind::Gt, "Expected '>' after generic type");
                 self.generic_stack -= 1;
+                self.cst.wrap(checkpoint, SyntaxKind::GenericList);
             }
             self.type_stack -= 1;
-            if generics.is_empty() && self.generic_stack > 0{
-                return Type::TypeVar {
-                    name: name.clone(),
-                    span: Span::new(start, self.peek().span.start),
-                };
-            }
             return Type::Named {
                 name: name.clone(),
-                generics: generics,
+                generics,
                 span: Span::new(start, self.peek().span.start),
             };
         }
@@ -275,7 +474,7 @@ impl<'src> Parser<'src> {
         // Parse tuples like (T, U)
         if self.match_token(TokenKind::Lparen) {
             let mut elements = vec![];
-            while !self.check(TokenKind::Rparen) {
+            while !self.check(TokenKind::Rparen) && !self.is_at_end() {
                 let element = self.type_expression();
                 elements.push(element);
                 if !self.check(TokenKind::Rparen) {
@@ -310,7 +509,7 @@ impl<'src> Parser<'src> {
         let start = self.peek().span.start;
         let mut stmts = vec![];
 
-        while !self.check(TokenKind::Rbrace) {
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
             stmts.push(self.parse_statement());
         }
 
@@ -321,32 +520,17 @@ impl<'src> Parser<'src> {
 
     fn parse_struct(&mut self) -> Struct {
         let start = self.peek().span.start;
+        let attributes = std::mem::take(&mut self.current_attributes);
         let access = self.current_modifier.clone();
         self.current_modifier = AccessModifier::None;
         let name = self.consume(TokenKind::Identifier, "Expected struct name").clone();
-
-        let mut generics = vec![];
-        if self.match_token(TokenKind::Lt) {
-            self.generic_stack += 1;
-            while !self.check(TokenKind::Gt) {
-                // For now only allow type variables as generics
-                self.type_var_only = true;
-                let generic_type = self.type_expression();
-                self.type_var_only = false;
-                generics.push(generic_type);
-                if !self.check(TokenKind::Gt) {
-                    self.consume(TokenKind::Comma, "Expected ',' after generic type");
-                }
-            }
-            self.consume(TokenKind::Gt, "Expected '>' after generic type");
-            self.generic_stack -= 1;
-        }
+        let generics = self.type_parameters();
 
         self.consume(TokenKind::Lbrace, "Expected '{' after struct name");
 
         let mut fields = vec![];
 
-        while !self.check(TokenKind::Rbrace) {
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
             fields = self.struct_fields();
             if !self.check(TokenKind::Rbrace) {
                 self.consume(TokenKind::Comma, "Expected ',' after field");
@@ -356,6 +540,7 @@ impl<'src> Parser<'src> {
         self.consume(TokenKind::Rbrace, "Expected '}' after struct declaration");
 
         Struct {
+            attributes,
             access,
             name,
             fields,
@@ -364,10 +549,35 @@ impl<'src> Parser<'src> {
         }
     }
 
+    /// The `<T, U>` list after a struct or enum name, if any.
+    fn type_parameters(&mut self) -> Vec<Type> {
+        let mut generics = vec![];
+        if self.match_token(TokenKind::Lt) {
+            let checkpoint = self.cst.checkpoint_before_last();
+            self.generic_stack += 1;
+            while !self.check(TokenKind::Gt) && !self.is_at_end() {
+                // For now only allow type variables as generics
+                self.type_var_only = true;
+                let generic_type = self.type_expression();
+                self.type_var_only = false;
+                generics.push(generic_type);
+                if !self.check(TokenKind::Gt) {
+                    self.consume(TokenKind::Comma, "Expected ',' after generic type");
+                }
+            }
+            self.consume(TokenKind::Gt, "Expected '>' after generic type");
+            self.generic_stack -= 1;
+            self.cst.wrap(checkpoint, SyntaxKind::GenericList);
+        }
+        generics
+    }
+
     fn struct_fields(&mut self) -> Vec<Field> {
         let mut fields = vec![];
 
-        while !self.check(TokenKind::Rbrace) {
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
+            let checkpoint = self.cst.checkpoint();
+            let attributes = self.attributes();
             let mut access = AccessModifier::None;
 
             if self.match_token(TokenKind::Pub) {
@@ -381,8 +591,10 @@ impl<'src> Parser<'src> {
             let name = self.consume(TokenKind::Identifier, "Expected field name").clone();
             self.consume(TokenKind::Colon, "Expected ':' after field name");
             let ty = self.type_expression();
+            self.cst.wrap(checkpoint, SyntaxKind::StructField);
 
             fields.push(Field {
+                attributes,
                 access,
                 name,
                 ty,
@@ -399,16 +611,20 @@ impl<'src> Parser<'src> {
 
     fn parse_enum(&mut self) -> Enum {
         let start = self.peek().span.start;
+        let attributes = std::mem::take(&mut self.current_attributes);
         let access = self.current_modifier.clone();
         self.current_modifier = AccessModifier::None;
         let name = self.consume(TokenKind::Identifier, "Expected enum name").clone();
+        let generics = self.type_parameters();
 
         self.consume(TokenKind::Lbrace, "Expected '{' after enum name");
 
         let mut variants = vec![];
 
-        while !self.check(TokenKind::Rbrace) {
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
+            let checkpoint = self.cst.checkpoint();
             let variant = self.parse_enum_variant();
+            self.cst.wrap(checkpoint, SyntaxKind::EnumVariant);
             variants.push(variant);
             if !self.check(TokenKind::Rbrace) {
                 self.consume(TokenKind::Comma, "Expected ',' after enum variant");
@@ -418,8 +634,10 @@ impl<'src> Parser<'src> {
         self.consume(TokenKind::Rbrace, "Expected '}' after enum declaration");
 
         Enum {
+            attributes,
             access,
             name,
+            generics,
             variants,
             span: Span::new(start, self.peek().span.start),
         }
@@ -432,7 +650,7 @@ impl<'src> Parser<'src> {
         let mut fields = vec![];
 
         if self.match_token(TokenKind::Lparen) {
-            while !self.check(TokenKind::Rparen) {
+            while !self.check(TokenKind::Rparen) && !self.is_at_end() {
                 let field = self.type_expression();
                 fields.push(field);
                 if !self.check(TokenKind::Rparen) {
@@ -457,8 +675,9 @@ impl<'src> Parser<'src> {
 
         let mut generics = vec![];
         if self.match_token(TokenKind::Lt) {
+            let checkpoint = self.cst.checkpoint_before_last();
             self.generic_stack += 1;
-            while !self.check(TokenKind::Gt) {
+            while !self.check(TokenKind::Gt) && !self.is_at_end() {
                 // For now only allow type variables as generics
                 self.type_var_only = true;
                 let generic_type = self.type_expression();
@@ -470,18 +689,31 @@ impl<'src> Parser<'src> {
             }
             self.consume(TokenKind::Gt, "Expected '>' after generic type");
             self.generic_stack -= 1;
+            self.cst.wrap(checkpoint, SyntaxKind::GenericList);
         }
 
         self.consume(TokenKind::Lbrace, "Expected '{' after trait name");
 
         let mut methods = vec![];
-
-        while !self.check(TokenKind::Rbrace) {
-            let method = self.parse_function("method");
-            methods.push(method);
-            if !self.check(TokenKind::Rbrace) {
-                self.consume(TokenKind::Comma, "Expected ',' after trait method");
+        let mut types = vec![];
+        let mut constants = vec![];
+
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
+            if self.match_token(TokenKind::Type) {
+                types.push(self.parse_associated_type(true));
+            } else if self.match_token(TokenKind::Const) {
+                constants.push(self.parse_constant(true));
+            } else {
+                let checkpoint = self.cst.checkpoint();
+                self.current_attributes = self.attributes();
+                // `func` is optional before trait methods
+                self.match_token(TokenKind::Func);
+                let method = self.parse_function("trait method");
+                self.cst.wrap(checkpoint, SyntaxKind::Function);
+                methods.push(method);
             }
+            // Members may also be separated by commas
+            self.match_token(TokenKind::Comma);
         }
 
         self.consume(TokenKind::Rbrace, "Expected '}' after trait declaration");
@@ -490,11 +722,88 @@ impl<'src> Parser<'src> {
             access,
             name,
             methods,
+            types,
+            constants,
             generics,
             span: Span::new(start, self.peek().span.start),
         }
     }
 
+    /// Parses an associated type after `type`: just its name in a trait,
+    /// and the type chosen for it in an implementation.
+    fn parse_associated_type(&mut self, in_trait: bool) -> AssociatedType {
+        let checkpoint = self.cst.checkpoint_before_last();
+        let start = self.previous().span.start;
+        let name = self.consume(TokenKind::Identifier, "Expected associated type name").clone();
+
+        let ty = if in_trait {
+            None
+        } else {
+            self.consume(TokenKind::Eq, "Expected '=' and a type after associated type name");
+            Some(self.type_expression())
+        };
+        self.consume(TokenKind::Semicolon, "Expected ';' after associated type");
+        self.cst.wrap(checkpoint, SyntaxKind::AssociatedType);
+
+        AssociatedType {
+            name,
+            ty,
+            span: Span::new(start, self.peek().span.start),
+        }
+    }
+
+    /// Parses an associated constant after `const`. Only traits may leave out its value.
+    fn parse_constant(&mut self, in_trait: bool) -> Constant {
+        let checkpoint = self.cst.checkpoint_before_last();
+        let start = self.previous().span.start;
+        let name = self.consume(TokenKind::Identifier, "Expected constant name").clone();
+        self.consume(TokenKind::Colon, "Expected ':' after constant name");
+        let ty = self.type_expression();
+
+        let value = if self.match_token(TokenKind::Eq) {
+            Some(self.expression())
+        } else {
+            if !in_trait {
+                self.error("Expected '=' and a value after constant type");
+            }
+            None
+        };
+        self.consume(TokenKind::Semicolon, "Expected ';' after constant");
+        self.cst.wrap(checkpoint, SyntaxKind::Constant);
+
+        Constant {
+            name,
+            ty,
+            value,
+            span: Span::new(start, self.peek().span.start),
+        }
+    }
+
+    fn parse_type_alias(&mut self) -> TypeAlias {
+        let start = self.peek().span.start;
+        let access = self.current_modifier.clone();
+        self.current_modifier = AccessModifier::None;
+        let name = self.consume(TokenKind::Identifier, "Expected type name").clone();
+        self.consume(TokenKind::Eq, "Expected '=' after type name");
+
+        // `new` is only a keyword here, so it stays usable as a method name
+        let newtype = self.check(TokenKind::Identifier) && self.peek().lexeme == "new"
+            && self.tokens.get(self.current + 1).is_some_and(|next| next.kind != TokenKind::Semicolon);
+        if newtype {
+            self.advance();
+        }
+        let ty = self.type_expression();
+        self.consume(TokenKind::Semicolon, "Expected ';' after type declaration");
+
+        TypeAlias {
+            access,
+            name,
+            ty,
+            newtype,
+            span: Span::new(start, self.peek().span.start),
+        }
+    }
+
     fn parse_import(&mut self) -> Import {
         let start = self.peek().span.start;
         let path = self.consume(TokenKind::String, "Expected import name").clone();
@@ -517,8 +826,9 @@ impl<'src> Parser<'src> {
 
         let mut first_generics = vec![];
         if self.match_token(TokenKind::Lt) {
+            let checkpoint = self.cst.checkpoint_before_last();
             self.generic_stack += 1;
-            while !self.check(TokenKind::Gt) {
+            while !self.check(TokenKind::Gt) && !self.is_at_end() {
                 // For now only allow type variables as generics
                 self.type_var_only = true;
                 let generic_type = self.type_expression();
@@ -530,6 +840,7 @@ impl<'src> Parser<'src> {
             }
             self.consume(TokenKind::Gt, "Expected '>' after generic type");
             self.generic_stack -= 1;
+            self.cst.wrap(checkpoint, SyntaxKind::GenericList);
         }
 
         let mut second_name = None;
@@ -539,8 +850,9 @@ impl<'src> Parser<'src> {
             second_name = Some(self.consume(TokenKind::Identifier, "Expected trait name").clone());
 
             if self.match_token(TokenKind::Lt) {
+                let checkpoint = self.cst.checkpoint_before_last();
                 self.generic_stack += 1;
-                while !self.check(TokenKind::Gt) {
+                while !self.check(TokenKind::Gt) && !self.is_at_end() {
                     // For now only allow type variables as generics
                     self.type_var_only = true;
                     let generic_type = self.type_expression();
@@ -552,13 +864,26 @@ impl<'src> Parser<'src> {
                 }
                 self.consume(TokenKind::Gt, "Expected '>' after generic type");
                 self.generic_stack -= 1;
+                self.cst.wrap(checkpoint, SyntaxKind::GenericList);
             }
         }
 
         self.consume(TokenKind::Lbrace, "Expected '{' after extend declaration");
 
         let mut methods = vec![];
-        while !self.check(TokenKind::Rbrace) {
+        let mut types = vec![];
+        let mut constants = vec![];
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
+            if self.match_token(TokenKind::Type) {
+                types.push(self.parse_associated_type(false));
+                continue;
+            } else if self.match_token(TokenKind::Const) {
+                constants.push(self.parse_constant(false));
+                continue;
+            }
+
+            let checkpoint = self.cst.checkpoint();
+            self.current_attributes = self.attributes();
             if self.match_token(TokenKind::Pub) {
                 self.current_modifier = AccessModifier::Public;
             } else if self.match_token(TokenKind::Priv) {
@@ -570,6 +895,7 @@ impl<'src> Parser<'src> {
             self.consume(TokenKind::Func, "Expected 'func' before extend method");
 
             let method = self.parse_function("method");
+            self.cst.wrap(checkpoint, SyntaxKind::Function);
             methods.push(method);
         }
         self.consume(TokenKind::Rbrace, "Expected '}' after extend declaration");
@@ -579,6 +905,8 @@ impl<'src> Parser<'src> {
                 name: first_name,
                 trait_name: None,
                 methods,
+                types,
+                constants,
                 first_generics,
                 second_generics: vec![],
                 span: Span::new(start, self.peek().span.start),
@@ -589,6 +917,8 @@ impl<'src> Parser<'src> {
             name: second_name.unwrap(),
             trait_name: Some(first_name),
             methods,
+            types,
+            constants,
             first_generics,
             second_generics,
             span: Span::new(start, self.peek().span.start),
@@ -599,17 +929,54 @@ impl<'src> Parser<'src> {
         let start = self.peek().span.start;
         let name = self.consume(TokenKind::Identifier, "Expected module name").clone();
 
-        return Module {
+        Module {
+            attributes: vec![],
             name,
             imports: vec![],
             stmts: vec![],
             span: Span::new(start, self.peek().span.start),
-        };
+        }
+    }
+
+    /// Parses the attributes in front of a declaration, if any.
+    fn attributes(&mut self) -> Vec<Attribute> {
+        let mut attributes = vec![];
+        while self.match_token(TokenKind::Hash) {
+            let checkpoint = self.cst.checkpoint_before_last();
+            let start = self.previous().span.start;
+            self.consume(TokenKind::Lbracket, "Expected '[' after '#'");
+            let name = self.consume(TokenKind::Identifier, "Expected attribute name").clone();
+
+            let mut args = vec![];
+            if self.match_token(TokenKind::Lparen) {
+                while !self.check(TokenKind::Rparen) && !self.is_at_end() {
+                    if !matches!(self.peek().kind, TokenKind::Identifier | TokenKind::String | TokenKind::Integer
+                        | TokenKind::Float | TokenKind::Char | TokenKind::True | TokenKind::False) {
+                        self.error("Expected a name or literal as attribute argument");
+                        break;
+                    }
+                    args.push(self.advance().clone());
+                    if !self.check(TokenKind::Rparen) {
+                        self.consume(TokenKind::Comma, "Expected ',' after attribute argument");
+                    }
+                }
+                self.consume(TokenKind::Rparen, "Expected ')' after attribute arguments");
+            }
+            self.consume(TokenKind::Rbracket, "Expected ']' after attribute");
+            self.cst.wrap(checkpoint, SyntaxKind::Attribute);
+
+            attributes.push(Attribute {
+                name,
+                args,
+                span: Span::new(start, self.previous().span.end),
+            });
+        }
+        attributes
     }
 
     fn parse_let_statement(&mut self) -> Statement {
         let start = self.peek().span.start;
-        let name = self.consume(TokenKind::Identifier, "Expected variable name").clone();
+        let pattern = Box::new(self.pattern());
 
         let mut ty = None;
         if self.match_token(TokenKind::Colon) {
@@ -624,7 +991,7 @@ impl<'src> Parser<'src> {
         self.consume(TokenKind::Semicolon, "Expected ';' after variable declaration");
 
         Statement::Let {
-            name,
+            pattern,
             ty,
             value,
             span: Span::new(start, self.peek().span.start),
@@ -683,61 +1050,120 @@ impl<'src> Parser<'src> {
 
     fn parse_match_statement(&mut self) -> Statement {
         let start = self.peek().span.start;
+        let (expr, cases) = self.match_body();
+        Statement::Match {
+            expr,
+            cases,
+            span: Span::new(start, self.peek().span.start),
+        }
+    }
+
+    /// The matched value and cases of a `match`, after the keyword.
+    fn match_body(&mut self) -> (Expr, Vec<Case>) {
         self.consume(TokenKind::Lparen, "Expected '(' after 'match'");
         let cond = self.expression();
         self.consume(TokenKind::Rparen, "Expected ')' after 'match' condition");
         self.consume(TokenKind::Lbrace, "Expected '{' after 'match' condition");
 
         let mut cases = vec![];
-        while !self.check(TokenKind::Rbrace) {
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
+            let checkpoint = self.cst.checkpoint();
             let case = self.parse_case();
+            self.cst.wrap(checkpoint, SyntaxKind::MatchCase);
             cases.push(case);
             if !self.check(TokenKind::Rbrace) {
                 self.consume(TokenKind::Comma, "Expected ',' after match case");
             }
         }
         self.consume(TokenKind::Rbrace, "Expected '}' after match statement");
-
-        Statement::Match {
-            expr: cond,
-            cases,
-            span: Span::new(start, self.peek().span.start),
-        }
+        (cond, cases)
     }
 
     fn parse_case(&mut self) -> Case {
         let start = self.peek().span.start;
         let pattern = self.pattern();
+        let guard = if self.match_token(TokenKind::If) { Some(self.expression()) } else { None };
         self.consume(TokenKind::Arrow, "Expected '->' after match case");
-        let body = self.parse_statement();
+        let body = self.case_body();
 
         Case {
             pattern,
+            guard,
             body,
             span: Span::new(start, self.peek().span.start),
         }
     }
 
+    /// A case body is a statement, or an expression giving the case its value, e.g. "1 -> \"one\","
+    fn case_body(&mut self) -> Statement {
+        let starts_statement = matches!(self.peek().kind,
+            TokenKind::Lbrace | TokenKind::Let | TokenKind::If | TokenKind::While | TokenKind::For
+            | TokenKind::Match | TokenKind::Return | TokenKind::Break | TokenKind::Continue);
+        if starts_statement {
+            return self.parse_statement();
+        }
+
+        let checkpoint = self.cst.checkpoint();
+        let expr = self.expression();
+        let body = if self.match_token(TokenKind::Semicolon) { Statement::Expr(expr) } else { Statement::Tail(expr) };
+        self.cst.wrap(checkpoint, SyntaxKind::ExprStmt);
+        body
+    }
+
     fn pattern(&mut self) -> Pattern {
+        let checkpoint = self.cst.checkpoint();
+        let start = self.peek().span.start;
+        let mut alternatives = vec![self.parse_pattern()];
+        while self.match_token(TokenKind::Pipe) {
+            alternatives.push(self.parse_pattern());
+        }
+        let pattern = match alternatives.len() {
+            1 => alternatives.remove(0),
+            _ => Pattern::Or(alternatives, Span::new(start, self.peek().span.start)),
+        };
+        self.cst.wrap(checkpoint, SyntaxKind::Pattern);
+        pattern
+    }
+
+    fn parse_pattern(&mut self) -> Pattern {
+        let checkpoint = self.cst.checkpoint();
         let start = self.peek().span.start;
         if self.match_token(TokenKind::Identifier) {
             let name = self.previous().clone();
-            return Pattern::Identifier(name.clone(), name.span);
-        } else if self.match_token(TokenKind::Integer) {
-            let lexeme = self.previous().lexeme.as_str();
-            match lexeme.parse() {
-                Ok(val) => return Pattern::Literal(Literal::Integer(val, self.previous().span.clone())),
-                Err(_) => {
-                    self.error("Invalid integer literal");
-                    return Pattern::Error;
+            if self.match_token(TokenKind::At) {
+                let pattern = Box::new(self.pattern());
+                return Pattern::Binding { name, pattern, span: Span::new(start, self.peek().span.start) };
+            }
+            if !self.check(TokenKind::Dot) && !self.check(TokenKind::Lparen) {
+                return Pattern::Identifier(name.clone(), name.span);
+            }
+
+            let (enum_name, name) = if self.match_token(TokenKind::Dot) {
+                (Some(name), self.consume(TokenKind::Identifier, "Expected variant name after '.'").clone())
+            } else {
+                (None, name)
+            };
+            let mut fields = vec![];
+            if self.match_token(TokenKind::Lparen) {
+                while !self.check(TokenKind::Rparen) && !self.is_at_end() {
+                    fields.push(self.pattern());
+                    if !self.check(TokenKind::Rparen) {
+                        self.consume(TokenKind::Comma, "Expected ',' after pattern");
+                    }
                 }
+                self.consume(TokenKind::Rparen, "Expected ')' after variant fields");
             }
-        } else if self.match_token(TokenKind::String) {
-            let value = self.previous().clone();
-            return Pattern::Literal(Literal::Token(value.clone(), value.span));
-        } else if self.match_token(TokenKind::Char) {
-            let value = self.previous().clone();
-            return Pattern::Literal(Literal::Token(value.clone(), value.span));
+            self.cst.wrap(checkpoint, SyntaxKind::VariantPattern);
+            return Pattern::Variant { enum_name, name, fields, span: Span::new(start, self.peek().span.start) };
+        } else if self.check(TokenKind::Integer) || self.check(TokenKind::String) || self.check(TokenKind::Minus) {
+            let Some(lower) = self.literal_pattern() else { return Pattern::Error };
+            if !self.check(TokenKind::Range) && !self.check(TokenKind::RangeEq) {
+                return Pattern::Literal(lower);
+            }
+            let inclusive = self.advance().kind == TokenKind::RangeEq;
+            let Some(upper) = self.literal_pattern() else { return Pattern::Error };
+            self.cst.wrap(checkpoint, SyntaxKind::RangePattern);
+            return Pattern::Range { start: lower, end: upper, inclusive, span: Span::new(start, self.peek().span.start) };
         } else if self.match_token(TokenKind::True) || self.match_token(TokenKind::False) {
             let value = self.previous().clone();
             return Pattern::Literal(Literal::Bool(
@@ -746,7 +1172,7 @@ impl<'src> Parser<'src> {
             ));
         } else if self.match_token(TokenKind::Lparen) {
             let mut patterns = vec![];
-            while !self.check(TokenKind::Rparen) {
+            while !self.check(TokenKind::Rparen) && !self.is_at_end() {
                 let pattern = self.pattern();
                 patterns.push(pattern);
                 if !self.check(TokenKind::Rparen) {
@@ -757,10 +1183,13 @@ impl<'src> Parser<'src> {
             return Pattern::Tuple(patterns, Span::new(start, self.peek().span.start));
         } else if self.match_token(TokenKind::Lbrace) {
             let mut fields: Vec<(Token, Pattern)> = vec![];
-            while !self.check(TokenKind::Rbrace) {
+            while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
                 let name = self.consume(TokenKind::Identifier, "Expected field name").clone();
-                self.consume(TokenKind::Colon, "Expected ':' after field name");
-                let pattern = self.pattern();
+                // `{ x }` is short for `{ x: x }`
+                let pattern = match self.match_token(TokenKind::Colon) {
+                    true => self.pattern(),
+                    false => Pattern::Identifier(name.clone(), name.span.clone()),
+                };
                 fields.push((name, pattern));
                 if !self.check(TokenKind::Rbrace) {
                     self.consume(TokenKind::Comma, "Expected ',' after field");
@@ -778,6 +1207,27 @@ impl<'src> Parser<'src> {
         Pattern::Error
     }
 
+    /// An integer, possibly negative, or a string.
+    fn literal_pattern(&mut self) -> Option<Literal> {
+        let start = self.peek().span.start;
+        if self.match_token(TokenKind::String) {
+            let value = self.previous().clone();
+            return Some(Literal::Token(value.clone(), value.span));
+        }
+        let negative = self.match_token(TokenKind::Minus);
+        if !self.match_token(TokenKind::Integer) {
+            self.error("Expected an integer after '-'");
+            return None;
+        }
+        match self.previous().lexeme.parse::<i64>() {
+            Ok(value) => Some(Literal::Integer(if negative { -value } else { value }, Span::new(start, self.previous().span.end))),
+            Err(_) => {
+                self.error("Invalid integer literal");
+                None
+            }
+        }
+    }
+
     fn parse_return_statement(&mut self) -> Statement {
         let start = self.peek().span.start;
         let mut value = None;
@@ -804,20 +1254,26 @@ impl<'src> Parser<'src> {
 
     fn parse_expression_statement(&mut self) -> Statement {
         let expr = self.expression();
+        // The last expression of a block may leave out the ';' to give the block its value
+        if self.check(TokenKind::Rbrace) {
+            return Statement::Tail(expr);
+        }
         self.consume(TokenKind::Semicolon, "Expected ';' after expression");
         Statement::Expr(expr)
     }
 
     fn expression(&mut self) -> Expr {
-        return self.assignment();
+        self.assignment()
     }
 
     fn assignment(&mut self) -> Expr {
-        let expr = self.logical_or();
+        let checkpoint = self.cst.checkpoint();
+        let expr = self.range();
 
         if TokenKind::assignment_operators().contains(&self.peek().kind) {
             let op = self.advance().clone();
             let value = self.assignment();
+            self.cst.wrap(checkpoint, SyntaxKind::AssignExpr);
             return Expr::Assignment {
                 left: Box::new(expr),
                 op,
@@ -829,7 +1285,36 @@ impl<'src> Parser<'src> {
         expr
     }
 
+    /// `a..b` and `a..=b`; either bound may be left out, as in `..b`, `a..` or `..`
+    fn range(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
+        let start_pos = self.peek().span.start;
+        let start = if self.check(TokenKind::Range) || self.check(TokenKind::RangeEq) {
+            None
+        } else {
+            let expr = self.logical_or();
+            if !self.check(TokenKind::Range) && !self.check(TokenKind::RangeEq) {
+                return expr;
+            }
+            Some(Box::new(expr))
+        };
+
+        let inclusive = self.advance().kind == TokenKind::RangeEq;
+        let open_end = [
+            TokenKind::Rparen, TokenKind::Rbracket, TokenKind::Rbrace, TokenKind::Lbrace,
+            TokenKind::Semicolon, TokenKind::Comma, TokenKind::Eof,
+        ].contains(&self.peek().kind);
+        let end = if open_end { None } else { Some(Box::new(self.logical_or())) };
+        if inclusive && end.is_none() {
+            self.error("Expected an end bound after '..='");
+        }
+
+        self.cst.wrap(checkpoint, SyntaxKind::RangeExpr);
+        Expr::Range { start, end, inclusive, span: Span::new(start_pos, self.peek().span.start) }
+    }
+
     fn logical_or(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.logical_and();
 
         while self.match_token(TokenKind::PipePipe) {
@@ -841,12 +1326,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn logical_and(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.nullish_coalesce();
 
         while self.match_token(TokenKind::AmpAmp) {
@@ -858,12 +1345,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn nullish_coalesce(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.bitwise_or();
 
         while self.match_token(TokenKind::QuestionQuestion) {
@@ -875,12 +1364,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn bitwise_or(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.bitwise_xor();
 
         while self.match_token(TokenKind::Pipe) {
@@ -892,12 +1383,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn bitwise_xor(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.bitwise_and();
 
         while self.match_token(TokenKind::Caret) {
@@ -909,12 +1402,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn bitwise_and(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.equality();
 
         while self.match_token(TokenKind::Amp) {
@@ -926,12 +1421,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn equality(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.comparison();
 
         while self.match_token(TokenKind::BangEq) || self.match_token(TokenKind::EqEq) {
@@ -943,12 +1440,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn comparison(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.addition();
 
         while self.match_token(TokenKind::Lt)
@@ -964,12 +1463,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn addition(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.multiplication();
 
         while self.match_token(TokenKind::Plus) || self.match_token(TokenKind::Minus) {
@@ -981,12 +1482,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn multiplication(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.exponent();
 
         while self.match_token(TokenKind::Star)
@@ -1001,12 +1504,14 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn exponent(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.unary();
 
         while self.match_token(TokenKind::Pow) {
@@ -1018,15 +1523,18 @@ impl<'src> Parser<'src> {
                 right: Box::new(right),
                 span: Span::new(self.previous().span.start, self.peek().span.start),
             };
+            self.cst.wrap(checkpoint, SyntaxKind::BinaryExpr);
         }
 
         expr
     }
 
     fn unary(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         if self.match_token(TokenKind::Bang) || self.match_token(TokenKind::Minus) {
             let op = self.previous().clone();
             let right = self.cast();
+            self.cst.wrap(checkpoint, SyntaxKind::UnaryExpr);
             return Expr::Unary {
                 op,
                 expr: Box::new(right),
@@ -1038,10 +1546,12 @@ impl<'src> Parser<'src> {
     }
 
     fn cast(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.index();
         while self.match_token(TokenKind::As) {
             let start = self.previous().span.start;
             let ty = self.type_expression();
+            self.cst.wrap(checkpoint, SyntaxKind::CastExpr);
             expr = Expr::Cast {
                 expr: Box::new(expr),
                 ty,
@@ -1052,11 +1562,13 @@ impl<'src> Parser<'src> {
     }
 
     fn index(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.property_access();
 
         while self.match_token(TokenKind::Lbracket) {
             let index = self.expression();
             self.consume(TokenKind::Rbracket, "Expected ']' after index");
+            self.cst.wrap(checkpoint, SyntaxKind::IndexExpr);
             expr = Expr::Index {
                 base: Box::new(expr),
                 index: Box::new(index),
@@ -1068,14 +1580,17 @@ impl<'src> Parser<'src> {
     }
 
     fn property_access(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.call();
 
-        while self.match_token(TokenKind::Dot) {
+        while let Some(optional) = self.field_access() {
             let name = self.consume(TokenKind::Identifier, "Expected property name").clone();
+            self.cst.wrap(checkpoint, SyntaxKind::FieldExpr);
 
             expr = Expr::Field { 
                 base: Box::new(expr),
                 field: name.clone(),
+                optional,
                 span: Span::new(self.previous().span.start, name.span.start),
             };
         }
@@ -1084,14 +1599,18 @@ impl<'src> Parser<'src> {
     }
 
     fn call(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint();
         let mut expr = self.primary();
+        self.cst.wrap(checkpoint, SyntaxKind::of_expr(&expr));
 
         let mut generics: Vec<Type> = vec![];
 
         loop {
-            if self.match_token(TokenKind::Lt) {
+            if self.check(TokenKind::Lt) && self.generic_args_ahead() {
+                self.advance();
+                let checkpoint = self.cst.checkpoint_before_last();
                 self.generic_stack += 1;
-                while !self.check(TokenKind::Gt) {
+                while !self.check(TokenKind::Gt) && !self.is_at_end() {
                     let generic_type = self.type_expression();
                     generics.push(generic_type);
                     if !self.check(TokenKind::Gt) {
@@ -1100,25 +1619,36 @@ impl<'src> Parser<'src> {
                 }
                 self.consume(TokenKind::Gt, "Expected '>' after generic type");
                 self.generic_stack -= 1;
+                self.cst.wrap(checkpoint, SyntaxKind::GenericList);
             }
 
             if self.match_token(TokenKind::Lparen) {
                 expr = self.finish_call(expr, generics.clone());
-            } else if self.match_token(TokenKind::Dot) {
+                self.cst.wrap(checkpoint, SyntaxKind::CallExpr);
+            } else if let Some(optional) = self.field_access() {
                 let name = self.consume(TokenKind::Identifier, "Expected property name").clone();
+                self.cst.wrap(checkpoint, SyntaxKind::FieldExpr);
                 expr = Expr::Field {
                     base: Box::new(expr),
                     field: name.clone(),
+                    optional,
                     span: Span::new(self.previous().span.start, name.span.start),
                 };
             } else if self.match_token(TokenKind::Lbracket) {
                 let index = self.expression();
                 self.consume(TokenKind::Rbracket, "Expected ']' after index");
+                self.cst.wrap(checkpoint, SyntaxKind::IndexExpr);
                 expr = Expr::Index {
                     base: Box::new(expr),
                     index: Box::new(index),
                     span: Span::new(self.previous().span.start, self.peek().span.start),
                 };
+            } else if self.match_token(TokenKind::Question) {
+                self.cst.wrap(checkpoint, SyntaxKind::TryExpr);
+                expr = Expr::Try {
+                    expr: Box::new(expr),
+                    span: self.previous().span.clone(),
+                };
             } else {
                 break;
             }
@@ -1127,25 +1657,57 @@ impl<'src> Parser<'src> {
         expr
     }
 
+    /// Consumes the `.` or `?.` of a field access, returning whether it was `?.`.
+    fn field_access(&mut self) -> Option<bool> {
+        if self.match_token(TokenKind::Dot) {
+            return Some(false);
+        }
+        if self.check(TokenKind::Question) && self.tokens.get(self.current + 1).is_some_and(|next| next.kind == TokenKind::Dot) {
+            self.advance();
+            self.advance();
+            return Some(true);
+        }
+        None
+    }
+
+    /// Whether the `<` at the current token opens the type arguments of a call, as in
+    /// `id<int>(x)`, rather than being a comparison like `a < b`.
+    fn generic_args_ahead(&self) -> bool {
+        let mut depth = 0;
+        for (i, token) in self.tokens.iter().enumerate().skip(self.current) {
+            match token.kind {
+                TokenKind::Lt => depth += 1,
+                TokenKind::Gt => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.tokens.get(i + 1).is_some_and(|token| token.kind == TokenKind::Lparen);
+                    }
+                }
+                TokenKind::Identifier | TokenKind::Comma | TokenKind::Arrow
+                | TokenKind::Lbracket | TokenKind::Rbracket | TokenKind::Lparen | TokenKind::Rparen => {}
+                _ => return false,
+            }
+        }
+        false
+    }
+
     fn finish_call(&mut self, callee: Expr, generics: Vec<Type>) -> Expr {
+        let checkpoint = self.cst.checkpoint_before_last();
         let mut args = vec![];
 
-        if !self.check(TokenKind::Rparen) {
-            loop {
-                if args.len() >= 255 {
-                    self.error("Cannot have more than 255 arguments");
-                }
-                args.push(self.expression());
-                if !self.check(TokenKind::Rparen) {
-                    self.consume(TokenKind::Comma, "Expected ',' after argument");
-                } else {
-                    break;
-                }
+        while !self.check(TokenKind::Rparen) && !self.is_at_end() {
+            if args.len() >= 255 {
+                self.error("Cannot have more than 255 arguments");
+            }
+            args.push(self.expression());
+            if !self.check(TokenKind::Rparen) {
+                self.consume(TokenKind::Comma, "Expected ',' after argument");
             }
         }
 
         let prev = self.previous().span.start;
-        let paren = self.consume(TokenKind::Rparen, "Expected ')' after arguments");
+        let paren = self.consume(TokenKind::Rparen, "Expected ')' after arguments").clone();
+        self.cst.wrap(checkpoint, SyntaxKind::ArgList);
 
         Expr::Call {
             callee: Box::new(callee),
@@ -1182,10 +1744,8 @@ impl<'src> Parser<'src> {
             ));
         }
         if self.match_token(TokenKind::String) {
-            return Expr::Literal(Literal::Token(
-                self.previous().clone(),
-                self.previous().span.clone(),
-            ));
+            let token = self.previous().clone();
+            return self.string_literal(token);
         }
         if self.match_token(TokenKind::Char) {
             return Expr::Literal(Literal::Token(
@@ -1208,9 +1768,23 @@ impl<'src> Parser<'src> {
             return self.closure();
         }
 
+        if self.match_token(TokenKind::If) {
+            return self.if_expression();
+        }
+
+        if self.match_token(TokenKind::Match) {
+            let start = self.previous().span.start;
+            let (expr, cases) = self.match_body();
+            return Expr::Match {
+                expr: Box::new(expr),
+                cases,
+                span: Span::new(start, self.peek().span.start),
+            };
+        }
+
         if self.match_token(TokenKind::Lbracket) {
             let mut elements = vec![];
-            while !self.check(TokenKind::Rbracket) {
+            while !self.check(TokenKind::Rbracket) && !self.is_at_end() {
                 let element = self.expression();
                 elements.push(element);
                 if !self.check(TokenKind::Rbracket) {
@@ -1229,7 +1803,7 @@ impl<'src> Parser<'src> {
             let expr = self.expression();
             if self.match_token(TokenKind::Comma) {
                 let mut elements = vec![expr];
-                while !self.check(TokenKind::Rparen) {
+                while !self.check(TokenKind::Rparen) && !self.is_at_end() {
                     let element = self.expression();
                     elements.push(element);
                     if !self.check(TokenKind::Rparen) {
@@ -1252,11 +1826,101 @@ impl<'src> Parser<'src> {
         Expr::Error
     }
 
+    /// An `if` in expression position, whose branches are blocks; `else if` chains are allowed.
+    fn if_expression(&mut self) -> Expr {
+        let start = self.previous().span.start;
+        self.consume(TokenKind::Lparen, "Expected '(' after 'if'");
+        let cond = self.expression();
+        self.consume(TokenKind::Rparen, "Expected ')' after 'if' condition");
+        let then_branch = self.branch_block("Expected '{' after 'if' condition");
+
+        let mut else_branch = None;
+        if self.match_token(TokenKind::Else) {
+            else_branch = Some(Box::new(match self.check(TokenKind::If) {
+                true => self.parse_statement(),
+                false => self.branch_block("Expected '{' after 'else'"),
+            }));
+        }
+
+        Expr::If {
+            cond: Box::new(cond),
+            then_branch: Box::new(then_branch),
+            else_branch,
+            span: Span::new(start, self.peek().span.start),
+        }
+    }
+
+    fn branch_block(&mut self, message: &str) -> Statement {
+        if self.check(TokenKind::Lbrace) {
+            return self.parse_statement();
+        }
+        self.error(message);
+        Statement::Block(vec![], self.peek().span.clone())
+    }
+
+    /// Builds a string literal, splitting out any `\(expr)` interpolations.
+    /// Each interpolated expression is lexed and parsed on its own, with spans offset
+    /// to its position inside the original string.
+    fn string_literal(&mut self, token: Token) -> Expr {
+        let interp = match InterpolatedString::extract_from_token(&token) {
+            Some(interp) => interp,
+            None => return Expr::Literal(Literal::Token(token.clone(), token.span)),
+        };
+
+        let mut segments = vec![];
+        // Skip the opening quote; the closing quote is dropped below.
+        let mut literal_start = 1;
+        for part in &interp.interpolations {
+            let literal_end = part.span.start - 2; // Position of the `\(`
+            if literal_end > literal_start {
+                segments.push(self.string_segment(&token, literal_start, literal_end));
+            }
+
+            let offset = token.span.start + part.span.start;
+            let mut sublexer = Lexer::new(&part.expr, self.filename.clone());
+            sublexer.set_offset(offset, part.line);
+            sublexer.scan_tokens();
+            for mut error in sublexer.diagnostics.drain(..) {
+                error.add_source(self.source.to_string());
+                self.diagnostics.push(error);
+            }
+
+            let mut subparser = Parser::new(&sublexer.tokens, self.source, self.filename.clone());
+            let expr = subparser.expression();
+            if !subparser.is_at_end() {
+                subparser.error("Unexpected token in string interpolation");
+            }
+            if subparser.errors > 0 {
+                self.had_error = true;
+                self.errors += subparser.errors;
+                self.diagnostics.append(&mut subparser.diagnostics);
+            }
+
+            segments.push(TokenSegment::Expr(expr, Span::new(offset, offset + part.expr.len())));
+            literal_start = part.span.end + 1; // Skip the closing `)`
+        }
+
+        let literal_end = interp.original.len().saturating_sub(1);
+        if literal_end > literal_start {
+            segments.push(self.string_segment(&token, literal_start, literal_end));
+        }
+
+        let span = token.span.clone();
+        Expr::TokenInterpolation(TokenInterpolation { segments, span: span.clone() }, span)
+    }
+
+    fn string_segment(&self, token: &Token, start: usize, end: usize) -> TokenSegment {
+        let span = Span::new(token.span.start + start, token.span.start + end);
+        let text = Token::new(TokenKind::String, token.lexeme[start..end].to_string(), token.line, span.clone());
+        TokenSegment::Literal(text, span)
+    }
+
     fn struct_init(&mut self, name: Token) -> Expr {
         let start = self.peek().span.start;
         let mut fields = vec![];
 
-        while !self.check(TokenKind::Rbrace) {
+        while !self.check(TokenKind::Rbrace) && !self.is_at_end() {
+            let checkpoint = self.cst.checkpoint();
             let field_name = self.consume(TokenKind::Identifier, "Expected field name").clone();
 
             if self.match_token(TokenKind::Colon) {
@@ -1270,6 +1934,7 @@ impl<'src> Parser<'src> {
                 // and we will resolve it later
                 fields.push((field_name.clone(), Expr::Identifier(field_name.clone(), field_name.span.clone())));
             }
+            self.cst.wrap(checkpoint, SyntaxKind::StructInitField);
     
             if !self.check(TokenKind::Rbrace) {
                 self.consume(TokenKind::Comma, "Expected ',' after field");
@@ -1286,14 +1951,16 @@ impl<'src> Parser<'src> {
     }
 
     fn closure(&mut self) -> Expr {
+        let checkpoint = self.cst.checkpoint_before_last();
         let start = self.previous().span.start;
         let mut params = vec![];
 
-        while !self.check(TokenKind::Pipe) {
+        while !self.check(TokenKind::Pipe) && !self.is_at_end() {
             params = self.parse_parameters(TokenKind::Pipe);
         }
 
         self.consume(TokenKind::Pipe, "Expected '|' after closure parameters");
+        self.cst.wrap(checkpoint, SyntaxKind::ParamList);
 
         let ty = self.type_expression();
 
@@ -1309,6 +1976,7 @@ impl<'src> Parser<'src> {
 
     fn advance(&mut self) -> &Token {
         if !self.is_at_end() {
+            self.cst.token(self.peek());
             self.current += 1;
         }
 
@@ -1333,7 +2001,7 @@ impl<'src> Parser<'src> {
     }
 
     fn previous(&self) -> &Token {
-        &self.tokens[self.current - 1]
+        &self.tokens[self.current.saturating_sub(1)]
     }
 
     fn peek(&self) -> Token {
@@ -1354,14 +2022,13 @@ impl<'src> Parser<'src> {
         self.previous()
     }
 
-    fn error(&mut self, message: &str) -> Error {
+    fn error(&mut self, message: &str) {
         self.had_error = true;
         self.errors += 1;
         let mut e = Error::new(message.to_string(), self.peek().line, self.peek().span.clone(), self.filename.clone());
         e.add_source(self.source.to_string());
 
-        eprintln!("{}", e.to_string());
-        e
+        self.diagnostics.push(e);
     }
 
     fn synchronise(&mut self) {
@@ -1371,8 +2038,8 @@ impl<'src> Parser<'src> {
                 return;
             }
             match self.peek().kind {
-                TokenKind::Func | TokenKind::Struct | TokenKind::Enum
-                | TokenKind::Trait | TokenKind::Import | TokenKind::Extend => return,
+                TokenKind::Func | TokenKind::Struct | TokenKind::Enum | TokenKind::Trait
+                | TokenKind::Import | TokenKind::Extend | TokenKind::Type | TokenKind::Hash => return,
                 TokenKind::Let | TokenKind::If | TokenKind::While | TokenKind::For
                 | TokenKind::Match | TokenKind::Return | TokenKind::Break
                 | TokenKind::Continue => return,
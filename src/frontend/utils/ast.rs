@@ -1,5 +1,9 @@
+use std::fmt;
+
 use crate::frontend::utils::token::{Span, Token};
 
+// Declarations are few, so they are stored inline rather than boxed
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum StatementKind {
     Function(Function),
@@ -7,6 +11,7 @@ pub enum StatementKind {
     Enum(Enum),
     Extend(Extend),
     Trait(Trait),
+    TypeAlias(TypeAlias),
     Import(Import),
     Statement(Statement),
 }
@@ -21,16 +26,48 @@ pub enum AccessModifier {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Function {
+    pub attributes: Vec<Attribute>,
     pub access: AccessModifier,
     pub name: Token,
+    pub generics: Vec<Type>,
+    /// Trait bounds from the generic list and the `where` clause
+    pub bounds: Vec<GenericBound>,
     pub params: Vec<Parameter>,
     pub return_type: Type,
-    pub body: Statement,
+    /// `None` for a trait method that implementations must provide
+    pub body: Option<Statement>,
     pub span: Span,
 
+    /// Whether the function takes a `self` receiver, as methods of `extend` blocks
+    /// and traits may; the others are called through the type, e.g. `Point.new()`
     pub is_method: bool,
 }
 
+/// "#[name(args)]" in front of a function, struct, field, enum or module, e.g.
+/// "#[deprecated("Use 'parse' instead")]" or "#[derive(Eq, Debug)]"
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attribute {
+    pub name: Token,
+    /// Names and literals between the parentheses, as written
+    pub args: Vec<Token>,
+    pub span: Span,
+}
+
+impl Attribute {
+    /// The attribute called `name` among `attributes`, if there is one.
+    pub fn find<'a>(attributes: &'a [Attribute], name: &str) -> Option<&'a Attribute> {
+        attributes.iter().find(|attribute| attribute.name.lexeme == name)
+    }
+}
+
+/// Traits a generic parameter must implement, e.g. "T: Display + Clone"
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericBound {
+    pub param: Token,
+    pub traits: Vec<Type>,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parameter {
     pub name: Token,
@@ -40,6 +77,7 @@ pub struct Parameter {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Struct {
+    pub attributes: Vec<Attribute>,
     pub access: AccessModifier,
     pub name: Token,
     pub fields: Vec<Field>,
@@ -49,6 +87,7 @@ pub struct Struct {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Field {
+    pub attributes: Vec<Attribute>,
     pub access: AccessModifier,
     pub name: Token,
     pub ty: Type,
@@ -57,8 +96,10 @@ pub struct Field {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Enum {
+    pub attributes: Vec<Attribute>,
     pub access: AccessModifier,
     pub name: Token,
+    pub generics: Vec<Type>,
     pub variants: Vec<EnumVariant>,
     pub span: Span,
 }
@@ -75,10 +116,41 @@ pub struct Trait {
     pub access: AccessModifier,
     pub name: Token,
     pub methods: Vec<Function>,
+    pub types: Vec<AssociatedType>,
+    pub constants: Vec<Constant>,
     pub generics: Vec<Type>,
     pub span: Span,
 }
 
+/// An associated type, declared as "type Item;" in a trait
+/// and chosen as "type Item = int;" in an implementation
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssociatedType {
+    pub name: Token,
+    pub ty: Option<Type>,
+    pub span: Span,
+}
+
+/// An associated constant, e.g. "const MAX: int = 10;"; traits may leave out the value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Constant {
+    pub name: Token,
+    pub ty: Type,
+    pub value: Option<Expr>,
+    pub span: Span,
+}
+
+/// "type Meters = float;", another name for the same type, or with `new`,
+/// as in "type UserId = new int;", a distinct type with the same values
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAlias {
+    pub access: AccessModifier,
+    pub name: Token,
+    pub ty: Type,
+    pub newtype: bool,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Import {
     pub path: Token,
@@ -88,6 +160,7 @@ pub struct Import {
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Module {
+    pub attributes: Vec<Attribute>,
     pub name: Token,
     pub imports: Vec<Import>,
     pub stmts: Vec<StatementKind>,
@@ -99,6 +172,8 @@ pub struct Extend {
     pub name: Token, // Name of the struct being extended
     pub trait_name: Option<Token>, // Name of the trait being implemented
     pub methods: Vec<Function>,
+    pub types: Vec<AssociatedType>,
+    pub constants: Vec<Constant>,
     pub first_generics: Vec<Type>,
     pub second_generics: Vec<Type>,
     pub span: Span,
@@ -114,12 +189,66 @@ pub enum Pattern {
         fields: Vec<(Token, Pattern)>,
         span: Span,
     },
+    /// An enum variant, e.g. "Shape.Circle(r)", or "Circle(r)" for a variant of the enum being matched
+    Variant {
+        enum_name: Option<Token>,
+        name: Token,
+        fields: Vec<Pattern>,
+        span: Span,
+    },
+    /// Any of several patterns, e.g. "1 | 2 | 3"
+    Or(Vec<Pattern>, Span),
+    /// Integers or characters from `start` up to `end`, e.g. "1..10"; "'a'..='z'" includes the end
+    Range {
+        start: Literal,
+        end: Literal,
+        inclusive: bool,
+        span: Span,
+    },
+    /// Binds the whole value when it matches `pattern`, e.g. "n @ 1..10"
+    Binding {
+        name: Token,
+        pattern: Box<Pattern>,
+        span: Span,
+    },
     Error
 }
 
+impl Pattern {
+    pub fn span(&self) -> Span {
+        match self {
+            Pattern::Identifier(_, span)
+            | Pattern::Wildcard(span)
+            | Pattern::Tuple(_, span)
+            | Pattern::Struct { span, .. }
+            | Pattern::Variant { span, .. }
+            | Pattern::Or(_, span)
+            | Pattern::Range { span, .. }
+            | Pattern::Binding { span, .. } => span.clone(),
+            Pattern::Literal(lit) => lit.span(),
+            Pattern::Error => Span::default(),
+        }
+    }
+
+    /// The names the pattern binds, in order.
+    pub fn bindings(&self) -> Vec<&Token> {
+        match self {
+            Pattern::Identifier(name, _) => vec![name],
+            Pattern::Binding { name, pattern, .. } => [vec![name], pattern.bindings()].concat(),
+            Pattern::Tuple(patterns, _) | Pattern::Variant { fields: patterns, .. } => patterns.iter().flat_map(Pattern::bindings).collect(),
+            Pattern::Struct { fields, .. } => fields.iter().flat_map(|(_, pattern)| pattern.bindings()).collect(),
+            // Every alternative binds the same names
+            Pattern::Or(alternatives, _) => alternatives[0].bindings(),
+            Pattern::Literal(_) | Pattern::Range { .. } | Pattern::Wildcard(_) | Pattern::Error => vec![],
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Case {
     pub pattern: Pattern,
+    /// A condition the case also requires, e.g. "x if x > 0 -> ..."
+    pub guard: Option<Expr>,
     pub body: Statement,
     pub span: Span,
 }
@@ -133,8 +262,9 @@ pub struct Match {
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
+    /// A binding, which may destructure its value, e.g. "let (a, { x, y }) = pair;"
     Let {
-        name: Token,
+        pattern: Box<Pattern>,
         ty: Option<Type>,
         value: Option<Expr>,
         span: Span,
@@ -166,6 +296,8 @@ pub enum Statement {
         cases: Vec<Case>,
         span: Span,
     },
+    /// An expression ending a block without a ';', which becomes the block's value
+    Tail(Expr),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -192,6 +324,8 @@ pub enum Expr {
     Field {
         base: Box<Expr>,
         field: Token,
+        /// `base?.field`, which is `null` when `base` is
+        optional: bool,
         span: Span,
     },
     Index {
@@ -231,9 +365,90 @@ pub enum Expr {
     },
     TokenInterpolation(TokenInterpolation, Span),
     Grouping(Box<Expr>, Span),
+    /// `a..b`, `a..=b` or a range with either bound left open, e.g. `..b` or `a..`
+    Range {
+        start: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+        inclusive: bool,
+        span: Span,
+    },
+    /// `if` used for its value, e.g. "let sign = if (x < 0) { -1 } else { 1 };"
+    If {
+        cond: Box<Expr>,
+        then_branch: Box<Statement>,
+        else_branch: Option<Box<Statement>>,
+        span: Span,
+    },
+    /// `match` used for its value, which is the value of the matching case's body
+    Match {
+        expr: Box<Expr>,
+        cases: Vec<Case>,
+        span: Span,
+    },
+    /// `expr?`: the `Ok` value of a `Result`, returning its `Err` from the enclosing function
+    Try {
+        expr: Box<Expr>,
+        span: Span,
+    },
     Error,
 }
 
+impl Extend {
+    /// The generic parameters of the extended type; in `extend Trait<A> for Type<B>`
+    /// the first list belongs to the trait.
+    pub fn type_generics(&self) -> &[Type] {
+        match self.trait_name {
+            Some(_) => &self.second_generics,
+            None => &self.first_generics,
+        }
+    }
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Let { span, .. }
+            | Statement::Return(_, span)
+            | Statement::Break(span)
+            | Statement::Continue(span)
+            | Statement::Block(_, span)
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::For { span, .. }
+            | Statement::Match { span, .. } => span.clone(),
+            Statement::Expr(expr) | Statement::Tail(expr) => expr.span(),
+        }
+    }
+}
+
+impl Expr {
+    /// Returns the source span covered by this expression
+    pub fn span(&self) -> Span {
+        match self {
+            Expr::Identifier(_, span)
+            | Expr::Binary { span, .. }
+            | Expr::Unary { span, .. }
+            | Expr::Call { span, .. }
+            | Expr::Field { span, .. }
+            | Expr::Index { span, .. }
+            | Expr::Assignment { span, .. }
+            | Expr::StructInit { span, .. }
+            | Expr::Array { span, .. }
+            | Expr::Tuple { span, .. }
+            | Expr::Cast { span, .. }
+            | Expr::Closure { span, .. }
+            | Expr::TokenInterpolation(_, span)
+            | Expr::Grouping(_, span)
+            | Expr::Range { span, .. }
+            | Expr::If { span, .. }
+            | Expr::Match { span, .. }
+            | Expr::Try { span, .. } => span.clone(),
+            Expr::Literal(lit) => lit.span(),
+            Expr::Error => Span::default(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Integer(i64, Span),
@@ -243,6 +458,18 @@ pub enum Literal {
     Token(Token, Span),
 }
 
+impl Literal {
+    pub fn span(&self) -> Span {
+        match self {
+            Literal::Integer(_, span)
+            | Literal::Float(_, span)
+            | Literal::Bool(_, span)
+            | Literal::Null(span)
+            | Literal::Token(_, span) => span.clone(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 /// Represents a Token literal with possible interpolations <br>
 /// For example, "Hello, \(name)!" would be represented as a TokenInterpolation with a segment for "Hello, " and an expression for \(name) <br>
@@ -292,6 +519,16 @@ pub enum Type {
         return_type: Box<Type>,
         span: Span,
     },
+    /// A type whose values may also be `null`, e.g. "int?"
+    Optional {
+        inner: Box<Type>,
+        span: Span,
+    },
+    /// A trait object, a value of any type implementing the trait, e.g. "dyn Shape"
+    Dyn {
+        trait_: Box<Type>,
+        span: Span,
+    },
     /// A type variable for generics, e.g. "T"
     TypeVar {
         name: Token,
@@ -300,3 +537,27 @@ pub enum Type {
 
     Error(Span), // Represents an error in type resolution
 }
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(types: &[Type]) -> String {
+            types.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", ")
+        }
+
+        match self {
+            // An inference variable the type checker hasn't solved
+            Type::TypeVar { name, .. } if name.lexeme.starts_with('?') => write!(f, "_"),
+            Type::Primitive { name, .. } | Type::TypeVar { name, .. } => write!(f, "{}", name.lexeme),
+            Type::Named { name, generics, .. } if generics.is_empty() => write!(f, "{}", name.lexeme),
+            Type::Named { name, generics, .. } => write!(f, "{}<{}>", name.lexeme, list(generics)),
+            Type::Array { element, size: Some(size), .. } => write!(f, "[{}; {}]", element, size),
+            Type::Array { element, size: None, .. } => write!(f, "[{}]", element),
+            Type::Tuple { elements, .. } => write!(f, "({})", list(elements)),
+            Type::Function { params, return_type, .. } => write!(f, "({}) -> {}", list(params), return_type),
+            Type::Optional { inner, .. } if matches!(**inner, Type::Function { .. } | Type::Dyn { .. }) => write!(f, "({})?", inner),
+            Type::Optional { inner, .. } => write!(f, "{}?", inner),
+            Type::Dyn { trait_, .. } => write!(f, "dyn {}", trait_),
+            Type::Error(_) => write!(f, "<error>"),
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use crate::frontend::utils::{
+    ast::*,
+    token::{Span, Token},
+};
+use crate::utils::json::Json;
+
+/// Conversion of syntax tree nodes into JSON, used by `y parse --emit=json`.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(ToJson::to_json).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        match self {
+            Some(value) => value.to_json(),
+            None => Json::Null,
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Box<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl ToJson for Span {
+    fn to_json(&self) -> Json {
+        Json::object(vec![("start", self.start.into()), ("end", self.end.into())])
+    }
+}
+
+impl ToJson for Token {
+    fn to_json(&self) -> Json {
+        Json::string(self.lexeme.clone())
+    }
+}
+
+fn node(kind: &str, mut members: Vec<(&str, Json)>) -> Json {
+    members.insert(0, ("kind", kind.into()));
+    Json::object(members)
+}
+
+impl ToJson for Module {
+    fn to_json(&self) -> Json {
+        node("Module", vec![
+            ("attributes", self.attributes.to_json()),
+            ("name", self.name.to_json()),
+            ("imports", self.imports.to_json()),
+            ("stmts", self.stmts.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for StatementKind {
+    fn to_json(&self) -> Json {
+        match self {
+            StatementKind::Function(f) => f.to_json(),
+            StatementKind::Struct(s) => s.to_json(),
+            StatementKind::Enum(e) => e.to_json(),
+            StatementKind::Extend(e) => e.to_json(),
+            StatementKind::Trait(t) => t.to_json(),
+            StatementKind::TypeAlias(a) => a.to_json(),
+            StatementKind::Import(i) => i.to_json(),
+            StatementKind::Statement(s) => s.to_json(),
+        }
+    }
+}
+
+impl ToJson for AccessModifier {
+    fn to_json(&self) -> Json {
+        match self {
+            AccessModifier::Public => "pub".into(),
+            AccessModifier::Private => "priv".into(),
+            AccessModifier::Protected => "protected".into(),
+            AccessModifier::None => Json::Null,
+        }
+    }
+}
+
+impl ToJson for Function {
+    fn to_json(&self) -> Json {
+        node("Function", vec![
+            ("attributes", self.attributes.to_json()),
+            ("access", self.access.to_json()),
+            ("name", self.name.to_json()),
+            ("generics", self.generics.to_json()),
+            ("bounds", self.bounds.to_json()),
+            ("params", self.params.to_json()),
+            ("return_type", self.return_type.to_json()),
+            ("body", self.body.to_json()),
+            ("is_method", self.is_method.into()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Attribute {
+    fn to_json(&self) -> Json {
+        node("Attribute", vec![
+            ("name", self.name.to_json()),
+            ("args", self.args.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for GenericBound {
+    fn to_json(&self) -> Json {
+        node("GenericBound", vec![
+            ("param", self.param.to_json()),
+            ("traits", self.traits.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Parameter {
+    fn to_json(&self) -> Json {
+        node("Parameter", vec![
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Struct {
+    fn to_json(&self) -> Json {
+        node("Struct", vec![
+            ("attributes", self.attributes.to_json()),
+            ("access", self.access.to_json()),
+            ("name", self.name.to_json()),
+            ("generics", self.generics.to_json()),
+            ("fields", self.fields.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Field {
+    fn to_json(&self) -> Json {
+        node("Field", vec![
+            ("attributes", self.attributes.to_json()),
+            ("access", self.access.to_json()),
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Enum {
+    fn to_json(&self) -> Json {
+        node("Enum", vec![
+            ("attributes", self.attributes.to_json()),
+            ("access", self.access.to_json()),
+            ("name", self.name.to_json()),
+            ("generics", self.generics.to_json()),
+            ("variants", self.variants.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for EnumVariant {
+    fn to_json(&self) -> Json {
+        node("EnumVariant", vec![
+            ("name", self.name.to_json()),
+            ("fields", self.fields.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Trait {
+    fn to_json(&self) -> Json {
+        node("Trait", vec![
+            ("access", self.access.to_json()),
+            ("name", self.name.to_json()),
+            ("generics", self.generics.to_json()),
+            ("methods", self.methods.to_json()),
+            ("types", self.types.to_json()),
+            ("constants", self.constants.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for AssociatedType {
+    fn to_json(&self) -> Json {
+        node("AssociatedType", vec![
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Constant {
+    fn to_json(&self) -> Json {
+        node("Constant", vec![
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+            ("value", self.value.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for TypeAlias {
+    fn to_json(&self) -> Json {
+        node("TypeAlias", vec![
+            ("access", self.access.to_json()),
+            ("name", self.name.to_json()),
+            ("ty", self.ty.to_json()),
+            ("newtype", Json::Bool(self.newtype)),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Import {
+    fn to_json(&self) -> Json {
+        node("Import", vec![
+            ("path", self.path.to_json()),
+            ("alias", self.alias.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Extend {
+    fn to_json(&self) -> Json {
+        node("Extend", vec![
+            ("name", self.name.to_json()),
+            ("trait_name", self.trait_name.to_json()),
+            ("first_generics", self.first_generics.to_json()),
+            ("second_generics", self.second_generics.to_json()),
+            ("methods", self.methods.to_json()),
+            ("types", self.types.to_json()),
+            ("constants", self.constants.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Pattern {
+    fn to_json(&self) -> Json {
+        match self {
+            Pattern::Identifier(name, span) => node("IdentifierPattern", vec![
+                ("name", name.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Literal(lit) => node("LiteralPattern", vec![("value", lit.to_json())]),
+            Pattern::Wildcard(span) => node("WildcardPattern", vec![("span", span.to_json())]),
+            Pattern::Tuple(elements, span) => node("TuplePattern", vec![
+                ("elements", elements.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Struct { fields, span } => node("StructPattern", vec![
+                ("fields", Json::Array(fields.iter().map(|(name, pattern)| {
+                    Json::object(vec![("name", name.to_json()), ("pattern", pattern.to_json())])
+                }).collect())),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Variant { enum_name, name, fields, span } => node("VariantPattern", vec![
+                ("enum_name", enum_name.to_json()),
+                ("name", name.to_json()),
+                ("fields", fields.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Or(alternatives, span) => node("OrPattern", vec![
+                ("alternatives", alternatives.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Range { start, end, inclusive, span } => node("RangePattern", vec![
+                ("start", start.to_json()),
+                ("end", end.to_json()),
+                ("inclusive", Json::Bool(*inclusive)),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Binding { name, pattern, span } => node("BindingPattern", vec![
+                ("name", name.to_json()),
+                ("pattern", pattern.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Pattern::Error => node("ErrorPattern", vec![]),
+        }
+    }
+}
+
+impl ToJson for Case {
+    fn to_json(&self) -> Json {
+        node("Case", vec![
+            ("pattern", self.pattern.to_json()),
+            ("guard", self.guard.to_json()),
+            ("body", self.body.to_json()),
+            ("span", self.span.to_json()),
+        ])
+    }
+}
+
+impl ToJson for Statement {
+    fn to_json(&self) -> Json {
+        match self {
+            Statement::Let { pattern, ty, value, span } => node("Let", vec![
+                ("pattern", pattern.to_json()),
+                ("ty", ty.to_json()),
+                ("value", value.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::Expr(expr) => node("ExprStatement", vec![("expr", expr.to_json())]),
+            Statement::Return(value, span) => node("Return", vec![
+                ("value", value.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::Break(span) => node("Break", vec![("span", span.to_json())]),
+            Statement::Continue(span) => node("Continue", vec![("span", span.to_json())]),
+            Statement::Block(stmts, span) => node("Block", vec![
+                ("stmts", stmts.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::If { cond, then_branch, else_branch, span } => node("If", vec![
+                ("cond", cond.to_json()),
+                ("then_branch", then_branch.to_json()),
+                ("else_branch", else_branch.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::While { cond, body, span } => node("While", vec![
+                ("cond", cond.to_json()),
+                ("body", body.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::For { var, iter, body, span } => node("For", vec![
+                ("var", var.to_json()),
+                ("iter", iter.to_json()),
+                ("body", body.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::Match { expr, cases, span } => node("Match", vec![
+                ("expr", expr.to_json()),
+                ("cases", cases.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Statement::Tail(expr) => node("Tail", vec![("expr", expr.to_json())]),
+        }
+    }
+}
+
+impl ToJson for Expr {
+    fn to_json(&self) -> Json {
+        match self {
+            Expr::Identifier(name, span) => node("Identifier", vec![
+                ("name", name.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Literal(lit) => lit.to_json(),
+            Expr::Binary { left, op, right, span } => node("Binary", vec![
+                ("left", left.to_json()),
+                ("op", op.to_json()),
+                ("right", right.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Unary { op, expr, span } => node("Unary", vec![
+                ("op", op.to_json()),
+                ("expr", expr.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Call { callee, args, generic_args, span } => node("Call", vec![
+                ("callee", callee.to_json()),
+                ("args", args.to_json()),
+                ("generic_args", generic_args.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Field { base, field, optional, span } => node("Field", vec![
+                ("base", base.to_json()),
+                ("field", field.to_json()),
+                ("optional", Json::Bool(*optional)),
+                ("span", span.to_json()),
+            ]),
+            Expr::Index { base, index, span } => node("Index", vec![
+                ("base", base.to_json()),
+                ("index", index.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Assignment { left, op, right, span } => node("Assignment", vec![
+                ("left", left.to_json()),
+                ("op", op.to_json()),
+                ("right", right.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::StructInit { name, fields, span } => node("StructInit", vec![
+                ("name", name.to_json()),
+                ("fields", Json::Array(fields.iter().map(|(name, value)| {
+                    Json::object(vec![("name", name.to_json()), ("value", value.to_json())])
+                }).collect())),
+                ("span", span.to_json()),
+            ]),
+            Expr::Array { elements, span } => node("Array", vec![
+                ("elements", elements.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Tuple { elements, span } => node("Tuple", vec![
+                ("elements", elements.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Cast { expr, ty, span } => node("Cast", vec![
+                ("expr", expr.to_json()),
+                ("ty", ty.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Closure { params, body, ty, span } => node("Closure", vec![
+                ("params", params.to_json()),
+                ("ty", ty.to_json()),
+                ("body", body.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::TokenInterpolation(interp, span) => node("Interpolation", vec![
+                ("segments", interp.segments.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Grouping(expr, span) => node("Grouping", vec![
+                ("expr", expr.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Try { expr, span } => node("Try", vec![
+                ("expr", expr.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Range { start, end, inclusive, span } => node("Range", vec![
+                ("start", start.to_json()),
+                ("end", end.to_json()),
+                ("inclusive", Json::Bool(*inclusive)),
+                ("span", span.to_json()),
+            ]),
+            Expr::If { cond, then_branch, else_branch, span } => node("IfExpr", vec![
+                ("cond", cond.to_json()),
+                ("then_branch", then_branch.to_json()),
+                ("else_branch", else_branch.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Match { expr, cases, span } => node("MatchExpr", vec![
+                ("expr", expr.to_json()),
+                ("cases", cases.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Expr::Error => node("Error", vec![]),
+        }
+    }
+}
+
+impl ToJson for TokenSegment {
+    fn to_json(&self) -> Json {
+        match self {
+            TokenSegment::Literal(text, span) => node("Text", vec![
+                ("value", text.to_json()),
+                ("span", span.to_json()),
+            ]),
+            TokenSegment::Expr(expr, span) => node("Expr", vec![
+                ("expr", expr.to_json()),
+                ("span", span.to_json()),
+            ]),
+        }
+    }
+}
+
+impl ToJson for Literal {
+    fn to_json(&self) -> Json {
+        match self {
+            Literal::Integer(value, span) => node("Integer", vec![
+                ("value", (*value).into()),
+                ("span", span.to_json()),
+            ]),
+            Literal::Float(value, span) => node("Float", vec![
+                ("value", (*value).into()),
+                ("span", span.to_json()),
+            ]),
+            Literal::Bool(value, span) => node("Bool", vec![
+                ("value", (*value).into()),
+                ("span", span.to_json()),
+            ]),
+            Literal::Null(span) => node("Null", vec![("span", span.to_json())]),
+            Literal::Token(token, span) => node("String", vec![
+                ("value", token.to_json()),
+                ("span", span.to_json()),
+            ]),
+        }
+    }
+}
+
+impl ToJson for Type {
+    fn to_json(&self) -> Json {
+        match self {
+            Type::Primitive { name, span } => node("Primitive", vec![
+                ("name", name.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::Named { name, generics, span } => node("Named", vec![
+                ("name", name.to_json()),
+                ("generics", generics.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::Array { element, size, span } => node("Array", vec![
+                ("element", element.to_json()),
+                ("size", (*size).into()),
+                ("span", span.to_json()),
+            ]),
+            Type::Tuple { elements, span } => node("Tuple", vec![
+                ("elements", elements.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::Function { params, return_type, span } => node("Function", vec![
+                ("params", params.to_json()),
+                ("return_type", return_type.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::Optional { inner, span } => node("Optional", vec![
+                ("inner", inner.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::Dyn { trait_, span } => node("Dyn", vec![
+                ("trait", trait_.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::TypeVar { name, span } => node("TypeVar", vec![
+                ("name", name.to_json()),
+                ("span", span.to_json()),
+            ]),
+            Type::Error(span) => node("Error", vec![("span", span.to_json())]),
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::frontend::utils::{
+    ast::{Expr, Statement},
+    token::{Span, Token},
+};
+
+/// The kind of a node in the concrete syntax tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    // Declarations
+    Module, ModuleDecl, Import, Function, ParamList, Param, GenericList, WhereClause,
+    Struct, StructField, Enum, EnumVariant, Trait, Extend, AssociatedType, Constant,
+    TypeAlias, Attribute,
+
+    // Types and patterns
+    Type, Pattern, VariantPattern, RangePattern,
+
+    // Statements
+    Block, LetStmt, IfStmt, WhileStmt, ForStmt, MatchStmt, MatchCase,
+    ReturnStmt, BreakStmt, ContinueStmt, ExprStmt,
+
+    // Expressions
+    BinaryExpr, AssignExpr, UnaryExpr, CastExpr, IndexExpr, FieldExpr,
+    CallExpr, ArgList, Literal, NameExpr, StructInit, StructInitField,
+    ClosureExpr, ArrayExpr, TupleExpr, GroupingExpr, RangeExpr, IfExpr, MatchExpr,
+    TryExpr,
+
+    Error,
+}
+
+/// A node of the concrete syntax tree.
+/// Unlike the AST it keeps every token, and every token keeps its trivia,
+/// so the text of the tree is exactly the source it was parsed from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+/// A position in the builder that a node can later be started at
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize);
+
+/// Builds a `SyntaxNode` bottom-up while the parser runs.
+/// The parser hands over every token it consumes; once a production is done,
+/// everything consumed since its checkpoint is wrapped into a node.
+#[derive(Debug, Default)]
+pub struct CstBuilder {
+    children: Vec<SyntaxElement>,
+}
+
+impl SyntaxKind {
+    pub fn of_statement(stmt: &Statement) -> SyntaxKind {
+        match stmt {
+            Statement::Let { .. } => SyntaxKind::LetStmt,
+            Statement::Expr(_) | Statement::Tail(_) => SyntaxKind::ExprStmt,
+            Statement::Return(..) => SyntaxKind::ReturnStmt,
+            Statement::Break(_) => SyntaxKind::BreakStmt,
+            Statement::Continue(_) => SyntaxKind::ContinueStmt,
+            Statement::Block(..) => SyntaxKind::Block,
+            Statement::If { .. } => SyntaxKind::IfStmt,
+            Statement::While { .. } => SyntaxKind::WhileStmt,
+            Statement::For { .. } => SyntaxKind::ForStmt,
+            Statement::Match { .. } => SyntaxKind::MatchStmt,
+        }
+    }
+
+    pub fn of_expr(expr: &Expr) -> SyntaxKind {
+        match expr {
+            Expr::Binary { .. } => SyntaxKind::BinaryExpr,
+            Expr::Unary { .. } => SyntaxKind::UnaryExpr,
+            Expr::Literal(_) | Expr::TokenInterpolation(..) => SyntaxKind::Literal,
+            Expr::Identifier(..) => SyntaxKind::NameExpr,
+            Expr::Call { .. } => SyntaxKind::CallExpr,
+            Expr::Index { .. } => SyntaxKind::IndexExpr,
+            Expr::Field { .. } => SyntaxKind::FieldExpr,
+            Expr::Assignment { .. } => SyntaxKind::AssignExpr,
+            Expr::StructInit { .. } => SyntaxKind::StructInit,
+            Expr::Array { .. } => SyntaxKind::ArrayExpr,
+            Expr::Tuple { .. } => SyntaxKind::TupleExpr,
+            Expr::Cast { .. } => SyntaxKind::CastExpr,
+            Expr::Closure { .. } => SyntaxKind::ClosureExpr,
+            Expr::Grouping(..) => SyntaxKind::GroupingExpr,
+            Expr::Range { .. } => SyntaxKind::RangeExpr,
+            Expr::If { .. } => SyntaxKind::IfExpr,
+            Expr::Match { .. } => SyntaxKind::MatchExpr,
+            Expr::Try { .. } => SyntaxKind::TryExpr,
+            Expr::Error => SyntaxKind::Error,
+        }
+    }
+}
+
+impl SyntaxNode {
+    /// The exact source text covered by this node, trivia included.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for token in self.tokens() {
+            for trivia in &token.leading_trivia {
+                text.push_str(&trivia.text);
+            }
+            text.push_str(&token.lexeme);
+        }
+        text
+    }
+
+    /// All tokens of this node, in source order.
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = vec![];
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => tokens.extend(node.tokens()),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+
+    pub fn span(&self) -> Span {
+        let tokens = self.tokens();
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => Span::new(first.span.start, last.span.end),
+            _ => Span::default(),
+        }
+    }
+}
+
+impl CstBuilder {
+    pub fn new() -> Self {
+        CstBuilder { children: vec![] }
+    }
+
+    pub fn token(&mut self, token: Token) {
+        self.children.push(SyntaxElement::Token(token));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// A checkpoint just before the last element added, i.e. a token that was just matched.
+    pub fn checkpoint_before_last(&self) -> Checkpoint {
+        Checkpoint(self.children.len().saturating_sub(1))
+    }
+
+    /// Wraps everything added since `checkpoint` into a node of the given kind.
+    pub fn wrap(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        let start = checkpoint.0.min(self.children.len());
+        let children = self.children.split_off(start);
+        self.children.push(SyntaxElement::Node(SyntaxNode { kind, children }));
+    }
+
+    /// Wraps everything into the root `Module` node.
+    pub fn finish(self) -> SyntaxNode {
+        SyntaxNode {
+            kind: SyntaxKind::Module,
+            children: self.children,
+        }
+    }
+}
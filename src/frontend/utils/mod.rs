@@ -1,4 +1,6 @@
 pub mod token;
 pub mod string_interpolation;
 pub mod ast;
-pub mod visitor;
\ No newline at end of file
+pub mod visitor;
+pub mod ast_json;
+pub mod cst;
\ No newline at end of file
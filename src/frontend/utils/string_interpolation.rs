@@ -92,6 +92,34 @@ impl Display for InterpolatedString {
     }
 }
 
+/// Resolves the backslash escapes in the body of a string literal.
+/// Unknown escapes are kept verbatim.
+pub fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('0') => out.push('\0'),
+            Some('\\') => out.push('\\'),
+            Some('"') => out.push('"'),
+            Some('\'') => out.push('\''),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
 /// Parses a list of tokens and returns a HashMap mapping string token spans to their InterpolatedString info.
 pub fn extract_interpolated_strings(tokens: &[Token]) -> HashMap<Span, InterpolatedString> {
     let mut map = HashMap::new();
@@ -111,12 +139,7 @@ mod tests {
 
     #[test]
     fn test_parse_interpolated_strings() {
-        let token = Token {
-            kind: TokenKind::String,
-            lexeme: "Hello, \\(name)!".to_string(),
-            line: 1,
-            span: Span::new(0, 15),
-        };
+        let token = Token::new(TokenKind::String, "Hello, \\(name)!".to_string(), 1, Span::new(0, 15));
         let tokens = vec![token.clone()];
         let map = extract_interpolated_strings(&tokens);
         assert!(map.contains_key(&token.span));
@@ -143,6 +166,7 @@ mod tests {
         assert_eq!(tokenised_offset[0][0].kind, TokenKind::Identifier);
         assert_eq!(tokenised_offset[0][0].lexeme, "name");
         assert_eq!(tokenised_offset[0][0].line, 1);
-        assert_eq!(tokenised_offset[0][0].span, Span::new(10, 14));
+        // Spans are byte offsets, so `name` covers bytes 9..13 of the string
+        assert_eq!(tokenised_offset[0][0].span, Span::new(9, 13));
     }
 }
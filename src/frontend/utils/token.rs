@@ -1,12 +1,36 @@
+use std::fmt;
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub kind: TokenKind,
     pub lexeme: String,
     pub line: usize,
     pub span: Span,
+    /// Whitespace and comments between the previous token and this one
+    pub leading_trivia: Vec<Trivia>,
+}
+
+/// Source text that carries no meaning for the program.
+/// Kept on tokens so the source can be reproduced exactly, e.g. by the formatter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
 }
 
-#[derive(Clone, PartialEq, Eq, Debug, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TriviaKind {
+    /// Spaces, tabs and carriage returns
+    Whitespace,
+    /// A single line feed
+    Newline,
+    /// A `//` comment, without its line feed
+    Comment,
+}
+
+/// A range of byte offsets into the source
+#[derive(Clone, PartialEq, Eq, Debug, Hash, Default)]
 pub struct Span {
     pub start: usize,
     pub end: usize
@@ -16,10 +40,15 @@ impl Span {
     pub fn new(start: usize, end: usize) -> Span {
         Span { start, end }
     }
+}
 
-    pub fn default() -> Span {
-        Span { start: 0, end: 0 }
-    }
+/// Converts a byte offset into a 1-based line and a 0-based byte column.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = &source.as_bytes()[..offset];
+    let line = before.iter().filter(|&&b| b == b'\n').count() + 1;
+    let line_start = before.iter().rposition(|&b| b == b'\n').map(|i| i + 1).unwrap_or(0);
+    (line, offset - line_start)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -30,13 +59,13 @@ pub enum TokenKind {
     Lbrace, Rbrace, Lbracket, Rbracket, GtEq, LtEq, EqEq, BangEq,   // { } [ ] >= <= == !=
     AmpAmp, PipePipe, PlusEq, MinusEq, StarEq, SlashEq, ModEq,      // && || += -= *= /= %=
     AmpEq, PipeEq, CaretEq, Range, Arrow, Hash, Pow,                // &= |= ^= .. -> # **
-    QuestionQuestion, Underscore,                                   // ?? _
+    QuestionQuestion, Underscore, RangeEq, At,                      // ?? _ ..= @
 
     // Keywords
     If, Else, While, For, In, Break, Continue, Return, Func,        // if else while for in break continue return func
     Struct, Enum, Import, As, Match, Case, Trait, Extend,           // struct enum import as match case trait extend
     Pub, Priv, Protected, Type, True, False, Null,                  // pub priv protected type true false null
-    Module, Extern, Let,                                            // module extern let
+    Module, Extern, Let, Where, Const, Dyn,                         // module extern let where const dyn
 
     // Literals
     Integer, Float, String, Char, Identifier,                       // integer float string char identifier
@@ -54,18 +83,23 @@ impl Token {
             kind,
             lexeme,
             line,
-            span
+            span,
+            leading_trivia: vec![],
         }
     }
 
-    pub fn to_string(&self) -> String {
-        format!("Token {{ kind: {:?}, lexeme: {}, line: {}, span: {:?} }}", self.kind, self.lexeme, self.line, self.span)
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Token {{ kind: {:?}, lexeme: {}, line: {}, span: {:?} }}", self.kind, self.lexeme, self.line, self.span)
     }
 }
 
 impl TokenKind {
     pub fn assignment_operators() -> Vec<Self> {
         vec![
+            TokenKind::Eq,
             TokenKind::PlusEq,
             TokenKind::MinusEq,
             TokenKind::StarEq,
@@ -19,6 +19,9 @@ pub trait Visitor {
     fn visit_trait(&mut self, trait_: &Trait) -> Result<(), String> {
         walk_trait(self, trait_)
     }
+    fn visit_type_alias(&mut self, alias: &TypeAlias) -> Result<(), String> {
+        self.visit_type(&alias.ty)
+    }
     fn visit_import(&mut self, _import: &Import) -> Result<(), String> {
         Ok(())
     }
@@ -49,7 +52,10 @@ pub fn walk_function<V: Visitor + ?Sized>(v: &mut V, function: &Function) -> Res
         v.visit_type(&param.ty)?;
     }
     v.visit_type(&function.return_type)?;
-    v.visit_statement(&function.body)
+    match &function.body {
+        Some(body) => v.visit_statement(body),
+        None => Ok(()),
+    }
 }
 
 pub fn walk_struct<V: Visitor + ?Sized>(v: &mut V, structure: &Struct) -> Result<(), String> {
@@ -70,6 +76,7 @@ pub fn walk_enum<V: Visitor + ?Sized>(v: &mut V, enumeration: &Enum) -> Result<(
 }
 
 pub fn walk_extend<V: Visitor + ?Sized>(v: &mut V, extend: &Extend) -> Result<(), String> {
+    walk_associated(v, &extend.types, &extend.constants)?;
     for method in &extend.methods {
         v.visit_function(method)?;
     }
@@ -77,12 +84,26 @@ pub fn walk_extend<V: Visitor + ?Sized>(v: &mut V, extend: &Extend) -> Result<()
 }
 
 pub fn walk_trait<V: Visitor + ?Sized>(v: &mut V, trait_: &Trait) -> Result<(), String> {
+    walk_associated(v, &trait_.types, &trait_.constants)?;
     for method in &trait_.methods {
         v.visit_function(method)?;
     }
     Ok(())
 }
 
+pub fn walk_associated<V: Visitor + ?Sized>(v: &mut V, types: &[AssociatedType], constants: &[Constant]) -> Result<(), String> {
+    for ty in types.iter().filter_map(|associated| associated.ty.as_ref()) {
+        v.visit_type(ty)?;
+    }
+    for constant in constants {
+        v.visit_type(&constant.ty)?;
+        if let Some(value) = &constant.value {
+            v.visit_expression(value)?;
+        }
+    }
+    Ok(())
+}
+
 pub fn walk_statement_kind<V: Visitor + ?Sized>(v: &mut V, statement: &StatementKind) -> Result<(), String> {
     match statement {
         StatementKind::Function(f) => v.visit_function(f),
@@ -90,6 +111,7 @@ pub fn walk_statement_kind<V: Visitor + ?Sized>(v: &mut V, statement: &Statement
         StatementKind::Enum(e) => v.visit_enum(e),
         StatementKind::Extend(ext) => v.visit_extend(ext),
         StatementKind::Trait(t) => v.visit_trait(t),
+        StatementKind::TypeAlias(a) => v.visit_type_alias(a),
         StatementKind::Import(i) => v.visit_import(i),
         StatementKind::Statement(stmt) => v.visit_statement(stmt),
     }
@@ -97,8 +119,8 @@ pub fn walk_statement_kind<V: Visitor + ?Sized>(v: &mut V, statement: &Statement
 
 pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, statement: &Statement) -> Result<(), String> {
     match statement {
-        Statement::Expr(expr) => v.visit_expression(expr),
-        Statement::Let { name: _, ty, value, .. } => {
+        Statement::Expr(expr) | Statement::Tail(expr) => v.visit_expression(expr),
+        Statement::Let { pattern: _, ty, value, .. } => {
             if let Some(val) = value {
                 v.visit_expression(val)?;
             }
@@ -133,10 +155,7 @@ pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, statement: &Statement) ->
         }
         Statement::Match { expr, cases, .. } => {
             v.visit_expression(expr)?;
-            for case in cases {
-                v.visit_statement(&case.body)?;
-            }
-            Ok(())
+            walk_cases(v, cases)
         }
         Statement::Return(val, _) => {
             if let Some(val) = val {
@@ -148,6 +167,16 @@ pub fn walk_statement<V: Visitor + ?Sized>(v: &mut V, statement: &Statement) ->
     }
 }
 
+pub fn walk_cases<V: Visitor + ?Sized>(v: &mut V, cases: &[Case]) -> Result<(), String> {
+    for case in cases {
+        if let Some(guard) = &case.guard {
+            v.visit_expression(guard)?;
+        }
+        v.visit_statement(&case.body)?;
+    }
+    Ok(())
+}
+
 pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, expr: &Expr) -> Result<(), String> {
     match expr {
         Expr::Unary { expr: e, .. } => v.visit_expression(e),
@@ -176,7 +205,13 @@ pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, expr: &Expr) -> Result<(), Stri
             v.visit_type(ty)?;
             Ok(())
         }
-        Expr::Grouping(e, _) => v.visit_expression(e),
+        Expr::Grouping(e, _) | Expr::Try { expr: e, .. } => v.visit_expression(e),
+        Expr::Range { start, end, .. } => {
+            for bound in start.iter().chain(end) {
+                v.visit_expression(bound)?;
+            }
+            Ok(())
+        }
         Expr::Array { elements, .. } => {
             for el in elements {
                 v.visit_expression(el)?;
@@ -202,6 +237,18 @@ pub fn walk_expr<V: Visitor + ?Sized>(v: &mut V, expr: &Expr) -> Result<(), Stri
             }
             v.visit_statement(body)
         }
+        Expr::If { cond, then_branch, else_branch, .. } => {
+            v.visit_expression(cond)?;
+            v.visit_statement(then_branch)?;
+            if let Some(else_b) = else_branch {
+                v.visit_statement(else_b)?;
+            }
+            Ok(())
+        }
+        Expr::Match { expr, cases, .. } => {
+            v.visit_expression(expr)?;
+            walk_cases(v, cases)
+        }
         Expr::TokenInterpolation(_, _) => Ok(()),
         Expr::Identifier(_, _) | Expr::Literal(_) | Expr::Error => Ok(()),
     }
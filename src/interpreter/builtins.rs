@@ -0,0 +1,115 @@
+use std::io::{BufRead, Write};
+
+use super::value::Value;
+use super::Interpreter;
+
+/// Looks up a member of one of the built-in modules (`io`, `math`).
+pub fn lookup(module: &str, member: &str) -> Option<Value> {
+    let value = match (module, member) {
+        ("io", "print") => Value::Native("io.print", io_print),
+        ("io", "println") => Value::Native("io.println", io_println),
+        ("io", "input") => Value::Native("io.input", io_input),
+
+        ("math", "sqrt") => Value::Native("math.sqrt", math_sqrt),
+        ("math", "pow") => Value::Native("math.pow", math_pow),
+        ("math", "abs") => Value::Native("math.abs", math_abs),
+        ("math", "floor") => Value::Native("math.floor", math_floor),
+        ("math", "ceil") => Value::Native("math.ceil", math_ceil),
+        ("math", "min") => Value::Native("math.min", math_min),
+        ("math", "max") => Value::Native("math.max", math_max),
+        ("math", "pi") => Value::Float(std::f64::consts::PI),
+        ("math", "e") => Value::Float(std::f64::consts::E),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Returns true if `path` names a module provided by the runtime.
+pub fn is_builtin_module(path: &str) -> bool {
+    matches!(path, "io" | "math")
+}
+
+fn expect_args(name: &str, args: &[Value], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!("{} expects {} argument(s), got {}", name, count, args.len()));
+    }
+    Ok(())
+}
+
+fn number(name: &str, value: &Value) -> Result<f64, String> {
+    match value {
+        Value::Int(n) => Ok(*n as f64),
+        Value::Float(n) => Ok(*n),
+        other => Err(format!("{} expects a number, got {}", name, other.type_name())),
+    }
+}
+
+fn write_out(interp: &mut Interpreter, text: &str) -> Result<(), String> {
+    interp.out.write_all(text.as_bytes()).map_err(|e| e.to_string())?;
+    interp.out.flush().map_err(|e| e.to_string())
+}
+
+fn io_print(interp: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    let text: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    write_out(interp, &text.join(" "))?;
+    Ok(Value::Void)
+}
+
+fn io_println(interp: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    let text: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    write_out(interp, &format!("{}\n", text.join(" ")))?;
+    Ok(Value::Void)
+}
+
+fn io_input(interp: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    if let Some(prompt) = args.first() {
+        write_out(interp, &prompt.to_string())?;
+    }
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line).map_err(|e| e.to_string())?;
+    Ok(Value::Str(line.trim_end_matches(['\n', '\r']).to_string()))
+}
+
+fn math_sqrt(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.sqrt", args, 1)?;
+    Ok(Value::Float(number("math.sqrt", &args[0])?.sqrt()))
+}
+
+fn math_pow(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.pow", args, 2)?;
+    Ok(Value::Float(number("math.pow", &args[0])?.powf(number("math.pow", &args[1])?)))
+}
+
+fn math_abs(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.abs", args, 1)?;
+    match &args[0] {
+        Value::Int(n) => Ok(Value::Int(n.abs())),
+        other => Ok(Value::Float(number("math.abs", other)?.abs())),
+    }
+}
+
+fn math_floor(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.floor", args, 1)?;
+    Ok(Value::Float(number("math.floor", &args[0])?.floor()))
+}
+
+fn math_ceil(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.ceil", args, 1)?;
+    Ok(Value::Float(number("math.ceil", &args[0])?.ceil()))
+}
+
+fn math_min(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.min", args, 2)?;
+    match (&args[0], &args[1]) {
+        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(*a.min(b))),
+        (a, b) => Ok(Value::Float(number("math.min", a)?.min(number("math.min", b)?))),
+    }
+}
+
+fn math_max(_: &mut Interpreter, args: &[Value]) -> Result<Value, String> {
+    expect_args("math.max", args, 2)?;
+    match (&args[0], &args[1]) {
+        (Value::Int(a), Value::Int(b)) => Ok(Value::Int(*a.max(b))),
+        (a, b) => Ok(Value::Float(number("math.max", a)?.max(number("math.max", b)?))),
+    }
+}
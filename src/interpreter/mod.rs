@@ -0,0 +1,1070 @@
+pub mod builtins;
+pub mod environment;
+pub mod value;
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::rc::Rc;
+
+use crate::frontend::utils::{
+    ast::*,
+    string_interpolation::unescape,
+    token::Span,
+};
+
+use environment::Environment;
+use value::{Callable, Value};
+
+/// An error raised while evaluating a program.
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl RuntimeError {
+    pub fn new(message: String, span: Span) -> Self {
+        RuntimeError { message, span }
+    }
+}
+
+/// How many calls may be in progress at once, so that runaway recursion ends in
+/// a runtime error rather than overflowing the native stack
+const MAX_CALL_DEPTH: usize = 2_000;
+
+/// The native stack size programs need to reach `MAX_CALL_DEPTH`; the interpreter
+/// recurses through several frames for each call, more so in debug builds
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// How control leaves a statement.
+enum Flow {
+    Normal,
+    Return(Value),
+    Break,
+    Continue,
+}
+
+/// What a `for` loop takes its values from.
+enum Iteration {
+    Values(std::vec::IntoIter<Value>),
+    /// The integers from `next` up to, but not including, `end`; without an end it never stops.
+    Range { next: i64, end: Option<i64> },
+    /// A value whose type implements `Iterator`, stepped with the `next` of that
+    /// implementation until it returns `null`.
+    Iterator { iterator: Value, next: Rc<Callable> },
+}
+
+/// The methods a type implements a trait with, through which calls on a value
+/// of that type dispatch, whether or not it is used as a `dyn` trait object.
+struct VTable {
+    trait_name: String,
+    methods: HashMap<String, Rc<Callable>>,
+}
+
+/// A tree-walking interpreter over the parsed syntax tree.
+pub struct Interpreter {
+    pub globals: Rc<RefCell<Environment>>,
+    env: Rc<RefCell<Environment>>,
+    /// Methods declared in plain `extend` blocks, keyed by type name then method name
+    methods: HashMap<String, HashMap<String, Rc<Callable>>>,
+    /// Default method bodies of traits, keyed by trait name then method name
+    defaults: HashMap<String, HashMap<String, Rc<Callable>>>,
+    /// Associated constants of traits and implementing types, keyed by their name then constant name
+    constants: HashMap<String, HashMap<String, Value>>,
+    /// The trait implementations of each type, in declaration order
+    vtables: HashMap<String, Vec<VTable>>,
+    /// Declared field order of each struct, used to normalise struct literals
+    structs: HashMap<String, Vec<String>>,
+    enums: HashMap<String, Vec<EnumVariant>>,
+    /// What each type alias stands for, which casts see through
+    aliases: HashMap<String, Type>,
+    /// The newtypes, whose values `as` wraps and unwraps
+    newtypes: HashSet<String>,
+    /// Set when a branch of an `if` or `match` expression leaves through `return`,
+    /// `break` or `continue`, until the statement holding the expression takes it up
+    escaping: Option<Flow>,
+    /// The declared return types of the functions being called, innermost last
+    return_types: Vec<Type>,
+
+    pub out: Box<dyn Write>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_output(Box::new(std::io::stdout()))
+    }
+
+    /// Creates an interpreter whose `io` output is written to `out`.
+    pub fn with_output(out: Box<dyn Write>) -> Self {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        Interpreter {
+            env: globals.clone(),
+            globals,
+            methods: HashMap::new(),
+            defaults: HashMap::new(),
+            constants: HashMap::new(),
+            vtables: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            aliases: HashMap::new(),
+            newtypes: HashSet::new(),
+            escaping: None,
+            return_types: vec![],
+            out,
+        }
+    }
+
+    /// Declares every item of `module` and then runs its top-level statements in order.
+    pub fn load_module(&mut self, module: &Module) -> Result<(), RuntimeError> {
+        for stmt in &module.stmts {
+            self.declare(stmt)?;
+        }
+        for stmt in &module.stmts {
+            if let StatementKind::Statement(stmt) = stmt {
+                self.execute(stmt)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calls the module's `main` function, returning its result.
+    pub fn run_main(&mut self) -> Result<Value, RuntimeError> {
+        let main = self.globals.borrow().get("main");
+        match main {
+            Some(main) => self.call_value(main, vec![], &Span::default()),
+            None => Err(RuntimeError::new("No 'main' function found".to_string(), Span::default())),
+        }
+    }
+
+    /// Declares or runs a single top-level item, as the REPL does for each input.
+    /// Returns the value of an expression statement.
+    pub fn run_item(&mut self, item: &StatementKind) -> Result<Option<Value>, RuntimeError> {
+        self.declare(item)?;
+        match item {
+            StatementKind::Statement(Statement::Expr(expr)) => self.evaluate(expr).map(Some),
+            StatementKind::Statement(stmt) => self.execute(stmt).map(|_| None),
+            _ => Ok(None),
+        }
+    }
+
+    fn declare(&mut self, stmt: &StatementKind) -> Result<(), RuntimeError> {
+        match stmt {
+            StatementKind::Function(function) => {
+                let callable = self.callable(function);
+                self.globals.borrow_mut().define(&function.name.lexeme, Value::Function(callable));
+            }
+            StatementKind::Struct(structure) => {
+                let fields = structure.fields.iter().map(|f| f.name.lexeme.clone()).collect();
+                self.structs.insert(structure.name.lexeme.clone(), fields);
+                self.globals.borrow_mut().define(&structure.name.lexeme, Value::Type(structure.name.lexeme.clone()));
+            }
+            StatementKind::Enum(enumeration) => {
+                self.enums.insert(enumeration.name.lexeme.clone(), enumeration.variants.clone());
+                self.globals.borrow_mut().define(&enumeration.name.lexeme, Value::Type(enumeration.name.lexeme.clone()));
+            }
+            StatementKind::TypeAlias(alias) if alias.newtype => {
+                self.newtypes.insert(alias.name.lexeme.clone());
+                self.globals.borrow_mut().define(&alias.name.lexeme, Value::Type(alias.name.lexeme.clone()));
+            }
+            StatementKind::TypeAlias(alias) => {
+                self.aliases.insert(alias.name.lexeme.clone(), alias.ty.clone());
+            }
+            StatementKind::Extend(extend) => {
+                self.declare_constants(&extend.name.lexeme, &extend.constants)?;
+                let methods = extend.methods.iter()
+                    .map(|method| (method.name.lexeme.clone(), self.callable(method)))
+                    .collect::<HashMap<_, _>>();
+                match &extend.trait_name {
+                    Some(trait_name) => self.vtables.entry(extend.name.lexeme.clone()).or_default().push(VTable {
+                        trait_name: trait_name.lexeme.clone(),
+                        methods,
+                    }),
+                    None => self.methods.entry(extend.name.lexeme.clone()).or_default().extend(methods),
+                }
+            }
+            StatementKind::Import(import) => {
+                let path = import.path.lexeme.trim_matches('"').to_string();
+                if !builtins::is_builtin_module(&path) {
+                    return Err(RuntimeError::new(format!("Unknown module '{}'", path), import.path.span.clone()));
+                }
+                self.globals.borrow_mut().define(&import.alias.lexeme, Value::Module(path));
+            }
+            StatementKind::Trait(trait_) => {
+                self.declare_constants(&trait_.name.lexeme, &trait_.constants)?;
+                for method in trait_.methods.iter().filter(|method| method.body.is_some()) {
+                    let callable = self.callable(method);
+                    self.defaults
+                        .entry(trait_.name.lexeme.clone())
+                        .or_default()
+                        .insert(method.name.lexeme.clone(), callable);
+                }
+            }
+            StatementKind::Statement(_) => {}
+        }
+        Ok(())
+    }
+
+    fn declare_constants(&mut self, owner: &str, constants: &[Constant]) -> Result<(), RuntimeError> {
+        for constant in constants {
+            if let Some(value) = &constant.value {
+                let value = self.evaluate(value)?;
+                self.constants.entry(owner.to_string()).or_default().insert(constant.name.lexeme.clone(), value);
+            }
+        }
+        Ok(())
+    }
+
+    fn callable(&self, function: &Function) -> Rc<Callable> {
+        Rc::new(Callable {
+            name: function.name.lexeme.clone(),
+            params: function.params.clone(),
+            return_type: function.return_type.clone(),
+            body: function.body.clone(),
+            closure: self.globals.clone(),
+        })
+    }
+
+    /// A method of `type_name` itself or, failing that, of one of its trait implementations,
+    /// falling back to the default bodies of the traits.
+    fn method(&self, type_name: &str, member: &str) -> Option<Rc<Callable>> {
+        if let Some(method) = self.methods.get(type_name).and_then(|methods| methods.get(member)) {
+            return Some(method.clone());
+        }
+        let vtables = self.vtables.get(type_name).map(Vec::as_slice).unwrap_or_default();
+        vtables.iter()
+            .find_map(|vtable| vtable.methods.get(member))
+            .or_else(|| vtables.iter().find_map(|vtable| self.defaults.get(&vtable.trait_name)?.get(member)))
+            .cloned()
+    }
+
+    /// The method `member` of the implementation of `trait_name` for `type_name`,
+    /// or the trait's default body.
+    fn trait_method(&self, type_name: &str, trait_name: &str, member: &str) -> Option<Rc<Callable>> {
+        let vtable = self.vtables.get(type_name)?.iter().find(|vtable| vtable.trait_name == trait_name)?;
+        vtable.methods.get(member)
+            .or_else(|| self.defaults.get(trait_name)?.get(member))
+            .cloned()
+    }
+
+    /// An associated constant of `type_name` or, failing that, a default from one of its traits.
+    fn constant(&self, type_name: &str, member: &str) -> Option<Value> {
+        let traits = self.vtables.get(type_name).into_iter().flatten().map(|vtable| vtable.trait_name.as_str());
+        std::iter::once(type_name).chain(traits)
+            .find_map(|owner| self.constants.get(owner).and_then(|constants| constants.get(member)))
+            .cloned()
+    }
+
+    fn execute(&mut self, stmt: &Statement) -> Result<Flow, RuntimeError> {
+        match (self.execute_statement(stmt), self.escaping.take()) {
+            (Err(_), Some(flow)) => Ok(flow),
+            (result, _) => result,
+        }
+    }
+
+    fn execute_statement(&mut self, stmt: &Statement) -> Result<Flow, RuntimeError> {
+        match stmt {
+            Statement::Let { pattern, value, .. } => {
+                let bindings = match value {
+                    Some(value) => {
+                        let value = self.evaluate(value)?;
+                        self.match_pattern(pattern, &value).ok_or_else(|| {
+                            RuntimeError::new(format!("The value {} doesn't match the pattern", value), pattern.span())
+                        })?
+                    }
+                    None => pattern.bindings().into_iter().map(|name| (name.lexeme.clone(), Value::Null)).collect(),
+                };
+                for (name, value) in bindings {
+                    self.env.borrow_mut().define(&name, value);
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Expr(expr) | Statement::Tail(expr) => {
+                self.evaluate(expr)?;
+                Ok(Flow::Normal)
+            }
+            Statement::Return(value, _) => {
+                let value = match value {
+                    Some(value) => self.evaluate(value)?,
+                    None => Value::Void,
+                };
+                Ok(Flow::Return(value))
+            }
+            Statement::Break(_) => Ok(Flow::Break),
+            Statement::Continue(_) => Ok(Flow::Continue),
+            Statement::Block(stmts, _) => {
+                let scope = Environment::with_parent(self.env.clone());
+                self.execute_block(stmts, scope)
+            }
+            Statement::If { cond, then_branch, else_branch, .. } => {
+                if self.evaluate(cond)?.is_truthy() {
+                    self.execute(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)
+                } else {
+                    Ok(Flow::Normal)
+                }
+            }
+            Statement::While { cond, body, .. } => {
+                while self.evaluate(cond)?.is_truthy() {
+                    match self.execute(body)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::For { var, iter, body, .. } => {
+                let iterable = self.evaluate(iter)?;
+                let mut iteration = self.iteration(iterable, &iter.span())?;
+                while let Some(item) = self.next_item(&mut iteration, &iter.span())? {
+                    let mut scope = Environment::with_parent(self.env.clone());
+                    scope.define(&var.lexeme, item);
+                    match self.execute_block(std::slice::from_ref(body), scope)? {
+                        Flow::Break => break,
+                        Flow::Return(value) => return Ok(Flow::Return(value)),
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+                Ok(Flow::Normal)
+            }
+            Statement::Match { expr, cases, .. } => match self.select_case(expr, cases)? {
+                Some((case, scope)) => self.execute_block(std::slice::from_ref(&case.body), scope),
+                None => Ok(Flow::Normal),
+            },
+        }
+    }
+
+    /// The first case matching the value of `expr`, with a scope holding the names it binds.
+    fn select_case<'c>(&mut self, expr: &Expr, cases: &'c [Case]) -> Result<Option<(&'c Case, Environment)>, RuntimeError> {
+        let value = self.evaluate(expr)?;
+        for case in cases {
+            let Some(bindings) = self.match_pattern(&case.pattern, &value) else { continue };
+            let scope = |parent: &Rc<RefCell<Environment>>| {
+                let mut scope = Environment::with_parent(parent.clone());
+                for (name, value) in &bindings {
+                    scope.define(name, value.clone());
+                }
+                scope
+            };
+            if let Some(guard) = &case.guard {
+                let passed = self.in_scope(scope(&self.env), |this| this.evaluate(guard))?;
+                if !passed.is_truthy() {
+                    continue;
+                }
+            }
+            return Ok(Some((case, scope(&self.env))));
+        }
+        Ok(None)
+    }
+
+    fn execute_block(&mut self, stmts: &[Statement], scope: Environment) -> Result<Flow, RuntimeError> {
+        self.in_scope(scope, |this| {
+            for stmt in stmts {
+                match this.execute(stmt)? {
+                    Flow::Normal => {}
+                    flow => return Ok(flow),
+                }
+            }
+            Ok(Flow::Normal)
+        })
+    }
+
+    /// Runs `f` with `scope` as the current environment.
+    fn in_scope<T>(&mut self, scope: Environment, f: impl FnOnce(&mut Self) -> Result<T, RuntimeError>) -> Result<T, RuntimeError> {
+        let previous = std::mem::replace(&mut self.env, Rc::new(RefCell::new(scope)));
+        let result = f(self);
+        self.env = previous;
+        result
+    }
+
+    /// The value a branch of an `if` or `match` expression leaves: that of the
+    /// expression ending a block, or 'void' if there is none.
+    fn branch_value(&mut self, stmt: &Statement) -> Result<Value, RuntimeError> {
+        match stmt {
+            Statement::Tail(expr) => self.evaluate(expr),
+            Statement::Block(stmts, _) => {
+                let Some((last, rest)) = stmts.split_last() else { return Ok(Value::Void) };
+                let scope = Environment::with_parent(self.env.clone());
+                self.in_scope(scope, |this| {
+                    for stmt in rest {
+                        this.branch_flow(stmt)?;
+                    }
+                    this.branch_value(last)
+                })
+            }
+            Statement::If { cond, then_branch, else_branch: Some(else_branch), .. } => {
+                if self.evaluate(cond)?.is_truthy() {
+                    self.branch_value(then_branch)
+                } else {
+                    self.branch_value(else_branch)
+                }
+            }
+            Statement::Match { expr, cases, span } => match self.select_case(expr, cases)? {
+                Some((case, scope)) => self.in_scope(scope, |this| this.branch_value(&case.body)),
+                None => Err(RuntimeError::new("No match case matches the value".to_string(), span.clone())),
+            },
+            _ => {
+                self.branch_flow(stmt)?;
+                Ok(Value::Void)
+            }
+        }
+    }
+
+    /// Runs a statement of a branch, leaving the expression the branch belongs to
+    /// if the statement returns, breaks or continues.
+    fn branch_flow(&mut self, stmt: &Statement) -> Result<(), RuntimeError> {
+        match self.execute(stmt)? {
+            Flow::Normal => Ok(()),
+            flow => {
+                self.escaping = Some(flow);
+                Err(RuntimeError::new("Cannot return, break or continue out of this expression".to_string(), stmt.span()))
+            }
+        }
+    }
+
+    fn iteration(&mut self, value: Value, span: &Span) -> Result<Iteration, RuntimeError> {
+        match value {
+            Value::Array(elements) => Ok(Iteration::Values(elements.borrow().clone().into_iter())),
+            Value::Str(s) => Ok(Iteration::Values(s.chars().map(|c| Value::Str(c.to_string())).collect::<Vec<_>>().into_iter())),
+            Value::Range { start: Some(start), end, inclusive } => Ok(Iteration::Range {
+                next: start,
+                end: end.map(|end| if inclusive { end.saturating_add(1) } else { end }),
+            }),
+            Value::Range { start: None, .. } => Err(RuntimeError::new("Cannot iterate over a range without a start".to_string(), span.clone())),
+            other => {
+                let type_name = other.type_name();
+                if let Some(next) = self.trait_method(&type_name, "Iterator", "next") {
+                    return Ok(Iteration::Iterator { iterator: other, next });
+                }
+                if let Some(iter) = self.trait_method(&type_name, "Iterable", "iter") {
+                    let iterator = self.call_method(other, iter, span)?;
+                    return self.iteration(iterator, span);
+                }
+                Err(RuntimeError::new(format!("Cannot iterate over a value of type '{}'", type_name), span.clone()))
+            }
+        }
+    }
+
+    fn next_item(&mut self, iteration: &mut Iteration, span: &Span) -> Result<Option<Value>, RuntimeError> {
+        match iteration {
+            Iteration::Values(values) => Ok(values.next()),
+            Iteration::Range { next, end } => {
+                if end.is_some_and(|end| *next >= end) {
+                    return Ok(None);
+                }
+                let value = *next;
+                *next += 1;
+                Ok(Some(Value::Int(value)))
+            }
+            Iteration::Iterator { iterator, next } => {
+                match self.call_method(iterator.clone(), next.clone(), span)? {
+                    Value::Null => Ok(None),
+                    item => Ok(Some(item)),
+                }
+            }
+        }
+    }
+
+    /// Calls `method` on `receiver` without arguments.
+    fn call_method(&mut self, receiver: Value, method: Rc<Callable>, span: &Span) -> Result<Value, RuntimeError> {
+        self.call_value(Value::BoundMethod { receiver: Box::new(receiver), function: method }, vec![], span)
+    }
+
+    fn match_pattern(&self, pattern: &Pattern, value: &Value) -> Option<Vec<(String, Value)>> {
+        match (pattern, value) {
+            (Pattern::Identifier(name, _), _) => Some(vec![(name.lexeme.clone(), value.clone())]),
+            (Pattern::Wildcard(_), _) => Some(vec![]),
+            (Pattern::Literal(lit), _) => {
+                if literal_value(lit).equals(value) { Some(vec![]) } else { None }
+            }
+            (Pattern::Tuple(patterns, _), Value::Tuple(values)) if patterns.len() == values.len() => {
+                let mut bindings = vec![];
+                for (pattern, value) in patterns.iter().zip(values.iter()) {
+                    bindings.extend(self.match_pattern(pattern, value)?);
+                }
+                Some(bindings)
+            }
+            (Pattern::Struct { fields: patterns, .. }, Value::Struct { fields, .. }) => {
+                let fields = fields.borrow();
+                let mut bindings = vec![];
+                for (name, pattern) in patterns {
+                    let (_, value) = fields.iter().find(|(field, _)| *field == name.lexeme)?;
+                    bindings.extend(self.match_pattern(pattern, value)?);
+                }
+                Some(bindings)
+            }
+            (Pattern::Variant { name, fields: patterns, .. }, Value::EnumVariant { variant, fields, .. }) => {
+                if *variant != name.lexeme || patterns.len() != fields.len() {
+                    return None;
+                }
+                let mut bindings = vec![];
+                for (pattern, value) in patterns.iter().zip(fields) {
+                    bindings.extend(self.match_pattern(pattern, value)?);
+                }
+                Some(bindings)
+            }
+            (Pattern::Or(alternatives, _), _) => alternatives.iter().find_map(|pattern| self.match_pattern(pattern, value)),
+            (Pattern::Range { start, end, inclusive, .. }, _) => {
+                let (start, end) = (literal_value(start), literal_value(end));
+                let above = matches!(compare(value, &start), Some(Ordering::Greater | Ordering::Equal));
+                let below = match compare(value, &end) {
+                    Some(Ordering::Less) => true,
+                    Some(Ordering::Equal) => *inclusive,
+                    _ => false,
+                };
+                (above && below).then(Vec::new)
+            }
+            (Pattern::Binding { name, pattern, .. }, _) => {
+                let mut bindings = self.match_pattern(pattern, value)?;
+                bindings.insert(0, (name.lexeme.clone(), value.clone()));
+                Some(bindings)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn evaluate(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        match expr {
+            Expr::Identifier(name, span) => self.env.borrow().get(&name.lexeme).ok_or_else(|| {
+                RuntimeError::new(format!("Undefined variable '{}'", name.lexeme), span.clone())
+            }),
+            Expr::Literal(lit) => Ok(literal_value(lit)),
+            Expr::TokenInterpolation(interp, _) => {
+                let mut text = String::new();
+                for segment in &interp.segments {
+                    match segment {
+                        TokenSegment::Literal(token, _) => text.push_str(&unescape(&token.lexeme)),
+                        TokenSegment::Expr(expr, _) => text.push_str(&self.evaluate(expr)?.to_string()),
+                    }
+                }
+                Ok(Value::Str(text))
+            }
+            Expr::Binary { left, op, right, span } => {
+                match op.lexeme.as_str() {
+                    "&&" => {
+                        let left = self.evaluate(left)?;
+                        if !left.is_truthy() {
+                            return Ok(Value::Bool(false));
+                        }
+                        Ok(Value::Bool(self.evaluate(right)?.is_truthy()))
+                    }
+                    "||" => {
+                        let left = self.evaluate(left)?;
+                        if left.is_truthy() {
+                            return Ok(Value::Bool(true));
+                        }
+                        Ok(Value::Bool(self.evaluate(right)?.is_truthy()))
+                    }
+                    "??" => {
+                        let left = self.evaluate(left)?;
+                        if matches!(left, Value::Null) {
+                            self.evaluate(right)
+                        } else {
+                            Ok(left)
+                        }
+                    }
+                    op => {
+                        let left = self.evaluate(left)?;
+                        let right = self.evaluate(right)?;
+                        binary_op(op, left, right, span)
+                    }
+                }
+            }
+            Expr::Unary { op, expr, span } => {
+                let value = self.evaluate(expr)?;
+                match (op.lexeme.as_str(), value) {
+                    ("!", Value::Bool(b)) => Ok(Value::Bool(!b)),
+                    ("-", Value::Int(n)) => n.checked_neg().map(Value::Int).ok_or_else(|| {
+                        RuntimeError::new(format!("Integer overflow in -({})", n), span.clone())
+                    }),
+                    ("-", Value::Float(n)) => Ok(Value::Float(-n)),
+                    (op, value) => Err(RuntimeError::new(
+                        format!("Cannot apply '{}' to a value of type '{}'", op, value.type_name()),
+                        span.clone(),
+                    )),
+                }
+            }
+            Expr::Call { callee, args, span, .. } => {
+                let chained = matches!(**callee, Expr::Field { optional: true, .. });
+                let callee = self.evaluate(callee)?;
+                // `base?.method(...)` skips the call, arguments included, when `base` is null
+                if chained && matches!(callee, Value::Null) {
+                    return Ok(Value::Null);
+                }
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.evaluate(arg)?);
+                }
+                self.call_value(callee, values, span)
+            }
+            Expr::Field { base, field, optional, .. } => match self.evaluate(base)? {
+                Value::Null if *optional => Ok(Value::Null),
+                base => self.get_member(base, &field.lexeme, &field.span),
+            },
+            Expr::Index { base, index, span } => {
+                let base = self.evaluate(base)?;
+                let index = self.evaluate(index)?;
+                index_value(&base, &index, span)
+            }
+            Expr::Assignment { left, op, right, span } => {
+                let mut value = self.evaluate(right)?;
+                if op.lexeme != "=" {
+                    let current = self.evaluate(left)?;
+                    value = binary_op(op.lexeme.trim_end_matches('='), current, value, span)?;
+                }
+                self.assign_to(left, value.clone())?;
+                Ok(value)
+            }
+            Expr::StructInit { name, fields, span } => {
+                let mut values = Vec::with_capacity(fields.len());
+                for (field, expr) in fields {
+                    values.push((field.lexeme.clone(), self.evaluate(expr)?));
+                }
+                if let Some(order) = self.structs.get(&name.lexeme) {
+                    for (field, _) in &values {
+                        if !order.contains(field) {
+                            return Err(RuntimeError::new(
+                                format!("Struct '{}' has no field '{}'", name.lexeme, field),
+                                span.clone(),
+                            ));
+                        }
+                    }
+                    values.sort_by_key(|(field, _)| order.iter().position(|f| f == field));
+                }
+                Ok(Value::Struct { name: name.lexeme.clone(), fields: Rc::new(RefCell::new(values)) })
+            }
+            Expr::Array { elements, .. } => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.evaluate(element)?);
+                }
+                Ok(Value::Array(Rc::new(RefCell::new(values))))
+            }
+            Expr::Tuple { elements, .. } => {
+                let mut values = Vec::with_capacity(elements.len());
+                for element in elements {
+                    values.push(self.evaluate(element)?);
+                }
+                Ok(Value::Tuple(values))
+            }
+            Expr::Cast { expr, ty, span } => {
+                let value = self.evaluate(expr)?;
+                self.convert(value, ty, span)
+            }
+            Expr::Closure { params, body, ty, .. } => Ok(Value::Function(Rc::new(Callable {
+                name: "<closure>".to_string(),
+                params: params.clone(),
+                return_type: ty.clone(),
+                body: Some((**body).clone()),
+                closure: self.env.clone(),
+            }))),
+            Expr::Grouping(expr, _) => self.evaluate(expr),
+            Expr::Try { expr, span } => self.try_value(expr, span),
+            Expr::Range { start, end, inclusive, .. } => {
+                let mut bound = |bound: &Option<Box<Expr>>| -> Result<Option<i64>, RuntimeError> {
+                    let Some(bound) = bound else { return Ok(None) };
+                    match self.evaluate(bound)? {
+                        Value::Int(n) => Ok(Some(n)),
+                        other => Err(RuntimeError::new(format!("Range bounds must be 'int', found '{}'", other.type_name()), bound.span())),
+                    }
+                };
+                Ok(Value::Range { start: bound(start)?, end: bound(end)?, inclusive: *inclusive })
+            }
+            Expr::If { cond, then_branch, else_branch, .. } => {
+                if self.evaluate(cond)?.is_truthy() {
+                    self.branch_value(then_branch)
+                } else if let Some(else_branch) = else_branch {
+                    self.branch_value(else_branch)
+                } else {
+                    Ok(Value::Void)
+                }
+            }
+            Expr::Match { expr, cases, span } => match self.select_case(expr, cases)? {
+                Some((case, scope)) => self.in_scope(scope, |this| this.branch_value(&case.body)),
+                None => Err(RuntimeError::new("No match case matches the value".to_string(), span.clone())),
+            },
+            Expr::Error => Err(RuntimeError::new("Cannot evaluate an invalid expression".to_string(), Span::default())),
+        }
+    }
+
+    pub fn call_value(&mut self, callee: Value, args: Vec<Value>, span: &Span) -> Result<Value, RuntimeError> {
+        match callee {
+            Value::Function(function) => self.call_function(&function, None, args, span),
+            Value::BoundMethod { receiver, function } => self.call_function(&function, Some(*receiver), args, span),
+            Value::Native(_, native) => native(self, &args).map_err(|message| RuntimeError::new(message, span.clone())),
+            Value::VariantConstructor { enum_name, variant, arity } => {
+                if args.len() != arity {
+                    return Err(RuntimeError::new(
+                        format!("'{}.{}' expects {} argument(s), got {}", enum_name, variant, arity, args.len()),
+                        span.clone(),
+                    ));
+                }
+                Ok(Value::EnumVariant { enum_name, variant, fields: args })
+            }
+            other => Err(RuntimeError::new(format!("A value of type '{}' is not callable", other.type_name()), span.clone())),
+        }
+    }
+
+    fn call_function(
+        &mut self,
+        function: &Callable,
+        receiver: Option<Value>,
+        args: Vec<Value>,
+        span: &Span,
+    ) -> Result<Value, RuntimeError> {
+        let Some(body) = &function.body else {
+            return Err(RuntimeError::new(format!("'{}' can only be called through 'as'", function.name), span.clone()));
+        };
+        if function.params.len() != args.len() {
+            return Err(RuntimeError::new(
+                format!("'{}' expects {} argument(s), got {}", function.name, function.params.len(), args.len()),
+                span.clone(),
+            ));
+        }
+
+        let mut scope = Environment::with_parent(function.closure.clone());
+        if let Some(receiver) = receiver {
+            scope.define("self", receiver);
+        }
+        for (param, arg) in function.params.iter().zip(args) {
+            scope.define(&param.name.lexeme, arg);
+        }
+
+        if self.return_types.len() >= MAX_CALL_DEPTH {
+            return Err(RuntimeError::new("Maximum recursion depth exceeded".to_string(), span.clone()));
+        }
+        self.return_types.push(function.return_type.clone());
+        let flow = self.execute_block(std::slice::from_ref(body), scope);
+        self.return_types.pop();
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Void),
+        }
+    }
+
+    /// `ty`, or the type it stands for when it names an alias.
+    fn expand(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Named { name, generics, .. } if generics.is_empty() => match self.aliases.get(&name.lexeme) {
+                Some(target) => self.expand(target),
+                None => ty.clone(),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    /// The value of `value as ty`. Converting to a newtype wraps the value, converting
+    /// from one unwraps it, and a type implementing `From` converts with its `from`.
+    fn convert(&mut self, value: Value, ty: &Type, span: &Span) -> Result<Value, RuntimeError> {
+        let ty = self.expand(ty);
+        let target = match &ty {
+            Type::Named { name, .. } => Some(name.lexeme.clone()),
+            _ => None,
+        };
+        match (value, target) {
+            (value, Some(name)) if self.newtypes.contains(&name) && value.type_name() != name => {
+                Ok(Value::Newtype { name, value: Box::new(value) })
+            }
+            (Value::Newtype { name, value }, target) if target.as_ref() != Some(&name) => cast(*value, &ty, span),
+            (value, _) if self.has_conversion(&value, &ty) => self.from(value, &ty, span),
+            (value, _) => cast(value, &ty, span),
+        }
+    }
+
+    /// Whether `value` isn't a `ty` yet, and `ty` implements `From` to convert it.
+    fn has_conversion(&self, value: &Value, ty: &Type) -> bool {
+        matches!(ty, Type::Named { name, .. } if value.type_name() != name.lexeme && self.trait_method(&name.lexeme, "From", "from").is_some())
+    }
+
+    /// `value` converted into `ty` by the `from` of its `From` implementation.
+    fn from(&mut self, value: Value, ty: &Type, span: &Span) -> Result<Value, RuntimeError> {
+        let Type::Named { name, generics, .. } = ty else {
+            return Err(RuntimeError::new(format!("Cannot convert a value of type '{}'", value.type_name()), span.clone()));
+        };
+        let Some(from) = self.trait_method(&name.lexeme, "From", "from") else {
+            return Err(RuntimeError::new(format!("'{}' doesn't implement 'From'", name.lexeme), span.clone()));
+        };
+        if from.body.is_some() {
+            return self.call_function(&from, None, vec![value], span);
+        }
+
+        // The prelude's implementation for `Result`, which parses the text into its `Ok` type
+        let Some(target) = generics.first() else {
+            return Err(RuntimeError::new(format!("Cannot convert into '{}' without its type arguments", name.lexeme), span.clone()));
+        };
+        let (variant, field) = match cast(value.clone(), &self.expand(target), span) {
+            Ok(parsed) => ("Ok", parsed),
+            Err(_) => ("Err", Value::Str(format!("'{}' is not a valid '{}'", value, target))),
+        };
+        Ok(Value::EnumVariant { enum_name: name.lexeme.clone(), variant: variant.to_string(), fields: vec![field] })
+    }
+
+    /// The value of `expr?`: the field of an `Ok`, or else the `Err` is returned from the
+    /// current function, converted by the `From` implementation of its error type when it has one.
+    fn try_value(&mut self, expr: &Expr, span: &Span) -> Result<Value, RuntimeError> {
+        let (variant, mut fields) = match self.evaluate(expr)? {
+            Value::EnumVariant { enum_name, variant, fields } if enum_name == "Result" && fields.len() == 1 => (variant, fields),
+            other => return Err(RuntimeError::new(format!("'?' expects a 'Result', found '{}'", other.type_name()), span.clone())),
+        };
+        let value = fields.remove(0);
+        if variant == "Ok" {
+            return Ok(value);
+        }
+
+        let target = match self.return_types.last().map(|ty| self.expand(ty)) {
+            Some(Type::Named { name, generics, .. }) if name.lexeme == "Result" => generics.get(1).cloned(),
+            _ => None,
+        };
+        let error = match target {
+            Some(target) if self.has_conversion(&value, &target) => self.from(value, &target, span)?,
+            _ => value,
+        };
+        self.escaping = Some(Flow::Return(Value::EnumVariant {
+            enum_name: "Result".to_string(),
+            variant,
+            fields: vec![error],
+        }));
+        Err(RuntimeError::new("Cannot return out of this expression".to_string(), span.clone()))
+    }
+
+    fn get_member(&mut self, base: Value, member: &str, span: &Span) -> Result<Value, RuntimeError> {
+        match &base {
+            Value::Module(path) => {
+                return builtins::lookup(path, member).ok_or_else(|| {
+                    RuntimeError::new(format!("Module '{}' has no member '{}'", path, member), span.clone())
+                });
+            }
+            Value::Type(name) => {
+                if let Some(method) = self.method(name, member) {
+                    return Ok(Value::Function(method));
+                }
+                if let Some(constant) = self.constant(name, member) {
+                    return Ok(constant);
+                }
+                if let Some(variant) = self.enums.get(name).and_then(|v| v.iter().find(|v| v.name.lexeme == member)) {
+                    if variant.fields.is_empty() {
+                        return Ok(Value::EnumVariant { enum_name: name.clone(), variant: member.to_string(), fields: vec![] });
+                    }
+                    return Ok(Value::VariantConstructor {
+                        enum_name: name.clone(),
+                        variant: member.to_string(),
+                        arity: variant.fields.len(),
+                    });
+                }
+            }
+            Value::Struct { fields, .. } => {
+                if let Some((_, value)) = fields.borrow().iter().find(|(field, _)| field == member) {
+                    return Ok(value.clone());
+                }
+            }
+            _ => {}
+        }
+
+        let type_name = match &base {
+            Value::Type(name) => name.clone(),
+            other => other.type_name(),
+        };
+        if let Some(method) = self.method(&type_name, member) {
+            return Ok(Value::BoundMethod { receiver: Box::new(base), function: method });
+        }
+        Err(RuntimeError::new(format!("'{}' has no member '{}'", type_name, member), span.clone()))
+    }
+
+    fn assign_to(&mut self, target: &Expr, value: Value) -> Result<(), RuntimeError> {
+        match target {
+            Expr::Identifier(name, span) => {
+                if self.env.borrow_mut().assign(&name.lexeme, value) {
+                    Ok(())
+                } else {
+                    Err(RuntimeError::new(format!("Undefined variable '{}'", name.lexeme), span.clone()))
+                }
+            }
+            Expr::Field { base, field, span, .. } => match self.evaluate(base)? {
+                Value::Struct { name, fields } => {
+                    let mut fields = fields.borrow_mut();
+                    match fields.iter_mut().find(|(f, _)| *f == field.lexeme) {
+                        Some((_, slot)) => {
+                            *slot = value;
+                            Ok(())
+                        }
+                        None => Err(RuntimeError::new(format!("Struct '{}' has no field '{}'", name, field.lexeme), span.clone())),
+                    }
+                }
+                other => Err(RuntimeError::new(format!("Cannot assign to a field of '{}'", other.type_name()), span.clone())),
+            },
+            Expr::Index { base, index, span } => {
+                let base = self.evaluate(base)?;
+                let index = self.evaluate(index)?;
+                match (base, index) {
+                    (Value::Array(elements), Value::Int(i)) => {
+                        let mut elements = elements.borrow_mut();
+                        let len = elements.len();
+                        match elements.get_mut(i as usize).filter(|_| i >= 0) {
+                            Some(slot) => {
+                                *slot = value;
+                                Ok(())
+                            }
+                            None => Err(RuntimeError::new(format!("Index {} out of bounds for length {}", i, len), span.clone())),
+                        }
+                    }
+                    (base, _) => Err(RuntimeError::new(format!("Cannot index into a value of type '{}'", base.type_name()), span.clone())),
+                }
+            }
+            other => Err(RuntimeError::new("Invalid assignment target".to_string(), other.span())),
+        }
+    }
+}
+
+pub fn literal_value(lit: &Literal) -> Value {
+    match lit {
+        Literal::Integer(n, _) => Value::Int(*n),
+        Literal::Float(n, _) => Value::Float(*n),
+        Literal::Bool(b, _) => Value::Bool(*b),
+        Literal::Null(_) => Value::Null,
+        Literal::Token(token, _) => {
+            let lexeme = &token.lexeme;
+            let inner = if lexeme.len() >= 2 { &lexeme[1..lexeme.len() - 1] } else { lexeme.as_str() };
+            Value::Str(unescape(inner))
+        }
+    }
+}
+
+/// Orders two values of a type range patterns can be written for.
+fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Int(a), Value::Int(b)) => Some(a.cmp(b)),
+        (Value::Str(a), Value::Str(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn index_value(base: &Value, index: &Value, span: &Span) -> Result<Value, RuntimeError> {
+    let out_of_bounds = |i: i64, len: usize| RuntimeError::new(format!("Index {} out of bounds for length {}", i, len), span.clone());
+    match (base, index) {
+        (Value::Array(elements), Value::Int(i)) => {
+            let elements = elements.borrow();
+            usize::try_from(*i).ok().and_then(|i| elements.get(i).cloned()).ok_or_else(|| out_of_bounds(*i, elements.len()))
+        }
+        (Value::Tuple(elements), Value::Int(i)) => {
+            usize::try_from(*i).ok().and_then(|i| elements.get(i).cloned()).ok_or_else(|| out_of_bounds(*i, elements.len()))
+        }
+        (Value::Str(s), Value::Int(i)) => usize::try_from(*i)
+            .ok()
+            .and_then(|i| s.chars().nth(i))
+            .map(|c| Value::Str(c.to_string()))
+            .ok_or_else(|| out_of_bounds(*i, s.chars().count())),
+        (base, index) => Err(RuntimeError::new(
+            format!("Cannot index a value of type '{}' with '{}'", base.type_name(), index.type_name()),
+            span.clone(),
+        )),
+    }
+}
+
+fn binary_op(op: &str, left: Value, right: Value, span: &Span) -> Result<Value, RuntimeError> {
+    let error = |left: &Value, right: &Value| RuntimeError::new(
+        format!("Invalid binary operation: {} {} {}", left.type_name(), op, right.type_name()),
+        span.clone(),
+    );
+
+    match op {
+        "==" => return Ok(Value::Bool(left.equals(&right))),
+        "!=" => return Ok(Value::Bool(!left.equals(&right))),
+        _ => {}
+    }
+
+    match (&left, &right) {
+        (Value::Int(a), Value::Int(b)) => {
+            let (a, b) = (*a, *b);
+            let checked = |result: Option<i64>| result.map(Value::Int).ok_or_else(|| {
+                RuntimeError::new(format!("Integer overflow in {} {} {}", a, op, b), span.clone())
+            });
+            let value = match op {
+                "+" => checked(a.checked_add(b))?,
+                "-" => checked(a.checked_sub(b))?,
+                "*" => checked(a.checked_mul(b))?,
+                "/" | "%" if b == 0 => return Err(RuntimeError::new("Division by zero".to_string(), span.clone())),
+                "/" => checked(a.checked_div(b))?,
+                "%" => checked(a.checked_rem(b))?,
+                "**" if b >= 0 => checked(u32::try_from(b).ok().and_then(|b| a.checked_pow(b)))?,
+                "**" => Value::Float((a as f64).powf(b as f64)),
+                "&" => Value::Int(a & b),
+                "|" => Value::Int(a | b),
+                "^" => Value::Int(a ^ b),
+                "<" => Value::Bool(a < b),
+                "<=" => Value::Bool(a <= b),
+                ">" => Value::Bool(a > b),
+                ">=" => Value::Bool(a >= b),
+                _ => return Err(error(&left, &right)),
+            };
+            Ok(value)
+        }
+        (Value::Int(_) | Value::Float(_), Value::Int(_) | Value::Float(_)) => {
+            let a = match left { Value::Int(n) => n as f64, Value::Float(n) => n, _ => unreachable!() };
+            let b = match right { Value::Int(n) => n as f64, Value::Float(n) => n, _ => unreachable!() };
+            let value = match op {
+                "+" => Value::Float(a + b),
+                "-" => Value::Float(a - b),
+                "*" => Value::Float(a * b),
+                "/" => Value::Float(a / b),
+                "%" => Value::Float(a % b),
+                "**" => Value::Float(a.powf(b)),
+                "<" => Value::Bool(a < b),
+                "<=" => Value::Bool(a <= b),
+                ">" => Value::Bool(a > b),
+                ">=" => Value::Bool(a >= b),
+                _ => return Err(RuntimeError::new(format!("Invalid binary operation: float {} float", op), span.clone())),
+            };
+            Ok(value)
+        }
+        (Value::Str(a), Value::Str(b)) => match op {
+            "+" => Ok(Value::Str(format!("{}{}", a, b))),
+            "<" => Ok(Value::Bool(a < b)),
+            "<=" => Ok(Value::Bool(a <= b)),
+            ">" => Ok(Value::Bool(a > b)),
+            ">=" => Ok(Value::Bool(a >= b)),
+            _ => Err(error(&left, &right)),
+        },
+        (Value::Bool(a), Value::Bool(b)) => match op {
+            "&" => Ok(Value::Bool(a & b)),
+            "|" => Ok(Value::Bool(a | b)),
+            "^" => Ok(Value::Bool(a ^ b)),
+            _ => Err(error(&left, &right)),
+        },
+        _ => Err(error(&left, &right)),
+    }
+}
+
+fn cast(value: Value, ty: &Type, span: &Span) -> Result<Value, RuntimeError> {
+    let target = match ty {
+        Type::Primitive { name, .. } => name.lexeme.as_str(),
+        Type::Named { name, .. } if value.type_name() == name.lexeme => return Ok(value),
+        _ => return Err(RuntimeError::new(format!("Cannot cast a value of type '{}'", value.type_name()), span.clone())),
+    };
+    let invalid = |value: &Value| RuntimeError::new(format!("Cannot convert {:?} to {}", value, target), span.clone());
+
+    let result = match (target, &value) {
+        ("int", Value::Int(n)) => Value::Int(*n),
+        ("int", Value::Float(n)) => Value::Int(*n as i64),
+        ("int", Value::Bool(b)) => Value::Int(*b as i64),
+        ("int", Value::Str(s)) => Value::Int(s.trim().parse().map_err(|_| invalid(&value))?),
+        ("float", Value::Int(n)) => Value::Float(*n as f64),
+        ("float", Value::Float(n)) => Value::Float(*n),
+        ("float", Value::Str(s)) => Value::Float(s.trim().parse().map_err(|_| invalid(&value))?),
+        ("bool", Value::Bool(b)) => Value::Bool(*b),
+        ("bool", Value::Int(n)) => Value::Bool(*n != 0),
+        ("bool", Value::Str(s)) => Value::Bool(s.trim().parse().map_err(|_| invalid(&value))?),
+        ("string", value) => Value::Str(value.to_string()),
+        ("char", Value::Str(s)) if s.chars().count() == 1 => Value::Str(s.clone()),
+        _ => return Err(invalid(&value)),
+    };
+    Ok(result)
+}
@@ -0,0 +1,222 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use crate::frontend::utils::ast::{Parameter, Statement, Type};
+use super::environment::Environment;
+use super::Interpreter;
+
+pub type NativeFn = fn(&mut Interpreter, &[Value]) -> Result<Value, String>;
+
+/// A runtime value produced by the interpreter.
+#[derive(Clone)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Str(String),
+    Null,
+    Void,
+    Array(Rc<RefCell<Vec<Value>>>),
+    Tuple(Vec<Value>),
+    /// `a..b` or `a..=b`, with `None` for a bound that was left out.
+    Range {
+        start: Option<i64>,
+        end: Option<i64>,
+        inclusive: bool,
+    },
+    Struct {
+        name: String,
+        fields: Rc<RefCell<Vec<(String, Value)>>>,
+    },
+    EnumVariant {
+        enum_name: String,
+        variant: String,
+        fields: Vec<Value>,
+    },
+    /// A value of a newtype, made by converting the wrapped value with `as`.
+    Newtype {
+        name: String,
+        value: Box<Value>,
+    },
+    /// A constructor for an enum variant that carries fields, e.g. `Shape.Circle`.
+    VariantConstructor {
+        enum_name: String,
+        variant: String,
+        arity: usize,
+    },
+    Function(Rc<Callable>),
+    /// A method looked up on a value, with the receiver already bound to `self`.
+    BoundMethod {
+        receiver: Box<Value>,
+        function: Rc<Callable>,
+    },
+    Native(&'static str, NativeFn),
+    /// An imported module, referred to by its path.
+    Module(String),
+    /// A type name used in expression position, e.g. `Point` in `Point.new(...)`.
+    Type(String),
+}
+
+/// A user-defined function or closure together with the scope it was created in.
+pub struct Callable {
+    pub name: String,
+    pub params: Vec<Parameter>,
+    /// The declared return type, which tells `?` what to convert errors into
+    pub return_type: Type,
+    /// `None` for a method the runtime provides, such as the prelude's `Result.from`
+    pub body: Option<Statement>,
+    pub closure: Rc<RefCell<Environment>>,
+}
+
+impl Value {
+    pub fn type_name(&self) -> String {
+        match self {
+            Value::Int(_) => "int".to_string(),
+            Value::Float(_) => "float".to_string(),
+            Value::Bool(_) => "bool".to_string(),
+            Value::Str(_) => "string".to_string(),
+            Value::Null => "null".to_string(),
+            Value::Void => "void".to_string(),
+            Value::Array(_) => "array".to_string(),
+            Value::Tuple(_) => "tuple".to_string(),
+            Value::Range { .. } => "Range".to_string(),
+            Value::Struct { name, .. } => name.clone(),
+            Value::EnumVariant { enum_name, .. } => enum_name.clone(),
+            Value::Newtype { name, .. } => name.clone(),
+            Value::VariantConstructor { .. }
+            | Value::Function(_)
+            | Value::BoundMethod { .. }
+            | Value::Native(..) => "function".to_string(),
+            Value::Module(_) => "module".to_string(),
+            Value::Type(_) => "type".to_string(),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        !matches!(self, Value::Bool(false) | Value::Null)
+    }
+
+    /// Structural equality, as used by `==`, `!=` and literal patterns.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a == b,
+            (Value::Float(a), Value::Float(b)) => a == b,
+            (Value::Int(a), Value::Float(b)) | (Value::Float(b), Value::Int(a)) => (*a as f64) == *b,
+            (Value::Bool(a), Value::Bool(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Null, Value::Null) | (Value::Void, Value::Void) => true,
+            (Value::Array(a), Value::Array(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.equals(y))
+            }
+            (Value::Tuple(a), Value::Tuple(b)) => {
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(x, y)| x.equals(y))
+            }
+            (
+                Value::Range { start: s1, end: e1, inclusive: i1 },
+                Value::Range { start: s2, end: e2, inclusive: i2 },
+            ) => s1 == s2 && e1 == e2 && i1 == i2,
+            (Value::Struct { name: n1, fields: f1 }, Value::Struct { name: n2, fields: f2 }) => {
+                let (f1, f2) = (f1.borrow(), f2.borrow());
+                n1 == n2 && f1.len() == f2.len()
+                    && f1.iter().zip(f2.iter()).all(|((k1, v1), (k2, v2))| k1 == k2 && v1.equals(v2))
+            }
+            (
+                Value::EnumVariant { enum_name: e1, variant: v1, fields: f1 },
+                Value::EnumVariant { enum_name: e2, variant: v2, fields: f2 },
+            ) => {
+                e1 == e2 && v1 == v2 && f1.len() == f2.len()
+                    && f1.iter().zip(f2.iter()).all(|(x, y)| x.equals(y))
+            }
+            (Value::Newtype { name: n1, value: v1 }, Value::Newtype { name: n2, value: v2 }) => n1 == n2 && v1.equals(v2),
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (Value::Module(a), Value::Module(b)) | (Value::Type(a), Value::Type(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => write!(f, "{}", s),
+            Value::Null => write!(f, "null"),
+            Value::Void => write!(f, "()"),
+            Value::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", element)?;
+                }
+                write!(f, "]")
+            }
+            Value::Tuple(elements) => {
+                write!(f, "(")?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{:?}", element)?;
+                }
+                write!(f, ")")
+            }
+            Value::Range { start, end, inclusive } => {
+                if let Some(start) = start {
+                    write!(f, "{}", start)?;
+                }
+                write!(f, "{}", if *inclusive { "..=" } else { ".." })?;
+                if let Some(end) = end {
+                    write!(f, "{}", end)?;
+                }
+                Ok(())
+            }
+            Value::Struct { name, fields } => {
+                write!(f, "{} {{ ", name)?;
+                for (i, (field, value)) in fields.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {:?}", field, value)?;
+                }
+                write!(f, " }}")
+            }
+            Value::EnumVariant { enum_name, variant, fields } => {
+                write!(f, "{}.{}", enum_name, variant)?;
+                if !fields.is_empty() {
+                    write!(f, "(")?;
+                    for (i, field) in fields.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{:?}", field)?;
+                    }
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Value::Newtype { name, value } => write!(f, "{}({:?})", name, value),
+            Value::VariantConstructor { enum_name, variant, .. } => write!(f, "<constructor {}.{}>", enum_name, variant),
+            Value::Function(callable) => write!(f, "<func {}>", callable.name),
+            Value::BoundMethod { function, .. } => write!(f, "<method {}>", function.name),
+            Value::Native(name, _) => write!(f, "<native {}>", name),
+            Value::Module(name) => write!(f, "<module {}>", name),
+            Value::Type(name) => write!(f, "<type {}>", name),
+        }
+    }
+}
+
+/// Like `Display`, but strings are quoted. Used for values nested in collections.
+impl fmt::Debug for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Str(s) => write!(f, "{:?}", s),
+            other => write!(f, "{}", other),
+        }
+    }
+}
@@ -0,0 +1,11 @@
+
+pub mod errors;
+pub mod frontend;
+pub mod interpreter;
+pub mod lsp;
+pub mod repl;
+pub mod sema;
+pub mod session;
+pub mod utils;
+
+pub use session::{CheckedModule, Compiler, PassTiming, Session};
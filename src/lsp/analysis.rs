@@ -0,0 +1,613 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::errors::{Error, Warning};
+use crate::frontend::utils::{
+    ast::*,
+    token::{Span, Token},
+    visitor::Visitor,
+};
+use crate::sema::{
+    imports,
+    passes::{attributes::AttributeChecker, populate_table::FullSymbolTablePass, resolver::Resolver, type_checker::TypeChecker},
+    prelude,
+    utils::{
+        symbol_table::{Symbol, SymbolKind},
+        MultiStageSymbolTable,
+    },
+};
+use crate::session::Session;
+
+/// Something a name in the document can refer to
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The span of the declared name
+    pub span: Span,
+    /// The span of the whole declaration
+    pub extent: Span,
+    pub ty: Option<Type>,
+    /// One line summary, shown on hover and in completions
+    pub detail: String,
+    /// The type a field, variant or method belongs to
+    pub container: Option<String>,
+}
+
+/// What the language server knows about one version of a document.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub diagnostics: Vec<Error>,
+    pub warnings: Vec<Warning>,
+    /// Whether the document parsed; nothing is indexed otherwise
+    pub parsed: bool,
+    pub definitions: Vec<Definition>,
+    /// Every resolved use of a name, with the index of its definition
+    pub references: Vec<(Span, usize)>,
+}
+
+impl Analysis {
+    /// Runs the front end and semantic analysis over `text`, collecting diagnostics
+    /// and an index of definitions and references.
+    pub fn new(name: &str, text: &str) -> Analysis {
+        let mut analysis = Analysis::default();
+
+        let module = match Session::new(name, text).parse() {
+            Ok(module) => module,
+            Err(errors) => {
+                analysis.diagnostics = errors;
+                return analysis;
+            }
+        };
+        analysis.parsed = true;
+        // The prelude is checked along with the document, but only the document is indexed
+        let directory = Path::new(name.strip_prefix("file://").unwrap_or(name)).parent().unwrap_or(Path::new(""));
+        let (checked, import_errors) = imports::with_imports(module.clone(), directory);
+        let checked = prelude::with_prelude(checked);
+
+        let mut pass = FullSymbolTablePass::new();
+        let mut attributes = AttributeChecker::new();
+        let mut resolver = Resolver::new();
+        let mut checker = TypeChecker::new();
+        let result = pass.visit_module(&checked)
+            .and_then(|_| attributes.visit_module(&checked))
+            .and_then(|_| resolver.visit_module(&checked))
+            .and_then(|_| {
+                checker.table = pass.table;
+                checker.visit_module(&checked)
+            });
+        if let Err(message) = result {
+            checker.errors.push(Error::new(message, 1, Span::default(), String::new()));
+        }
+
+        // As when compiling, type errors are only reported once every name resolves
+        let errors: Vec<Error> = if attributes.errors.is_empty() && resolver.errors.is_empty() {
+            checker.errors.drain(..).collect()
+        } else {
+            attributes.errors.into_iter().chain(resolver.errors.drain(..)).collect()
+        };
+        analysis.diagnostics = import_errors.into_iter().chain(errors).map(|error| error.locate(name, text)).collect();
+        analysis.warnings = attributes.warnings.into_iter()
+            .chain(resolver.warnings)
+            .chain(checker.warnings.drain(..))
+            .map(|warning| warning.locate(name, text))
+            .collect();
+
+        let mut indexer = Indexer {
+            table: MultiStageSymbolTable::new(),
+            by_span: HashMap::new(),
+            binding_types: &checker.binding_types,
+            analysis: &mut analysis,
+        };
+        indexer.module(&module);
+
+        analysis
+    }
+
+    /// The definition named or referred to at `offset`.
+    pub fn definition_at(&self, offset: usize) -> Option<usize> {
+        let contains = |span: &Span| span.start <= offset && offset <= span.end;
+
+        self.references.iter()
+            .find(|(span, _)| contains(span))
+            .map(|(_, definition)| *definition)
+            .or_else(|| self.definitions.iter().position(|definition| contains(&definition.span)))
+    }
+
+    pub fn references_to(&self, definition: usize) -> Vec<Span> {
+        self.references.iter()
+            .filter(|(_, target)| *target == definition)
+            .map(|(span, _)| span.clone())
+            .collect()
+    }
+
+    /// Fields, variants and methods of the named type.
+    pub fn members(&self, container: &str) -> Vec<&Definition> {
+        self.definitions.iter()
+            .filter(|definition| definition.container.as_deref() == Some(container))
+            .collect()
+    }
+
+    /// The type whose members can be completed at `offset`, i.e. after `name.`.
+    pub fn completion_container(&self, text: &str, offset: usize) -> Option<String> {
+        let before = &text[..offset.min(text.len())];
+        let is_name = |c: char| c.is_alphanumeric() || c == '_';
+
+        let before = before.trim_end_matches(is_name).strip_suffix('.')?;
+        let base = &before[before.trim_end_matches(is_name).len()..];
+
+        // The closest variable of that name declared before the cursor
+        let binding = self.definitions.iter()
+            .filter(|definition| definition.name == base && definition.span.start < offset)
+            .filter(|definition| matches!(definition.kind, SymbolKind::Variable | SymbolKind::Parameter))
+            .max_by_key(|definition| definition.span.start);
+        if let Some(binding) = binding {
+            return match &binding.ty {
+                Some(Type::Named { name, .. }) => Some(name.lexeme.clone()),
+                _ => None,
+            };
+        }
+
+        // `Type.` completes static methods and enum variants
+        self.definitions.iter()
+            .find(|definition| definition.name == base && definition.container.is_none()
+                && matches!(definition.kind, SymbolKind::Struct | SymbolKind::Enum | SymbolKind::Trait | SymbolKind::Newtype))
+            .map(|definition| definition.name.clone())
+    }
+}
+
+/// Walks a module, recording definitions and resolving the names that refer to them.
+struct Indexer<'a> {
+    table: MultiStageSymbolTable,
+    /// Definitions by the span of their name, which is also what `Symbol.span` holds
+    by_span: HashMap<Span, usize>,
+    binding_types: &'a HashMap<Span, Type>,
+    analysis: &'a mut Analysis,
+}
+
+impl Indexer<'_> {
+    fn module(&mut self, module: &Module) {
+        // Items can be used before they are declared
+        for item in &module.stmts {
+            self.declare_item(item);
+        }
+        for item in &module.stmts {
+            self.item(item);
+        }
+    }
+
+    fn define(&mut self, name: &Token, kind: SymbolKind, extent: Span, ty: Option<Type>, detail: String, container: Option<String>) -> Symbol {
+        let definition = Definition {
+            name: name.lexeme.clone(),
+            kind: kind.clone(),
+            span: name.span.clone(),
+            extent,
+            ty: ty.clone(),
+            detail,
+            container,
+        };
+        self.by_span.insert(name.span.clone(), self.analysis.definitions.len());
+        self.analysis.definitions.push(definition);
+
+        Symbol {
+            name: name.lexeme.clone(),
+            kind,
+            access: AccessModifier::None,
+            ty,
+            span: Some(name.span.clone()),
+            struct_fields: None,
+            enum_variants: None,
+        }
+    }
+
+    fn define_value(&mut self, name: &Token, kind: SymbolKind, ty: Option<Type>, detail: String) {
+        let symbol = self.define(name, kind, name.span.clone(), ty, detail, None);
+        self.table.values.insert(symbol);
+    }
+
+    fn refer(&mut self, token: &Token, symbol: Option<Symbol>) {
+        let definition = symbol
+            .and_then(|symbol| symbol.span)
+            .and_then(|span| self.by_span.get(&span).copied());
+        if let Some(definition) = definition {
+            self.analysis.references.push((token.span.clone(), definition));
+        }
+    }
+
+    fn member(&self, container: &str, name: &str) -> Option<usize> {
+        self.analysis.definitions.iter().position(|definition| {
+            definition.container.as_deref() == Some(container) && definition.name == name
+        })
+    }
+
+    fn declare_item(&mut self, item: &StatementKind) {
+        match item {
+            StatementKind::Function(function) => {
+                let symbol = self.define(&function.name, SymbolKind::Function, function.span.clone(),
+                    Some(function_type(function)), function_detail(function), None);
+                self.table.values.insert(symbol);
+            }
+            StatementKind::Struct(structure) => {
+                let name = structure.name.lexeme.clone();
+                let symbol = self.define(&structure.name, SymbolKind::Struct, structure.span.clone(),
+                    None, format!("struct {}{}", name, generics_detail(&structure.generics)), None);
+                self.table.types.insert(symbol);
+
+                for field in &structure.fields {
+                    // The field's own span only covers its type
+                    let span = Span::new(field.name.span.start, field.span.end);
+                    self.define(&field.name, SymbolKind::Field, span, Some(field.ty.clone()),
+                        format!("{}: {}", field.name.lexeme, field.ty), Some(name.clone()));
+                }
+            }
+            StatementKind::Enum(enumeration) => {
+                let name = enumeration.name.lexeme.clone();
+                let symbol = self.define(&enumeration.name, SymbolKind::Enum, enumeration.span.clone(),
+                    None, format!("enum {}", name), None);
+                self.table.types.insert(symbol);
+
+                for variant in &enumeration.variants {
+                    let fields = if variant.fields.is_empty() {
+                        String::new()
+                    } else {
+                        format!("({})", variant.fields.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", "))
+                    };
+                    self.define(&variant.name, SymbolKind::Variant, variant.span.clone(), None,
+                        format!("{}.{}{}", name, variant.name.lexeme, fields), Some(name.clone()));
+                }
+            }
+            StatementKind::Trait(trait_) => {
+                let name = trait_.name.lexeme.clone();
+                let symbol = self.define(&trait_.name, SymbolKind::Trait, trait_.span.clone(),
+                    None, format!("trait {}{}", name, generics_detail(&trait_.generics)), None);
+                self.table.types.insert(symbol);
+
+                for method in &trait_.methods {
+                    self.define(&method.name, function_kind(method), method.span.clone(),
+                        Some(function_type(method)), function_detail(method), Some(name.clone()));
+                }
+            }
+            StatementKind::Extend(extend) => {
+                for method in &extend.methods {
+                    self.define(&method.name, function_kind(method), method.span.clone(),
+                        Some(function_type(method)), function_detail(method), Some(extend.name.lexeme.clone()));
+                }
+            }
+            StatementKind::TypeAlias(alias) => {
+                let (kind, new) = if alias.newtype { (SymbolKind::Newtype, "new ") } else { (SymbolKind::Alias, "") };
+                let symbol = self.define(&alias.name, kind, alias.span.clone(), None,
+                    format!("type {} = {}{}", alias.name.lexeme, new, alias.ty), None);
+                self.table.types.insert(symbol);
+            }
+            StatementKind::Import(import) => {
+                let symbol = self.define(&import.alias, SymbolKind::Module, import.span.clone(), None,
+                    format!("import {} as {}", import.path.lexeme, import.alias.lexeme), None);
+                self.table.values.insert(symbol);
+            }
+            StatementKind::Statement(_) => {}
+        }
+    }
+
+    fn item(&mut self, item: &StatementKind) {
+        match item {
+            StatementKind::Function(function) => self.function(function, None),
+            StatementKind::Struct(structure) => {
+                for field in &structure.fields {
+                    self.type_ref(&field.ty);
+                }
+            }
+            StatementKind::Enum(enumeration) => {
+                for variant in &enumeration.variants {
+                    variant.fields.iter().for_each(|ty| self.type_ref(ty));
+                }
+            }
+            StatementKind::Trait(trait_) => trait_.methods.iter().for_each(|method| self.function(method, None)),
+            StatementKind::TypeAlias(alias) => self.type_ref(&alias.ty),
+            StatementKind::Extend(extend) => {
+                let target = self.table.types.get(&extend.name.lexeme).cloned();
+                self.refer(&extend.name, target);
+                if let Some(trait_name) = &extend.trait_name {
+                    let symbol = self.table.types.get(&trait_name.lexeme).cloned();
+                    self.refer(trait_name, symbol);
+                }
+                extend.methods.iter().for_each(|method| self.function(method, Some(&extend.name)));
+            }
+            StatementKind::Import(_) => {}
+            StatementKind::Statement(statement) => self.statement(statement),
+        }
+    }
+
+    /// Indexes a function; methods of an `extend` block pass the extended type as `owner`.
+    fn function(&mut self, function: &Function, owner: Option<&Token>) {
+        self.table.values.enter_scope();
+        if let (true, Some(owner)) = (function.is_method, owner) {
+            // `self` has no type annotation, so it gets no definition of its own
+            self.table.values.insert(Symbol {
+                name: "self".to_string(),
+                kind: SymbolKind::Parameter,
+                access: AccessModifier::None,
+                ty: Some(Type::Named { name: owner.clone(), generics: vec![], span: owner.span.clone() }),
+                span: None,
+                struct_fields: None,
+                enum_variants: None,
+            });
+        }
+        self.parameters(&function.params);
+        self.type_ref(&function.return_type);
+        if let Some(body) = &function.body {
+            self.statement(body);
+        }
+        self.table.values.exit_scope();
+    }
+
+    fn parameters(&mut self, params: &[Parameter]) {
+        for param in params {
+            self.type_ref(&param.ty);
+            let detail = format!("{}: {}", param.name.lexeme, param.ty);
+            self.define_value(&param.name, SymbolKind::Parameter, Some(param.ty.clone()), detail);
+        }
+    }
+
+    fn statement(&mut self, statement: &Statement) {
+        match statement {
+            Statement::Let { pattern, ty, value, .. } => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+                if let Some(ty) = ty {
+                    self.type_ref(ty);
+                }
+                let Pattern::Identifier(name, _) = &**pattern else {
+                    return self.pattern(pattern);
+                };
+                let ty = ty.clone()
+                    .or_else(|| self.binding_types.get(&name.span).cloned())
+                    .or_else(|| value.as_ref().and_then(|value| self.type_of(value)));
+                let detail = binding_detail(name, &ty);
+                self.define_value(name, SymbolKind::Variable, ty, detail);
+            }
+            Statement::Expr(expr) | Statement::Tail(expr) | Statement::Return(Some(expr), _) => self.expr(expr),
+            Statement::Return(None, _) | Statement::Break(_) | Statement::Continue(_) => {}
+            Statement::Block(stmts, _) => {
+                self.table.values.enter_scope();
+                stmts.iter().for_each(|stmt| self.statement(stmt));
+                self.table.values.exit_scope();
+            }
+            Statement::If { cond, then_branch, else_branch, .. } => self.branches(cond, then_branch, else_branch.as_deref()),
+            Statement::While { cond, body, .. } => {
+                self.expr(cond);
+                self.statement(body);
+            }
+            Statement::For { var, iter, body, .. } => {
+                self.expr(iter);
+                self.table.values.enter_scope();
+                self.define_value(var, SymbolKind::Variable, None, binding_detail(var, &None));
+                self.statement(body);
+                self.table.values.exit_scope();
+            }
+            Statement::Match { expr, cases, .. } => self.cases(expr, cases),
+        }
+    }
+
+    fn branches(&mut self, cond: &Expr, then_branch: &Statement, else_branch: Option<&Statement>) {
+        self.expr(cond);
+        self.statement(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.statement(else_branch);
+        }
+    }
+
+    fn cases(&mut self, expr: &Expr, cases: &[Case]) {
+        self.expr(expr);
+        for case in cases {
+            self.table.values.enter_scope();
+            self.pattern(&case.pattern);
+            if let Some(guard) = &case.guard {
+                self.expr(guard);
+            }
+            self.statement(&case.body);
+            self.table.values.exit_scope();
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Identifier(name, _) => {
+                // Names bound by a `let` have their types recorded by the type checker
+                let ty = self.binding_types.get(&name.span).cloned();
+                let detail = binding_detail(name, &ty);
+                self.define_value(name, SymbolKind::Variable, ty, detail);
+            }
+            Pattern::Tuple(patterns, _) => patterns.iter().for_each(|pattern| self.pattern(pattern)),
+            Pattern::Struct { fields, .. } => fields.iter().for_each(|(_, pattern)| self.pattern(pattern)),
+            Pattern::Variant { enum_name, fields, .. } => {
+                if let Some(enum_name) = enum_name {
+                    let symbol = self.table.types.get(&enum_name.lexeme).cloned();
+                    self.refer(enum_name, symbol);
+                }
+                fields.iter().for_each(|pattern| self.pattern(pattern));
+            }
+            // Every alternative binds the same names, so the first one declares them
+            Pattern::Or(alternatives, _) => self.pattern(&alternatives[0]),
+            Pattern::Binding { name, pattern, .. } => {
+                self.define_value(name, SymbolKind::Variable, None, binding_detail(name, &None));
+                self.pattern(pattern);
+            }
+            Pattern::Literal(_) | Pattern::Range { .. } | Pattern::Wildcard(_) | Pattern::Error => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(name, _) => {
+                let symbol = self.table.values.get(&name.lexeme).or_else(|| self.table.types.get(&name.lexeme)).cloned();
+                self.refer(name, symbol);
+            }
+            Expr::Literal(_) | Expr::Error => {}
+            Expr::TokenInterpolation(interpolation, _) => {
+                for segment in &interpolation.segments {
+                    if let TokenSegment::Expr(expr, _) = segment {
+                        self.expr(expr);
+                    }
+                }
+            }
+            Expr::Binary { left, right, .. } | Expr::Assignment { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Unary { expr, .. } | Expr::Grouping(expr, _) | Expr::Try { expr, .. } => self.expr(expr),
+            Expr::Range { start, end, .. } => start.iter().chain(end).for_each(|bound| self.expr(bound)),
+            Expr::Call { callee, args, generic_args, .. } => {
+                self.expr(callee);
+                args.iter().for_each(|arg| self.expr(arg));
+                generic_args.iter().for_each(|ty| self.type_ref(ty));
+            }
+            Expr::Field { base, field, .. } => {
+                self.expr(base);
+                let member = self.container_of(base).and_then(|container| self.member(&container, &field.lexeme));
+                if let Some(member) = member {
+                    self.analysis.references.push((field.span.clone(), member));
+                }
+            }
+            Expr::Index { base, index, .. } => {
+                self.expr(base);
+                self.expr(index);
+            }
+            Expr::StructInit { name, fields, .. } => {
+                let symbol = self.table.types.get(&name.lexeme).cloned();
+                self.refer(name, symbol);
+
+                for (field, value) in fields {
+                    // In the shorthand `Point { x }` the name refers to the variable
+                    let shorthand = matches!(value, Expr::Identifier(_, span) if *span == field.span);
+                    if !shorthand {
+                        if let Some(member) = self.member(&name.lexeme, &field.lexeme) {
+                            self.analysis.references.push((field.span.clone(), member));
+                        }
+                    }
+                    self.expr(value);
+                }
+            }
+            Expr::Array { elements, .. } | Expr::Tuple { elements, .. } => {
+                elements.iter().for_each(|element| self.expr(element));
+            }
+            Expr::Cast { expr, ty, .. } => {
+                self.expr(expr);
+                self.type_ref(ty);
+            }
+            Expr::Closure { params, body, ty, .. } => {
+                self.table.values.enter_scope();
+                self.parameters(params);
+                self.type_ref(ty);
+                self.statement(body);
+                self.table.values.exit_scope();
+            }
+            Expr::If { cond, then_branch, else_branch, .. } => self.branches(cond, then_branch, else_branch.as_deref()),
+            Expr::Match { expr, cases, .. } => self.cases(expr, cases),
+        }
+    }
+
+    fn type_ref(&mut self, ty: &Type) {
+        match ty {
+            Type::Named { name, generics, .. } => {
+                let symbol = self.table.types.get(&name.lexeme).cloned();
+                self.refer(name, symbol);
+                generics.iter().for_each(|ty| self.type_ref(ty));
+            }
+            Type::Array { element, .. } | Type::Dyn { trait_: element, .. } | Type::Optional { inner: element, .. } => self.type_ref(element),
+            Type::Tuple { elements, .. } => elements.iter().for_each(|ty| self.type_ref(ty)),
+            Type::Function { params, return_type, .. } => {
+                params.iter().for_each(|ty| self.type_ref(ty));
+                self.type_ref(return_type);
+            }
+            Type::Primitive { .. } | Type::TypeVar { .. } | Type::Error(_) => {}
+        }
+    }
+
+    /// The type whose members are accessed by `base.member`.
+    fn container_of(&self, base: &Expr) -> Option<String> {
+        if let Expr::Identifier(name, _) = base {
+            // A type name, as in `Point.new` or `Shape.Circle`
+            if self.table.values.get(&name.lexeme).is_none() && self.table.types.get(&name.lexeme).is_some() {
+                return Some(name.lexeme.clone());
+            }
+        }
+
+        match self.type_of(base)? {
+            Type::Named { name, .. } => Some(name.lexeme),
+            _ => None,
+        }
+    }
+
+    /// A best effort type for the expressions that can appear before a `.`.
+    fn type_of(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Identifier(name, _) => self.table.values.get(&name.lexeme)?.ty.clone(),
+            Expr::StructInit { name, span, .. } => Some(Type::Named {
+                name: name.clone(),
+                generics: vec![],
+                span: span.clone(),
+            }),
+            Expr::Call { callee, .. } => match self.type_of(callee)? {
+                Type::Function { return_type, .. } => Some(*return_type),
+                _ => None,
+            },
+            Expr::Field { base, field, .. } => {
+                let container = self.container_of(base)?;
+                let member = self.member(&container, &field.lexeme)?;
+                self.analysis.definitions[member].ty.clone()
+            }
+            Expr::Grouping(expr, _) => self.type_of(expr),
+            Expr::Cast { ty, .. } => Some(ty.clone()),
+            _ => None,
+        }
+    }
+}
+
+fn function_type(function: &Function) -> Type {
+    Type::Function {
+        params: function.params.iter().map(|param| param.ty.clone()).collect(),
+        return_type: Box::new(function.return_type.clone()),
+        span: function.span.clone(),
+    }
+}
+
+fn function_detail(function: &Function) -> String {
+    let receiver = function.is_method.then(|| "self".to_string());
+    let params: Vec<String> = receiver.into_iter()
+        .chain(function.params.iter().map(|param| format!("{}: {}", param.name.lexeme, param.ty)))
+        .collect();
+    let mut detail = format!("func {}{}({})", function.name.lexeme, generics_detail(&function.generics), params.join(", "));
+
+    if !matches!(&function.return_type, Type::Primitive { name, .. } if name.lexeme == "void") {
+        detail.push_str(&format!(" -> {}", function.return_type));
+    }
+    if !function.bounds.is_empty() {
+        let bounds: Vec<String> = function.bounds.iter()
+            .map(|bound| {
+                let traits: Vec<String> = bound.traits.iter().map(|ty| ty.to_string()).collect();
+                format!("{}: {}", bound.param.lexeme, traits.join(" + "))
+            })
+            .collect();
+        detail.push_str(&format!(" where {}", bounds.join(", ")));
+    }
+    detail
+}
+
+fn function_kind(function: &Function) -> SymbolKind {
+    if function.is_method { SymbolKind::Method } else { SymbolKind::Function }
+}
+
+fn generics_detail(generics: &[Type]) -> String {
+    if generics.is_empty() {
+        return String::new();
+    }
+    format!("<{}>", generics.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", "))
+}
+
+fn binding_detail(name: &Token, ty: &Option<Type>) -> String {
+    match ty {
+        Some(ty) => format!("let {}: {}", name.lexeme, ty),
+        None => format!("let {}", name.lexeme),
+    }
+}
@@ -0,0 +1,436 @@
+pub mod analysis;
+pub mod protocol;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::sema::utils::symbol_table::SymbolKind;
+use crate::utils::json::Json;
+use analysis::Analysis;
+use protocol::*;
+
+/// An open document and the analysis of its current text
+struct Document {
+    text: String,
+    analysis: Analysis,
+    /// The latest analysis of a version that parsed, used for completion
+    /// while the user is in the middle of typing
+    last_parsed: Option<Analysis>,
+}
+
+/// A language server speaking JSON-RPC over a pair of streams.
+/// Documents are synchronised in full on every change and re-analysed each time.
+#[derive(Default)]
+pub struct Server {
+    documents: HashMap<String, Document>,
+    shutdown_requested: bool,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Server::default()
+    }
+
+    /// Serves requests until `exit` or the end of the input.
+    /// Returns the exit code, which is 0 only if `shutdown` came first.
+    pub fn run(&mut self, input: &mut impl BufRead, output: &mut impl Write) -> io::Result<i32> {
+        while let Some(body) = read_message(input)? {
+            let message = match Json::parse(&body) {
+                Ok(message) => message,
+                Err(error) => {
+                    write_message(output, &error_response(Json::Null, PARSE_ERROR, &error))?;
+                    continue;
+                }
+            };
+
+            if message.get("method").and_then(Json::as_str) == Some("exit") {
+                break;
+            }
+            for reply in self.handle(&message) {
+                write_message(output, &reply)?;
+            }
+        }
+
+        Ok(if self.shutdown_requested { 0 } else { 1 })
+    }
+
+    /// Handles one request or notification, returning the messages to send back.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(Json::as_str).unwrap_or("");
+        let params = message.get("params").cloned().unwrap_or(Json::Null);
+        let id = message.get("id").cloned();
+
+        match method {
+            "initialize" => return respond(id, initialize_result()),
+            "initialized" => return vec![],
+            "shutdown" => {
+                self.shutdown_requested = true;
+                return respond(id, Json::Null);
+            }
+            "textDocument/didOpen" => {
+                let document = params.get("textDocument");
+                let uri = document.and_then(|d| d.get("uri")).and_then(Json::as_str);
+                let text = document.and_then(|d| d.get("text")).and_then(Json::as_str);
+                let version = document.and_then(|d| d.get("version")).cloned().unwrap_or(Json::Null);
+                return match (uri, text) {
+                    (Some(uri), Some(text)) => self.update(uri, text.to_string(), version),
+                    _ => vec![],
+                };
+            }
+            "textDocument/didChange" => {
+                let uri = uri_of(&params);
+                // Full synchronisation, so the last change holds the whole text
+                let text = params.get("contentChanges")
+                    .and_then(Json::as_array)
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change.get("text"))
+                    .and_then(Json::as_str);
+                let version = params.get("textDocument").and_then(|d| d.get("version")).cloned().unwrap_or(Json::Null);
+                return match (uri, text) {
+                    (Some(uri), Some(text)) => self.update(&uri, text.to_string(), version),
+                    _ => vec![],
+                };
+            }
+            "textDocument/didClose" => {
+                return match uri_of(&params) {
+                    Some(uri) => {
+                        self.documents.remove(&uri);
+                        vec![publish_diagnostics(&uri, Json::Null, vec![])]
+                    }
+                    None => vec![],
+                };
+            }
+            _ => {}
+        }
+
+        // Anything else without an id is a notification we don't care about
+        let Some(id) = id else {
+            return vec![];
+        };
+
+        let result = match method {
+            "textDocument/definition" => self.definition(&params),
+            "textDocument/references" => self.references(&params),
+            "textDocument/hover" => self.hover(&params),
+            "textDocument/documentSymbol" => self.document_symbols(&params),
+            "textDocument/completion" => self.completion(&params),
+            _ => return vec![error_response(id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", method))],
+        };
+        vec![response(id, result.unwrap_or(Json::Null))]
+    }
+
+    fn update(&mut self, uri: &str, text: String, version: Json) -> Vec<Json> {
+        let analysis = Analysis::new(uri, &text);
+        let diagnostic = |span, severity: usize, message: &str| Json::object(vec![
+            ("range", range(&text, span)),
+            ("severity", Json::from(severity)),
+            ("source", Json::from("y")),
+            ("message", Json::from(message)),
+        ]);
+        let errors = analysis.diagnostics.iter().map(|error| diagnostic(&error.span, 1, &error.message));
+        let warnings = analysis.warnings.iter().map(|warning| diagnostic(&warning.span, 2, &warning.message));
+        let diagnostics = errors.chain(warnings).collect();
+        let notification = publish_diagnostics(uri, version, diagnostics);
+
+        let last_parsed = match self.documents.remove(uri) {
+            Some(previous) if previous.analysis.parsed => Some(previous.analysis),
+            Some(previous) => previous.last_parsed,
+            None => None,
+        };
+        self.documents.insert(uri.to_string(), Document { text, analysis, last_parsed });
+
+        vec![notification]
+    }
+
+    /// The document and byte offset a position request is about.
+    fn locate(&self, params: &Json) -> Option<(String, &Document, usize)> {
+        let uri = uri_of(params)?;
+        let document = self.documents.get(&uri)?;
+        let offset = position_to_offset(&document.text, params.get("position")?)?;
+        Some((uri, document, offset))
+    }
+
+    fn definition(&self, params: &Json) -> Option<Json> {
+        let (uri, document, offset) = self.locate(params)?;
+        let definition = &document.analysis.definitions[document.analysis.definition_at(offset)?];
+        Some(location(&uri, &document.text, &definition.span))
+    }
+
+    fn references(&self, params: &Json) -> Option<Json> {
+        let (uri, document, offset) = self.locate(params)?;
+        let index = document.analysis.definition_at(offset)?;
+        let include_declaration = params.get("context")
+            .and_then(|context| context.get("includeDeclaration"))
+            .and_then(Json::as_bool)
+            .unwrap_or(false);
+
+        let mut spans = vec![];
+        if include_declaration {
+            spans.push(document.analysis.definitions[index].span.clone());
+        }
+        spans.extend(document.analysis.references_to(index));
+
+        Some(Json::from(spans.iter().map(|span| location(&uri, &document.text, span)).collect::<Vec<_>>()))
+    }
+
+    fn hover(&self, params: &Json) -> Option<Json> {
+        let (_, document, offset) = self.locate(params)?;
+        let definition = &document.analysis.definitions[document.analysis.definition_at(offset)?];
+
+        Some(Json::object(vec![
+            ("contents", Json::object(vec![
+                ("kind", Json::from("markdown")),
+                ("value", Json::from(format!("```y\n{}\n```", definition.detail))),
+            ])),
+        ]))
+    }
+
+    fn document_symbols(&self, params: &Json) -> Option<Json> {
+        let document = self.documents.get(&uri_of(params)?)?;
+        let analysis = &document.analysis;
+
+        let symbol = |definition: &analysis::Definition, children: Vec<Json>| {
+            let mut members = vec![
+                ("name", Json::from(definition.name.as_str())),
+                ("detail", Json::from(definition.detail.as_str())),
+                ("kind", Json::from(symbol_kind(&definition.kind))),
+                ("range", range(&document.text, &definition.extent)),
+                ("selectionRange", range(&document.text, &definition.span)),
+            ];
+            if !children.is_empty() {
+                members.push(("children", Json::from(children)));
+            }
+            Json::object(members)
+        };
+
+        let symbols = analysis.definitions.iter()
+            .filter(|definition| definition.container.is_none() && matches!(definition.kind,
+                SymbolKind::Function | SymbolKind::Struct | SymbolKind::Enum | SymbolKind::Trait
+                | SymbolKind::Alias | SymbolKind::Newtype | SymbolKind::Module))
+            .map(|definition| {
+                let children = analysis.members(&definition.name).into_iter()
+                    .map(|member| symbol(member, vec![]))
+                    .collect();
+                symbol(definition, children)
+            })
+            .collect::<Vec<_>>();
+
+        Some(Json::from(symbols))
+    }
+
+    fn completion(&self, params: &Json) -> Option<Json> {
+        let (_, document, offset) = self.locate(params)?;
+        let analysis = if document.analysis.parsed {
+            &document.analysis
+        } else {
+            document.last_parsed.as_ref()?
+        };
+
+        let container = analysis.completion_container(&document.text, offset)?;
+        let items = analysis.members(&container).into_iter()
+            .map(|member| Json::object(vec![
+                ("label", Json::from(member.name.as_str())),
+                ("kind", Json::from(completion_kind(&member.kind))),
+                ("detail", Json::from(member.detail.as_str())),
+            ]))
+            .collect::<Vec<_>>();
+
+        Some(Json::from(items))
+    }
+}
+
+fn respond(id: Option<Json>, result: Json) -> Vec<Json> {
+    id.map(|id| response(id, result)).into_iter().collect()
+}
+
+fn uri_of(params: &Json) -> Option<String> {
+    params.get("textDocument")?.get("uri")?.as_str().map(str::to_string)
+}
+
+fn initialize_result() -> Json {
+    Json::object(vec![
+        ("capabilities", Json::object(vec![
+            ("textDocumentSync", Json::object(vec![
+                ("openClose", Json::from(true)),
+                ("change", Json::from(1usize)),
+            ])),
+            ("definitionProvider", Json::from(true)),
+            ("referencesProvider", Json::from(true)),
+            ("hoverProvider", Json::from(true)),
+            ("documentSymbolProvider", Json::from(true)),
+            ("completionProvider", Json::object(vec![
+                ("triggerCharacters", Json::from(vec![Json::from(".")])),
+            ])),
+        ])),
+        ("serverInfo", Json::object(vec![
+            ("name", Json::from("y")),
+            ("version", Json::from(env!("CARGO_PKG_VERSION"))),
+        ])),
+    ])
+}
+
+fn publish_diagnostics(uri: &str, version: Json, diagnostics: Vec<Json>) -> Json {
+    let mut params = vec![
+        ("uri", Json::from(uri)),
+        ("diagnostics", Json::from(diagnostics)),
+    ];
+    if version != Json::Null {
+        params.push(("version", version));
+    }
+    notification("textDocument/publishDiagnostics", Json::object(params))
+}
+
+/// The LSP `SymbolKind` for a definition
+fn symbol_kind(kind: &SymbolKind) -> usize {
+    match kind {
+        SymbolKind::Module => 2,
+        SymbolKind::Method => 6,
+        SymbolKind::Field => 8,
+        SymbolKind::Enum => 10,
+        SymbolKind::Trait => 11,
+        SymbolKind::Function => 12,
+        SymbolKind::Variant => 22,
+        SymbolKind::Struct => 23,
+        SymbolKind::Alias | SymbolKind::Newtype => 26,
+        _ => 13,
+    }
+}
+
+/// The LSP `CompletionItemKind` for a member
+fn completion_kind(kind: &SymbolKind) -> usize {
+    match kind {
+        SymbolKind::Function | SymbolKind::Method => 2,
+        SymbolKind::Field => 5,
+        SymbolKind::Variant => 20,
+        _ => 6,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const SOURCE: &str = "\
+module point;
+
+struct Point {
+    x: float,
+    y: float,
+}
+
+extend Point {
+    func norm(self) -> float {
+        return self.x;
+    }
+}
+
+func main() {
+    let p = Point { x: 1.0, y: 2.0 };
+    let n: int = \"one\";
+    p.x;
+    p.norm();
+}
+";
+
+    fn frame(message: Json) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    fn request(id: usize, method: &str, params: Json) -> String {
+        frame(Json::object(vec![
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(id)),
+            ("method", Json::from(method)),
+            ("params", params),
+        ]))
+    }
+
+    fn position(uri: &str, line: usize, character: usize) -> Json {
+        Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(uri))])),
+            ("position", Json::object(vec![
+                ("line", Json::from(line)),
+                ("character", Json::from(character)),
+            ])),
+        ])
+    }
+
+    #[test]
+    fn test_scripted_session() {
+        let uri = "file:///point.y";
+        let mut input = String::new();
+        input += &request(1, "initialize", Json::object(Vec::<(&str, Json)>::new()));
+        input += &frame(notification("textDocument/didOpen", Json::object(vec![
+            ("textDocument", Json::object(vec![
+                ("uri", Json::from(uri)),
+                ("version", Json::from(1usize)),
+                ("text", Json::from(SOURCE)),
+            ])),
+        ])));
+        // `p` in `p.norm()`, `x` in `p.x`, and `norm`
+        input += &request(2, "textDocument/definition", position(uri, 17, 4));
+        input += &request(3, "textDocument/references", position(uri, 3, 4));
+        input += &request(4, "textDocument/hover", position(uri, 17, 7));
+        input += &request(5, "textDocument/documentSymbol", position(uri, 0, 0));
+        input += &frame(notification("textDocument/didChange", Json::object(vec![
+            ("textDocument", Json::object(vec![("uri", Json::from(uri)), ("version", Json::from(2usize))])),
+            ("contentChanges", Json::from(vec![Json::object(vec![
+                ("text", Json::from(SOURCE.replace("p.norm();", "p."))),
+            ])])),
+        ])));
+        input += &request(6, "textDocument/completion", position(uri, 17, 6));
+        input += &request(7, "textDocument/formatting", position(uri, 0, 0));
+        input += &request(8, "shutdown", Json::Null);
+        input += &frame(notification("exit", Json::Null));
+
+        let mut output = vec![];
+        let code = Server::new().run(&mut Cursor::new(input), &mut output).unwrap();
+        assert_eq!(code, 0);
+
+        let mut output = Cursor::new(output);
+        let mut messages = vec![];
+        while let Some(body) = read_message(&mut output).unwrap() {
+            messages.push(Json::parse(&body).unwrap());
+        }
+        let reply = |id: usize| {
+            messages.iter()
+                .find(|message| message.get("id").and_then(Json::as_usize) == Some(id))
+                .unwrap()
+        };
+        let result = |id: usize| reply(id).get("result").unwrap();
+
+        assert!(result(1).get("capabilities").unwrap().get("hoverProvider").is_some());
+
+        // The string assigned to an `int` is reported
+        let diagnostics = messages[1].get("params").unwrap().get("diagnostics").unwrap().as_array().unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].get("range").unwrap().get("start").unwrap().get("line").unwrap().as_usize(), Some(15));
+
+        let definition = result(2).get("range").unwrap().get("start").unwrap();
+        assert_eq!(definition.get("line").unwrap().as_usize(), Some(14));
+        assert_eq!(definition.get("character").unwrap().as_usize(), Some(8));
+
+        // `self.x`, `Point { x: .. }` and `p.x`
+        assert_eq!(result(3).as_array().unwrap().len(), 3);
+
+        let hover = result(4).get("contents").unwrap().get("value").unwrap().as_str().unwrap();
+        assert!(hover.contains("func norm(self) -> float"));
+
+        let symbols = result(5).as_array().unwrap();
+        let names: Vec<_> = symbols.iter().map(|s| s.get("name").unwrap().as_str().unwrap()).collect();
+        assert_eq!(names, ["Point", "main"]);
+        assert_eq!(symbols[0].get("children").unwrap().as_array().unwrap().len(), 3);
+        let field = &symbols[0].get("children").unwrap().as_array().unwrap()[0];
+        let start = |key: &str| field.get(key).unwrap().get("start").unwrap().get("character").unwrap().as_usize();
+        assert_eq!(start("range"), start("selectionRange"));
+
+        let labels: Vec<_> = result(6).as_array().unwrap().iter()
+            .map(|item| item.get("label").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(labels, ["x", "y", "norm"]);
+
+        assert!(reply(7).get("error").is_some());
+    }
+}
@@ -0,0 +1,120 @@
+use std::io::{self, BufRead, Write};
+
+use crate::frontend::utils::token::Span;
+use crate::utils::json::Json;
+
+/// JSON-RPC error code for requests the server doesn't know
+pub const METHOD_NOT_FOUND: i64 = -32601;
+/// JSON-RPC error code for messages that aren't valid JSON
+pub const PARSE_ERROR: i64 = -32700;
+
+/// Reads one message framed by a `Content-Length` header.
+/// Returns `Ok(None)` when the input ends.
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut length = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing Content-Length header"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Message is not valid UTF-8"))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+pub fn response(id: Json, result: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id),
+        ("result", result),
+    ])
+}
+
+pub fn error_response(id: Json, code: i64, message: &str) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("id", id),
+        ("error", Json::object(vec![
+            ("code", Json::from(code)),
+            ("message", Json::from(message)),
+        ])),
+    ])
+}
+
+pub fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::from("2.0")),
+        ("method", Json::from(method)),
+        ("params", params),
+    ])
+}
+
+/// Converts an LSP position (0-based line, UTF-16 column) into a byte offset.
+pub fn position_to_offset(text: &str, position: &Json) -> Option<usize> {
+    let line = position.get("line")?.as_usize()?;
+    let character = position.get("character")?.as_usize()?;
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        line_start += text[line_start..].find('\n')? + 1;
+    }
+
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if units >= character || c == '\n' {
+            return Some(line_start + i);
+        }
+        units += c.len_utf16();
+    }
+    Some(text.len())
+}
+
+/// Converts a byte offset into an LSP position.
+pub fn offset_to_position(text: &str, offset: usize) -> Json {
+    let offset = offset.min(text.len());
+    let before = &text[..offset];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+
+    Json::object(vec![
+        ("line", Json::from(line)),
+        ("character", Json::from(character)),
+    ])
+}
+
+pub fn range(text: &str, span: &Span) -> Json {
+    Json::object(vec![
+        ("start", offset_to_position(text, span.start)),
+        ("end", offset_to_position(text, span.end)),
+    ])
+}
+
+pub fn location(uri: &str, text: &str, span: &Span) -> Json {
+    Json::object(vec![
+        ("uri", Json::from(uri)),
+        ("range", range(text, span)),
+    ])
+}
@@ -0,0 +1,241 @@
+use std::io::Write;
+
+use crate::errors::Error;
+use crate::frontend::{
+    lexer::Lexer,
+    parser::Parser,
+    utils::{
+        ast::*,
+        token::{Span, Token, TokenKind},
+        visitor::Visitor,
+    },
+};
+use crate::interpreter::{value::Value, Interpreter};
+use crate::sema::{
+    passes::{attributes::AttributeChecker, populate_table::FullSymbolTablePass, resolver::Resolver, type_checker::TypeChecker},
+    prelude,
+    utils::MultiStageSymbolTable,
+};
+
+/// The name inputs are given in diagnostics
+const NAME: &str = "<repl>";
+
+pub const HELP: &str = "\
+Enter declarations, statements or expressions; the value and type of expressions are printed.
+Input continues on the next line while braces, brackets or parentheses are unbalanced.
+
+Commands:
+    :type <expr>    Print the type of an expression without evaluating it
+    :ast <input>    Print the syntax tree of the input
+    :tokens <input> Print the tokens of the input
+    :help           Show this message
+    :quit           Leave the REPL";
+
+/// An interactive session.
+/// Definitions and bindings outlive the input that declared them: the symbol table
+/// and the interpreter's globals persist between inputs. An input that fails to
+/// check leaves both untouched.
+pub struct Repl {
+    table: MultiStageSymbolTable,
+    interpreter: Interpreter,
+}
+
+impl Repl {
+    /// Creates a REPL whose programs write their `io` output to `out`.
+    pub fn new(out: Box<dyn Write>) -> Self {
+        let mut repl = Repl {
+            table: MultiStageSymbolTable::new(),
+            interpreter: Interpreter::with_output(out),
+        };
+        // The prelude is defined as if it were the first input
+        let items = prelude::items();
+        if let Ok(checker) = repl.check(&items, "") {
+            repl.table = checker.table;
+            for item in &items {
+                let _ = repl.interpreter.run_item(item);
+            }
+        }
+        repl
+    }
+
+    /// Whether `input` is ready to be evaluated, or needs more lines to close its brackets.
+    pub fn is_complete(input: &str) -> bool {
+        let mut lexer = Lexer::new(input, NAME.to_string());
+        lexer.scan_tokens();
+
+        let depth: i32 = lexer.tokens.iter()
+            .map(|token| match token.kind {
+                TokenKind::Lbrace | TokenKind::Lbracket | TokenKind::Lparen => 1,
+                TokenKind::Rbrace | TokenKind::Rbracket | TokenKind::Rparen => -1,
+                _ => 0,
+            })
+            .sum();
+        depth <= 0
+    }
+
+    /// Evaluates one complete input, returning the text to show for it.
+    pub fn eval(&mut self, input: &str) -> Result<Option<String>, Vec<Error>> {
+        let input = input.trim();
+
+        if let Some(command) = input.strip_prefix(':') {
+            let (command, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+            let argument = argument.trim();
+            return match command {
+                "type" => self.type_command(argument).map(Some),
+                "ast" => parse(&source_of(argument)).map(|items| Some(format!("{:#?}", items))),
+                "tokens" => lex(argument).map(|tokens| {
+                    Some(tokens.iter().map(|token| token.to_string()).collect::<Vec<_>>().join("\n"))
+                }),
+                "help" => Ok(Some(HELP.to_string())),
+                _ => Err(vec![Error::new(format!("Unknown command ':{}', try ':help'", command), 1, Span::default(), NAME.to_string())]),
+            };
+        }
+        if input.is_empty() {
+            return Ok(None);
+        }
+
+        let source = source_of(input);
+        let items = parse(&source)?;
+        let mut checker = self.check(&items, &source)?;
+
+        // The checker doesn't keep the types it infers, so expression
+        // statements are inferred again for display.
+        let types: Vec<_> = items.iter()
+            .map(|item| match item {
+                StatementKind::Statement(Statement::Expr(expr)) => checker.infer_type(expr),
+                _ => None,
+            })
+            .collect();
+        self.table = checker.table;
+
+        let mut lines = vec![];
+        for (item, ty) in items.iter().zip(types) {
+            let value = self.interpreter.run_item(item).map_err(|error| {
+                vec![Error::new(error.message, 0, error.span, String::new()).locate(NAME, &source)]
+            })?;
+
+            let is_assignment = matches!(item, StatementKind::Statement(Statement::Expr(Expr::Assignment { .. })));
+            match (value, ty) {
+                (None | Some(Value::Void), _) => {}
+                (Some(_), _) if is_assignment => {}
+                (Some(value), Some(ty)) => lines.push(format!("{:?}: {}", value, ty)),
+                (Some(value), None) => lines.push(format!("{:?}", value)),
+            }
+        }
+
+        Ok(if lines.is_empty() { None } else { Some(lines.join("\n")) })
+    }
+
+    fn type_command(&self, argument: &str) -> Result<String, Vec<Error>> {
+        let source = source_of(argument);
+        let items = parse(&source)?;
+        let expr = match items.as_slice() {
+            [StatementKind::Statement(Statement::Expr(expr))] => expr,
+            _ => return Err(vec![Error::new("':type' expects a single expression".to_string(), 1, Span::default(), NAME.to_string())]),
+        };
+
+        let mut checker = self.check(&items, &source)?;
+        let ty = checker.infer_type(expr);
+        if !checker.errors.is_empty() {
+            return Err(locate(checker.errors, &source));
+        }
+        match ty {
+            Some(ty) => Ok(ty.to_string()),
+            None => Err(vec![Error::new("Unable to infer the type of this expression".to_string(), 0, expr.span(), String::new()).locate(NAME, &source)]),
+        }
+    }
+
+    /// Checks `items` against everything defined so far, without committing them.
+    fn check(&self, items: &[StatementKind], source: &str) -> Result<TypeChecker, Vec<Error>> {
+        let module = Module {
+            attributes: vec![],
+            name: Token::new(TokenKind::Identifier, "repl".to_string(), 1, Span::default()),
+            imports: vec![],
+            stmts: items.to_vec(),
+            span: Span::new(0, source.len()),
+        };
+        let internal_error = |message: String| vec![Error::new(message, 1, Span::default(), NAME.to_string())];
+
+        let mut pass = FullSymbolTablePass::new();
+        pass.table = self.table.clone();
+        pass.visit_module(&module).map_err(internal_error)?;
+
+        let mut attributes = AttributeChecker::new();
+        attributes.visit_module(&module).map_err(internal_error)?;
+
+        // Warnings aren't shown here: rebinding a name is normal in a REPL
+        let mut resolver = Resolver::with_globals(&self.table);
+        resolver.visit_module(&module).map_err(internal_error)?;
+        if !attributes.errors.is_empty() || !resolver.errors.is_empty() {
+            return Err(locate(attributes.errors.into_iter().chain(resolver.errors).collect(), source));
+        }
+
+        let mut checker = TypeChecker::new();
+        checker.table = pass.table;
+        checker.visit_module(&module).map_err(internal_error)?;
+
+        if !checker.errors.is_empty() {
+            return Err(locate(std::mem::take(&mut checker.errors), source));
+        }
+        Ok(checker)
+    }
+}
+
+/// Lets a lone expression or statement leave out its final `;`.
+fn source_of(input: &str) -> String {
+    let input = input.trim_end();
+    if input.is_empty() || input.ends_with(';') || input.ends_with('}') {
+        input.to_string()
+    } else {
+        format!("{};", input)
+    }
+}
+
+fn locate(errors: Vec<Error>, source: &str) -> Vec<Error> {
+    errors.into_iter().map(|error| error.locate(NAME, source)).collect()
+}
+
+fn lex(source: &str) -> Result<Vec<Token>, Vec<Error>> {
+    let mut lexer = Lexer::new(source, NAME.to_string());
+    lexer.scan_tokens();
+    if lexer.had_error {
+        return Err(locate(lexer.diagnostics, source));
+    }
+    Ok(lexer.tokens)
+}
+
+fn parse(source: &str) -> Result<Vec<StatementKind>, Vec<Error>> {
+    let tokens = lex(source)?;
+    let mut parser = Parser::new(&tokens, source, NAME.to_string());
+    let items = parser.parse_items();
+    if parser.errors > 0 {
+        return Err(locate(parser.diagnostics, source));
+    }
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repl_keeps_definitions() {
+        let mut repl = Repl::new(Box::new(std::io::sink()));
+        let mut eval = |input: &str| repl.eval(input);
+
+        assert_eq!(eval("let x: int = 40").unwrap(), None);
+        assert_eq!(eval("func add(a: int, b: int) -> int {\n    return a + b;\n}").unwrap(), None);
+        assert_eq!(eval("add(x, 2)").unwrap().as_deref(), Some("42: int"));
+        assert_eq!(eval(":type add(x, 1)").unwrap().as_deref(), Some("int"));
+
+        // A rejected input doesn't leave its definitions behind
+        assert!(eval("let y: int = true").is_err());
+        assert!(eval("y").is_err());
+        assert_eq!(eval("x = x + 1; x").unwrap().as_deref(), Some("41: int"));
+        assert_eq!(eval("let s = match (x) { 41 -> \"yes\", _ -> \"no\" }; s").unwrap().as_deref(), Some("\"yes\": string"));
+
+        assert!(!Repl::is_complete("func f() {\n    if true {"));
+        assert!(Repl::is_complete("func f() {\n    if true {}\n}"));
+        assert!(Repl::is_complete("\"{\""));
+    }
+}
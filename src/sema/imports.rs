@@ -0,0 +1,138 @@
+//! Modules imported from other files.
+//!
+//! `import "units" as units;` reads `units.y` next to the importing file. Its type
+//! aliases and newtypes are added to the importing module as `units.Name`, keeping
+//! their access modifier, and the type checker only lets the importer name the `pub`
+//! ones. Functions and other items are not exported yet.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::errors::{Error, Note};
+use crate::frontend::utils::{ast::*, token::{Span, Token}};
+use crate::interpreter::builtins::is_builtin_module;
+use crate::session::Session;
+
+/// `module` with the type aliases of the modules it imports from files added to it,
+/// which are found relative to `directory`, and the errors of imports that failed.
+/// The imports themselves are removed, since the runtime only provides the built-in modules.
+pub fn with_imports(mut module: Module, directory: &Path) -> (Module, Vec<Error>) {
+    let mut errors = vec![];
+    let mut stmts = Vec::with_capacity(module.stmts.len());
+    for item in module.stmts {
+        match item {
+            StatementKind::Import(import) if !is_builtin_module(import.path.lexeme.trim_matches('"')) => {
+                let aliases = exports(&import, directory, &mut errors);
+                stmts.extend(aliases.into_iter().map(StatementKind::TypeAlias));
+            }
+            item => stmts.push(item),
+        }
+    }
+    module.stmts = stmts;
+    (module, errors)
+}
+
+/// The type aliases of the module `import` names, qualified with its alias and
+/// placed at the import so that diagnostics about them point there.
+fn exports(import: &Import, directory: &Path, errors: &mut Vec<Error>) -> Vec<TypeAlias> {
+    let path = import.path.lexeme.trim_matches('"');
+    let file = directory.join(format!("{}.y", path));
+    let Ok(source) = fs::read_to_string(&file) else {
+        let mut error = Error::new(format!("Unknown module '{}'", path), 0, import.path.span.clone(), String::new());
+        error.add_note(Note::new(format!("No file '{}' was found", file.display()), 0, import.path.span.clone(), String::new()));
+        errors.push(error);
+        return vec![];
+    };
+    let imported = match Session::new(file.display().to_string(), source).parse() {
+        Ok(imported) => imported,
+        Err(parse_errors) => {
+            let mut error = Error::new(format!("Unable to parse module '{}'", path), 0, import.path.span.clone(), String::new());
+            for parse_error in parse_errors {
+                let message = format!("{}:{}: {}", parse_error.filename, parse_error.line, parse_error.message);
+                error.add_note(Note::new(message, 0, import.path.span.clone(), String::new()));
+            }
+            errors.push(error);
+            return vec![];
+        }
+    };
+
+    let aliases: Vec<&TypeAlias> = imported.stmts.iter()
+        .filter_map(|item| match item {
+            StatementKind::TypeAlias(alias) => Some(alias),
+            _ => None,
+        })
+        .collect();
+    let names: HashSet<&str> = aliases.iter().map(|alias| alias.name.lexeme.as_str()).collect();
+    let qualifier = &import.alias.lexeme;
+
+    aliases.iter()
+        .map(|alias| {
+            let mut name = import.alias.clone();
+            name.lexeme = format!("{}.{}", qualifier, alias.name.lexeme);
+            TypeAlias {
+                access: alias.access.clone(),
+                name,
+                ty: qualify(&alias.ty, qualifier, &names, &import.span),
+                newtype: alias.newtype,
+                span: import.span.clone(),
+            }
+        })
+        .collect()
+}
+
+/// `ty` as seen from the importing module: names of the imported module's aliases
+/// are qualified with `module`, and every span is moved to the import.
+fn qualify(ty: &Type, module: &str, names: &HashSet<&str>, span: &Span) -> Type {
+    let each = |types: &[Type]| types.iter().map(|ty| qualify(ty, module, names, span)).collect();
+    let boxed = |ty: &Type| Box::new(qualify(ty, module, names, span));
+    let at_import = |name: &Token| {
+        let mut name = name.clone();
+        name.span = span.clone();
+        name
+    };
+
+    match ty {
+        Type::Named { name, generics, .. } => {
+            let mut name = at_import(name);
+            if names.contains(name.lexeme.as_str()) {
+                name.lexeme = format!("{}.{}", module, name.lexeme);
+            }
+            Type::Named { name, generics: each(generics), span: span.clone() }
+        }
+        Type::Primitive { name, .. } => Type::Primitive { name: at_import(name), span: span.clone() },
+        Type::TypeVar { name, .. } => Type::TypeVar { name: at_import(name), span: span.clone() },
+        Type::Array { element, size, .. } => Type::Array { element: boxed(element), size: *size, span: span.clone() },
+        Type::Tuple { elements, .. } => Type::Tuple { elements: each(elements), span: span.clone() },
+        Type::Function { params, return_type, .. } => Type::Function {
+            params: each(params),
+            return_type: boxed(return_type),
+            span: span.clone(),
+        },
+        Type::Optional { inner, .. } => Type::Optional { inner: boxed(inner), span: span.clone() },
+        Type::Dyn { trait_, .. } => Type::Dyn { trait_: boxed(trait_), span: span.clone() },
+        Type::Error(_) => Type::Error(span.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Compiler;
+
+    #[test]
+    fn test_imported_aliases() {
+        let directory = std::env::temp_dir().join(format!("y-imports-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("units.y"), "module units;\n\npub type Meters = float;\npub type Route = [Meters];\ntype Secret = string;\n").unwrap();
+
+        let main = directory.join("main.y").display().to_string();
+        let source = "module main;\nimport \"units\" as units;\n\nfunc main() {\n    let route: units.Route = [1.5];\n    let first: units.Meters = route[0];\n    let secret: units.Secret = \"\";\n}\n";
+        let errors = Compiler::new().check_str(&main, source).unwrap_err();
+        let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, ["Type 'Secret' is private to module 'units'"]);
+
+        let errors = Compiler::new().check_str(&main, "module main;\nimport \"metres\" as metres;\n").unwrap_err();
+        assert_eq!(errors[0].message, "Unknown module 'metres'");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
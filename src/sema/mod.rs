@@ -1,2 +1,4 @@
 pub mod utils;
-pub mod passes;
\ No newline at end of file
+pub mod imports;
+pub mod passes;
+pub mod prelude;
\ No newline at end of file
@@ -0,0 +1,158 @@
+use crate::errors::{Error, Help, Warning};
+use crate::frontend::utils::ast::*;
+use crate::frontend::utils::token::{Span, Token, TokenKind};
+use crate::frontend::utils::visitor::{Visitor, walk_module};
+use crate::utils::closest_match;
+
+/// What an attribute is written on
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Target {
+    Module,
+    Function,
+    Struct,
+    Field,
+    Enum,
+}
+
+/// The arguments a built-in attribute takes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Arguments {
+    None,
+    /// An optional string, e.g. `#[deprecated("Use 'parse' instead")]`
+    Message,
+    /// Exactly one string, e.g. `#[extern_name("c_add")]`
+    String,
+    /// One or more names, e.g. `#[derive(Eq, Debug)]`
+    Names,
+}
+
+/// The attributes later passes act on, with the declarations they apply to
+const BUILTIN_ATTRIBUTES: &[(&str, &[Target], Arguments)] = &[
+    ("test", &[Target::Function], Arguments::None),
+    ("inline", &[Target::Function], Arguments::None),
+    ("deprecated", &[Target::Function, Target::Struct, Target::Enum], Arguments::Message),
+    ("derive", &[Target::Struct, Target::Enum], Arguments::Names),
+    ("extern_name", &[Target::Function], Arguments::String),
+];
+
+impl Target {
+    fn plural(self) -> &'static str {
+        match self {
+            Target::Module => "modules",
+            Target::Function => "functions",
+            Target::Struct => "structs",
+            Target::Field => "fields",
+            Target::Enum => "enums",
+        }
+    }
+}
+
+/// Checks that every attribute is known, is written on a declaration it applies to
+/// and has the right arguments. Unknown attributes are only warned about.
+#[derive(Debug, Default)]
+pub struct AttributeChecker {
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Warning>,
+}
+
+impl AttributeChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn error(&mut self, message: String, span: &Span) -> &mut Error {
+        self.errors.push(Error::new(message, 0, span.clone(), String::new()));
+        self.errors.last_mut().unwrap()
+    }
+
+    fn attributes(&mut self, attributes: &[Attribute], target: Target) {
+        for attribute in attributes {
+            let name = &attribute.name.lexeme;
+            let Some(&(_, targets, arguments)) = BUILTIN_ATTRIBUTES.iter().find(|(builtin, ..)| builtin == name) else {
+                let mut warning = Warning::new(format!("Unknown attribute '#[{}]'", name), 0, attribute.span.clone(), String::new());
+                if let Some(suggestion) = closest_match(name, BUILTIN_ATTRIBUTES.iter().map(|(builtin, ..)| *builtin)) {
+                    warning.add_help(Help::new(format!("Did you mean '#[{}]'?", suggestion), 0, attribute.name.span.clone(), String::new()));
+                }
+                self.warnings.push(warning);
+                continue;
+            };
+
+            if !targets.contains(&target) {
+                let names: Vec<&str> = targets.iter().map(|target| target.plural()).collect();
+                let applies_to = match names.split_last() {
+                    Some((last, [])) => last.to_string(),
+                    Some((last, rest)) => format!("{} and {}", rest.join(", "), last),
+                    None => unreachable!("every built-in attribute applies to something"),
+                };
+                self.error(format!("'#[{}]' can only be applied to {}, not {}", name, applies_to, target.plural()), &attribute.span);
+                continue;
+            }
+
+            let is_string = |arg: &Token| arg.kind == TokenKind::String;
+            let (valid, usage) = match arguments {
+                Arguments::None => (attribute.args.is_empty(), format!("#[{}]", name)),
+                Arguments::Message => (attribute.args.len() <= 1 && attribute.args.iter().all(is_string), format!("#[{}(\"message\")]", name)),
+                Arguments::String => (attribute.args.len() == 1 && is_string(&attribute.args[0]), format!("#[{}(\"name\")]", name)),
+                Arguments::Names => (!attribute.args.is_empty() && attribute.args.iter().all(|arg| arg.kind == TokenKind::Identifier), format!("#[{}(Eq, Debug)]", name)),
+            };
+            if !valid {
+                let error = self.error(format!("Invalid arguments for '#[{}]'", name), &attribute.span);
+                error.add_help(Help::new(format!("Write it as '{}'", usage), 0, attribute.span.clone(), String::new()));
+            }
+        }
+    }
+}
+
+impl Visitor for AttributeChecker {
+    fn visit_module(&mut self, module: &Module) -> Result<(), String> {
+        self.attributes(&module.attributes, Target::Module);
+        walk_module(self, module)
+    }
+
+    fn visit_function(&mut self, function: &Function) -> Result<(), String> {
+        self.attributes(&function.attributes, Target::Function);
+        Ok(())
+    }
+
+    fn visit_struct(&mut self, structure: &Struct) -> Result<(), String> {
+        self.attributes(&structure.attributes, Target::Struct);
+        for field in &structure.fields {
+            self.attributes(&field.attributes, Target::Field);
+        }
+        Ok(())
+    }
+
+    fn visit_enum(&mut self, enumeration: &Enum) -> Result<(), String> {
+        self.attributes(&enumeration.attributes, Target::Enum);
+        Ok(())
+    }
+
+    // Attributes are only written on declarations
+    fn visit_statement(&mut self, _statement: &Statement) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+
+    #[test]
+    fn test_attributes() {
+        let module = Compiler::new().parse_str("test.y", "#[deprecated]\nmodule test;\n\n#[derive(Eq, Debug)]\nstruct Point {\n    #[inline]\n    x: int,\n}\n\n#[tset]\nfunc sum() -> int {\n    return 0;\n}\n\n#[extern_name(sum)]\nfunc main() {\n    sum();\n}\n").unwrap();
+        let mut checker = AttributeChecker::new();
+        checker.visit_module(&module).unwrap();
+
+        let errors: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(errors, [
+            "'#[deprecated]' can only be applied to functions, structs and enums, not modules",
+            "'#[inline]' can only be applied to functions, not fields",
+            "Invalid arguments for '#[extern_name]'",
+        ]);
+
+        let warnings: Vec<_> = checker.warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(warnings, ["Unknown attribute '#[tset]'"]);
+        assert_eq!(checker.warnings[0].helps[0].message, "Did you mean '#[test]'?");
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::frontend::utils::ast::*;
+use crate::sema::passes::type_checker::substitute;
+use crate::sema::utils::{symbol_table::{Symbol, SymbolKind}, MultiStageSymbolTable};
+
+/// How many missing patterns are listed before the rest are summarised
+const MAX_WITNESSES: usize = 3;
+
+/// Checks which cases of a `match` can never be chosen and which values no case matches.
+///
+/// Patterns are reduced to constructors applied to sub-patterns, and a case is useful when some
+/// value matches it but none of the cases before it. That a value matches no case at all is the
+/// same question asked of a `_` after the last case; the value found is the witness reported.
+/// See Maranget, "Warnings for pattern matching" (2007).
+pub struct MatchChecker<'a> {
+    table: &'a MultiStageSymbolTable,
+}
+
+/// The outcome of checking one `match`
+#[derive(Debug, Default)]
+pub struct MatchReport {
+    /// The indices of the cases no value reaches
+    pub unreachable: Vec<usize>,
+    /// Patterns for some of the values that no case matches
+    pub missing: Vec<Pat>,
+    /// Whether there are more missing values than those in `missing`
+    pub more_missing: bool,
+}
+
+/// A pattern reduced to what matters for matching
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pat {
+    /// Matches anything, like `_` or a binding
+    Wild,
+    Ctor(Ctor, Vec<Pat>),
+    /// Matches what any of the patterns match
+    Or(Vec<Pat>),
+}
+
+/// What a value is built from, e.g. `true`, a tuple of two or `Shape.Circle`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ctor {
+    Bool(bool),
+    /// An integer, or a character by its code point
+    Int(i64),
+    /// The integers or characters from the first to the second, inclusive
+    Range(i64, i64),
+    /// Any other literal, e.g. a string
+    Literal(String),
+    Tuple(usize),
+    /// A struct, with the names of its fields in declaration order
+    Struct(Vec<String>),
+    Variant { name: String, arity: usize },
+}
+
+impl Ctor {
+    fn arity(&self) -> usize {
+        match self {
+            Ctor::Bool(_) | Ctor::Int(_) | Ctor::Range(..) | Ctor::Literal(_) => 0,
+            Ctor::Tuple(arity) | Ctor::Variant { arity, .. } => *arity,
+            Ctor::Struct(fields) => fields.len(),
+        }
+    }
+
+    /// Whether every value `other` builds is also built by this constructor.
+    fn covers(&self, other: &Ctor) -> bool {
+        match (self, other) {
+            (Ctor::Range(start, end), Ctor::Int(value)) => start <= value && value <= end,
+            (Ctor::Range(start, end), Ctor::Range(other_start, other_end)) => start <= other_start && other_end <= end,
+            _ => self == other,
+        }
+    }
+}
+
+impl fmt::Display for Pat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn list(pats: &[Pat]) -> String {
+            pats.iter().map(|pat| pat.to_string()).collect::<Vec<_>>().join(", ")
+        }
+
+        match self {
+            Pat::Wild => write!(f, "_"),
+            Pat::Ctor(Ctor::Bool(value), _) => write!(f, "{}", value),
+            Pat::Ctor(Ctor::Int(value), _) => write!(f, "{}", value),
+            Pat::Ctor(Ctor::Range(start, end), _) => write!(f, "{}..={}", start, end),
+            Pat::Ctor(Ctor::Literal(value), _) => write!(f, "{}", value),
+            Pat::Ctor(Ctor::Tuple(_), pats) => write!(f, "({})", list(pats)),
+            Pat::Ctor(Ctor::Struct(fields), pats) => {
+                let fields: Vec<String> = fields.iter().zip(pats).map(|(field, pat)| format!("{}: {}", field, pat)).collect();
+                write!(f, "{{ {} }}", fields.join(", "))
+            }
+            Pat::Ctor(Ctor::Variant { name, .. }, pats) if pats.is_empty() => write!(f, "{}", name),
+            Pat::Ctor(Ctor::Variant { name, .. }, pats) => write!(f, "{}({})", name, list(pats)),
+            Pat::Or(pats) => write!(f, "{}", pats.iter().map(|pat| pat.to_string()).collect::<Vec<_>>().join(" | ")),
+        }
+    }
+}
+
+impl<'a> MatchChecker<'a> {
+    pub fn new(table: &'a MultiStageSymbolTable) -> Self {
+        MatchChecker { table }
+    }
+
+    /// Checks the cases of a `match` on a value of type `ty`, which is `None` when unknown.
+    pub fn check(&self, ty: Option<&Type>, cases: &[Case]) -> MatchReport {
+        let types = vec![ty.cloned()];
+        let mut report = MatchReport::default();
+        let mut rows: Vec<Vec<Pat>> = vec![];
+        for (i, case) in cases.iter().enumerate() {
+            let row = vec![self.lower(&case.pattern, ty)];
+            if self.useful(&rows, &row, &types).is_none() {
+                report.unreachable.push(i);
+            }
+            // A case with a guard may not match even the values its pattern does
+            if case.guard.is_none() {
+                rows.push(row);
+            }
+        }
+
+        // Without any cases, the variants of an enum say more than `_`
+        if let Some(ctors) = ty.and_then(|ty| self.constructors(ty)).filter(|ctors| cases.is_empty() && ctors.len() > 1) {
+            report.missing = ctors.iter().take(MAX_WITNESSES).map(|ctor| Pat::Ctor(ctor.clone(), vec![Pat::Wild; ctor.arity()])).collect();
+            report.more_missing = ctors.len() > MAX_WITNESSES;
+            return report;
+        }
+
+        // Each witness found is added as a case, so that the next one is a different value
+        while let Some(mut witness) = self.useful(&rows, &[Pat::Wild], &types) {
+            if report.missing.len() == MAX_WITNESSES {
+                report.more_missing = true;
+                break;
+            }
+            let pat = witness.remove(0);
+            let done = pat == Pat::Wild;
+            report.missing.push(pat.clone());
+            rows.push(vec![pat]);
+            if done {
+                break;
+            }
+        }
+        report
+    }
+
+    /// Reduces `pattern`, matched against a value of type `ty`, to constructors.
+    fn lower(&self, pattern: &Pattern, ty: Option<&Type>) -> Pat {
+        match pattern {
+            Pattern::Identifier(..) | Pattern::Wildcard(_) | Pattern::Error => Pat::Wild,
+            Pattern::Binding { pattern, .. } => self.lower(pattern, ty),
+            Pattern::Or(alternatives, _) => Pat::Or(alternatives.iter().map(|pattern| self.lower(pattern, ty)).collect()),
+            Pattern::Literal(Literal::Bool(value, _)) => Pat::Ctor(Ctor::Bool(*value), vec![]),
+            Pattern::Literal(literal) => match ordinal(literal) {
+                Some(value) => Pat::Ctor(Ctor::Int(value), vec![]),
+                None => Pat::Ctor(Ctor::Literal(literal_text(literal)), vec![]),
+            },
+            Pattern::Range { start, end, inclusive, .. } => match (ordinal(start), ordinal(end)) {
+                (Some(start), Some(end)) => Pat::Ctor(Ctor::Range(start, if *inclusive { end } else { end - 1 }), vec![]),
+                // Matches nothing else, so it covers no other case
+                _ => Pat::Ctor(Ctor::Literal(format!("{}..{}", literal_text(start), literal_text(end))), vec![]),
+            },
+            Pattern::Variant { enum_name, name, fields, .. } => {
+                let enum_name = match ty {
+                    Some(Type::Named { name, .. }) => Some(name.lexeme.clone()),
+                    _ => enum_name.as_ref().map(|name| name.lexeme.clone()),
+                };
+                let full_name = match enum_name {
+                    Some(enum_name) => format!("{}.{}", enum_name, name.lexeme),
+                    None => name.lexeme.clone(),
+                };
+                let ctor = ty.and_then(|ty| self.constructors(ty)).into_iter().flatten()
+                    .find(|ctor| matches!(ctor, Ctor::Variant { name, .. } if *name == full_name))
+                    .unwrap_or(Ctor::Variant { name: full_name, arity: fields.len() });
+                // A pattern with the wrong number of fields has been reported already
+                let types = self.field_types(&ctor, ty);
+                let pats = types.iter().enumerate()
+                    .map(|(i, ty)| fields.get(i).map_or(Pat::Wild, |pattern| self.lower(pattern, ty.as_ref())))
+                    .collect();
+                Pat::Ctor(ctor, pats)
+            }
+            Pattern::Tuple(patterns, _) => {
+                let ctor = Ctor::Tuple(patterns.len());
+                let types = self.field_types(&ctor, ty);
+                let pats = patterns.iter().zip(types).map(|(pattern, ty)| self.lower(pattern, ty.as_ref())).collect();
+                Pat::Ctor(ctor, pats)
+            }
+            Pattern::Struct { fields, .. } => {
+                // Fields a pattern leaves out match anything
+                let ctor = match ty.and_then(|ty| self.constructors(ty)) {
+                    Some(ctors) if matches!(ctors.as_slice(), [Ctor::Struct(_)]) => ctors[0].clone(),
+                    _ => Ctor::Struct(fields.iter().map(|(name, _)| name.lexeme.clone()).collect()),
+                };
+                let Ctor::Struct(names) = &ctor else { unreachable!() };
+                let types = self.field_types(&ctor, ty);
+                let pats = names.iter().zip(types)
+                    .map(|(name, ty)| match fields.iter().find(|(field, _)| &field.lexeme == name) {
+                        Some((_, pattern)) => self.lower(pattern, ty.as_ref()),
+                        None => Pat::Wild,
+                    })
+                    .collect();
+                Pat::Ctor(ctor, pats)
+            }
+        }
+    }
+
+    /// Every constructor of `ty`, or `None` when there are too many to list.
+    fn constructors(&self, ty: &Type) -> Option<Vec<Ctor>> {
+        match ty {
+            Type::Primitive { name, .. } if name.lexeme == "bool" => Some(vec![Ctor::Bool(true), Ctor::Bool(false)]),
+            Type::Tuple { elements, .. } => Some(vec![Ctor::Tuple(elements.len())]),
+            Type::Named { name, .. } => {
+                let symbol = self.table.types.get(&name.lexeme)?;
+                match symbol.kind {
+                    SymbolKind::Struct => {
+                        let fields = symbol.struct_fields.as_ref()?;
+                        Some(vec![Ctor::Struct(fields.iter().map(|field| field.name.lexeme.clone()).collect())])
+                    }
+                    SymbolKind::Enum => Some(symbol.enum_variants.as_ref()?.iter()
+                        .map(|variant| Ctor::Variant {
+                            name: format!("{}.{}", name.lexeme, variant.name.lexeme),
+                            arity: variant.fields.len(),
+                        })
+                        .collect()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// The types of the values `ctor` is built from, when it builds a value of type `ty`.
+    fn field_types(&self, ctor: &Ctor, ty: Option<&Type>) -> Vec<Option<Type>> {
+        let unknown = vec![None; ctor.arity()];
+        match (ctor, ty) {
+            (Ctor::Tuple(arity), Some(Type::Tuple { elements, .. })) if elements.len() == *arity => {
+                elements.iter().cloned().map(Some).collect()
+            }
+            (Ctor::Struct(names), Some(Type::Named { name, generics, .. })) => {
+                let Some(symbol) = self.table.types.get(&name.lexeme) else { return unknown };
+                let bindings = bindings(symbol, generics);
+                let fields = symbol.struct_fields.iter().flatten();
+                names.iter()
+                    .map(|name| fields.clone().find(|field| &field.name.lexeme == name).map(|field| substitute(&field.ty, &bindings)))
+                    .collect()
+            }
+            (Ctor::Variant { name: variant, .. }, Some(Type::Named { name, generics, .. })) => {
+                let Some(symbol) = self.table.types.get(&name.lexeme) else { return unknown };
+                let bindings = bindings(symbol, generics);
+                match symbol.enum_variants.iter().flatten().find(|candidate| format!("{}.{}", name.lexeme, candidate.name.lexeme) == *variant) {
+                    Some(found) => found.fields.iter().map(|field| Some(substitute(field, &bindings))).collect(),
+                    None => unknown,
+                }
+            }
+            _ => unknown,
+        }
+    }
+
+    /// Whether some value matched by `row` is matched by none of `rows`, returning a pattern
+    /// for such a value; `types` are the types of the columns.
+    fn useful(&self, rows: &[Vec<Pat>], row: &[Pat], types: &[Option<Type>]) -> Option<Vec<Pat>> {
+        let Some((head, rest)) = row.split_first() else {
+            return rows.is_empty().then(Vec::new);
+        };
+        let ty = types[0].as_ref();
+        let rows = &expand(rows);
+
+        if let Pat::Or(alternatives) = head {
+            return alternatives.iter().find_map(|alternative| self.useful(rows, &[vec![alternative.clone()], rest.to_vec()].concat(), types));
+        }
+        if let Pat::Ctor(ctor, pats) = head {
+            let witness = self.useful_with(ctor, rows, &[pats.as_slice(), rest].concat(), types)?;
+            return Some(rebuild(ctor, witness));
+        }
+
+        let seen: Vec<&Ctor> = rows.iter()
+            .filter_map(|row| match &row[0] {
+                Pat::Ctor(ctor, _) => Some(ctor),
+                Pat::Wild | Pat::Or(_) => None,
+            })
+            .collect();
+        let all = ty.and_then(|ty| self.constructors(ty));
+        let unseen: Vec<&Ctor> = match &all {
+            Some(all) => all.iter().filter(|ctor| !seen.contains(ctor)).collect(),
+            None => vec![],
+        };
+
+        match &all {
+            // Every constructor is matched somewhere, so try each in turn
+            Some(all) if unseen.is_empty() && !seen.is_empty() => all.iter().find_map(|ctor| {
+                let wilds = vec![Pat::Wild; ctor.arity()];
+                let witness = self.useful_with(ctor, rows, &[wilds.as_slice(), rest].concat(), types)?;
+                Some(rebuild(ctor, witness))
+            }),
+            // Otherwise only the cases that match anything here can cover the rest
+            _ => {
+                let defaults: Vec<Vec<Pat>> = rows.iter()
+                    .filter(|row| row[0] == Pat::Wild)
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+                let mut witness = self.useful(&defaults, rest, &types[1..])?;
+                let head = match unseen.first() {
+                    Some(ctor) if !seen.is_empty() => Pat::Ctor((*ctor).clone(), vec![Pat::Wild; ctor.arity()]),
+                    _ => Pat::Wild,
+                };
+                witness.insert(0, head);
+                Some(witness)
+            }
+        }
+    }
+
+    /// `useful` for the values built by `ctor`, where `row` already has the constructor's
+    /// fields in place of its first column.
+    fn useful_with(&self, ctor: &Ctor, rows: &[Vec<Pat>], row: &[Pat], types: &[Option<Type>]) -> Option<Vec<Pat>> {
+        let specialised: Vec<Vec<Pat>> = rows.iter().filter_map(|row| specialise(ctor, row)).collect();
+        let types = [self.field_types(ctor, types[0].as_ref()).as_slice(), &types[1..]].concat();
+        self.useful(&specialised, row, &types)
+    }
+}
+
+/// The arguments a use of the struct or enum `symbol` gives to its generic parameters.
+fn bindings(symbol: &Symbol, arguments: &[Type]) -> HashMap<String, Type> {
+    match &symbol.ty {
+        Some(Type::Named { generics: params, .. }) => params.iter().map(|param| param.to_string()).zip(arguments.iter().cloned()).collect(),
+        _ => HashMap::new(),
+    }
+}
+
+/// The row for the values built by `ctor` that `row` matches, with the constructor's fields in
+/// place of its first column, or `None` when the row matches none of them.
+fn specialise(ctor: &Ctor, row: &[Pat]) -> Option<Vec<Pat>> {
+    let fields = match &row[0] {
+        Pat::Ctor(other, pats) if other == ctor => pats.clone(),
+        Pat::Ctor(other, _) if other.covers(ctor) => vec![],
+        Pat::Ctor(..) | Pat::Or(_) => return None,
+        Pat::Wild => vec![Pat::Wild; ctor.arity()],
+    };
+    Some([fields.as_slice(), &row[1..]].concat())
+}
+
+/// Replaces each row starting with an or-pattern by one row per alternative.
+fn expand(rows: &[Vec<Pat>]) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .flat_map(|row| match &row[0] {
+            Pat::Or(alternatives) => {
+                let rows: Vec<Vec<Pat>> = alternatives.iter().map(|alternative| [vec![alternative.clone()], row[1..].to_vec()].concat()).collect();
+                expand(&rows)
+            }
+            _ => vec![row.clone()],
+        })
+        .collect()
+}
+
+/// The number an integer or character literal stands for, by which ranges are compared.
+fn ordinal(literal: &Literal) -> Option<i64> {
+    match literal {
+        Literal::Integer(value, _) => Some(*value),
+        // A character, i.e. a string of one
+        Literal::Token(token, _) => {
+            let mut chars = token.lexeme.chars().skip(1);
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(c), Some(_), None) => Some(c as i64),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn literal_text(literal: &Literal) -> String {
+    match literal {
+        Literal::Integer(value, _) => value.to_string(),
+        Literal::Float(value, _) => value.to_string(),
+        Literal::Bool(value, _) => value.to_string(),
+        Literal::Null(_) => "null".to_string(),
+        Literal::Token(token, _) => token.lexeme.clone(),
+    }
+}
+
+/// Puts the witness for the fields of `ctor` back together.
+fn rebuild(ctor: &Ctor, mut witness: Vec<Pat>) -> Vec<Pat> {
+    let rest = witness.split_off(ctor.arity());
+    [vec![Pat::Ctor(ctor.clone(), witness)], rest].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Compiler;
+
+    const PRELUDE: &str = "module test;\n\nstruct Point {\n    x: int,\n    on: bool,\n}\n\nenum Shape {\n    Circle(float),\n    Square(float),\n    Empty,\n}\n";
+
+    /// The errors and warnings for a match on `value`, whose cases are separated by `;`.
+    fn check(value: &str, cases: &str) -> Vec<String> {
+        let cases: String = cases.split(';').map(|case| format!("        {} -> {{ }},\n", case.trim())).collect();
+        let source = format!("{}\nfunc main() {{\n    match ({}) {{\n{}    }}\n}}\n", PRELUDE, value, cases);
+        let mut session = Compiler::new().session("test.y", source);
+        match session.check() {
+            Ok(_) => session.warnings.into_iter().map(|warning| warning.message).collect(),
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    #[test]
+    fn test_exhaustiveness() {
+        assert_eq!(check("(true, false)", "(true, _)"), ["Non-exhaustive match: '(false, _)' not covered"]);
+        assert_eq!(check("(true, false)", "(true, _); (false, true)"), ["Non-exhaustive match: '(false, false)' not covered"]);
+        assert_eq!(check("(true, false)", "(_, true); (true, false); (false, _)"), Vec::<String>::new());
+        assert_eq!(check("Point { x: 1, on: true }", "{ on: true }"), ["Non-exhaustive match: '{ x: _, on: false }' not covered"]);
+        assert_eq!(check("Shape.Empty", "_"), Vec::<String>::new());
+        assert_eq!(check("1", "1; 2"), ["Non-exhaustive match: '_' not covered"]);
+        assert_eq!(check("\"a\"", "\"a\"; s"), Vec::<String>::new());
+
+        let source = format!("{}\nfunc main(shape: Shape) {{\n    match (shape) {{\n    }}\n}}\n", PRELUDE);
+        let errors = Compiler::new().check_str("test.y", &source).unwrap_err();
+        assert_eq!(errors[0].message, "Non-exhaustive match: 'Shape.Circle(_)', 'Shape.Square(_)' and 'Shape.Empty' not covered");
+
+        assert_eq!(check("(true, 1)", "(true, _); (false, n); (_, 2)"), ["Unreachable match case"]);
+        assert_eq!(check("1", "n; _"), ["Unreachable match case"]);
+    }
+
+    #[test]
+    fn test_pattern_forms() {
+        let value = "Shape.Circle(1.0)";
+        assert_eq!(check(value, "Shape.Circle(_) | Shape.Square(_); Empty()"), Vec::<String>::new());
+        assert_eq!(check(value, "Shape.Circle(r) if r > 1.0; Square(_); Empty()"), ["Non-exhaustive match: 'Shape.Circle(_)' not covered"]);
+        assert_eq!(check(value, "s @ Circle(_); Square(_) | Empty(); Empty()"), ["Unreachable match case"]);
+        assert_eq!(check(value, "Circle(_, _); _"), ["Variant 'Shape.Circle' has 1 field, but the pattern has 2"]);
+        assert_eq!(check("5", "1 | 2; 3..10; 10..=100"), ["Non-exhaustive match: '_' not covered"]);
+        assert_eq!(check("5", "0..10; 3..=7; _"), ["Unreachable match case"]);
+        assert_eq!(check("5", "10..0; _"), ["Range pattern matches no value"]);
+        assert_eq!(check("(1, true)", "(a, true) | (a, false)"), Vec::<String>::new());
+        assert_eq!(check("(1, true)", "(a, true) | (_, false)"), ["'a' is not bound in every alternative of the pattern"]);
+    }
+}
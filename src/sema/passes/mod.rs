@@ -1,2 +1,6 @@
+pub mod attributes;
+pub mod exhaustiveness;
+pub mod monomorphise;
 pub mod populate_table;
+pub mod resolver;
 pub mod type_checker;
\ No newline at end of file
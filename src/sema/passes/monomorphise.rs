@@ -0,0 +1,660 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::errors::{Error, Help, Note};
+use crate::frontend::utils::ast::*;
+use crate::frontend::utils::token::{Span, Token};
+use crate::sema::passes::type_checker::{has_inference_vars, substitute, Instantiation};
+
+/// How many instances of one generic item may lead to one another before the
+/// instantiation is considered infinite, as in `func f<T>(x: T) { f([x]); }`
+const RECURSION_LIMIT: usize = 8;
+
+/// Replaces the generic structs, functions and `extend` blocks of a checked module with one
+/// copy per set of type arguments the program uses them with, for backends that need
+/// every type to be known.
+///
+/// Starting from the non-generic items, every use of a generic item instantiates it with the
+/// types the type checker inferred there. Each instance is a clone of the item with its
+/// generic parameters substituted and is named after its arguments, e.g. `Foo<[int]>` or
+/// `apply<int, string>`; the uses are renamed to match.
+pub struct Monomorphiser<'a> {
+    module: &'a Module,
+    instantiations: &'a HashMap<Span, Instantiation>,
+    binding_types: &'a HashMap<Span, Type>,
+    functions: HashMap<&'a str, &'a Function>,
+    structs: HashMap<&'a str, &'a Struct>,
+    traits: HashMap<&'a str, &'a Trait>,
+    /// The names of the instances created so far
+    instances: HashSet<String>,
+    /// Instances whose bodies are still to be copied
+    queue: VecDeque<Pending<'a>>,
+    /// The generic items whose infinite instantiation has been reported
+    reported: HashSet<String>,
+    struct_instances: Vec<Struct>,
+    function_instances: Vec<Function>,
+    /// The monomorphic `extend` blocks, one per type and trait
+    extends: Vec<Extend>,
+    pub errors: Vec<Error>,
+}
+
+/// An instance waiting to be built
+struct Pending<'a> {
+    item: Item<'a>,
+    name: String,
+    context: Context,
+}
+
+enum Item<'a> {
+    Function(&'a Function),
+    Struct(&'a Struct),
+    /// A method of `extend`, or a default of the trait it implements, copied as `method`
+    /// for the type instance named `receiver`
+    Method {
+        function: &'a Function,
+        method: String,
+        receiver: String,
+        extend: &'a Extend,
+    },
+}
+
+/// Where a method called on some type is defined
+#[derive(Clone, Copy)]
+enum Owner<'a> {
+    Extend(&'a Extend),
+    Trait(&'a Trait),
+}
+
+/// What the generic parameters stand for in the item being copied, and how it came to be needed.
+#[derive(Default, Clone)]
+struct Context {
+    bindings: HashMap<String, Type>,
+    chain: Vec<Link>,
+}
+
+/// One instance in the chain of instantiations leading to another
+#[derive(Clone)]
+struct Link {
+    /// The generic item, e.g. "f"
+    item: String,
+    /// The instance, e.g. "f<[int]>"
+    instance: String,
+    /// The use that required the instance
+    span: Span,
+}
+
+impl<'a> Monomorphiser<'a> {
+    pub fn new(module: &'a Module, instantiations: &'a HashMap<Span, Instantiation>, binding_types: &'a HashMap<Span, Type>) -> Self {
+        let mut functions = HashMap::new();
+        let mut structs = HashMap::new();
+        let mut traits = HashMap::new();
+        for item in &module.stmts {
+            match item {
+                StatementKind::Function(function) => { functions.insert(function.name.lexeme.as_str(), function); }
+                StatementKind::Struct(structure) => { structs.insert(structure.name.lexeme.as_str(), structure); }
+                StatementKind::Trait(trait_) => { traits.insert(trait_.name.lexeme.as_str(), trait_); }
+                _ => {}
+            }
+        }
+
+        Monomorphiser {
+            module,
+            instantiations,
+            binding_types,
+            functions,
+            structs,
+            traits,
+            instances: HashSet::new(),
+            queue: VecDeque::new(),
+            reported: HashSet::new(),
+            struct_instances: vec![],
+            function_instances: vec![],
+            extends: vec![],
+            errors: vec![],
+        }
+    }
+
+    /// Builds the monomorphic module: the non-generic items, followed by the instances
+    /// of the generic ones.
+    pub fn monomorphise(&mut self) -> Module {
+        let root = Context::default();
+        let mut stmts = vec![];
+        for item in &self.module.stmts {
+            match item {
+                StatementKind::Function(function) if function.generics.is_empty() => {
+                    stmts.push(StatementKind::Function(self.function(function, &function.name.lexeme, &root)));
+                }
+                StatementKind::Struct(structure) if structure.generics.is_empty() => {
+                    stmts.push(StatementKind::Struct(self.structure(structure, &structure.name.lexeme, &root)));
+                }
+                StatementKind::Extend(extend) if extend.type_generics().is_empty() => {
+                    // Generic methods are copied into the block once they are used
+                    let methods: Vec<_> = extend.methods.iter()
+                        .filter(|method| method.generics.is_empty())
+                        .map(|method| self.function(method, &method.name.lexeme, &root))
+                        .collect();
+                    self.extend_block(&extend.name.lexeme, extend, &root).methods.extend(methods);
+                }
+                StatementKind::TypeAlias(alias) => stmts.push(StatementKind::TypeAlias(TypeAlias {
+                    ty: self.concrete(&alias.ty, &root, &alias.span),
+                    ..alias.clone()
+                })),
+                StatementKind::Statement(statement) => stmts.push(StatementKind::Statement(self.statement(statement, &root))),
+                StatementKind::Enum(_) | StatementKind::Trait(_) | StatementKind::Import(_) => stmts.push(item.clone()),
+                StatementKind::Function(_) | StatementKind::Struct(_) | StatementKind::Extend(_) => {}
+            }
+        }
+
+        while let Some(pending) = self.queue.pop_front() {
+            match pending.item {
+                Item::Function(function) => {
+                    let instance = self.function(function, &pending.name, &pending.context);
+                    self.function_instances.push(instance);
+                }
+                Item::Struct(structure) => {
+                    let instance = self.structure(structure, &pending.name, &pending.context);
+                    self.struct_instances.push(instance);
+                }
+                Item::Method { function, method, receiver, extend } => {
+                    let instance = self.function(function, &method, &pending.context);
+                    self.extend_block(&receiver, extend, &pending.context).methods.push(instance);
+                }
+            }
+        }
+
+        stmts.extend(std::mem::take(&mut self.struct_instances).into_iter().map(StatementKind::Struct));
+        stmts.extend(std::mem::take(&mut self.function_instances).into_iter().map(StatementKind::Function));
+        stmts.extend(std::mem::take(&mut self.extends).into_iter().map(StatementKind::Extend));
+        Module { stmts, ..self.module.clone() }
+    }
+
+    /// Creates an instance named `name` of `item` unless it already exists, reporting instead
+    /// when the instance would be one more in an endless series. Returns whether the instance
+    /// can be used.
+    fn request(&mut self, item: Item<'a>, item_name: &str, name: String, bindings: HashMap<String, Type>, context: &Context, span: &Span) -> bool {
+        if self.instances.contains(&name) {
+            return true;
+        }
+
+        let mut chain = context.chain.clone();
+        chain.push(Link { item: item_name.to_string(), instance: name.clone(), span: span.clone() });
+        let repeated: Vec<&Link> = chain.iter().filter(|link| link.item == item_name).collect();
+        if repeated.len() > RECURSION_LIMIT {
+            if self.reported.insert(item_name.to_string()) {
+                let mut error = Error::new(format!("Infinite recursion while instantiating '{}'", item_name), 0, span.clone(), String::new());
+                let steps: Vec<String> = repeated.iter().take(3).map(|link| format!("'{}'", link.instance)).collect();
+                error.add_note(Note::new(
+                    format!("Each instance requires a larger one: {}, ...", steps.join(" requires ")),
+                    0, repeated[1].span.clone(), String::new(),
+                ));
+                error.add_help(Help::new(
+                    format!("Call '{}' with the same type arguments it was given, or use a trait object", item_name),
+                    0, span.clone(), String::new(),
+                ));
+                self.errors.push(error);
+            }
+            return false;
+        }
+
+        self.instances.insert(name.clone());
+        self.queue.push_back(Pending { item, name, context: Context { bindings, chain } });
+        true
+    }
+
+    /// The monomorphic copy of `extend` for the type named `receiver`, created on first use.
+    fn extend_block(&mut self, receiver: &str, extend: &Extend, context: &Context) -> &mut Extend {
+        let index = self.extends.iter().position(|block| {
+            block.name.lexeme == receiver && block.trait_name.as_ref().map(|name| &name.lexeme) == extend.trait_name.as_ref().map(|name| &name.lexeme)
+        });
+        let index = match index {
+            Some(index) => index,
+            None => {
+                let block = Extend {
+                    name: with_lexeme(&extend.name, receiver),
+                    methods: vec![],
+                    types: extend.types.iter()
+                        .map(|associated| AssociatedType {
+                            ty: associated.ty.as_ref().map(|ty| self.concrete(ty, context, &associated.span)),
+                            ..associated.clone()
+                        })
+                        .collect(),
+                    constants: extend.constants.iter().map(|constant| self.constant(constant, context)).collect(),
+                    // The trait's arguments stay, but the type is now an instance
+                    first_generics: match extend.trait_name {
+                        Some(_) => extend.first_generics.iter().map(|ty| self.concrete(ty, context, &extend.span)).collect(),
+                        None => vec![],
+                    },
+                    second_generics: vec![],
+                    ..extend.clone()
+                };
+                self.extends.push(block);
+                self.extends.len() - 1
+            }
+        };
+        &mut self.extends[index]
+    }
+
+    fn function(&mut self, function: &Function, name: &str, context: &Context) -> Function {
+        Function {
+            name: with_lexeme(&function.name, name),
+            generics: vec![],
+            bounds: vec![],
+            params: function.params.iter()
+                .map(|param| Parameter { ty: self.concrete(&param.ty, context, &param.span), ..param.clone() })
+                .collect(),
+            return_type: self.concrete(&function.return_type, context, &function.span),
+            body: function.body.as_ref().map(|body| self.statement(body, context)),
+            ..function.clone()
+        }
+    }
+
+    fn structure(&mut self, structure: &Struct, name: &str, context: &Context) -> Struct {
+        Struct {
+            name: with_lexeme(&structure.name, name),
+            generics: vec![],
+            fields: structure.fields.iter()
+                .map(|field| Field { ty: self.concrete(&field.ty, context, &field.span), ..field.clone() })
+                .collect(),
+            ..structure.clone()
+        }
+    }
+
+    fn constant(&mut self, constant: &Constant, context: &Context) -> Constant {
+        Constant {
+            ty: self.concrete(&constant.ty, context, &constant.span),
+            value: constant.value.as_ref().map(|value| self.expr(value, context)),
+            ..constant.clone()
+        }
+    }
+
+    /// `ty` with the generic parameters of the context filled in and every generic
+    /// struct replaced by its instance.
+    fn concrete(&mut self, ty: &Type, context: &Context, span: &Span) -> Type {
+        let ty = substitute(ty, &context.bindings);
+        self.instances_in(&ty, context, span)
+    }
+
+    fn instances_in(&mut self, ty: &Type, context: &Context, span: &Span) -> Type {
+        match ty {
+            Type::Named { name, generics, span: ty_span } if !generics.is_empty() => {
+                let generics: Vec<Type> = generics.iter().map(|ty| self.instances_in(ty, context, span)).collect();
+                let structure = self.structs.get(name.lexeme.as_str()).copied();
+                let Some(structure) = structure.filter(|_| !generics.iter().any(has_inference_vars)) else {
+                    return Type::Named { name: name.clone(), generics, span: ty_span.clone() };
+                };
+
+                let instance = Type::Named { name: name.clone(), generics: generics.clone(), span: ty_span.clone() }.to_string();
+                let bindings = structure.generics.iter().map(|param| param.to_string()).zip(generics.iter().cloned()).collect();
+                if !self.request(Item::Struct(structure), &name.lexeme, instance.clone(), bindings, context, span) {
+                    return ty.clone();
+                }
+                Type::Named { name: with_lexeme(name, &instance), generics: vec![], span: ty_span.clone() }
+            }
+            Type::Array { element, size, span: ty_span } => Type::Array {
+                element: Box::new(self.instances_in(element, context, span)),
+                size: *size,
+                span: ty_span.clone(),
+            },
+            Type::Tuple { elements, span: ty_span } => Type::Tuple {
+                elements: elements.iter().map(|ty| self.instances_in(ty, context, span)).collect(),
+                span: ty_span.clone(),
+            },
+            Type::Function { params, return_type, span: ty_span } => Type::Function {
+                params: params.iter().map(|ty| self.instances_in(ty, context, span)).collect(),
+                return_type: Box::new(self.instances_in(return_type, context, span)),
+                span: ty_span.clone(),
+            },
+            Type::Optional { inner, span: ty_span } => Type::Optional {
+                inner: Box::new(self.instances_in(inner, context, span)),
+                span: ty_span.clone(),
+            },
+            other => other.clone(),
+        }
+    }
+
+    /// The types the generic parameters `params` of `name` stand for at a use, reporting
+    /// those that were never determined.
+    fn arguments(&mut self, name: &str, params: &[Type], instantiation: &Instantiation, context: &Context, span: &Span) -> Option<Vec<Type>> {
+        let mut arguments = vec![];
+        for param in params {
+            let param = param.to_string();
+            match instantiation.args.get(&param).map(|ty| substitute(ty, &context.bindings)) {
+                Some(ty) if !has_inference_vars(&ty) => arguments.push(self.instances_in(&ty, context, span)),
+                _ => {
+                    let mut error = Error::new(format!("Cannot infer the type argument '{}' of '{}'", param, name), 0, span.clone(), String::new());
+                    error.add_help(Help::new(format!("Give the type arguments explicitly, e.g. '{}<...>(...)'", name), 0, span.clone(), String::new()));
+                    self.errors.push(error);
+                    return None;
+                }
+            }
+        }
+        Some(arguments)
+    }
+
+    /// The instance of the generic function `name` used at `span`, if it is one.
+    fn function_instance(&mut self, name: &str, span: &Span, context: &Context) -> Option<String> {
+        let function = self.functions.get(name).copied().filter(|function| !function.generics.is_empty())?;
+        let instantiation = self.instantiations.get(span)?;
+        let arguments = self.arguments(name, &function.generics, instantiation, context, span)?;
+
+        let instance = format!("{}<{}>", name, list(&arguments));
+        let bindings = function.generics.iter().map(|param| param.to_string()).zip(arguments).collect();
+        self.request(Item::Function(function), name, instance.clone(), bindings, context, span)
+            .then_some(instance)
+    }
+
+    /// The instance of the method `member` used at `span`, if the method is generic, belongs
+    /// to a generic `extend` block or is a trait default, which is generic over `Self`.
+    /// Returns the type it is called through and the name of the method instance.
+    fn method_instance(&mut self, member: &str, span: &Span, context: &Context) -> Option<(Type, String)> {
+        let instantiation = self.instantiations.get(span)?;
+        let receiver = substitute(instantiation.receiver.as_ref()?, &context.bindings);
+        let (type_name, receiver_generics) = match &receiver {
+            Type::Named { name, generics, .. } => (name.lexeme.clone(), generics.clone()),
+            Type::Primitive { name, .. } => (name.lexeme.clone(), vec![]),
+            // Calls on trait objects are dispatched at run time
+            _ => return None,
+        };
+        let (owner, function) = self.find_method(&type_name, member)?;
+        let generic_block = match owner {
+            Owner::Extend(extend) => !extend.type_generics().is_empty(),
+            Owner::Trait(_) => true,
+        };
+        if !generic_block && function.generics.is_empty() {
+            return None;
+        }
+        if has_inference_vars(&receiver) {
+            return None;
+        }
+
+        let arguments = self.arguments(member, &function.generics, instantiation, context, span)?;
+        let concrete_receiver = self.instances_in(&receiver, context, span);
+        let receiver_name = concrete_receiver.to_string();
+
+        // A trait default is copied into the type's implementation of the trait
+        let extend = match owner {
+            Owner::Extend(extend) => extend,
+            Owner::Trait(trait_) => self.implementation(&type_name, &trait_.name.lexeme)?,
+        };
+        let mut bindings: HashMap<String, Type> = HashMap::from([("Self".to_string(), receiver.clone())]);
+        bindings.extend(extend.type_generics().iter().map(|param| param.to_string()).zip(receiver_generics));
+        let associated: Vec<(String, Type)> = extend.types.iter()
+            .filter_map(|associated| Some((associated.name.lexeme.clone(), substitute(associated.ty.as_ref()?, &bindings))))
+            .collect();
+        for (name, ty) in associated {
+            bindings.insert(format!("Self.{}", name), ty.clone());
+            bindings.insert(name, ty);
+        }
+        bindings.extend(function.generics.iter().map(|param| param.to_string()).zip(arguments.iter().cloned()));
+
+        let method = match arguments.is_empty() {
+            true => member.to_string(),
+            false => format!("{}<{}>", member, list(&arguments)),
+        };
+        let instance = format!("{}.{}", receiver_name, method);
+        let item = Item::Method { function, method: method.clone(), receiver: receiver_name, extend };
+        self.request(item, &format!("{}.{}", type_name, member), instance, bindings, context, span)
+            .then_some((concrete_receiver, method))
+    }
+
+    /// Where the method `member` of `type_name` is defined: the type's own `extend` blocks
+    /// come first, then its trait implementations, then the defaults of its traits.
+    fn find_method(&self, type_name: &str, member: &str) -> Option<(Owner<'a>, &'a Function)> {
+        let extends: Vec<&'a Extend> = self.module.stmts.iter()
+            .filter_map(|item| match item {
+                StatementKind::Extend(extend) if extend.name.lexeme == type_name => Some(extend),
+                _ => None,
+            })
+            .collect();
+        let defined = |inherent: bool| extends.iter()
+            .filter(|extend| extend.trait_name.is_none() == inherent)
+            .find_map(|extend| Some((Owner::Extend(extend), extend.methods.iter().find(|method| method.name.lexeme == member)?)));
+
+        defined(true)
+            .or_else(|| defined(false))
+            .or_else(|| extends.iter()
+                .filter_map(|extend| self.traits.get(extend.trait_name.as_ref()?.lexeme.as_str()).copied())
+                .find_map(|trait_| {
+                    let method = trait_.methods.iter().find(|method| method.name.lexeme == member && method.body.is_some())?;
+                    Some((Owner::Trait(trait_), method))
+                }))
+    }
+
+    /// The `extend Trait for Type` block of `type_name`, if there is one.
+    fn implementation(&self, type_name: &str, trait_name: &str) -> Option<&'a Extend> {
+        self.module.stmts.iter().find_map(|item| match item {
+            StatementKind::Extend(extend)
+                if extend.name.lexeme == type_name
+                    && extend.trait_name.as_ref().is_some_and(|name| name.lexeme == trait_name) => Some(extend),
+            _ => None,
+        })
+    }
+
+    fn statement(&mut self, statement: &Statement, context: &Context) -> Statement {
+        match statement {
+            Statement::Let { pattern, ty, value, span } => {
+                // Inferred types are written out, so that every binding has one
+                let ty = ty.clone()
+                    .or_else(|| self.binding_types.get(&pattern.span()).cloned())
+                    .map(|ty| self.concrete(&ty, context, span))
+                    .filter(|ty| !has_inference_vars(ty));
+                Statement::Let {
+                    pattern: pattern.clone(),
+                    ty,
+                    value: value.as_ref().map(|value| self.expr(value, context)),
+                    span: span.clone(),
+                }
+            }
+            Statement::Expr(expr) => Statement::Expr(self.expr(expr, context)),
+            Statement::Return(value, span) => Statement::Return(value.as_ref().map(|value| self.expr(value, context)), span.clone()),
+            Statement::Block(statements, span) => {
+                Statement::Block(statements.iter().map(|statement| self.statement(statement, context)).collect(), span.clone())
+            }
+            Statement::If { cond, then_branch, else_branch, span } => Statement::If {
+                cond: self.expr(cond, context),
+                then_branch: Box::new(self.statement(then_branch, context)),
+                else_branch: else_branch.as_ref().map(|branch| Box::new(self.statement(branch, context))),
+                span: span.clone(),
+            },
+            Statement::While { cond, body, span } => Statement::While {
+                cond: self.expr(cond, context),
+                body: Box::new(self.statement(body, context)),
+                span: span.clone(),
+            },
+            Statement::For { var, iter, body, span } => Statement::For {
+                var: var.clone(),
+                iter: self.expr(iter, context),
+                body: Box::new(self.statement(body, context)),
+                span: span.clone(),
+            },
+            Statement::Match { expr, cases, span } => Statement::Match {
+                expr: self.expr(expr, context),
+                cases: self.cases(cases, context),
+                span: span.clone(),
+            },
+            Statement::Tail(expr) => Statement::Tail(self.expr(expr, context)),
+            Statement::Break(_) | Statement::Continue(_) => statement.clone(),
+        }
+    }
+
+    fn cases(&mut self, cases: &[Case], context: &Context) -> Vec<Case> {
+        cases.iter()
+            .map(|case| Case {
+                guard: case.guard.as_ref().map(|guard| self.expr(guard, context)),
+                body: self.statement(&case.body, context),
+                ..case.clone()
+            })
+            .collect()
+    }
+
+    fn expr(&mut self, expr: &Expr, context: &Context) -> Expr {
+        let boxed = |this: &mut Self, expr: &Expr| Box::new(this.expr(expr, context));
+        match expr {
+            Expr::Identifier(name, span) => match self.function_instance(&name.lexeme, &name.span, context) {
+                Some(instance) => Expr::Identifier(with_lexeme(name, &instance), span.clone()),
+                None => expr.clone(),
+            },
+            Expr::Field { base, field, optional, span } => {
+                let Some((receiver, method)) = self.method_instance(&field.lexeme, &field.span, context) else {
+                    return Expr::Field { base: boxed(self, base), field: field.clone(), optional: *optional, span: span.clone() };
+                };
+                let base = match &**base {
+                    // A static call goes through the instance of the type
+                    Expr::Identifier(name, base_span) if self.structs.contains_key(name.lexeme.as_str()) => {
+                        Box::new(Expr::Identifier(with_lexeme(name, &receiver.to_string()), base_span.clone()))
+                    }
+                    base => boxed(self, base),
+                };
+                Expr::Field { base, field: with_lexeme(field, &method), optional: *optional, span: span.clone() }
+            }
+            Expr::Call { callee, args, span, .. } => Expr::Call {
+                callee: boxed(self, callee),
+                args: args.iter().map(|arg| self.expr(arg, context)).collect(),
+                // The instances take no type arguments
+                generic_args: vec![],
+                span: span.clone(),
+            },
+            Expr::StructInit { name, fields, span } => {
+                let instance = self.instantiations.get(&name.span)
+                    .and_then(|instantiation| instantiation.receiver.clone())
+                    .map(|ty| self.concrete(&ty, context, span));
+                let name = match instance {
+                    Some(Type::Named { name, .. }) => name,
+                    _ => name.clone(),
+                };
+                Expr::StructInit {
+                    name,
+                    fields: fields.iter().map(|(field, value)| (field.clone(), self.expr(value, context))).collect(),
+                    span: span.clone(),
+                }
+            }
+            Expr::Binary { left, op, right, span } => Expr::Binary {
+                left: boxed(self, left),
+                op: op.clone(),
+                right: boxed(self, right),
+                span: span.clone(),
+            },
+            Expr::Unary { op, expr, span } => Expr::Unary { op: op.clone(), expr: boxed(self, expr), span: span.clone() },
+            Expr::Index { base, index, span } => Expr::Index { base: boxed(self, base), index: boxed(self, index), span: span.clone() },
+            Expr::Assignment { left, op, right, span } => Expr::Assignment {
+                left: boxed(self, left),
+                op: op.clone(),
+                right: boxed(self, right),
+                span: span.clone(),
+            },
+            Expr::Array { elements, span } => Expr::Array {
+                elements: elements.iter().map(|element| self.expr(element, context)).collect(),
+                span: span.clone(),
+            },
+            Expr::Tuple { elements, span } => Expr::Tuple {
+                elements: elements.iter().map(|element| self.expr(element, context)).collect(),
+                span: span.clone(),
+            },
+            Expr::Cast { expr, ty, span } => Expr::Cast { expr: boxed(self, expr), ty: self.concrete(ty, context, span), span: span.clone() },
+            Expr::Closure { params, body, ty, span } => Expr::Closure {
+                params: params.iter()
+                    .map(|param| Parameter { ty: self.concrete(&param.ty, context, &param.span), ..param.clone() })
+                    .collect(),
+                body: Box::new(self.statement(body, context)),
+                ty: self.concrete(ty, context, span),
+                span: span.clone(),
+            },
+            Expr::TokenInterpolation(interpolation, span) => Expr::TokenInterpolation(
+                TokenInterpolation {
+                    segments: interpolation.segments.iter()
+                        .map(|segment| match segment {
+                            TokenSegment::Expr(expr, span) => TokenSegment::Expr(self.expr(expr, context), span.clone()),
+                            literal => literal.clone(),
+                        })
+                        .collect(),
+                    span: interpolation.span.clone(),
+                },
+                span.clone(),
+            ),
+            Expr::Grouping(expr, span) => Expr::Grouping(boxed(self, expr), span.clone()),
+            Expr::Try { expr, span } => Expr::Try { expr: boxed(self, expr), span: span.clone() },
+            Expr::Range { start, end, inclusive, span } => Expr::Range {
+                start: start.as_ref().map(|start| boxed(self, start)),
+                end: end.as_ref().map(|end| boxed(self, end)),
+                inclusive: *inclusive,
+                span: span.clone(),
+            },
+            Expr::If { cond, then_branch, else_branch, span } => Expr::If {
+                cond: boxed(self, cond),
+                then_branch: Box::new(self.statement(then_branch, context)),
+                else_branch: else_branch.as_ref().map(|branch| Box::new(self.statement(branch, context))),
+                span: span.clone(),
+            },
+            Expr::Match { expr, cases, span } => Expr::Match {
+                expr: boxed(self, expr),
+                cases: self.cases(cases, context),
+                span: span.clone(),
+            },
+            Expr::Literal(_) | Expr::Error => expr.clone(),
+        }
+    }
+}
+
+fn with_lexeme(token: &Token, lexeme: &str) -> Token {
+    let mut token = token.clone();
+    token.lexeme = lexeme.to_string();
+    token
+}
+
+fn list(types: &[Type]) -> String {
+    types.iter().map(|ty| ty.to_string()).collect::<Vec<_>>().join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+
+    /// The items of the monomorphic module, with the types of struct fields and function parameters.
+    fn items(source: &str) -> Result<Vec<String>, Vec<String>> {
+        let checked = Compiler::new().check_str("test.y", source)
+            .map_err(|errors| errors.into_iter().map(|error| error.message).collect::<Vec<_>>())?;
+        let signature = |function: &Function| format!(
+            "{}({}) -> {}",
+            function.name.lexeme,
+            function.params.iter().map(|param| param.ty.to_string()).collect::<Vec<_>>().join(", "),
+            function.return_type,
+        );
+        Ok(checked.monomorphic.stmts.iter()
+            .filter_map(|item| match item {
+                StatementKind::Struct(structure) => Some(format!(
+                    "struct {} {{ {} }}",
+                    structure.name.lexeme,
+                    structure.fields.iter().map(|field| format!("{}: {}", field.name.lexeme, field.ty)).collect::<Vec<_>>().join(", "),
+                )),
+                StatementKind::Function(function) => Some(format!("func {}", signature(function))),
+                StatementKind::Extend(extend) => Some(format!(
+                    "extend {} {{ {} }}",
+                    extend.name.lexeme,
+                    extend.methods.iter().map(signature).collect::<Vec<_>>().join(", "),
+                )),
+                _ => None,
+            })
+            .collect())
+    }
+
+    #[test]
+    fn test_monomorphise() {
+        let source = "module test;\n\nstruct Foo<T> {\n    x: T\n}\n\nextend Foo<T> {\n    func get(self) -> T {\n        return self.x;\n    }\n\n    func map<U>(self, f: (T) -> U) -> Foo<U> {\n        return Foo { x: f(self.x) };\n    }\n}\n\nfunc apply<T, U>(x: T, f: (T) -> U) -> U {\n    return f(x);\n}\n\nfunc main() {\n    let inferred = Foo { x: [1, 2, 3] };\n    let first: int = inferred.get()[0];\n    let doubled = apply<int, int>(first, |n: int| int { return n * 2; });\n    let again = apply(doubled, |n: int| int { return n + 1; });\n    let text = Foo { x: again }.map(|n: int| string { return \"done\"; }).get();\n}\n";
+        assert_eq!(items(source).unwrap(), [
+            "func main() -> void",
+            "struct Foo<[int]> { x: [int] }",
+            "struct Foo<string> { x: string }",
+            "struct Foo<int> { x: int }",
+            // Both calls use the same instance
+            "func apply<int, int>(int, (int) -> int) -> int",
+            "extend Foo<[int]> { get() -> [int] }",
+            "extend Foo<string> { get() -> string }",
+            "extend Foo<int> { map<string>((int) -> string) -> Foo<string> }",
+        ]);
+
+        let recursive = "module test;\n\nfunc f<T>(x: T) {\n    f([x]);\n}\n\nfunc main() {\n    f(1);\n}\n";
+        let error = Compiler::new().check_str("test.y", recursive).unwrap_err().remove(0);
+        assert_eq!(error.message, "Infinite recursion while instantiating 'f'");
+        assert_eq!(error.notes[0].message, "Each instance requires a larger one: 'f<int>' requires 'f<[int]>' requires 'f<[[int]]>', ...");
+    }
+}
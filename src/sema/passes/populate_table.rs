@@ -1,7 +1,12 @@
+use std::collections::HashMap;
+
 use crate::frontend::utils::ast::*;
-use crate::frontend::utils::visitor::Visitor;
+use crate::frontend::utils::visitor::{Visitor, walk_function};
+use crate::frontend::utils::token::Token;
 use crate::sema::utils::{
+    GenericParams,
     MultiStageSymbolTable,
+    TraitMembers,
     symbol_table::{Symbol, SymbolKind},
 };
 
@@ -10,34 +15,61 @@ pub struct FullSymbolTablePass {
     pub table: MultiStageSymbolTable,
 }
 
+impl Default for FullSymbolTablePass {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FullSymbolTablePass {
     pub fn new() -> Self {
         FullSymbolTablePass { table: MultiStageSymbolTable::new() }
     }
+
+    /// Records the methods an `extend` block or trait declares for `owner`. `source` is the
+    /// trait an `extend` block implements; methods of the type itself take precedence.
+    fn methods(&mut self, owner: &Token, source: Option<&Token>, outer: &[Type], methods: &[Function]) {
+        for method in methods {
+            let key = format!("{}.{}", owner.lexeme, method.name.lexeme);
+            let sources = self.table.method_sources.entry(key.clone()).or_default();
+            let shadowed = source.is_some() && sources.contains(&owner.lexeme);
+            sources.push(source.unwrap_or(owner).lexeme.clone());
+            if shadowed {
+                continue;
+            }
+
+            self.table.methods.entry(owner.lexeme.clone()).or_default()
+                .insert(method.name.lexeme.clone(), function_symbol(method));
+            self.table.generics.insert(key, GenericParams {
+                outer: names(outer),
+                own: names(&method.generics),
+                bounds: bounds(&method.bounds),
+            });
+        }
+    }
 }
 
 impl Visitor for FullSymbolTablePass {
     fn visit_function(&mut self, function: &Function) -> Result<(), String> {
-        self.table.values.insert(Symbol {
-            name: function.name.lexeme.clone(),
-            kind: SymbolKind::Function,
-            ty: Some(function.return_type.clone()),
-            span: Some(function.name.span.clone()),
-            struct_fields: None,
-            enum_variants: None,
+        self.table.values.insert(function_symbol(function));
+        self.table.generics.insert(function.name.lexeme.clone(), GenericParams {
+            outer: vec![],
+            own: names(&function.generics),
+            bounds: bounds(&function.bounds),
         });
         self.table.values.enter_scope();
         for param in &function.params {
             self.table.values.insert(Symbol {
                 name: param.name.lexeme.clone(),
                 kind: SymbolKind::Parameter,
+                access: AccessModifier::None,
                 ty: Some(param.ty.clone()),
                 span: Some(param.name.span.clone()),
                 struct_fields: None,
                 enum_variants: None,
             });
         }
-        Visitor::visit_function(self, function)?;
+        walk_function(self, function)?;
         self.table.values.exit_scope();
         Ok(())
     }
@@ -46,7 +78,13 @@ impl Visitor for FullSymbolTablePass {
         self.table.types.insert(Symbol {
             name: structure.name.lexeme.clone(),
             kind: SymbolKind::Struct,
-            ty: None,
+            access: structure.access.clone(),
+            // The struct's own type, which carries its generic parameters
+            ty: Some(Type::Named {
+                name: structure.name.clone(),
+                generics: structure.generics.clone(),
+                span: structure.span.clone(),
+            }),
             span: Some(structure.name.span.clone()),
             struct_fields: Some(structure.fields.clone()),
             enum_variants: None,
@@ -59,7 +97,12 @@ impl Visitor for FullSymbolTablePass {
         self.table.types.insert(Symbol {
             name: enumeration.name.lexeme.clone(),
             kind: SymbolKind::Enum,
-            ty: None,
+            access: enumeration.access.clone(),
+            ty: Some(Type::Named {
+                name: enumeration.name.clone(),
+                generics: enumeration.generics.clone(),
+                span: enumeration.span.clone(),
+            }),
             span: Some(enumeration.name.span.clone()),
             struct_fields: None,
             enum_variants: Some(enumeration.variants.clone()),
@@ -67,19 +110,134 @@ impl Visitor for FullSymbolTablePass {
         Ok(())
     }
 
+    fn visit_type_alias(&mut self, alias: &TypeAlias) -> Result<(), String> {
+        let name = alias.name.lexeme.clone();
+        let ty = if alias.newtype {
+            self.table.newtypes.insert(name.clone(), alias.ty.clone());
+            Type::Named { name: alias.name.clone(), generics: vec![], span: alias.span.clone() }
+        } else {
+            alias.ty.clone()
+        };
+        self.table.types.insert(Symbol {
+            name,
+            kind: if alias.newtype { SymbolKind::Newtype } else { SymbolKind::Alias },
+            access: alias.access.clone(),
+            ty: Some(ty),
+            span: Some(alias.name.span.clone()),
+            struct_fields: None,
+            enum_variants: None,
+        });
+        Ok(())
+    }
+
+    fn visit_extend(&mut self, extend: &Extend) -> Result<(), String> {
+        // Methods are looked up through their type, so they don't become global values
+        self.methods(&extend.name, extend.trait_name.as_ref(), extend.type_generics(), &extend.methods);
+        if let Some(trait_name) = &extend.trait_name {
+            self.table.implementations.entry(extend.name.lexeme.clone()).or_default()
+                .insert(trait_name.lexeme.clone());
+        }
+
+        let types = self.table.associated_types.entry(extend.name.lexeme.clone()).or_default();
+        for associated in &extend.types {
+            if let Some(ty) = &associated.ty {
+                types.insert(associated.name.lexeme.clone(), ty.clone());
+            }
+        }
+        let constants = self.table.constants.entry(extend.name.lexeme.clone()).or_default();
+        for constant in &extend.constants {
+            constants.insert(constant.name.lexeme.clone(), constant.ty.clone());
+        }
+        Ok(())
+    }
+
+    fn visit_trait(&mut self, trait_: &Trait) -> Result<(), String> {
+        self.table.types.insert(Symbol {
+            name: trait_.name.lexeme.clone(),
+            kind: SymbolKind::Trait,
+            access: trait_.access.clone(),
+            // Like a struct's type, this carries the trait's generic parameters
+            ty: Some(Type::Named {
+                name: trait_.name.clone(),
+                generics: trait_.generics.clone(),
+                span: trait_.span.clone(),
+            }),
+            span: Some(trait_.name.span.clone()),
+            struct_fields: None,
+            enum_variants: None,
+        });
+
+        self.methods(&trait_.name, None, &trait_.generics, &trait_.methods);
+        let provided_methods = trait_.methods.iter().filter(|method| method.body.is_some()).map(|method| &method.name);
+        let provided_constants = trait_.constants.iter().filter(|constant| constant.value.is_some()).map(|constant| &constant.name);
+        self.table.traits.insert(trait_.name.lexeme.clone(), TraitMembers {
+            types: trait_.types.iter().map(|associated| associated.name.lexeme.clone()).collect(),
+            constants: trait_.constants.iter().map(|constant| (constant.name.lexeme.clone(), constant.ty.clone())).collect(),
+            provided: provided_methods.chain(provided_constants).map(|name| name.lexeme.clone()).collect(),
+        });
+        Ok(())
+    }
+
+    fn visit_import(&mut self, import: &Import) -> Result<(), String> {
+        self.table.values.insert(Symbol {
+            name: import.alias.lexeme.clone(),
+            kind: SymbolKind::Module,
+            access: AccessModifier::None,
+            ty: None,
+            span: Some(import.alias.span.clone()),
+            struct_fields: None,
+            enum_variants: None,
+        });
+        Ok(())
+    }
+
     fn visit_statement(&mut self, statement: &Statement) -> Result<(), String> {
-        if let Statement::Let { name, ty, .. } = statement {
-            self.table.values.insert(Symbol {
-                name: name.lexeme.clone(),
-                kind: SymbolKind::Variable,
-                ty: ty.clone(),
-                span: Some(name.span.clone()),
-                struct_fields: None,
-                enum_variants: None,
-            });
+        if let Statement::Let { pattern, ty, .. } = statement {
+            for name in pattern.bindings() {
+                self.table.values.insert(Symbol {
+                    name: name.lexeme.clone(),
+                    kind: SymbolKind::Variable,
+                    access: AccessModifier::None,
+                    // A destructured name's type is only known once the value is checked
+                    ty: ty.clone().filter(|_| matches!(**pattern, Pattern::Identifier(..))),
+                    span: Some(name.span.clone()),
+                    struct_fields: None,
+                    enum_variants: None,
+                });
+            }
         }
 
         // Don't call the base visitor method here, as we don't want to traverse into the statement body.
         Ok(())
     }
 }
+
+fn function_symbol(function: &Function) -> Symbol {
+    Symbol {
+        name: function.name.lexeme.clone(),
+        kind: if function.is_method { SymbolKind::Method } else { SymbolKind::Function },
+        access: function.access.clone(),
+        ty: Some(Type::Function {
+            params: function.params.iter().map(|param| param.ty.clone()).collect(),
+            return_type: Box::new(function.return_type.clone()),
+            span: function.span.clone(),
+        }),
+        span: Some(function.name.span.clone()),
+        struct_fields: None,
+        enum_variants: None,
+    }
+}
+
+/// The names of the type variables in a generic parameter list.
+fn names(generics: &[Type]) -> Vec<String> {
+    generics.iter().map(|ty| ty.to_string()).collect()
+}
+
+/// The traits required of each generic parameter, merging repeated bounds on one parameter.
+fn bounds(bounds: &[GenericBound]) -> HashMap<String, Vec<Type>> {
+    let mut traits: HashMap<String, Vec<Type>> = HashMap::new();
+    for bound in bounds {
+        traits.entry(bound.param.lexeme.clone()).or_default().extend(bound.traits.iter().cloned());
+    }
+    traits
+}
@@ -0,0 +1,447 @@
+use std::collections::HashMap;
+
+use crate::errors::{Error, Help, Note, Warning};
+use crate::frontend::utils::ast::*;
+use crate::frontend::utils::string_interpolation::unescape;
+use crate::frontend::utils::token::{Span, Token};
+use crate::frontend::utils::visitor::{Visitor, walk_expr, walk_extend, walk_module, walk_statement, walk_trait};
+use crate::sema::utils::{symbol_table::SymbolKind, MultiStageSymbolTable};
+use crate::utils::closest_match;
+
+/// A name bound in a scope
+#[derive(Debug, Clone)]
+struct Binding {
+    /// Where the name is declared; `None` for implicit bindings such as `self`
+    span: Option<Span>,
+    /// Whether this is a local binding (`let`, parameter, loop variable or pattern)
+    /// rather than an item or import
+    local: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scope {
+    names: HashMap<String, Binding>,
+    /// `let`s of this block that haven't been reached yet, to tell a use before
+    /// declaration apart from an undefined name
+    pending: HashMap<String, Span>,
+    /// Whether this scope holds the parameters of a function or method; closures
+    /// don't count, as they may be called before the block they are in has run
+    is_function: bool,
+}
+
+/// Resolves every identifier to its declaration, following lexical block scopes.
+///
+/// Items and imports are visible throughout the module, local bindings from their
+/// declaration to the end of their block. The body of a function runs later, so it may
+/// also refer to top-level bindings that are declared after it; a closure may not.
+pub struct Resolver {
+    /// Innermost scope last; the first scope holds names defined before the module,
+    /// such as earlier REPL inputs
+    scopes: Vec<Scope>,
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Warning>,
+    /// The declaration each name refers to, keyed by the span of the use
+    pub resolutions: HashMap<Span, Span>,
+    /// The field names of each struct in the module
+    struct_fields: HashMap<String, Vec<String>>,
+    /// The fields of the type whose `extend` block or trait is being resolved, if any,
+    /// to point at `self` when a method uses one of them on its own
+    owner_fields: Option<Vec<String>>,
+    /// The items marked `#[deprecated]`, keyed by the span of their name, with the
+    /// message to show where they are used
+    deprecated: HashMap<Span, Option<String>>,
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Resolver {
+            scopes: vec![Scope::default()],
+            errors: vec![],
+            warnings: vec![],
+            resolutions: HashMap::new(),
+            struct_fields: HashMap::new(),
+            owner_fields: None,
+            deprecated: HashMap::new(),
+        }
+    }
+
+    /// Creates a resolver that also sees the global names of an existing table.
+    pub fn with_globals(table: &MultiStageSymbolTable) -> Self {
+        let mut resolver = Resolver::new();
+        let globals = [&table.values, &table.types]
+            .into_iter()
+            .filter_map(|table| table.scopes.first())
+            .flat_map(|scope| scope.symbols.values());
+
+        for symbol in globals {
+            let local = matches!(symbol.kind, SymbolKind::Variable | SymbolKind::Parameter);
+            resolver.scopes[0].names.insert(symbol.name.clone(), Binding { span: symbol.span.clone(), local });
+        }
+        resolver
+    }
+
+    fn error(&mut self, message: String, span: &Span) -> &mut Error {
+        self.errors.push(Error::new(message, 0, span.clone(), String::new()));
+        self.errors.last_mut().unwrap()
+    }
+
+    fn enter_scope(&mut self, statements: &[Statement], is_function: bool) {
+        let mut scope = Scope { is_function, ..Scope::default() };
+        for statement in statements {
+            if let Statement::Let { pattern, .. } = statement {
+                for name in pattern.bindings() {
+                    scope.pending.entry(name.lexeme.clone()).or_insert_with(|| name.span.clone());
+                }
+            }
+        }
+        self.scopes.push(scope);
+    }
+
+    fn exit_scope(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// Declares an item or import in the module scope.
+    fn declare_item(&mut self, name: &Token) {
+        let scope = self.scopes.last_mut().unwrap();
+        if let Some(previous) = scope.names.get(&name.lexeme) {
+            let previous = previous.span.clone();
+            let error = self.error(format!("'{}' is already declared in this module", name.lexeme), &name.span);
+            if let Some(previous) = previous {
+                error.add_note(Note::new("First declared here".to_string(), 0, previous, String::new()));
+            }
+            return;
+        }
+        scope.names.insert(name.lexeme.clone(), Binding { span: Some(name.span.clone()), local: false });
+    }
+
+    /// Declares a local binding in the innermost scope, warning if it shadows another local.
+    fn declare_local(&mut self, name: &Token) {
+        let shadowed = self.scopes.iter().rev()
+            .find_map(|scope| scope.names.get(&name.lexeme))
+            .filter(|binding| binding.local)
+            .and_then(|binding| binding.span.clone());
+
+        if let Some(previous) = shadowed {
+            let mut warning = Warning::new(format!("'{}' shadows an earlier binding", name.lexeme), 0, name.span.clone(), String::new());
+            warning.add_note(Note::new(format!("'{}' was previously bound here", name.lexeme), 0, previous, String::new()));
+            self.warnings.push(warning);
+        }
+
+        let scope = self.scopes.last_mut().unwrap();
+        scope.pending.remove(&name.lexeme);
+        scope.names.insert(name.lexeme.clone(), Binding { span: Some(name.span.clone()), local: true });
+    }
+
+    fn declare_implicit(&mut self, name: &str) {
+        let scope = self.scopes.last_mut().unwrap();
+        scope.names.insert(name.to_string(), Binding { span: None, local: true });
+    }
+
+    fn resolve(&mut self, name: &Token) {
+        let mut crossed_function = false;
+        for scope in self.scopes.iter().rev() {
+            if let Some(binding) = scope.names.get(&name.lexeme) {
+                if let Some(declaration) = &binding.span {
+                    self.resolutions.insert(name.span.clone(), declaration.clone());
+                    if let Some(message) = self.deprecated.get(declaration) {
+                        let mut warning = Warning::new(format!("'{}' is deprecated", name.lexeme), 0, name.span.clone(), String::new());
+                        let note = message.clone().unwrap_or_else(|| format!("'{}' is marked '#[deprecated]' here", name.lexeme));
+                        warning.add_note(Note::new(note, 0, declaration.clone(), String::new()));
+                        self.warnings.push(warning);
+                    }
+                }
+                return;
+            }
+            if let Some(declaration) = scope.pending.get(&name.lexeme).cloned() {
+                if crossed_function {
+                    self.resolutions.insert(name.span.clone(), declaration);
+                } else {
+                    let error = self.error(format!("Cannot use '{}' before its declaration", name.lexeme), &name.span);
+                    error.add_note(Note::new(format!("'{}' is declared here", name.lexeme), 0, declaration, String::new()));
+                }
+                return;
+            }
+            crossed_function |= scope.is_function;
+        }
+
+        if let Some(fields) = &self.owner_fields {
+            if name.lexeme == "self" {
+                let error = self.error("'self' is only available in methods that take it".to_string(), &name.span);
+                error.add_help(Help::new("Add 'self' as the first parameter".to_string(), 0, name.span.clone(), String::new()));
+                return;
+            }
+            if fields.contains(&name.lexeme) {
+                let error = self.error(format!("Undefined name '{}'", name.lexeme), &name.span);
+                error.add_help(Help::new(format!("Did you mean 'self.{}'?", name.lexeme), 0, name.span.clone(), String::new()));
+                return;
+            }
+        }
+
+        let candidates: Vec<String> = self.scopes.iter()
+            .flat_map(|scope| scope.names.keys().cloned())
+            .collect();
+        let suggestion = closest_match(&name.lexeme, candidates.iter().map(String::as_str)).map(str::to_string);
+
+        let error = self.error(format!("Undefined name '{}'", name.lexeme), &name.span);
+        if let Some(suggestion) = suggestion {
+            error.add_help(Help::new(format!("Did you mean '{}'?", suggestion), 0, name.span.clone(), String::new()));
+        }
+    }
+
+    fn function(&mut self, function: &Function) -> Result<(), String> {
+        self.enter_scope(&[], true);
+        if function.is_method {
+            self.declare_implicit("self");
+        }
+        for param in &function.params {
+            self.declare_local(&param.name);
+        }
+        let result = function.body.as_ref().map_or(Ok(()), |body| self.visit_statement(body));
+        self.exit_scope();
+        result
+    }
+
+    /// Each case gets its own scope, holding the names its pattern binds.
+    fn cases(&mut self, cases: &[Case]) -> Result<(), String> {
+        for case in cases {
+            self.enter_scope(&[], false);
+            self.pattern(&case.pattern);
+            let result = case.guard.as_ref().map_or(Ok(()), |guard| self.visit_expression(guard))
+                .and_then(|_| self.visit_statement(&case.body));
+            self.exit_scope();
+            result?;
+        }
+        Ok(())
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match pattern {
+            Pattern::Identifier(name, _) => self.declare_local(name),
+            Pattern::Tuple(patterns, _) => patterns.iter().for_each(|pattern| self.pattern(pattern)),
+            Pattern::Struct { fields, .. } => fields.iter().for_each(|(_, pattern)| self.pattern(pattern)),
+            Pattern::Variant { enum_name, fields, .. } => {
+                if let Some(enum_name) = enum_name {
+                    self.resolve(enum_name);
+                }
+                fields.iter().for_each(|pattern| self.pattern(pattern));
+            }
+            Pattern::Or(alternatives, _) => {
+                // The body can't tell which alternative matched, so each must bind the same names
+                let names = alternatives[0].bindings();
+                for alternative in &alternatives[1..] {
+                    let other = alternative.bindings();
+                    let missing = names.iter().find(|name| !other.iter().any(|bound| bound.lexeme == name.lexeme));
+                    let extra = other.iter().find(|bound| !names.iter().any(|name| name.lexeme == bound.lexeme));
+                    if let Some(name) = missing.or(extra) {
+                        let error = self.error(format!("'{}' is not bound in every alternative of the pattern", name.lexeme), &alternative.span());
+                        error.add_help(Help::new("Bind the same names in each alternative, or use '_'".to_string(), 0, name.span.clone(), String::new()));
+                    }
+                }
+                self.pattern(&alternatives[0]);
+            }
+            Pattern::Binding { name, pattern, .. } => {
+                self.declare_local(name);
+                self.pattern(pattern);
+            }
+            Pattern::Literal(_) | Pattern::Range { .. } | Pattern::Wildcard(_) | Pattern::Error => {}
+        }
+    }
+}
+
+impl Visitor for Resolver {
+    fn visit_module(&mut self, module: &Module) -> Result<(), String> {
+        let statements: Vec<Statement> = module.stmts.iter()
+            .filter_map(|item| match item {
+                StatementKind::Statement(statement) => Some(statement.clone()),
+                _ => None,
+            })
+            .collect();
+        self.enter_scope(&statements, false);
+
+        // Uses of deprecated items are warned about wherever they are
+        for item in &module.stmts {
+            let (name, attributes) = match item {
+                StatementKind::Function(function) => (&function.name, &function.attributes),
+                StatementKind::Struct(structure) => (&structure.name, &structure.attributes),
+                StatementKind::Enum(enumeration) => (&enumeration.name, &enumeration.attributes),
+                _ => continue,
+            };
+            if let Some(deprecated) = Attribute::find(attributes, "deprecated") {
+                let message = deprecated.args.first().map(|message| {
+                    let quoted = &message.lexeme;
+                    unescape(quoted.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(quoted))
+                });
+                self.deprecated.insert(name.span.clone(), message);
+            }
+        }
+        // Items can be used before they are declared
+        for item in &module.stmts {
+            match item {
+                StatementKind::Function(function) => self.declare_item(&function.name),
+                StatementKind::Struct(structure) => {
+                    self.declare_item(&structure.name);
+                    let fields = structure.fields.iter().map(|field| field.name.lexeme.clone()).collect();
+                    self.struct_fields.insert(structure.name.lexeme.clone(), fields);
+                }
+                StatementKind::Enum(enumeration) => self.declare_item(&enumeration.name),
+                StatementKind::Trait(trait_) => self.declare_item(&trait_.name),
+                StatementKind::TypeAlias(alias) => self.declare_item(&alias.name),
+                StatementKind::Import(import) => self.declare_item(&import.alias),
+                StatementKind::Extend(_) | StatementKind::Statement(_) => {}
+            }
+        }
+
+        walk_module(self, module)?;
+        self.exit_scope();
+        Ok(())
+    }
+
+    fn visit_function(&mut self, function: &Function) -> Result<(), String> {
+        self.function(function)
+    }
+
+    fn visit_extend(&mut self, extend: &Extend) -> Result<(), String> {
+        let fields = self.struct_fields.get(&extend.name.lexeme).cloned().unwrap_or_default();
+        let old_fields = self.owner_fields.replace(fields);
+        let result = walk_extend(self, extend);
+        self.owner_fields = old_fields;
+        result
+    }
+
+    fn visit_trait(&mut self, trait_: &Trait) -> Result<(), String> {
+        let old_fields = self.owner_fields.replace(vec![]);
+        let result = walk_trait(self, trait_);
+        self.owner_fields = old_fields;
+        result
+    }
+
+    fn visit_struct(&mut self, _structure: &Struct) -> Result<(), String> {
+        // Field names aren't uses of anything
+        Ok(())
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Let { pattern, value, .. } => {
+                // The initialiser can't see the bindings it initialises
+                if let Some(value) = value {
+                    self.visit_expression(value)?;
+                }
+                self.pattern(pattern);
+                Ok(())
+            }
+            Statement::Block(statements, _) => {
+                self.enter_scope(statements, false);
+                let result = walk_statement(self, statement);
+                self.exit_scope();
+                result
+            }
+            Statement::For { var, iter, body, .. } => {
+                self.visit_expression(iter)?;
+                self.enter_scope(&[], false);
+                self.declare_local(var);
+                let result = self.visit_statement(body);
+                self.exit_scope();
+                result
+            }
+            Statement::Match { expr, cases, .. } => {
+                self.visit_expression(expr)?;
+                self.cases(cases)
+            }
+            _ => walk_statement(self, statement),
+        }
+    }
+
+    fn visit_expression(&mut self, expr: &Expr) -> Result<(), String> {
+        match expr {
+            Expr::Identifier(name, _) => {
+                self.resolve(name);
+                Ok(())
+            }
+            Expr::TokenInterpolation(interpolation, _) => {
+                for segment in &interpolation.segments {
+                    if let TokenSegment::Expr(expr, _) = segment {
+                        self.visit_expression(expr)?;
+                    }
+                }
+                Ok(())
+            }
+            Expr::StructInit { name, fields, .. } => {
+                self.resolve(name);
+                for (_, value) in fields {
+                    self.visit_expression(value)?;
+                }
+                Ok(())
+            }
+            Expr::Match { expr, cases, .. } => {
+                self.visit_expression(expr)?;
+                self.cases(cases)
+            }
+            Expr::Closure { params, body, .. } => {
+                self.enter_scope(&[], false);
+                for param in params {
+                    self.declare_local(&param.name);
+                }
+                let result = self.visit_statement(body);
+                self.exit_scope();
+                result
+            }
+            _ => walk_expr(self, expr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Compiler;
+
+    fn resolve(source: &str) -> Resolver {
+        let module = Compiler::new().parse_str("test.y", source).unwrap();
+        let mut resolver = Resolver::new();
+        resolver.visit_module(&module).unwrap();
+        resolver
+    }
+
+    #[test]
+    fn test_resolve_scopes() {
+        let resolver = resolve("module test;\n\nfunc main() {\n    let total = 0;\n    for (i in [1, 2]) {\n        let total = totl + i;\n    }\n    print(later);\n    let later = |x: int| int { return x + count; };\n    let count = 1;\n}\n\nfunc limit() -> int {\n    return max;\n}\n\nlet max = 3;\n");
+
+        let messages: Vec<_> = resolver.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Undefined name 'totl'",
+            "Undefined name 'print'",
+            "Cannot use 'later' before its declaration",
+            "Cannot use 'count' before its declaration",
+        ]);
+        assert_eq!(resolver.errors[0].helps[0].message, "Did you mean 'total'?");
+
+        // The inner `total` shadows the outer one; `limit` may use `max`, but the closure not `count`
+        assert_eq!(resolver.warnings.len(), 1);
+        assert_eq!(resolver.resolutions.len(), 3);
+    }
+
+    #[test]
+    fn test_resolve_methods() {
+        let resolver = resolve("module test;\n\nstruct Point {\n    x: float,\n}\n\nextend Point {\n    func origin() -> float {\n        return self.x;\n    }\n    func get(self) -> float {\n        return x;\n    }\n}\n");
+
+        let helps: Vec<_> = resolver.errors.iter().map(|error| error.helps[0].message.as_str()).collect();
+        assert_eq!(helps, ["Add 'self' as the first parameter", "Did you mean 'self.x'?"]);
+    }
+
+    #[test]
+    fn test_deprecated() {
+        let resolver = resolve("module test;\n\n#[deprecated(\"Use 'total' instead.\\nIt is removed in 2.0\")]\nfunc sum() -> int {\n    return 0;\n}\n\n#[deprecated]\nstruct Point {}\n\nfunc main() {\n    sum();\n    let p = Point {};\n}\n");
+
+        let warnings: Vec<_> = resolver.warnings.iter().map(|warning| warning.message.as_str()).collect();
+        assert_eq!(warnings, ["'sum' is deprecated", "'Point' is deprecated"]);
+        assert_eq!(resolver.warnings[0].notes[0].message, "Use 'total' instead.\nIt is removed in 2.0");
+    }
+}
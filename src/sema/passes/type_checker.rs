@@ -1,16 +1,103 @@
 use crate::frontend::utils::ast::*;
-use crate::frontend::utils::visitor::Visitor;
-use crate::sema::utils::MultiStageSymbolTable;
-use std::collections::HashMap;
+use crate::frontend::utils::visitor::{Visitor, walk_expr, walk_function, walk_statement};
+use crate::sema::utils::{GenericParams, MultiStageSymbolTable, TraitMembers};
+use std::collections::{HashMap, HashSet};
 use crate::frontend::utils::token::{Token, Span, TokenKind};
 use crate::sema::utils::symbol_table::{Symbol, SymbolKind};
+use crate::errors::{Error, Help, Note, Warning};
+use crate::sema::passes::exhaustiveness::{MatchChecker, MatchReport};
+use crate::utils::closest_match;
 
 #[derive(Debug)]
 pub struct TypeChecker {
     pub table: MultiStageSymbolTable,
-    pub errors: Vec<String>,
+    pub errors: Vec<Error>,
+    pub warnings: Vec<Warning>,
     pub current_return_type: Option<Type>,
+    /// The solution found so far for each inference variable
     pub type_vars: HashMap<String, Type>,
+    /// The inference variables in the order they were solved, so that a failed
+    /// unification can take back what it solved
+    solved: Vec<String>,
+    /// The type of every `let` binding, keyed by the span of its name
+    pub binding_types: HashMap<Span, Type>,
+    /// The types each use of a generic function, method or struct instantiates it with,
+    /// keyed by the span of the name at the use
+    pub instantiations: HashMap<Span, Instantiation>,
+    /// The type `self` has in the methods being checked
+    current_self: Option<Type>,
+    next_var: usize,
+    /// `let` bindings whose type still depends on inference variables,
+    /// checked once the whole module has been seen
+    deferred: Vec<(Token, Type)>,
+    /// The traits required of each generic parameter of the function being checked
+    bounds: HashMap<String, Vec<Type>>,
+    /// Trait bounds that the types chosen at a use of a generic function must satisfy,
+    /// checked once the enclosing function has been seen
+    obligations: Vec<Obligation>,
+    /// Where each trait was implemented for each type, by trait and type name
+    implementations: HashMap<(String, String), Span>,
+    /// What `Self` and the associated types stand for in the `extend` block or trait being checked
+    associated: HashMap<String, Type>,
+    /// The declared optional type of each variable a null check has narrowed, keyed by
+    /// the span of its declaration
+    narrowed: HashMap<Span, Type>,
+    /// Type aliases that expand to themselves, which are left unexpanded
+    cyclic_aliases: HashSet<String>,
+    /// The imported module whose alias is being checked, which may name that module's
+    /// private types
+    exporting_module: Option<String>,
+    /// The generic parameters of the items being checked, which types may name
+    type_params: Vec<String>,
+}
+
+/// The types one use of a function, method or struct literal fills in
+#[derive(Debug, Clone)]
+pub struct Instantiation {
+    /// The type of the value a method is called on, the type a static method is called
+    /// through, or the type a struct literal builds
+    pub receiver: Option<Type>,
+    /// What each of the function's own generic parameters stands for
+    pub args: HashMap<String, Type>,
+}
+
+/// A use of a generic function or method whose type argument must implement a trait
+#[derive(Debug)]
+struct Obligation {
+    ty: Type,
+    trait_: Type,
+    /// The bounded parameter and the function declaring it, for the error message
+    param: String,
+    owner: String,
+    span: Span,
+}
+
+/// A method that `value.member` or `Type.member` could refer to
+#[derive(Debug, Clone)]
+struct Candidate {
+    /// The type or trait the method is looked up in
+    owner: String,
+    /// Where the method is defined, for the error message
+    source: String,
+    /// What the generic parameters of the `extend` block or trait stand for
+    outer: Vec<Type>,
+    /// What `Self` and the associated types stand for
+    associated: HashMap<String, Type>,
+}
+
+/// Why two types could not be unified
+#[derive(Debug)]
+enum Mismatch {
+    /// The innermost pair of types that differ
+    Types(Box<Type>, Box<Type>),
+    /// Solving a variable would make it part of its own solution
+    Infinite,
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TypeChecker {
@@ -18,211 +105,2632 @@ impl TypeChecker {
         TypeChecker {
             table: MultiStageSymbolTable::new(),
             errors: Vec::new(),
+            warnings: Vec::new(),
             current_return_type: None,
             type_vars: HashMap::new(),
+            solved: vec![],
+            binding_types: HashMap::new(),
+            instantiations: HashMap::new(),
+            current_self: None,
+            next_var: 0,
+            deferred: vec![],
+            bounds: HashMap::new(),
+            obligations: vec![],
+            implementations: HashMap::new(),
+            associated: HashMap::new(),
+            narrowed: HashMap::new(),
+            cyclic_aliases: HashSet::new(),
+            exporting_module: None,
+            type_params: vec![],
         }
     }
 
     fn error(&mut self, message: String, span: &Span) {
-        self.errors.push(format!("Type error at {:?}: {}", span, message));
+        self.errors.push(Error::new(message, 0, span.clone(), String::new()));
     }
 
-    fn check_type_compatibility(&self, expected: &Type, actual: &Type) -> bool {
-        match (expected, actual) {
-            (Type::Primitive { name: n1, .. }, Type::Primitive { name: n2, .. }) => n1.lexeme == n2.lexeme,
-            (Type::Named { name: n1, generics: g1, .. }, Type::Named { name: n2, generics: g2, .. }) => {
-                n1.lexeme == n2.lexeme && g1.len() == g2.len() && 
-                g1.iter().zip(g2.iter()).all(|(t1, t2)| self.check_type_compatibility(t1, t2))
-            }
-            (Type::Array { element: e1, size: s1, .. }, Type::Array { element: e2, size: s2, .. }) => {
-                s1 == s2 && self.check_type_compatibility(e1, e2)
+    fn mismatch(&mut self, mut message: String, span: &Span, mismatch: Mismatch) {
+        let mut help = None;
+        let note = match mismatch {
+            Mismatch::Types(expected, actual) => {
+                let (expected, actual) = (self.resolve(&expected), self.resolve(&actual));
+                if let (Type::Dyn { trait_, .. }, Some(name)) = (&expected, type_name(&actual)) {
+                    if self.table.types.get(&trait_name_of(trait_)).is_some_and(|symbol| symbol.kind == SymbolKind::Trait) {
+                        help = Some(format!("Implement it with 'extend {} for {}'", trait_, name));
+                    }
+                }
+                if self.converts(&expected, &actual) || self.converts(&actual, &expected) {
+                    help = Some(format!("Convert it explicitly with 'as {}'", expected));
+                }
+                if is_nullable(&actual) && !is_nullable(&expected) {
+                    help = Some(match is_primitive(&actual, "null") {
+                        true => format!("Make the type optional to allow 'null': '{}?'", expected),
+                        false => "Check it against 'null' first, or give a default with '??'".to_string(),
+                    });
+                }
+                format!("Expected '{}', found '{}'", expected, actual)
             }
-            (Type::Tuple { elements: e1, .. }, Type::Tuple { elements: e2, .. }) => {
-                e1.len() == e2.len() && 
-                e1.iter().zip(e2.iter()).all(|(t1, t2)| self.check_type_compatibility(t1, t2))
+            // The types involved are only partly known, so showing them doesn't help
+            Mismatch::Infinite => {
+                message = "Cannot infer a type that contains itself".to_string();
+                "The value would have to be part of its own type".to_string()
             }
-            (Type::Function { params: p1, return_type: r1, .. }, Type::Function { params: p2, return_type: r2, .. }) => {
-                p1.len() == p2.len() && 
-                p1.iter().zip(p2.iter()).all(|(t1, t2)| self.check_type_compatibility(t1, t2)) &&
-                self.check_type_compatibility(r1, r2)
+        };
+        let mut error = Error::new(message, 0, span.clone(), String::new());
+        error.add_note(Note::new(note, 0, span.clone(), String::new()));
+        if let Some(help) = help {
+            error.add_help(Help::new(help, 0, span.clone(), String::new()));
+        }
+        self.errors.push(error);
+    }
+
+    /// A new inference variable, standing for a type that isn't known yet.
+    fn fresh_var(&mut self, span: &Span) -> Type {
+        self.next_var += 1;
+        Type::TypeVar {
+            name: Token::new(TokenKind::Identifier, format!("?{}", self.next_var), 0, span.clone()),
+            span: span.clone(),
+        }
+    }
+
+    /// Replaces every solved inference variable in `ty` with its solution.
+    pub fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::TypeVar { name, .. } => match self.type_vars.get(&name.lexeme) {
+                Some(solution) => self.resolve(solution),
+                None => ty.clone(),
+            },
+            Type::Named { name, generics, span } => match self.table.alias(&name.lexeme) {
+                // Aliases are transparent: they are replaced by the type they stand for
+                Some(target) if generics.is_empty() && !self.cyclic_aliases.contains(&name.lexeme) => self.resolve(target),
+                _ => Type::Named {
+                    name: name.clone(),
+                    generics: generics.iter().map(|ty| self.resolve(ty)).collect(),
+                    span: span.clone(),
+                },
+            },
+            Type::Array { element, size, span } => Type::Array {
+                element: Box::new(self.resolve(element)),
+                size: *size,
+                span: span.clone(),
+            },
+            Type::Tuple { elements, span } => Type::Tuple {
+                elements: elements.iter().map(|ty| self.resolve(ty)).collect(),
+                span: span.clone(),
+            },
+            Type::Function { params, return_type, span } => Type::Function {
+                params: params.iter().map(|ty| self.resolve(ty)).collect(),
+                return_type: Box::new(self.resolve(return_type)),
+                span: span.clone(),
+            },
+            Type::Optional { inner, span } => optional(self.resolve(inner), span),
+            Type::Dyn { trait_, span } => Type::Dyn { trait_: Box::new(self.resolve(trait_)), span: span.clone() },
+            Type::Primitive { .. } | Type::Error(_) => ty.clone(),
+        }
+    }
+
+    /// Makes a value of type `actual` fit where `expected` is required, solving
+    /// inference variables along the way. A failed unification solves nothing.
+    fn unify(&mut self, expected: &Type, actual: &Type) -> Result<(), Mismatch> {
+        let mark = self.solved.len();
+        let result = self.unify_parts(expected, actual);
+        if result.is_err() {
+            for name in self.solved.drain(mark..) {
+                self.type_vars.remove(&name);
             }
-            (Type::TypeVar { name: n1, .. }, Type::TypeVar { name: n2, .. }) => n1.lexeme == n2.lexeme,
-            _ => false,
         }
+        result
     }
 
-    fn infer_type(&mut self, expr: &Expr) -> Option<Type> {
-        match expr {
-            Expr::Identifier(token, _) => {
-                if let Some(symbol) = self.table.values.get(&token.lexeme) {
-                    symbol.ty.clone()
-                } else {
-                    self.error(format!("Undefined variable '{}'", token.lexeme), &token.span);
-                    None
+    fn unify_parts(&mut self, expected: &Type, actual: &Type) -> Result<(), Mismatch> {
+        let expected = self.resolve(expected);
+        let actual = self.resolve(actual);
+
+        match (&expected, &actual) {
+            (Type::TypeVar { name: n1, .. }, Type::TypeVar { name: n2, .. }) if n1.lexeme == n2.lexeme => Ok(()),
+            (Type::TypeVar { name, .. }, other) | (other, Type::TypeVar { name, .. }) if is_inference_var(name) => {
+                if occurs(&name.lexeme, other) {
+                    return Err(Mismatch::Infinite);
                 }
+                self.type_vars.insert(name.lexeme.clone(), other.clone());
+                self.solved.push(name.lexeme.clone());
+                Ok(())
+            }
+            (Type::Error(_), _) | (_, Type::Error(_)) => Ok(()),
+            (Type::Optional { inner: e, .. }, Type::Optional { inner: a, .. }) => self.unify_parts(e, a),
+            // An optional type holds `null` as well as any value of the type it wraps
+            (Type::Optional { .. }, actual) if is_primitive(actual, "null") => Ok(()),
+            (Type::Optional { inner, .. }, actual) => self.unify_parts(inner, actual),
+            (Type::Primitive { name: n1, .. }, Type::Primitive { name: n2, .. }) if n1.lexeme == n2.lexeme => Ok(()),
+            (Type::Named { name: n1, generics: g1, .. }, Type::Named { name: n2, generics: g2, .. })
+                if n1.lexeme == n2.lexeme && g1.len() == g2.len() =>
+            {
+                g1.iter().zip(g2).try_for_each(|(t1, t2)| self.unify_parts(t1, t2))
+            }
+            // An unsized array type accepts arrays of any length
+            (Type::Array { element: e1, size: s1, .. }, Type::Array { element: e2, size: s2, .. })
+                if s1.is_none() || s1 == s2 =>
+            {
+                self.unify_parts(e1, e2)
+            }
+            (Type::Tuple { elements: e1, .. }, Type::Tuple { elements: e2, .. }) if e1.len() == e2.len() => {
+                e1.iter().zip(e2).try_for_each(|(t1, t2)| self.unify_parts(t1, t2))
             }
+            (Type::Function { params: p1, return_type: r1, .. }, Type::Function { params: p2, return_type: r2, .. })
+                if p1.len() == p2.len() =>
+            {
+                p1.iter().zip(p2).try_for_each(|(t1, t2)| self.unify_parts(t1, t2))?;
+                self.unify_parts(r1, r2)
+            }
+            (Type::Dyn { trait_: t1, .. }, Type::Dyn { trait_: t2, .. }) => self.unify_parts(t1, t2),
+            // Any value whose type implements the trait can be used as a trait object
+            (Type::Dyn { trait_, .. }, other) if self.implements(other, trait_) => Ok(()),
+            // A generic parameter is written as a plain name outside of generic lists
+            (Type::TypeVar { name: n1, .. }, Type::Named { name: n2, generics, .. })
+            | (Type::Named { name: n2, generics, .. }, Type::TypeVar { name: n1, .. })
+                if generics.is_empty() && n1.lexeme == n2.lexeme =>
+            {
+                Ok(())
+            }
+            _ => Err(Mismatch::Types(Box::new(expected), Box::new(actual))),
+        }
+    }
+
+    /// Infers the type of `expr`, with every inference variable solved so far filled in.
+    pub fn infer_type(&mut self, expr: &Expr) -> Option<Type> {
+        let ty = self.infer(expr)?;
+        Some(self.resolve(&ty))
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Identifier(..) | Expr::Field { .. } => self.infer_reference(expr, &[]),
             Expr::Literal(lit) => match lit {
-                Literal::Integer(_, span) => Some(Type::Primitive { 
-                    name: Token { 
-                        lexeme: "int".to_string(), 
-                        span: span.clone(),
-                        kind: TokenKind::Identifier,
-                        line: 0,
-                    },
-                    span: span.clone()
-                }),
-                Literal::Float(_, span) => Some(Type::Primitive { 
-                    name: Token { 
-                        lexeme: "float".to_string(), 
-                        span: span.clone(),
-                        kind: TokenKind::Identifier,
-                        line: 0,
-                    },
-                    span: span.clone()
-                }),
-                Literal::Bool(_, span) => Some(Type::Primitive { 
-                    name: Token { 
-                        lexeme: "bool".to_string(), 
-                        span: span.clone(),
-                        kind: TokenKind::Identifier,
-                        line: 0,
-                    },
-                    span: span.clone()
-                }),
-                Literal::Null(span) => Some(Type::Primitive { 
-                    name: Token { 
-                        lexeme: "null".to_string(), 
-                        span: span.clone(),
-                        kind: TokenKind::Identifier,
-                        line: 0,
-                    },
-                    span: span.clone()
-                }),
-                Literal::Token(_, span) => Some(Type::Primitive { 
-                    name: Token { 
-                        lexeme: "string".to_string(), 
-                        span: span.clone(),
-                        kind: TokenKind::Identifier,
-                        line: 0,
-                    },
-                    span: span.clone()
-                }),
+                Literal::Integer(_, span) => Some(primitive("int", span)),
+                Literal::Float(_, span) => Some(primitive("float", span)),
+                Literal::Bool(_, span) => Some(primitive("bool", span)),
+                Literal::Null(span) => Some(primitive("null", span)),
+                Literal::Token(_, span) => Some(primitive("string", span)),
             },
+            Expr::TokenInterpolation(interpolation, span) => {
+                for segment in &interpolation.segments {
+                    if let TokenSegment::Expr(expr, _) = segment {
+                        self.infer_type(expr);
+                    }
+                }
+                Some(primitive("string", span))
+            }
+            Expr::Binary { left, op, right, span } if op.lexeme == "??" => self.default_value(left, right, span),
             Expr::Binary { left, op, right, span } => {
                 let left_ty = self.infer_type(left);
-                let right_ty = self.infer_type(right);
-                
-                match (&left_ty, &right_ty) {
-                    (Some(Type::Primitive { name: n1, .. }), Some(Type::Primitive { name: n2, .. })) => {
-                        match (n1.lexeme.as_str(), n2.lexeme.as_str(), op.lexeme.as_str()) {
-                            ("int", "int", _) | ("float", "float", _) => left_ty,
-                            ("int", "float", _) | ("float", "int", _) => Some(Type::Primitive {
-                                name: Token { 
-                                    lexeme: "float".to_string(), 
-                                    span: span.clone(),
-                                    kind: TokenKind::Identifier,
-                                    line: 0,
-                                },
-                                span: span.clone()
-                            }),
-                            ("bool", "bool", "&&" | "||") => left_ty,
-                            _ => {
-                                self.error(format!("Invalid binary operation: {} {} {}", 
-                                    n1.lexeme, op.lexeme, n2.lexeme), span);
-                                None
-                            }
-                        }
-                    }
-                    _ => {
-                        self.error("Invalid operands for binary operation".to_string(), span);
+                // The right side only runs once the left has decided the result
+                let checked = match op.lexeme.as_str() {
+                    "&&" => null_checks(left).0,
+                    "||" => null_checks(left).1,
+                    _ => vec![],
+                };
+                let right_ty = self.with_narrowed(&checked, |checker| checker.infer_type(right));
+                let (mut left_ty, mut right_ty) = (known(left_ty)?, known(right_ty)?);
+                if op.lexeme != "==" && op.lexeme != "!=" {
+                    left_ty = self.non_null(left_ty, &left.span());
+                    right_ty = self.non_null(right_ty, &right.span());
+                }
+                self.binary_result(&op.lexeme, &left_ty, &right_ty, span)
+            }
+            Expr::Unary { op, expr, span } => {
+                let ty = known(self.infer_type(expr))?;
+                let ty = self.non_null(ty, &expr.span());
+                match op.lexeme.as_str() {
+                    "!" if is_primitive(&ty, "bool") => Some(ty),
+                    "-" if is_numeric(&ty) => Some(ty),
+                    op => {
+                        self.error(format!("Cannot apply '{}' to a value of type '{}'", op, ty), span);
                         None
                     }
                 }
             }
             Expr::Call { callee, args, generic_args, span } => {
-                let callee_ty = self.infer_type(callee);
-                if let Some(Type::Function { params, return_type, .. }) = callee_ty {
-                    if params.len() != args.len() {
-                        self.error(format!("Expected {} arguments, got {}", params.len(), args.len()), span);
-                        return None;
-                    }
-                    
-                    for (param_ty, arg) in params.iter().zip(args.iter()) {
-                        let arg_ty = self.infer_type(arg);
-                        if let Some(arg_ty) = arg_ty {
-                            if !self.check_type_compatibility(param_ty, &arg_ty) {
-                                self.error(format!("Type mismatch in function call"), span);
-                                return None;
+                let callee_ty = match &**callee {
+                    Expr::Identifier(..) | Expr::Field { .. } => self.infer_reference(callee, generic_args),
+                    _ => {
+                        if !generic_args.is_empty() {
+                            self.error("Only functions and methods can be given type arguments".to_string(), span);
+                        }
+                        self.infer(callee)
+                    }
+                };
+                // `base?.method()` calls the method only when `base` isn't null
+                let chained = matches!(**callee, Expr::Field { optional: true, .. });
+                let callee_ty = known(callee_ty.map(|ty| self.resolve(&ty))).map(|ty| match ty {
+                    Type::Optional { inner, .. } if chained => *inner,
+                    ty => self.non_null(ty, &callee.span()),
+                });
+                let Some(Type::Function { params, return_type, .. }) = callee_ty else {
+                    for arg in args {
+                        self.infer_type(arg);
+                    }
+                    if let Some(other) = callee_ty {
+                        self.error(format!("A value of type '{}' is not callable", other), span);
+                    }
+                    return None;
+                };
+
+                let arg_tys: Vec<_> = args.iter().enumerate()
+                    .map(|(i, arg)| self.check_expr(arg, params.get(i)))
+                    .collect();
+                if params.len() != args.len() {
+                    self.error(format!("Expected {} arguments, got {}", params.len(), args.len()), span);
+                    return None;
+                }
+
+                for ((param_ty, arg), arg_ty) in params.iter().zip(args).zip(arg_tys) {
+                    let Some(arg_ty) = arg_ty else { continue };
+                    if let Err(mismatch) = self.unify(param_ty, &arg_ty) {
+                        let message = format!("Expected an argument of type '{}', found '{}'", self.resolve(param_ty), arg_ty);
+                        self.mismatch(message, &arg.span(), mismatch);
+                    }
+                }
+                match chained {
+                    true => Some(optional(*return_type, span)),
+                    false => Some(*return_type),
+                }
+            }
+            Expr::Index { base, index, span } => {
+                let base_ty = known(self.infer_type(base)).map(|ty| self.non_null(ty, &base.span()));
+                if let Some(index_ty) = known(self.infer_type(index)) {
+                    if !is_primitive(&index_ty, "int") {
+                        self.error(format!("Index must be an int, found '{}'", index_ty), &index.span());
+                    }
+                }
+
+                match base_ty? {
+                    Type::Array { element, .. } => Some(*element),
+                    ty if is_primitive(&ty, "string") => Some(ty),
+                    Type::Tuple { elements, .. } => match &**index {
+                        Expr::Literal(Literal::Integer(i, _)) => {
+                            let element = usize::try_from(*i).ok().and_then(|i| elements.get(i)).cloned();
+                            if element.is_none() {
+                                self.error(format!("Index {} is out of bounds for a tuple of {} elements", i, elements.len()), span);
                             }
+                            element
                         }
+                        // The element can't be known without a constant index
+                        _ => None,
+                    },
+                    other => {
+                        self.error(format!("Cannot index a value of type '{}'", other), span);
+                        None
                     }
-                    
-                    Some(*return_type)
+                }
+            }
+            Expr::Assignment { left, op, right, span } => {
+                if let Expr::Field { optional: true, .. } = &**left {
+                    self.error("Cannot assign through '?.'".to_string(), span);
+                }
+                // A variable narrowed by a null check can still be given any value of its declared type
+                let declared = match &**left {
+                    Expr::Identifier(name, _) => self.table.values.get(&name.lexeme)
+                        .and_then(|symbol| self.narrowed.get(symbol.span.as_ref()?))
+                        .cloned(),
+                    _ => None,
+                };
+                let target_ty = match &declared {
+                    Some(declared) => Some(declared.clone()),
+                    None => self.infer_type(left),
+                };
+                let expected = target_ty.as_ref().filter(|_| op.lexeme == "=");
+                let value_ty = self.check_expr(right, expected);
+                let (Some(target_ty), Some(value_ty)) = (target_ty, value_ty) else {
+                    return None;
+                };
+
+                let result_ty = if op.lexeme == "=" {
+                    value_ty
                 } else {
-                    self.error("Expression is not callable".to_string(), span);
-                    None
+                    let (target_ty, value_ty) = (known(Some(target_ty.clone()))?, known(Some(value_ty))?);
+                    self.binary_result(op.lexeme.trim_end_matches('='), &target_ty, &value_ty, span)?
+                };
+                if let Err(mismatch) = self.unify(&target_ty, &result_ty) {
+                    let message = format!("Cannot assign a value of type '{}' to '{}'", result_ty, target_ty);
+                    self.mismatch(message, span, mismatch);
                 }
+                // Which ends the narrowing if the new value may be null
+                if let (Some(declared), Expr::Identifier(name, _)) = (declared, &**left) {
+                    if is_nullable(&result_ty) {
+                        if let Some(symbol) = self.table.values.get_mut(&name.lexeme) {
+                            symbol.ty = Some(declared);
+                        }
+                    }
+                }
+                Some(target_ty)
             }
-            // Add more expression type inference cases here
-            _ => None,
+            Expr::StructInit { name, fields, span } => self.struct_init(name, fields, span),
+            Expr::Array { elements, span } => {
+                let element = self.fresh_var(span);
+                self.array_elements(elements, &element);
+                Some(Type::Array {
+                    element: Box::new(element),
+                    size: Some(elements.len()),
+                    span: span.clone(),
+                })
+            }
+            Expr::Tuple { elements, span } => {
+                let element_tys: Vec<_> = elements.iter().map(|element| self.infer_type(element)).collect();
+                Some(Type::Tuple {
+                    elements: element_tys.into_iter().collect::<Option<_>>()?,
+                    span: span.clone(),
+                })
+            }
+            Expr::Cast { expr, ty, span } => {
+                self.check_type_arguments(ty);
+                if let Some(from) = known(self.infer_type(expr)).map(|ty| self.non_null(ty, &expr.span())) {
+                    let to = self.resolve(ty);
+                    let castable = match &to {
+                        Type::Dyn { .. } => self.unify(&to, &from).is_ok(),
+                        _ => self.converts(&from, &to) || self.converts(&to, &from) || can_cast(&from, &to) || self.conversion(&from, &to),
+                    };
+                    if !castable {
+                        self.error(format!("Cannot cast a value of type '{}' to '{}'", from, ty), span);
+                    }
+                }
+                Some(ty.clone())
+            }
+            Expr::Closure { params, ty, span, .. } => Some(Type::Function {
+                params: params.iter().map(|param| param.ty.clone()).collect(),
+                return_type: Box::new(ty.clone()),
+                span: span.clone(),
+            }),
+            Expr::Grouping(expr, _) => self.infer(expr),
+            Expr::Range { start, end, span, .. } => {
+                for bound in start.iter().chain(end) {
+                    if let Some(ty) = known(self.infer_type(bound)).filter(|ty| !is_primitive(ty, "int")) {
+                        self.error(format!("Range bounds must be 'int', found '{}'", ty), &bound.span());
+                    }
+                }
+                Some(range_type(span))
+            }
+            Expr::If { .. } | Expr::Match { .. } => self.conditional(expr, None),
+            Expr::Try { expr, span } => self.try_value(expr, span),
+            Expr::Error => None,
         }
     }
-}
 
-impl Visitor for TypeChecker {
-    fn visit_function(&mut self, function: &Function) -> Result<(), String> {
-        let old_return_type = self.current_return_type.take();
-        self.current_return_type = Some(function.return_type.clone());
-        
-        self.table.values.enter_scope();
-        for param in &function.params {
-            self.table.values.insert(Symbol {
-                name: param.name.lexeme.clone(),
-                kind: SymbolKind::Parameter,
-                ty: Some(param.ty.clone()),
-                span: Some(param.name.span.clone()),
-                struct_fields: None,
-                enum_variants: None,
-            });
+    /// Infers the type of `expr`, letting the type its context expects fill in what
+    /// the expression doesn't determine on its own, such as the element type of `[]`.
+    pub fn check_expr(&mut self, expr: &Expr, expected: Option<&Type>) -> Option<Type> {
+        match (expr, expected.map(|ty| self.resolve(ty))) {
+            (Expr::Array { elements, span }, Some(Type::Array { element, .. })) => {
+                self.array_elements(elements, &element);
+                Some(Type::Array {
+                    element: Box::new(self.resolve(&element)),
+                    size: Some(elements.len()),
+                    span: span.clone(),
+                })
+            }
+            (Expr::Literal(Literal::Null(_)), Some(expected @ Type::Optional { .. })) => Some(expected),
+            (Expr::Grouping(expr, _), _) => self.check_expr(expr, expected),
+            (Expr::If { .. } | Expr::Match { .. }, expected) => {
+                let ty = self.conditional(expr, expected.as_ref())?;
+                Some(self.resolve(&ty))
+            }
+            _ => self.infer_type(expr),
         }
-        
-        Visitor::visit_function(self, function)?;
-        
-        self.table.values.exit_scope();
-        self.current_return_type = old_return_type;
-        Ok(())
     }
 
-    fn visit_statement(&mut self, statement: &Statement) -> Result<(), String> {
+    /// The type of an `if` or `match` used for its value, which every branch that
+    /// doesn't leave early must agree on. `expected` is passed on to the branches.
+    fn conditional(&mut self, expr: &Expr, expected: Option<&Type>) -> Option<Type> {
+        match expr {
+            Expr::If { cond, then_branch, else_branch, span } => {
+                let Some(else_branch) = else_branch else {
+                    self.condition("if", cond);
+                    self.visit_statement(then_branch).ok()?;
+                    let mut error = Error::new("'if' without 'else' has no value when its condition is false".to_string(), 0, span.clone(), String::new());
+                    error.add_help(Help::new("Add an 'else' branch".to_string(), 0, span.clone(), String::new()));
+                    self.errors.push(error);
+                    return None;
+                };
+                self.if_value(cond, then_branch, else_branch, expected)
+            }
+            Expr::Match { expr, cases, .. } => self.match_value(expr, cases, expected),
+            _ => self.infer_type(expr),
+        }
+    }
+
+    /// Checks the condition of an `if` or `while`, which must be a `bool`.
+    fn condition(&mut self, keyword: &str, cond: &Expr) {
+        if let Some(ty) = known(self.infer_type(cond)) {
+            if self.unify(&primitive("bool", &cond.span()), &ty).is_err() {
+                let ty = self.resolve(&ty);
+                self.error(format!("'{}' condition must be a 'bool', found '{}'", keyword, ty), &cond.span());
+            }
+        }
+        // Closures in the condition are checked by the visitor
+        let _ = self.visit_expression(cond);
+    }
+
+    fn if_value(&mut self, cond: &Expr, then_branch: &Statement, else_branch: &Statement, expected: Option<&Type>) -> Option<Type> {
+        self.condition("if", cond);
+        let (when_true, when_false) = null_checks(cond);
+        let branches = [(then_branch, when_true), (else_branch, when_false)].map(|(branch, checked)| {
+            (self.with_narrowed(&checked, |checker| checker.branch_value(branch, expected)), branch.span())
+        });
+        self.agree(&branches, "'if' and 'else' have incompatible types")
+    }
+
+    fn match_value(&mut self, expr: &Expr, cases: &[Case], expected: Option<&Type>) -> Option<Type> {
+        let branches = self.match_cases(expr, cases, |checker, body| checker.branch_value(body, expected));
+        self.agree(&branches, "Match cases have incompatible types")
+    }
+
+    /// Checks the matched value and the cases of a `match`, with each case body
+    /// checked by `body` in a scope holding the names its pattern binds.
+    fn match_cases(&mut self, expr: &Expr, cases: &[Case], mut body: impl FnMut(&mut Self, &Statement) -> Option<Type>) -> Vec<(Option<Type>, Span)> {
+        let ty = self.infer_type(expr);
+        let _ = self.visit_expression(expr);
+        self.check_cases(expr, ty.as_ref(), cases);
+        let mut branches = vec![];
+        for case in cases {
+            self.table.values.enter_scope();
+            self.check_binding_name(&case.pattern, ty.as_ref());
+            self.bind_pattern(&case.pattern, ty.clone());
+            if let Some(guard) = &case.guard {
+                if let Some(guard_ty) = known(self.infer_type(guard)).filter(|ty| !is_primitive(ty, "bool")) {
+                    self.error(format!("Match guard must be a 'bool', found '{}'", guard_ty), &guard.span());
+                }
+            }
+            branches.push((body(self, &case.body), case.body.span()));
+            self.table.values.exit_scope();
+        }
+        branches
+    }
+
+    /// Checks `statement` as a branch of an `if` or `match` used for its value, and
+    /// returns the type of the value it leaves: that of the expression ending a block,
+    /// 'void' if there is none, or nothing if the branch always leaves early.
+    fn branch_value(&mut self, statement: &Statement, expected: Option<&Type>) -> Option<Type> {
         match statement {
-            Statement::Let { name, ty, value, span } => {
-                if let Some(value) = value {
-                    let value_ty = self.infer_type(value);
-                    if let Some(value_ty) = value_ty {
-                        if let Some(declared_ty) = ty {
-                            if !self.check_type_compatibility(declared_ty, &value_ty) {
-                                self.error(format!("Type mismatch in let binding"), span);
-                            }
+            Statement::Tail(expr) => {
+                let ty = self.check_expr(expr, expected);
+                let _ = self.visit_expression(expr);
+                ty
+            }
+            Statement::Block(statements, span) => {
+                self.table.values.enter_scope();
+                let ty = match statements.split_last() {
+                    Some((last, rest)) => {
+                        rest.iter().for_each(|statement| { let _ = self.visit_statement(statement); });
+                        self.branch_value(last, expected)
+                    }
+                    None => Some(primitive("void", span)),
+                };
+                self.table.values.exit_scope();
+                ty
+            }
+            Statement::If { cond, then_branch, else_branch: Some(else_branch), .. } => {
+                self.if_value(cond, then_branch, else_branch, expected)
+            }
+            Statement::Match { expr, cases, .. } => self.match_value(expr, cases, expected),
+            Statement::Return(..) | Statement::Break(_) | Statement::Continue(_) => {
+                let _ = self.visit_statement(statement);
+                None
+            }
+            _ => {
+                let _ = self.visit_statement(statement);
+                Some(primitive("void", &statement.span()))
+            }
+        }
+    }
+
+    /// The type every branch has; a branch of a different type is an error.
+    fn agree(&mut self, branches: &[(Option<Type>, Span)], message: &str) -> Option<Type> {
+        let mut result: Option<Type> = None;
+        for (ty, span) in branches {
+            let Some(ty) = known(ty.clone()) else { continue };
+            match result.clone() {
+                None => result = Some(widen(ty)),
+                // A branch that is `null` makes the value optional
+                Some(expected) if is_primitive(&expected, "null") => result = Some(optional(widen(ty), span)),
+                Some(expected) => {
+                    let expected = match is_nullable(&ty) {
+                        true => optional(expected, span),
+                        false => expected,
+                    };
+                    result = Some(expected.clone());
+                    if let Err(mismatch) = self.unify(&expected, &ty) {
+                        self.mismatch(message.to_string(), span, mismatch);
+                        if is_primitive(&ty, "void") {
+                            let help = Help::new("End the block with an expression and no ';' to give it a value".to_string(), 0, span.clone(), String::new());
+                            self.errors.last_mut().unwrap().add_help(help);
                         }
                     }
                 }
             }
-            Statement::Return(expr, span) => {
-                if let Some(return_type) = &self.current_return_type {
-                    if let Some(expr) = expr {
-                        // Clone the return type to avoid the borrow checker issue
-                        let return_type = return_type.clone();
-                        let expr_ty = self.infer_type(expr);
-                        if let Some(expr_ty) = expr_ty {
-                            if !self.check_type_compatibility(&return_type, &expr_ty) {
-                                self.error(format!("Return type mismatch"), span);
-                            }
+        }
+        result
+    }
+
+    /// Checks that every element of an array literal has the type `element`.
+    fn array_elements(&mut self, elements: &[Expr], element: &Type) {
+        for expr in elements {
+            let Some(ty) = self.check_expr(expr, Some(element)) else { continue };
+            if let Err(mismatch) = self.unify(element, &ty) {
+                let message = format!("Array elements must have the same type, expected '{}', found '{}'", self.resolve(element), ty);
+                self.mismatch(message, &expr.span(), mismatch);
+            }
+        }
+    }
+
+    /// The type of a name or member, instantiating generic functions and methods with
+    /// `type_args` or, when there are none, with fresh inference variables.
+    fn infer_reference(&mut self, expr: &Expr, type_args: &[Type]) -> Option<Type> {
+        match expr {
+            Expr::Identifier(token, _) => {
+                let Some(symbol) = self.table.values.get(&token.lexeme).cloned() else {
+                    if !self.table.has_type(&token.lexeme) {
+                        self.error(format!("Undefined variable '{}'", token.lexeme), &token.span);
+                    }
+                    // Otherwise a type name used for static access, handled by `Expr::Field`
+                    return None;
+                };
+
+                let params = match symbol.kind {
+                    SymbolKind::Function => self.table.generic_params(None, &token.lexeme),
+                    _ => GenericParams::default(),
+                };
+                let instance = self.instantiate(&token.lexeme, &params.own, type_args, &token.span);
+                self.require_bounds(&token.lexeme, &params, &instance, &token.span);
+                if !instance.is_empty() {
+                    self.instantiations.insert(token.span.clone(), Instantiation { receiver: None, args: instance.clone() });
+                }
+                symbol.ty.map(|ty| substitute(&ty, &instance))
+            }
+            Expr::Field { base, field, optional: false, .. } => {
+                if let Expr::Identifier(name, _) = &**base {
+                    if self.table.values.get(&name.lexeme).is_none() {
+                        if let Some(symbol) = self.table.types.get(&name.lexeme).cloned() {
+                            return self.static_member(&symbol, field, type_args);
                         }
                     }
                 }
+
+                let base_ty = known(self.infer_type(base))?;
+                let base_ty = self.non_null(base_ty, &base.span());
+                self.member_type(&base_ty, field, type_args)
             }
-            _ => {}
+            Expr::Field { base, field, optional: true, span } => {
+                let base_ty = match known(self.infer_type(base))? {
+                    Type::Optional { inner, .. } => *inner,
+                    ty => {
+                        let mut warning = Warning::new(format!("'?.' on a value of type '{}', which is never null", ty), 0, span.clone(), String::new());
+                        warning.add_help(Help::new("Use '.' instead".to_string(), 0, span.clone(), String::new()));
+                        self.warnings.push(warning);
+                        ty
+                    }
+                };
+                let member_ty = self.member_type(&base_ty, field, type_args)?;
+                Some(optional(member_ty, span))
+            }
+            _ => self.infer(expr),
+        }
+    }
+
+    /// Chooses the types that the generic parameters of `name` stand for at one use:
+    /// the explicit type arguments if there are any, otherwise fresh inference variables.
+    fn instantiate(&mut self, name: &str, params: &[String], type_args: &[Type], span: &Span) -> HashMap<String, Type> {
+        let explicit = !type_args.is_empty();
+        if explicit && type_args.len() != params.len() {
+            self.error(format!("'{}' expects {} type arguments, found {}", name, params.len(), type_args.len()), span);
+        }
+        type_args.iter().for_each(|ty| self.check_type_arguments(ty));
+
+        params.iter().enumerate()
+            .map(|(i, param)| {
+                let ty = match type_args.get(i) {
+                    Some(ty) if type_args.len() == params.len() => ty.clone(),
+                    _ => self.fresh_var(span),
+                };
+                (param.clone(), ty)
+            })
+            .collect()
+    }
+
+    /// Records the trait bounds that the types chosen for `name`'s own parameters must satisfy.
+    fn require_bounds(&mut self, name: &str, params: &GenericParams, instance: &HashMap<String, Type>, span: &Span) {
+        for param in &params.own {
+            let (Some(traits), Some(ty)) = (params.bounds.get(param), instance.get(param)) else { continue };
+            for trait_ in traits {
+                self.obligations.push(Obligation {
+                    ty: ty.clone(),
+                    trait_: trait_.clone(),
+                    param: param.clone(),
+                    owner: name.to_string(),
+                    span: span.clone(),
+                });
+            }
+        }
+    }
+
+    /// Reports the obligations whose type turned out not to implement the required trait.
+    fn check_obligations(&mut self, obligations: Vec<Obligation>) {
+        for obligation in obligations {
+            let ty = self.resolve(&obligation.ty);
+            let trait_name = obligation.trait_.to_string();
+            // Still unknown, or already reported
+            if type_name(&ty).is_none() || self.implements(&ty, &obligation.trait_) {
+                continue;
+            }
+
+            let mut error = Error::new(format!("Type '{}' does not implement trait '{}'", ty, trait_name), 0, obligation.span.clone(), String::new());
+            error.add_note(Note::new(
+                format!("Required by the bound '{}: {}' of '{}'", obligation.param, trait_name, obligation.owner),
+                0, obligation.span.clone(), String::new(),
+            ));
+            let help = match type_param(&ty, &self.table) {
+                Some(param) => format!("Add the bound '{}: {}'", param, trait_name),
+                None => format!("Implement it with 'extend {} for {}'", trait_name, ty),
+            };
+            error.add_help(Help::new(help, 0, obligation.span.clone(), String::new()));
+            self.errors.push(error);
+        }
+    }
+
+    /// Whether a value of type `ty` has the methods of `trait_`: it implements the trait,
+    /// is a generic parameter bounded by it, or is an object of that trait.
+    fn implements(&self, ty: &Type, trait_: &Type) -> bool {
+        if let Some(param) = type_param(ty, &self.table) {
+            return self.bounds.get(param).is_some_and(|traits| traits.iter().any(|bound| same_trait(bound, trait_)));
+        }
+        match ty {
+            Type::Dyn { trait_: object, .. } => same_trait(object, trait_),
+            _ => type_name(ty).is_some_and(|name| self.table.implements(&name, &trait_name_of(trait_))),
+        }
+    }
+
+    /// Checks that `dyn Trait` names a trait whose methods can all be called without knowing
+    /// the type behind the object: each takes `self`, has no type parameters of its own and
+    /// doesn't mention `Self`, and the trait has no associated types.
+    fn check_object_safety(&mut self, trait_: &Type, span: &Span) {
+        let name = trait_name_of(trait_);
+        match self.table.types.get(&name).map(|symbol| symbol.kind.clone()) {
+            Some(SymbolKind::Trait) => {}
+            Some(_) => return self.error(format!("'{}' is not a trait", name), span),
+            None => return self.error(format!("Unknown trait '{}'", name), span),
+        }
+
+        let mut methods: Vec<Symbol> = self.table.methods.get(&name).into_iter().flatten().map(|(_, method)| method.clone()).collect();
+        methods.sort_by_key(|method| method.span.as_ref().map(|span| span.start));
+        let mut reasons = vec![];
+        for method in &methods {
+            if method.kind != SymbolKind::Method {
+                reasons.push(format!("'{}' doesn't take 'self'", method.name));
+            } else if !self.table.generic_params(Some(&name), &method.name).own.is_empty() {
+                reasons.push(format!("'{}' has type parameters of its own", method.name));
+            } else if method.ty.as_ref().is_some_and(mentions_self) {
+                reasons.push(format!("'{}' mentions 'Self' in its signature", method.name));
+            }
+        }
+        let types = self.table.traits.get(&name).map(|members| members.types.clone()).unwrap_or_default();
+        reasons.extend(types.iter().map(|associated| format!("It has the associated type '{}'", associated)));
+        if reasons.is_empty() {
+            return;
+        }
+
+        let mut error = Error::new(format!("Trait '{}' cannot be used as 'dyn {}'", name, name), 0, span.clone(), String::new());
+        for reason in reasons {
+            error.add_note(Note::new(reason, 0, span.clone(), String::new()));
+        }
+        error.add_help(Help::new(
+            format!("Use a generic parameter bounded by '{}' instead", name),
+            0, span.clone(), String::new(),
+        ));
+        self.errors.push(error);
+    }
+
+    /// Checks that every bound of `function` names one of its generic parameters and a trait.
+    fn check_bounds(&mut self, function: &Function) {
+        let params = names_of(&function.generics);
+        for bound in &function.bounds {
+            if !params.contains(&bound.param.lexeme) {
+                self.error(format!("'{}' is not a generic parameter of '{}'", bound.param.lexeme, function.name.lexeme), &bound.param.span);
+            }
+            for trait_ in &bound.traits {
+                let name = trait_name_of(trait_);
+                match self.table.types.get(&name).map(|symbol| symbol.kind.clone()) {
+                    Some(SymbolKind::Trait) => {}
+                    Some(_) => self.error(format!("'{}' is not a trait", name), &bound.span),
+                    None => self.error(format!("Unknown trait '{}'", name), &bound.span),
+                }
+            }
+        }
+    }
+
+    /// Checks that an `extend` block names an existing type with the right number of parameters.
+    fn check_extended_type(&mut self, extend: &Extend) {
+        let name = &extend.name;
+        let expected = match self.table.types.get(&name.lexeme) {
+            Some(symbol) if symbol.kind == SymbolKind::Trait => {
+                self.error(format!("Cannot extend the trait '{}' itself", name.lexeme), &name.span);
+                return;
+            }
+            Some(symbol) => generic_params(symbol).len(),
+            None if is_primitive_name(&name.lexeme) => 0,
+            None => {
+                self.error(format!("Cannot extend unknown type '{}'", name.lexeme), &name.span);
+                return;
+            }
+        };
+
+        let found = extend.type_generics().len();
+        if found != expected {
+            self.error(format!("'{}' expects {} type arguments, found {}", name.lexeme, expected, found), &name.span);
+        }
+    }
+
+    /// Checks that `extend Trait for Type` implements exactly the methods the trait declares,
+    /// with the same signatures, and that the type doesn't implement the trait twice.
+    fn check_implementation(&mut self, extend: &Extend, trait_name: &Token, self_ty: &Type) {
+        let trait_symbol = match self.table.types.get(&trait_name.lexeme) {
+            Some(symbol) if symbol.kind == SymbolKind::Trait => symbol.clone(),
+            Some(_) => return self.error(format!("'{}' is not a trait", trait_name.lexeme), &trait_name.span),
+            None => return self.error(format!("Unknown trait '{}'", trait_name.lexeme), &trait_name.span),
+        };
+
+        let key = (trait_name.lexeme.clone(), extend.name.lexeme.clone());
+        if let Some(first) = self.implementations.get(&key).cloned() {
+            let mut error = Error::new(
+                format!("Conflicting implementations of trait '{}' for '{}'", trait_name.lexeme, extend.name.lexeme),
+                0, trait_name.span.clone(), String::new(),
+            );
+            error.add_note(Note::new("First implemented here".to_string(), 0, first, String::new()));
+            self.errors.push(error);
+        } else {
+            self.implementations.insert(key, trait_name.span.clone());
+        }
+
+        let params = generic_params(&trait_symbol);
+        if params.len() != extend.first_generics.len() {
+            let message = format!("'{}' expects {} type arguments, found {}", trait_name.lexeme, params.len(), extend.first_generics.len());
+            self.error(message, &trait_name.span);
+        }
+        let members = self.table.traits.get(&trait_name.lexeme).cloned().unwrap_or_default();
+        self.check_associated_items(extend, trait_name, &members);
+
+        // `Self` and the associated types mean the same in the trait and the implementation
+        let associated = self.associated_of(&extend.name.lexeme, self_ty);
+        let mut trait_args: HashMap<_, _> = params.into_iter().zip(extend.first_generics.iter().cloned()).collect();
+        trait_args.extend(associated.clone());
+
+        let mut declared_methods: Vec<Symbol> = self.table.methods.get(&trait_name.lexeme)
+            .map(|methods| methods.values().cloned().collect())
+            .unwrap_or_default();
+        declared_methods.sort_by_key(|symbol| symbol.span.as_ref().map(|span| span.start));
+
+        for declared in &declared_methods {
+            let Some(method) = extend.methods.iter().find(|method| method.name.lexeme == declared.name) else {
+                if members.provided.contains(&declared.name) {
+                    continue;
+                }
+                let mut error = Error::new(
+                    format!("Missing method '{}' of trait '{}' in the implementation for '{}'", declared.name, trait_name.lexeme, extend.name.lexeme),
+                    0, trait_name.span.clone(), String::new(),
+                );
+                if let Some(span) = &declared.span {
+                    error.add_note(Note::new(format!("'{}' is declared here", declared.name), 0, span.clone(), String::new()));
+                }
+                self.errors.push(error);
+                continue;
+            };
+
+            // The trait's own method parameters correspond to the implementation's by position
+            let own = self.table.generic_params(Some(&trait_name.lexeme), &declared.name).own;
+            let mut bindings = trait_args.clone();
+            bindings.extend(own.iter().cloned().zip(method.generics.iter().cloned()));
+            let expected = declared.ty.as_ref().map(|ty| substitute(ty, &bindings));
+            let actual = Type::Function {
+                params: method.params.iter().map(|param| substitute(&param.ty, &associated)).collect(),
+                return_type: Box::new(substitute(&method.return_type, &associated)),
+                span: method.span.clone(),
+            };
+
+            let receiver = declared.kind == SymbolKind::Method;
+            let matches = own.len() == method.generics.len() && receiver == method.is_method
+                && expected.as_ref().is_none_or(|expected| self.unify(expected, &actual).is_ok());
+            if !matches {
+                let expected = expected.map(|ty| ty.to_string()).unwrap_or_default();
+                let mut error = Error::new(
+                    format!("Method '{}' doesn't match its declaration in trait '{}'", declared.name, trait_name.lexeme),
+                    0, method.name.span.clone(), String::new(),
+                );
+                error.add_note(Note::new(
+                    format!("Expected 'func {}{}', found 'func {}{}'", declared.name, signature(&own, receiver, &expected), method.name.lexeme, signature(&names_of(&method.generics), method.is_method, &actual.to_string())),
+                    0, method.name.span.clone(), String::new(),
+                ));
+                self.errors.push(error);
+            }
+        }
+
+        for method in &extend.methods {
+            if !declared_methods.iter().any(|declared| declared.name == method.name.lexeme) {
+                let mut error = Error::new(
+                    format!("Method '{}' is not a member of trait '{}'", method.name.lexeme, trait_name.lexeme),
+                    0, method.name.span.clone(), String::new(),
+                );
+                error.add_help(Help::new(
+                    format!("Move it to a separate 'extend {}' block", extend.name.lexeme),
+                    0, method.name.span.clone(), String::new(),
+                ));
+                self.errors.push(error);
+            }
+        }
+    }
+
+    /// Checks the associated types and constants of `extend Trait for Type` against the trait.
+    fn check_associated_items(&mut self, extend: &Extend, trait_name: &Token, members: &TraitMembers) {
+        let missing = |kind: &str, name: &str| format!(
+            "Missing associated {} '{}' of trait '{}' in the implementation for '{}'",
+            kind, name, trait_name.lexeme, extend.name.lexeme,
+        );
+        for name in &members.types {
+            if !extend.types.iter().any(|associated| &associated.name.lexeme == name) {
+                self.error(missing("type", name), &trait_name.span);
+            }
+        }
+        for associated in &extend.types {
+            if !members.types.contains(&associated.name.lexeme) {
+                self.error(format!("Type '{}' is not a member of trait '{}'", associated.name.lexeme, trait_name.lexeme), &associated.name.span);
+            }
+        }
+
+        let mut declared: Vec<_> = members.constants.iter().collect();
+        declared.sort_by_key(|(name, _)| name.as_str());
+        for (name, ty) in declared {
+            match extend.constants.iter().find(|constant| &constant.name.lexeme == name) {
+                Some(constant) if self.unify(ty, &constant.ty).is_err() => {
+                    let message = format!("Constant '{}' of trait '{}' has type '{}', found '{}'", name, trait_name.lexeme, ty, constant.ty);
+                    self.error(message, &constant.name.span);
+                }
+                Some(_) => {}
+                None if members.provided.contains(name) => {}
+                None => self.error(missing("constant", name), &trait_name.span),
+            }
+        }
+        for constant in &extend.constants {
+            if !members.constants.contains_key(&constant.name.lexeme) {
+                self.error(format!("Constant '{}' is not a member of trait '{}'", constant.name.lexeme, trait_name.lexeme), &constant.name.span);
+            }
+        }
+    }
+
+    /// Checks the values of associated constants against their declared types.
+    fn check_constants(&mut self, constants: &[Constant]) {
+        for constant in constants {
+            let ty = substitute(&constant.ty, &self.associated);
+            let Some(value) = &constant.value else { continue };
+            let Some(value_ty) = self.check_expr(value, Some(&ty)) else { continue };
+            if let Err(mismatch) = self.unify(&ty, &value_ty) {
+                self.mismatch(format!("Type mismatch in constant '{}'", constant.name.lexeme), &constant.span, mismatch);
+            }
+        }
+    }
+
+    /// What `Self` and the associated types of `type_name` stand for, with `self_ty` as `Self`.
+    /// Associated types can be written both as `Item` and as `Self.Item`.
+    fn associated_of(&self, type_name: &str, self_ty: &Type) -> HashMap<String, Type> {
+        let mut associated = HashMap::from([("Self".to_string(), self_ty.clone())]);
+        let chosen = self.table.associated_types.get(type_name).cloned().unwrap_or_default();
+        for (name, ty) in &chosen {
+            associated.insert(name.clone(), ty.clone());
+            associated.insert(format!("Self.{}", name), ty.clone());
+        }
+
+        // Types an implementation fails to choose are reported once, not at every use
+        let traits = self.table.implementations.get(type_name).into_iter().flatten();
+        let missing = traits.filter_map(|trait_name| self.table.traits.get(trait_name))
+            .flat_map(|members| &members.types)
+            .filter(|name| !chosen.contains_key(*name));
+        for name in missing {
+            associated.insert(name.clone(), Type::Error(Span::default()));
+            associated.insert(format!("Self.{}", name), Type::Error(Span::default()));
+        }
+        associated
+    }
+
+    /// What `Self` and the associated types of `trait_name` stand for when a type parameter
+    /// `param` is bounded by it: the parameter itself, and projections such as `T.Item`.
+    fn projections(&self, trait_name: &str, param: &Type) -> HashMap<String, Type> {
+        let mut associated = HashMap::from([("Self".to_string(), param.clone())]);
+        for name in self.table.traits.get(trait_name).iter().flat_map(|members| &members.types) {
+            let projection = Type::Named {
+                name: Token::new(TokenKind::Identifier, format!("{}.{}", param, name), 0, Span::default()),
+                generics: vec![],
+                span: Span::default(),
+            };
+            associated.insert(name.clone(), projection.clone());
+            associated.insert(format!("Self.{}", name), projection);
+        }
+        associated
+    }
+
+    /// The type of `left ?? right`, where `right` stands in for `left` when it is `null`.
+    fn default_value(&mut self, left: &Expr, right: &Expr, span: &Span) -> Option<Type> {
+        let inner = match known(self.infer_type(left)) {
+            Some(Type::Optional { inner, .. }) => Some(*inner),
+            Some(ty) if is_primitive(&ty, "null") => None,
+            Some(ty) => {
+                let mut warning = Warning::new("The left side of '??' is never null".to_string(), 0, left.span(), String::new());
+                warning.add_note(Note::new(format!("It has type '{}'", ty), 0, left.span(), String::new()));
+                self.warnings.push(warning);
+                Some(ty)
+            }
+            None => None,
+        };
+        let Some(default_ty) = known(self.check_expr(right, inner.as_ref())) else { return inner };
+        let Some(inner) = inner else { return Some(default_ty) };
+
+        // A default that may itself be null leaves the result optional
+        let result = match is_nullable(&default_ty) {
+            true => optional(inner, span),
+            false => inner,
+        };
+        if let Err(mismatch) = self.unify(&result, &default_ty) {
+            let message = format!("The default of '??' must have type '{}', found '{}'", self.resolve(&result), default_ty);
+            self.mismatch(message, &right.span(), mismatch);
         }
-        Visitor::visit_statement(self, statement)
+        Some(self.resolve(&result))
     }
-} 
\ No newline at end of file
+
+    /// The type of `expr?`, which is the `Ok` type of the `Result` `expr` produces. Its
+    /// `Err` is returned as is, or through the `from` method of the function's error type.
+    fn try_value(&mut self, expr: &Expr, span: &Span) -> Option<Type> {
+        let ty = known(self.infer_type(expr)).map(|ty| self.non_null(ty, &expr.span()))?;
+        let Some((value, error)) = result_types(&ty) else {
+            self.error(format!("'?' expects a 'Result', found '{}'", ty), span);
+            return None;
+        };
+
+        let return_type = self.current_return_type.clone().map(|ty| self.resolve(&ty));
+        match return_type.as_ref().and_then(result_types) {
+            Some((_, target)) => self.convert_error(&error, &target, span),
+            None => {
+                let mut diagnostic = Error::new("'?' can only be used in a function that returns 'Result'".to_string(), 0, span.clone(), String::new());
+                let returned = return_type.map(|ty| ty.to_string()).unwrap_or_else(|| "void".to_string());
+                diagnostic.add_note(Note::new(format!("The enclosing function returns '{}'", returned), 0, span.clone(), String::new()));
+                let suggestion = match returned.as_str() {
+                    "void" => format!("Return a 'Result<T, {}>' instead", self.resolve(&error)),
+                    _ => format!("Change the return type to 'Result<{}, {}>'", returned, self.resolve(&error)),
+                };
+                diagnostic.add_help(Help::new(suggestion, 0, span.clone(), String::new()));
+                self.errors.push(diagnostic);
+            }
+        }
+        Some(self.resolve(&value))
+    }
+
+    /// Checks that `?` can return an error of type `from` from a function whose errors
+    /// have type `to`: either they agree, or `to` implements `From` with `from` as its `Source`.
+    fn convert_error(&mut self, from: &Type, to: &Type, span: &Span) {
+        if self.unify(to, from).is_ok() {
+            return;
+        }
+        let from = self.resolve(from);
+        let to = self.resolve(to);
+        if self.conversion(&from, &to) {
+            return;
+        }
+        let mut error = Error::new(format!("'?' cannot convert an error of type '{}' into '{}'", from, to), 0, span.clone(), String::new());
+        error.add_help(Help::new(
+            format!("Implement 'From' for '{}' with 'type Source = {};'", to, from),
+            0, span.clone(), String::new(),
+        ));
+        self.errors.push(error);
+    }
+
+    /// Whether `to` implements `From` with `from` as its `Source`, so that `to.from`
+    /// converts values of type `from` into it.
+    fn conversion(&mut self, from: &Type, to: &Type) -> bool {
+        let Some(name) = type_name(to).filter(|name| self.table.implements(name, "From")) else {
+            return false;
+        };
+        let source = self.table.associated_types.get(&name).and_then(|types| types.get("Source")).cloned();
+        source.is_some_and(|source| self.unify(&source, from).is_ok())
+    }
+
+    /// Whether `newtype` is a newtype wrapping values of type `inner`, so that `as`
+    /// converts between the two.
+    fn converts(&mut self, newtype: &Type, inner: &Type) -> bool {
+        let wrapped = type_name(newtype).and_then(|name| self.table.newtypes.get(&name).cloned());
+        wrapped.is_some_and(|wrapped| self.unify(&wrapped, inner).is_ok())
+    }
+
+    /// `ty` for a value used where it can't be `null`, which an optional type doesn't ensure.
+    fn non_null(&mut self, ty: Type, span: &Span) -> Type {
+        let Type::Optional { inner, .. } = &ty else { return ty };
+        let mut error = Error::new(format!("A value of type '{}' may be null", ty), 0, span.clone(), String::new());
+        error.add_help(Help::new("Check it against 'null' first, or give a default with '??'".to_string(), 0, span.clone(), String::new()));
+        self.errors.push(error);
+        *inner.clone()
+    }
+
+    /// Treats each of the optional variables `names` as not null until the current scope ends.
+    fn narrow(&mut self, names: &[Token]) {
+        for name in names {
+            let Some(symbol) = self.table.values.get(&name.lexeme).cloned() else { continue };
+            let (Some(declared), Some(span)) = (symbol.ty.clone(), symbol.span.clone()) else { continue };
+            let Type::Optional { inner, .. } = self.resolve(&declared) else { continue };
+            self.narrowed.insert(span, declared);
+            self.table.values.insert(Symbol { ty: Some(*inner), ..symbol });
+        }
+    }
+
+    fn with_narrowed<T>(&mut self, names: &[Token], check: impl FnOnce(&mut Self) -> T) -> T {
+        if names.is_empty() {
+            return check(self);
+        }
+        self.table.values.enter_scope();
+        self.narrow(names);
+        let result = check(self);
+        self.table.values.exit_scope();
+        result
+    }
+
+    /// The type of `left op right`, reporting operand types the operator doesn't accept.
+    fn binary_result(&mut self, op: &str, left: &Type, right: &Type, span: &Span) -> Option<Type> {
+        let name = |ty: &Type| match ty {
+            Type::Primitive { name, .. } => name.lexeme.clone(),
+            _ => String::new(),
+        };
+
+        let result = match (name(left).as_str(), name(right).as_str(), op) {
+            (_, _, "==" | "!=") => Some("bool"),
+            ("bool", "bool", "&&" | "||") => Some("bool"),
+            ("int", "int", "+" | "-" | "*" | "/" | "%" | "**" | "&" | "|" | "^") => Some("int"),
+            ("int" | "float", "int" | "float", "+" | "-" | "*" | "/" | "%" | "**") => Some("float"),
+            ("int" | "float", "int" | "float", "<" | "<=" | ">" | ">=") => Some("bool"),
+            ("string", "string", "+") => Some("string"),
+            ("string", "string", "<" | "<=" | ">" | ">=") => Some("bool"),
+            _ => None,
+        };
+
+        match result {
+            Some(result) => Some(primitive(result, span)),
+            None => {
+                self.error(format!("Invalid binary operation: {} {} {}", left, op, right), span);
+                None
+            }
+        }
+    }
+
+    /// The type of the values `for` takes from `iter`, a value of type `ty`: the elements
+    /// of an array, the characters of a string, the integers of a range, the non-null
+    /// values `next` returns for an `Iterator`, or the elements of the iterator an
+    /// `Iterable` returns. Both traits are declared in the prelude.
+    fn element_type(&mut self, ty: &Type, iter: &Expr, iterable: bool) -> Option<Type> {
+        match ty {
+            Type::Array { element, .. } => return Some(*element.clone()),
+            ty if is_primitive(ty, "string") => return Some(ty.clone()),
+            ty if is_range(ty) => {
+                if let Expr::Range { start: None, span, .. } = iter {
+                    self.error("Cannot iterate over a range without a start".to_string(), span);
+                }
+                return Some(primitive("int", &iter.span()));
+            }
+            _ => {}
+        }
+
+        let protocol = |name: &str| Type::Named {
+            name: Token::new(TokenKind::Identifier, name.to_string(), 0, iter.span()),
+            generics: vec![],
+            span: iter.span(),
+        };
+        if self.implements(ty, &protocol("Iterator")) {
+            return match self.protocol_method(ty, "next", iter)? {
+                Type::Optional { inner, .. } => Some(*inner),
+                // An implementation whose `next` never returns `null` goes on forever
+                item => Some(item),
+            };
+        }
+        if iterable && self.implements(ty, &protocol("Iterable")) {
+            let iterator = self.protocol_method(ty, "iter", iter)?;
+            return self.element_type(&iterator, iter, false);
+        }
+
+        let mut error = Error::new(format!("Cannot iterate over a value of type '{}'", ty), 0, iter.span(), String::new());
+        error.add_help(Help::new(
+            "'for' takes arrays, strings, ranges and types implementing 'Iterator' or 'Iterable'".to_string(),
+            0, iter.span(), String::new(),
+        ));
+        self.errors.push(error);
+        None
+    }
+
+    /// The return type of the method `for` calls on `ty`, which must take no arguments.
+    fn protocol_method(&mut self, ty: &Type, name: &str, iter: &Expr) -> Option<Type> {
+        // Sits at the end of `iter` so its instantiation doesn't replace one inside it
+        let end = iter.span().end;
+        let method = Token::new(TokenKind::Identifier, name.to_string(), 0, Span::new(end, end));
+        match self.member_type(ty, &method, &[]).map(|method_ty| self.resolve(&method_ty)) {
+            Some(Type::Function { params, return_type, .. }) if params.is_empty() => Some(*return_type),
+            Some(_) => {
+                self.error(format!("'{}.{}' must take no arguments to be used by 'for'", ty, name), &iter.span());
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// The type of `base.member` for a value of type `base`.
+    fn member_type(&mut self, base: &Type, member: &Token, type_args: &[Type]) -> Option<Type> {
+        // Nothing is known about the members of an inference variable yet
+        let type_name = type_name(base)?;
+
+        if let Some(field_ty) = self.field_of(base, &member.lexeme) {
+            self.instantiate(&member.lexeme, &[], type_args, &member.span);
+            return Some(field_ty);
+        }
+        let Some(candidate) = self.choose_method(&type_name, base, member) else {
+            self.missing_member(base, &type_name, member);
+            return None;
+        };
+        if self.table.method(&candidate.owner, &member.lexeme).is_some_and(|method| method.kind != SymbolKind::Method) {
+            let mut error = Error::new(
+                format!("'{}' is a static method of '{}'", member.lexeme, type_name),
+                0, member.span.clone(), String::new(),
+            );
+            error.add_help(Help::new(format!("Call it as '{}.{}(...)'", type_name, member.lexeme), 0, member.span.clone(), String::new()));
+            self.errors.push(error);
+        }
+        let method_ty = self.method_type(&candidate.owner, member, type_args, &candidate.outer, base)?;
+        Some(substitute(&method_ty, &candidate.associated))
+    }
+
+    /// The methods `member` could refer to on a value of type `base`, from the most specific
+    /// source to the least: the type's own `extend` blocks, then the implementations of its
+    /// traits, then the traits a generic parameter is bounded by, then default methods.
+    fn method_candidates(&self, type_name: &str, base: &Type, member: &str) -> Vec<Candidate> {
+        if self.table.method(type_name, member).is_some() {
+            let outer = match base {
+                Type::Named { generics, .. } => generics.clone(),
+                Type::Dyn { trait_, .. } => match &**trait_ {
+                    Type::Named { generics, .. } => generics.clone(),
+                    _ => vec![],
+                },
+                _ => vec![],
+            };
+            let sources = self.table.method_sources.get(&format!("{}.{}", type_name, member)).cloned().unwrap_or_default();
+            let mut sources: Vec<_> = match sources.iter().any(|source| source == type_name) {
+                true => vec![type_name.to_string()],
+                false => sources,
+            };
+            sources.dedup();
+            return sources.into_iter()
+                .map(|source| Candidate {
+                    owner: type_name.to_string(),
+                    source,
+                    outer: outer.clone(),
+                    associated: self.associated_of(type_name, base),
+                })
+                .collect();
+        }
+
+        // A type parameter has the methods of the traits it is bounded by
+        if let Some(param) = type_param(base, &self.table) {
+            return self.bounds.get(param).into_iter().flatten()
+                .filter(|trait_| self.table.method(&trait_name_of(trait_), member).is_some())
+                .map(|trait_| Candidate {
+                    owner: trait_name_of(trait_),
+                    source: trait_name_of(trait_),
+                    outer: match trait_ {
+                        Type::Named { generics, .. } => generics.clone(),
+                        _ => vec![],
+                    },
+                    associated: self.projections(&trait_name_of(trait_), base),
+                })
+                .collect();
+        }
+
+        // Otherwise a default method of one of the traits the type implements
+        let mut traits: Vec<_> = self.table.implementations.get(type_name).into_iter().flatten()
+            .filter(|trait_name| self.table.method(trait_name, member).is_some())
+            .cloned()
+            .collect();
+        traits.sort();
+        traits.into_iter()
+            .map(|trait_name| Candidate {
+                owner: trait_name.clone(),
+                source: trait_name,
+                outer: vec![],
+                associated: self.associated_of(type_name, base),
+            })
+            .collect()
+    }
+
+    /// The method `member` refers to on a value of type `base`, reporting the
+    /// candidates when more than one trait provides it.
+    fn choose_method(&mut self, type_name: &str, base: &Type, member: &Token) -> Option<Candidate> {
+        let mut candidates = self.method_candidates(type_name, base, &member.lexeme);
+        if candidates.len() > 1 {
+            let mut error = Error::new(
+                format!("Ambiguous method '{}' for type '{}'", member.lexeme, base),
+                0, member.span.clone(), String::new(),
+            );
+            let sources: Vec<_> = candidates.iter().map(|candidate| format!("'{}.{}'", candidate.source, member.lexeme)).collect();
+            error.add_note(Note::new(format!("Candidates are {}", sources.join(", ")), 0, member.span.clone(), String::new()));
+            error.add_help(Help::new(
+                format!("Define '{}' in an 'extend {}' block to choose one", member.lexeme, type_name),
+                0, member.span.clone(), String::new(),
+            ));
+            self.errors.push(error);
+        }
+        (!candidates.is_empty()).then(|| candidates.remove(0))
+    }
+
+    /// Reports that a value of type `base` has no field or method `member`,
+    /// listing the methods it does have.
+    fn missing_member(&mut self, base: &Type, type_name: &str, member: &Token) {
+        let mut available: Vec<String> = self.table.methods.get(type_name).into_iter().flatten()
+            .filter(|(_, method)| method.kind == SymbolKind::Method)
+            .map(|(name, _)| name.clone())
+            .collect();
+        for trait_name in self.table.implementations.get(type_name).into_iter().flatten() {
+            available.extend(self.table.methods.get(trait_name).into_iter().flatten()
+                .filter(|(_, method)| method.kind == SymbolKind::Method)
+                .map(|(name, _)| name.clone()));
+        }
+        available.sort();
+        available.dedup();
+        let fields: Vec<String> = match self.table.types.get(type_name) {
+            Some(symbol) => symbol.struct_fields.iter().flatten().map(|field| field.name.lexeme.clone()).collect(),
+            None => vec![],
+        };
+
+        let mut error = Error::new(
+            format!("Type '{}' has no field or method '{}'", base, member.lexeme),
+            0, member.span.clone(), String::new(),
+        );
+        if let Some(suggestion) = closest_match(&member.lexeme, fields.iter().chain(&available).map(String::as_str)) {
+            error.add_help(Help::new(format!("Did you mean '{}'?", suggestion), 0, member.span.clone(), String::new()));
+        }
+        if !available.is_empty() {
+            let methods: Vec<_> = available.iter().map(|name| format!("'{}'", name)).collect();
+            error.add_note(Note::new(
+                format!("Methods of '{}': {}", type_name, methods.join(", ")),
+                0, member.span.clone(), String::new(),
+            ));
+        }
+        self.errors.push(error);
+    }
+
+    /// The type of the method `member` of `owner`, whose `extend` block or trait parameters
+    /// stand for `outer` and whose own parameters are instantiated with `type_args`.
+    fn method_type(&mut self, owner: &str, member: &Token, type_args: &[Type], outer: &[Type], receiver: &Type) -> Option<Type> {
+        let method = self.table.method(owner, &member.lexeme).cloned()?;
+        let params = self.table.generic_params(Some(owner), &member.lexeme);
+        let mut instance = self.instantiate(&member.lexeme, &params.own, type_args, &member.span);
+        self.require_bounds(&member.lexeme, &params, &instance, &member.span);
+        self.instantiations.insert(member.span.clone(), Instantiation { receiver: Some(receiver.clone()), args: instance.clone() });
+        instance.extend(params.outer.iter().cloned().zip(outer.iter().cloned()));
+        method.ty.map(|ty| substitute(&ty, &instance))
+    }
+
+    /// The type of `Type.member`: an enum variant or a static method.
+    fn static_member(&mut self, symbol: &Symbol, member: &Token, type_args: &[Type]) -> Option<Type> {
+        let variant = symbol.enum_variants.iter().flatten().find(|variant| variant.name.lexeme == member.lexeme);
+        if let (Some(variant), Some(enum_ty)) = (variant, &symbol.ty) {
+            self.instantiate(&member.lexeme, &[], type_args, &member.span);
+            // Like a struct literal, each use instantiates the enum's generic parameters afresh
+            let instance: HashMap<_, _> = generic_params(symbol).into_iter()
+                .map(|param| (param, self.fresh_var(&member.span)))
+                .collect();
+            let enum_ty = substitute(enum_ty, &instance);
+            if variant.fields.is_empty() {
+                return Some(enum_ty);
+            }
+            // Variants with fields are constructed by calling them
+            return Some(Type::Function {
+                params: variant.fields.iter().map(|field| substitute(field, &instance)).collect(),
+                return_type: Box::new(enum_ty),
+                span: member.span.clone(),
+            });
+        }
+
+        if let Some(ty) = self.constant_type(&symbol.name, &member.lexeme) {
+            self.instantiate(&member.lexeme, &[], type_args, &member.span);
+            return Some(ty);
+        }
+        let base = symbol.ty.clone().unwrap_or(Type::Error(member.span.clone()));
+        if let Some(candidate) = self.choose_method(&symbol.name, &base, member) {
+            if self.table.method(&candidate.owner, &member.lexeme).is_some_and(|method| method.kind == SymbolKind::Method) {
+                let mut error = Error::new(
+                    format!("Method '{}' of '{}' takes 'self', so it must be called on a value", member.lexeme, symbol.name),
+                    0, member.span.clone(), String::new(),
+                );
+                error.add_help(Help::new(
+                    format!("Call it on a '{}', e.g. 'value.{}(...)'", symbol.name, member.lexeme),
+                    0, member.span.clone(), String::new(),
+                ));
+                self.errors.push(error);
+                return None;
+            }
+            // Without a receiver, the parameters of the `extend` block are inferred too
+            let params = self.table.generic_params(Some(&candidate.owner), &member.lexeme);
+            let outer: Vec<_> = params.outer.iter().map(|_| self.fresh_var(&member.span)).collect();
+            // The type the method is called through, with its parameters still to be inferred
+            let receiver = match &base {
+                Type::Named { name, span, .. } if candidate.owner == symbol.name => {
+                    Type::Named { name: name.clone(), generics: outer.clone(), span: span.clone() }
+                }
+                other => other.clone(),
+            };
+            let method_ty = self.method_type(&candidate.owner, member, type_args, &outer, &receiver)?;
+            return Some(substitute(&method_ty, &candidate.associated));
+        }
+        let kind = if symbol.kind == SymbolKind::Enum { "variant or method" } else { "method" };
+        self.error(format!("Type '{}' has no {} '{}'", symbol.name, kind, member.lexeme), &member.span);
+        None
+    }
+
+    /// The type of the associated constant `member` of `type_name`, possibly
+    /// a default from one of the traits it implements.
+    fn constant_type(&self, type_name: &str, member: &str) -> Option<Type> {
+        if let Some(ty) = self.table.constants.get(type_name).and_then(|constants| constants.get(member)) {
+            return Some(ty.clone());
+        }
+        let mut traits: Vec<_> = self.table.implementations.get(type_name).into_iter().flatten().collect();
+        traits.sort();
+        traits.into_iter()
+            .filter_map(|trait_name| self.table.traits.get(trait_name))
+            .find(|members| members.provided.contains(member))
+            .and_then(|members| members.constants.get(member).cloned())
+    }
+
+    fn struct_init(&mut self, name: &Token, fields: &[(Token, Expr)], span: &Span) -> Option<Type> {
+        let Some(symbol) = self.table.types.get(&name.lexeme).cloned() else {
+            for (_, value) in fields {
+                self.infer_type(value);
+            }
+            return None;
+        };
+        let Some(declared) = &symbol.struct_fields else {
+            self.error(format!("'{}' is not a struct", name.lexeme), &name.span);
+            return None;
+        };
+        // Each literal instantiates the struct's generic parameters afresh
+        let instance: HashMap<_, _> = generic_params(&symbol).into_iter()
+            .map(|param| (param, self.fresh_var(span)))
+            .collect();
+
+        for (field, value) in fields {
+            let field_ty = declared.iter()
+                .find(|decl| decl.name.lexeme == field.lexeme)
+                .map(|decl| substitute(&decl.ty, &instance));
+            let value_ty = self.check_expr(value, field_ty.as_ref());
+
+            let Some(field_ty) = field_ty else {
+                self.error(format!("Struct '{}' has no field '{}'", name.lexeme, field.lexeme), &field.span);
+                continue;
+            };
+            let Some(value_ty) = value_ty else { continue };
+            if let Err(mismatch) = self.unify(&field_ty, &value_ty) {
+                let message = format!("Field '{}' of '{}' expects '{}', found '{}'", field.lexeme, name.lexeme, self.resolve(&field_ty), value_ty);
+                self.mismatch(message, &value.span(), mismatch);
+            }
+        }
+
+        for decl in declared {
+            if !fields.iter().any(|(field, _)| field.lexeme == decl.name.lexeme) {
+                self.error(format!("Missing field '{}' in '{}'", decl.name.lexeme, name.lexeme), span);
+            }
+        }
+
+        let generics: Vec<_> = generic_params(&symbol).iter()
+            .map(|param| widen(self.resolve(&instance[param])))
+            .collect();
+        let ty = Type::Named { name: name.clone(), generics, span: span.clone() };
+        self.instantiations.insert(name.span.clone(), Instantiation { receiver: Some(ty.clone()), args: HashMap::new() });
+        Some(ty)
+    }
+
+    /// The declared type of a struct field, with the struct's generic arguments filled in.
+    fn field_of(&self, ty: &Type, field: &str) -> Option<Type> {
+        let Type::Named { name, generics, .. } = ty else { return None };
+        let symbol = self.table.types.get(&name.lexeme)?;
+        let field = symbol.struct_fields.as_ref()?.iter().find(|decl| decl.name.lexeme == field)?;
+        Some(substitute(&field.ty, &generic_bindings(symbol, generics)))
+    }
+
+    /// Binds the names introduced by a match pattern, given the type being matched.
+    fn bind_pattern(&mut self, pattern: &Pattern, ty: Option<Type>) {
+        match pattern {
+            Pattern::Identifier(name, _) => self.declare_variable(name, ty),
+            Pattern::Tuple(patterns, span) => {
+                let element_tys = match known(ty) {
+                    Some(Type::Tuple { elements, .. }) if elements.len() == patterns.len() => elements,
+                    Some(Type::Error(_)) | None => vec![],
+                    Some(other) => {
+                        self.error(format!("A tuple pattern of {} elements cannot match a value of type '{}'", patterns.len(), other), span);
+                        vec![]
+                    }
+                };
+                for (i, pattern) in patterns.iter().enumerate() {
+                    self.bind_pattern(pattern, element_tys.get(i).cloned());
+                }
+            }
+            Pattern::Struct { fields, span } => {
+                let ty = known(ty).filter(|ty| !matches!(ty, Type::Error(_)));
+                let is_struct = ty.as_ref().and_then(type_name)
+                    .and_then(|name| self.table.types.get(&name))
+                    .is_some_and(|symbol| symbol.struct_fields.is_some());
+                if let (Some(ty), false) = (&ty, is_struct) {
+                    self.error(format!("A struct pattern cannot match a value of type '{}'", ty), span);
+                }
+                for (field, pattern) in fields {
+                    let field_ty = ty.as_ref().and_then(|ty| self.field_of(ty, &field.lexeme));
+                    if let (Some(ty), true, None) = (&ty, is_struct, &field_ty) {
+                        self.error(format!("Struct '{}' has no field '{}'", ty, field.lexeme), &field.span);
+                    }
+                    self.bind_pattern(pattern, field_ty);
+                }
+            }
+            Pattern::Variant { enum_name, name, fields, span } => {
+                let field_tys = self.variant_fields(enum_name.as_ref(), name, fields.len(), ty.as_ref(), span);
+                for (i, pattern) in fields.iter().enumerate() {
+                    self.bind_pattern(pattern, field_tys.get(i).cloned());
+                }
+            }
+            Pattern::Or(alternatives, _) => {
+                for alternative in alternatives {
+                    self.bind_pattern(alternative, ty.clone());
+                }
+            }
+            Pattern::Binding { name, pattern, .. } => {
+                self.declare_variable(name, ty.clone());
+                self.bind_pattern(pattern, ty);
+            }
+            Pattern::Literal(literal) => {
+                if let Some(ty) = ty {
+                    self.check_pattern_type(&Expr::Literal(literal.clone()), &ty, &literal.span());
+                }
+            }
+            Pattern::Range { start, end, inclusive, span } => {
+                let is_character = |literal: &Literal| matches!(literal, Literal::Token(token, _) if token.lexeme.chars().count() == 3);
+                if ![start, end].iter().all(|bound| matches!(bound, Literal::Integer(..)) || is_character(bound)) {
+                    self.error("Range patterns take integers or single characters".to_string(), span);
+                    return;
+                }
+                let Some(bound_ty) = self.infer_type(&Expr::Literal(start.clone())) else { return };
+                if let Some(ty) = ty {
+                    self.check_pattern_type(&Expr::Literal(start.clone()), &ty, span);
+                }
+                self.check_pattern_type(&Expr::Literal(end.clone()), &bound_ty, &end.span());
+
+                let empty = match (start, end) {
+                    (Literal::Integer(start, _), Literal::Integer(end, _)) => start > end || (start == end && !inclusive),
+                    (Literal::Token(start, _), Literal::Token(end, _)) => start.lexeme > end.lexeme || (start.lexeme == end.lexeme && !inclusive),
+                    _ => false,
+                };
+                if empty {
+                    self.error("Range pattern matches no value".to_string(), span);
+                }
+            }
+            Pattern::Wildcard(_) | Pattern::Error => {}
+        }
+    }
+
+    /// Checks that a pattern built from `expr`, e.g. a literal, can match a value of type `ty`.
+    fn check_pattern_type(&mut self, expr: &Expr, ty: &Type, span: &Span) {
+        let Some(pattern_ty) = self.infer_type(expr) else { return };
+        if let Err(mismatch) = self.unify(ty, &pattern_ty) {
+            self.mismatch(format!("Pattern of type '{}' cannot match a value of type '{}'", pattern_ty, self.resolve(ty)), span, mismatch);
+        }
+    }
+
+    /// The types of the fields of the variant a pattern names, checking that it exists and that
+    /// the pattern gives one sub-pattern per field. `ty` is the type of the value being matched.
+    fn variant_fields(&mut self, enum_name: Option<&Token>, name: &Token, arity: usize, ty: Option<&Type>, span: &Span) -> Vec<Type> {
+        let matched = ty.and_then(|ty| known(Some(ty.clone())));
+        let symbol = match (enum_name, &matched) {
+            (Some(enum_name), _) => self.table.types.get(&enum_name.lexeme).cloned(),
+            (None, Some(Type::Named { name, .. })) => self.table.types.get(&name.lexeme).cloned(),
+            (None, _) => None,
+        };
+        let Some(symbol) = symbol.filter(|symbol| symbol.kind == SymbolKind::Enum) else {
+            match (enum_name, matched) {
+                (Some(enum_name), _) => self.error(format!("'{}' is not an enum", enum_name.lexeme), &enum_name.span),
+                (None, Some(matched)) => self.error(format!("Cannot match variant '{}' against a value of type '{}'", name.lexeme, matched), span),
+                (None, None) => {}
+            }
+            return vec![];
+        };
+
+        let instance: HashMap<_, _> = generic_params(&symbol).into_iter()
+            .map(|param| (param, self.fresh_var(span)))
+            .collect();
+        if let (Some(enum_ty), Some(matched)) = (&symbol.ty, &matched) {
+            if let Err(mismatch) = self.unify(matched, &substitute(enum_ty, &instance)) {
+                self.mismatch(format!("Pattern of type '{}' cannot match a value of type '{}'", symbol.name, matched), span, mismatch);
+                return vec![];
+            }
+        }
+
+        let variants = symbol.enum_variants.clone().unwrap_or_default();
+        let Some(variant) = variants.iter().find(|variant| variant.name.lexeme == name.lexeme) else {
+            let mut error = Error::new(format!("Enum '{}' has no variant '{}'", symbol.name, name.lexeme), 0, name.span.clone(), String::new());
+            if let Some(suggestion) = closest_match(&name.lexeme, variants.iter().map(|variant| variant.name.lexeme.as_str())) {
+                error.add_help(Help::new(format!("Did you mean '{}'?", suggestion), 0, name.span.clone(), String::new()));
+            }
+            self.errors.push(error);
+            return vec![];
+        };
+        if variant.fields.len() != arity {
+            let plural = |count: usize| if count == 1 { "field" } else { "fields" };
+            let mut error = Error::new(
+                format!("Variant '{}.{}' has {} {}, but the pattern has {}", symbol.name, name.lexeme, variant.fields.len(), plural(variant.fields.len()), arity),
+                0, span.clone(), String::new(),
+            );
+            error.add_note(Note::new(format!("'{}' is declared here", name.lexeme), 0, variant.span.clone(), String::new()));
+            self.errors.push(error);
+        }
+        variant.fields.iter().map(|field| self.resolve(&substitute(field, &instance))).collect()
+    }
+
+    /// Warns about a binding pattern named like a variant of the enum being matched, which
+    /// matches every value rather than the variant.
+    fn check_binding_name(&mut self, pattern: &Pattern, ty: Option<&Type>) {
+        let Pattern::Identifier(name, span) = pattern else { return };
+        let Some(Type::Named { name: enum_name, .. }) = ty else { return };
+        let is_variant = self.table.types.get(&enum_name.lexeme)
+            .and_then(|symbol| symbol.enum_variants.as_ref())
+            .is_some_and(|variants| variants.iter().any(|variant| variant.name.lexeme == name.lexeme));
+        if is_variant {
+            let mut warning = Warning::new(format!("'{}' binds the value instead of matching '{}.{}'", name.lexeme, enum_name.lexeme, name.lexeme), 0, span.clone(), String::new());
+            warning.add_help(Help::new(format!("Write '{}.{}' to match the variant", enum_name.lexeme, name.lexeme), 0, span.clone(), String::new()));
+            self.warnings.push(warning);
+        }
+    }
+
+    /// Reports the cases of a `match` that can't be reached and the values no case matches.
+    fn check_cases(&mut self, expr: &Expr, ty: Option<&Type>, cases: &[Case]) {
+        let ty = ty.map(|ty| self.resolve(ty));
+        let report = MatchChecker::new(&self.table).check(ty.as_ref(), cases);
+
+        for &i in &report.unreachable {
+            let mut warning = Warning::new("Unreachable match case".to_string(), 0, cases[i].span.clone(), String::new());
+            warning.add_note(Note::new("The cases before it already match every value it would".to_string(), 0, cases[i].span.clone(), String::new()));
+            self.warnings.push(warning);
+        }
+
+        if report.missing.is_empty() {
+            return;
+        }
+        let mut error = Error::new(format!("Non-exhaustive match: {} not covered", missing_patterns(&report)), 0, expr.span(), String::new());
+        if let Some(ty) = &ty {
+            error.add_note(Note::new(format!("The matched value has type '{}'", ty), 0, expr.span(), String::new()));
+        }
+        error.add_help(Help::new("Add a case for each missing pattern, or a '_' case for the rest".to_string(), 0, expr.span(), String::new()));
+        self.errors.push(error);
+    }
+
+    /// Checks that the pattern of a `let` matches every value of type `ty`.
+    fn check_irrefutable(&mut self, pattern: &Pattern, ty: &Type) {
+        let span = pattern.span();
+        let case = Case { pattern: pattern.clone(), guard: None, body: Statement::Block(vec![], span.clone()), span: span.clone() };
+        let report = MatchChecker::new(&self.table).check(Some(ty), std::slice::from_ref(&case));
+        if report.missing.is_empty() {
+            return;
+        }
+        let mut error = Error::new(format!("Refutable pattern in 'let': {} not covered", missing_patterns(&report)), 0, span.clone(), String::new());
+        error.add_note(Note::new(format!("The value has type '{}'", ty), 0, span.clone(), String::new()));
+        error.add_help(Help::new("Use 'match' to handle the values the pattern doesn't match".to_string(), 0, span, String::new()));
+        self.errors.push(error);
+    }
+
+    /// Checks that each generic struct named in `ty` is given one type argument per parameter.
+    fn check_type_arguments(&mut self, ty: &Type) {
+        match ty {
+            Type::Named { name, generics, span } => {
+                if !self.is_type_name(&name.lexeme) {
+                    self.unknown_type(name, span);
+                }
+                let private = self.table.types.get(&name.lexeme).is_some_and(|symbol| symbol.access != AccessModifier::Public);
+                if let Some((module, item)) = name.lexeme.split_once('.').filter(|(module, _)| private && self.exporting_module.as_deref() != Some(module)) {
+                    let mut error = Error::new(format!("Type '{}' is private to module '{}'", item, module), 0, span.clone(), String::new());
+                    error.add_help(Help::new(format!("Declare it with 'pub type {}' to export it", item), 0, span.clone(), String::new()));
+                    self.errors.push(error);
+                }
+                let expected = self.table.types.get(&name.lexeme).map(|symbol| generic_params(symbol).len());
+                if let Some(expected) = expected.filter(|expected| *expected != generics.len()) {
+                    self.error(format!("'{}' expects {} type arguments, found {}", name.lexeme, expected, generics.len()), span);
+                }
+                generics.iter().for_each(|ty| self.check_type_arguments(ty));
+            }
+            Type::Array { element, .. } | Type::Optional { inner: element, .. } => self.check_type_arguments(element),
+            Type::Tuple { elements, .. } => elements.iter().for_each(|ty| self.check_type_arguments(ty)),
+            Type::Function { params, return_type, .. } => {
+                params.iter().for_each(|ty| self.check_type_arguments(ty));
+                self.check_type_arguments(return_type);
+            }
+            Type::Dyn { trait_, span } => {
+                self.check_object_safety(trait_, span);
+                if let Type::Named { generics, .. } = &**trait_ {
+                    generics.iter().for_each(|ty| self.check_type_arguments(ty));
+                }
+            }
+            Type::Primitive { .. } | Type::TypeVar { .. } | Type::Error(_) => {}
+        }
+    }
+
+    /// Whether `name` is a declared type, a generic parameter or an associated type in scope.
+    fn is_type_name(&self, name: &str) -> bool {
+        self.table.types.get(name).is_some()
+            || self.type_params.iter().any(|param| param == name)
+            || self.associated.contains_key(name)
+            || (name == "Self" && self.current_self.is_some())
+    }
+
+    fn unknown_type(&mut self, name: &Token, span: &Span) {
+        let candidates: Vec<String> = self.table.types.scopes.iter()
+            .flat_map(|scope| scope.symbols.keys().cloned())
+            .chain(self.type_params.iter().cloned())
+            .collect();
+        let suggestion = closest_match(&name.lexeme, candidates.iter().map(String::as_str)).map(str::to_string);
+        let mut error = Error::new(format!("Unknown type '{}'", name.lexeme), 0, span.clone(), String::new());
+        if let Some(suggestion) = suggestion {
+            error.add_help(Help::new(format!("Did you mean '{}'?", suggestion), 0, name.span.clone(), String::new()));
+        }
+        self.errors.push(error);
+    }
+
+    /// Runs `check` with `generics` as the generic parameters in scope, besides those of
+    /// the enclosing items.
+    fn with_type_params<R>(&mut self, generics: &[Type], check: impl FnOnce(&mut Self) -> R) -> R {
+        let outer = self.type_params.len();
+        self.type_params.extend(generics.iter().map(|param| param.to_string()));
+        let result = check(self);
+        self.type_params.truncate(outer);
+        result
+    }
+
+    fn cannot_infer(&mut self, name: &Token, reason: &str) {
+        let mut error = Error::new(format!("Cannot infer the type of '{}'", name.lexeme), 0, name.span.clone(), String::new());
+        error.add_note(Note::new(format!("The type of '{}' can't be inferred: {}", name.lexeme, reason), 0, name.span.clone(), String::new()));
+        error.add_help(Help::new(format!("Add a type annotation: 'let {}: <type>'", name.lexeme), 0, name.span.clone(), String::new()));
+        self.errors.push(error);
+    }
+
+    fn declare_variable(&mut self, name: &Token, ty: Option<Type>) {
+        self.table.values.insert(Symbol {
+            name: name.lexeme.clone(),
+            kind: SymbolKind::Variable,
+            access: AccessModifier::None,
+            ty,
+            span: Some(name.span.clone()),
+            struct_fields: None,
+            enum_variants: None,
+        });
+    }
+}
+
+impl Visitor for TypeChecker {
+    fn visit_module(&mut self, module: &Module) -> Result<(), String> {
+        self.cyclic_aliases = alias_cycles(&self.table);
+        // Top-level statements run before `main`, so functions see the types inferred for globals
+        let (statements, items): (Vec<_>, Vec<_>) = module.stmts.iter()
+            .partition(|item| matches!(item, StatementKind::Statement(_)));
+        for item in statements.into_iter().chain(items) {
+            self.visit_statement_kind(item)?;
+        }
+
+        let obligations = std::mem::take(&mut self.obligations);
+        self.check_obligations(obligations);
+        for (name, ty) in std::mem::take(&mut self.deferred) {
+            if has_inference_vars(&self.resolve(&ty)) {
+                self.cannot_infer(&name, "neither its initial value nor its uses determine one");
+            }
+        }
+        // Record the solved types for the passes and tools that read them afterwards
+        let binding_types = std::mem::take(&mut self.binding_types);
+        self.binding_types = binding_types.into_iter().map(|(span, ty)| (span, self.resolve(&ty))).collect();
+        let instantiations = std::mem::take(&mut self.instantiations);
+        self.instantiations = instantiations.into_iter()
+            .map(|(span, Instantiation { receiver, args })| (span, Instantiation {
+                receiver: receiver.map(|ty| widen(self.resolve(&ty))),
+                args: args.into_iter().map(|(param, ty)| (param, widen(self.resolve(&ty)))).collect(),
+            }))
+            .collect();
+        let globals: Vec<_> = self.table.values.scopes.first().into_iter()
+            .flat_map(|scope| scope.symbols.values().cloned())
+            .collect();
+        for mut symbol in globals {
+            symbol.ty = symbol.ty.map(|ty| self.resolve(&ty));
+            self.table.values.scopes[0].symbols.insert(symbol.name.clone(), symbol);
+        }
+        Ok(())
+    }
+
+    fn visit_function(&mut self, function: &Function) -> Result<(), String> {
+        let old_return_type = self.current_return_type.take();
+        self.current_return_type = Some(substitute(&function.return_type, &self.associated));
+        let outer_params = self.type_params.len();
+        self.type_params.extend(function.generics.iter().map(|param| param.to_string()));
+
+        self.check_bounds(function);
+        function.params.iter().for_each(|param| self.check_type_arguments(&param.ty));
+        self.check_type_arguments(&function.return_type);
+        let old_bounds = self.bounds.clone();
+        for bound in &function.bounds {
+            self.bounds.entry(bound.param.lexeme.clone()).or_default().extend(bound.traits.iter().cloned());
+        }
+        let old_obligations = std::mem::take(&mut self.obligations);
+
+        self.table.values.enter_scope();
+        if function.is_method {
+            self.table.values.insert(Symbol {
+                name: "self".to_string(),
+                kind: SymbolKind::Parameter,
+                access: AccessModifier::None,
+                ty: self.current_self.clone(),
+                span: None,
+                struct_fields: None,
+                enum_variants: None,
+            });
+        }
+        for param in &function.params {
+            self.table.values.insert(Symbol {
+                name: param.name.lexeme.clone(),
+                kind: SymbolKind::Parameter,
+                access: AccessModifier::None,
+                ty: Some(substitute(&param.ty, &self.associated)),
+                span: Some(param.name.span.clone()),
+                struct_fields: None,
+                enum_variants: None,
+            });
+        }
+
+        walk_function(self, function)?;
+
+        let obligations = std::mem::replace(&mut self.obligations, old_obligations);
+        self.check_obligations(obligations);
+        self.bounds = old_bounds;
+        self.table.values.exit_scope();
+        self.type_params.truncate(outer_params);
+        self.current_return_type = old_return_type;
+        Ok(())
+    }
+
+    fn visit_type_alias(&mut self, alias: &TypeAlias) -> Result<(), String> {
+        self.exporting_module = alias.name.lexeme.split_once('.').map(|(module, _)| module.to_string());
+        self.check_type_arguments(&alias.ty);
+        self.exporting_module = None;
+        if self.cyclic_aliases.contains(&alias.name.lexeme) {
+            let mut error = Error::new(format!("Type alias '{}' refers to itself", alias.name.lexeme), 0, alias.name.span.clone(), String::new());
+            error.add_note(Note::new(format!("'{}' stands for '{}'", alias.name.lexeme, alias.ty), 0, alias.span.clone(), String::new()));
+            error.add_help(Help::new(
+                format!("A newtype may contain itself: 'type {} = new {};'", alias.name.lexeme, alias.ty),
+                0, alias.span.clone(), String::new(),
+            ));
+            self.errors.push(error);
+        }
+        Ok(())
+    }
+
+    fn visit_struct(&mut self, structure: &Struct) -> Result<(), String> {
+        self.with_type_params(&structure.generics, |checker| {
+            structure.fields.iter().for_each(|field| checker.check_type_arguments(&field.ty));
+        });
+        Ok(())
+    }
+
+    fn visit_enum(&mut self, enumeration: &Enum) -> Result<(), String> {
+        self.with_type_params(&enumeration.generics, |checker| {
+            let fields = enumeration.variants.iter().flat_map(|variant| &variant.fields);
+            fields.for_each(|ty| checker.check_type_arguments(ty));
+        });
+        Ok(())
+    }
+
+    fn visit_extend(&mut self, extend: &Extend) -> Result<(), String> {
+        let self_ty = match extend.name.lexeme.as_str() {
+            name if is_primitive_name(name) => primitive(name, &extend.name.span),
+            _ => Type::Named {
+                name: extend.name.clone(),
+                generics: extend.type_generics().to_vec(),
+                span: extend.span.clone(),
+            },
+        };
+        self.check_extended_type(extend);
+        if let Some(trait_name) = &extend.trait_name {
+            self.check_implementation(extend, trait_name, &self_ty);
+        }
+
+        let associated = self.associated_of(&extend.name.lexeme, &self_ty);
+        let old_associated = std::mem::replace(&mut self.associated, associated);
+        let old_self = self.current_self.replace(self_ty);
+        let generics: Vec<Type> = extend.first_generics.iter().chain(&extend.second_generics).cloned().collect();
+        let result = self.with_type_params(&generics, |checker| {
+            checker.check_constants(&extend.constants);
+            extend.methods.iter().try_for_each(|method| checker.visit_function(method))
+        });
+        self.current_self = old_self;
+        self.associated = old_associated;
+        result
+    }
+
+    fn visit_trait(&mut self, trait_: &Trait) -> Result<(), String> {
+        // `self` could be any type implementing the trait, so all that is known
+        // about it is that it implements this one
+        let self_ty = Type::Named {
+            name: Token::new(TokenKind::Identifier, "Self".to_string(), 0, trait_.name.span.clone()),
+            generics: vec![],
+            span: trait_.name.span.clone(),
+        };
+        let trait_ty = Type::Named { name: trait_.name.clone(), generics: trait_.generics.clone(), span: trait_.span.clone() };
+        let old_bounds = std::mem::replace(&mut self.bounds, HashMap::from([("Self".to_string(), vec![trait_ty])]));
+        let associated = self.projections(&trait_.name.lexeme, &self_ty);
+        let old_associated = std::mem::replace(&mut self.associated, associated);
+        let old_self = self.current_self.replace(self_ty);
+
+        let result = self.with_type_params(&trait_.generics, |checker| {
+            checker.check_constants(&trait_.constants);
+            trait_.methods.iter().try_for_each(|method| checker.visit_function(method))
+        });
+
+        self.current_self = old_self;
+        self.associated = old_associated;
+        self.bounds = old_bounds;
+        result
+    }
+
+    fn visit_statement(&mut self, statement: &Statement) -> Result<(), String> {
+        match statement {
+            Statement::Let { pattern, ty, value, span } => {
+                let ty = &ty.as_ref().map(|ty| substitute(ty, &self.associated));
+                if let Some(ty) = ty {
+                    self.check_type_arguments(ty);
+                }
+                let value_ty = value.as_ref().and_then(|value| self.check_expr(value, ty.as_ref()));
+                if let (Some(value_ty), Some(declared_ty)) = (&value_ty, ty) {
+                    if let Err(mismatch) = self.unify(declared_ty, value_ty) {
+                        self.mismatch("Type mismatch in let binding".to_string(), span, mismatch);
+                    }
+                }
+
+                let binding_ty = match (ty, value, &**pattern) {
+                    (Some(ty), _, _) => Some(ty.clone()),
+                    (None, Some(Expr::Literal(Literal::Null(_))), Pattern::Identifier(name, _)) => {
+                        self.cannot_infer(name, "'null' could be a value of many types");
+                        None
+                    }
+                    (None, Some(_), _) => value_ty.map(widen),
+                    // The uses of the binding decide its type
+                    (None, None, Pattern::Identifier(name, _)) => Some(self.fresh_var(&name.span)),
+                    (None, None, _) => {
+                        self.error("A destructuring 'let' needs a value or a type annotation".to_string(), span);
+                        None
+                    }
+                };
+                if let Some(binding_ty) = &binding_ty {
+                    self.binding_types.insert(pattern.span(), binding_ty.clone());
+                }
+                let errors = self.errors.len();
+                self.bind_pattern(pattern, binding_ty.clone());
+                // A pattern that doesn't fit the value's type is only reported once
+                if let (Some(binding_ty), true) = (&binding_ty, self.errors.len() == errors) {
+                    self.check_irrefutable(pattern, binding_ty);
+                }
+                for name in pattern.bindings() {
+                    let Some(ty) = self.table.values.get(&name.lexeme).and_then(|symbol| symbol.ty.clone()) else { continue };
+                    if has_inference_vars(&ty) {
+                        self.deferred.push((name.clone(), ty.clone()));
+                    }
+                    self.binding_types.insert(name.span.clone(), ty);
+                }
+            }
+            Statement::Expr(expr) | Statement::Tail(expr) => {
+                self.infer_type(expr);
+            }
+            Statement::If { cond, then_branch, else_branch, .. } => {
+                self.condition("if", cond);
+                let (when_true, when_false) = null_checks(cond);
+                self.with_narrowed(&when_true, |checker| checker.visit_statement(then_branch))?;
+                if let Some(else_branch) = else_branch {
+                    self.with_narrowed(&when_false, |checker| checker.visit_statement(else_branch))?;
+                }
+                // Past a branch that always leaves, the condition that skips it holds
+                match (leaves(then_branch), else_branch.as_deref().map(leaves)) {
+                    (true, None | Some(false)) => self.narrow(&when_false),
+                    (false, Some(true)) => self.narrow(&when_true),
+                    _ => {}
+                }
+                return Ok(());
+            }
+            Statement::While { cond, body, .. } => {
+                self.condition("while", cond);
+                let (when_true, _) = null_checks(cond);
+                return self.with_narrowed(&when_true, |checker| checker.visit_statement(body));
+            }
+            Statement::Block(..) => {
+                self.table.values.enter_scope();
+                let result = walk_statement(self, statement);
+                self.table.values.exit_scope();
+                return result;
+            }
+            Statement::For { var, iter, body, .. } => {
+                let element_ty = known(self.infer_type(iter))
+                    .map(|ty| self.non_null(ty, &iter.span()))
+                    .and_then(|ty| self.element_type(&ty, iter, true));
+
+                self.visit_expression(iter)?;
+                self.table.values.enter_scope();
+                self.declare_variable(var, element_ty);
+                let result = self.visit_statement(body);
+                self.table.values.exit_scope();
+                return result;
+            }
+            Statement::Match { expr, cases, .. } => {
+                self.match_cases(expr, cases, |checker, body| {
+                    let _ = checker.visit_statement(body);
+                    None
+                });
+                return Ok(());
+            }
+            Statement::Return(expr, span) => {
+                if let Some(return_type) = &self.current_return_type {
+                    if let Some(expr) = expr {
+                        // Clone the return type to avoid the borrow checker issue
+                        let return_type = return_type.clone();
+                        let expr_ty = self.check_expr(expr, Some(&return_type));
+                        if let Some(expr_ty) = expr_ty {
+                            if let Err(mismatch) = self.unify(&return_type, &expr_ty) {
+                                self.mismatch("Return type mismatch".to_string(), span, mismatch);
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        walk_statement(self, statement)
+    }
+
+    fn visit_expression(&mut self, expr: &Expr) -> Result<(), String> {
+        // Their branches were checked while inferring their type
+        if let Expr::If { .. } | Expr::Match { .. } = expr {
+            return Ok(());
+        }
+        if let Expr::Closure { params, ty, .. } = expr {
+            let old_return_type = self.current_return_type.replace(ty.clone());
+            self.table.values.enter_scope();
+            for param in params {
+                self.table.values.insert(Symbol {
+                    name: param.name.lexeme.clone(),
+                    kind: SymbolKind::Parameter,
+                    access: AccessModifier::None,
+                    ty: Some(param.ty.clone()),
+                    span: Some(param.name.span.clone()),
+                    struct_fields: None,
+                    enum_variants: None,
+                });
+            }
+            let result = walk_expr(self, expr);
+            self.table.values.exit_scope();
+            self.current_return_type = old_return_type;
+            return result;
+        }
+        walk_expr(self, expr)
+    }
+}
+
+/// The patterns a match leaves uncovered, e.g. "'a', 'b' and 'c'".
+fn missing_patterns(report: &MatchReport) -> String {
+    let mut missing: Vec<String> = report.missing.iter().map(|pat| format!("'{}'", pat)).collect();
+    if report.more_missing {
+        missing.push("more".to_string());
+    }
+    match missing.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} and {}", rest.join(", "), last),
+        _ => missing.concat(),
+    }
+}
+
+fn primitive(name: &str, span: &Span) -> Type {
+    Type::Primitive {
+        name: Token::new(TokenKind::Identifier, name.to_string(), 0, span.clone()),
+        span: span.clone(),
+    }
+}
+
+/// The variables `cond` compares with `null`: those it shows aren't null when it is
+/// true, and those it shows aren't null when it is false.
+fn null_checks(cond: &Expr) -> (Vec<Token>, Vec<Token>) {
+    match cond {
+        Expr::Binary { left, op, right, .. } if op.lexeme == "==" || op.lexeme == "!=" => {
+            let checked = match (&**left, &**right) {
+                (Expr::Identifier(name, _), Expr::Literal(Literal::Null(_)))
+                | (Expr::Literal(Literal::Null(_)), Expr::Identifier(name, _)) => vec![name.clone()],
+                _ => vec![],
+            };
+            match op.lexeme.as_str() {
+                "!=" => (checked, vec![]),
+                _ => (vec![], checked),
+            }
+        }
+        Expr::Binary { left, op, right, .. } if op.lexeme == "&&" => {
+            let (mut when_true, _) = null_checks(left);
+            when_true.extend(null_checks(right).0);
+            (when_true, vec![])
+        }
+        Expr::Binary { left, op, right, .. } if op.lexeme == "||" => {
+            let (_, mut when_false) = null_checks(left);
+            when_false.extend(null_checks(right).1);
+            (vec![], when_false)
+        }
+        Expr::Unary { op, expr, .. } if op.lexeme == "!" => {
+            let (when_true, when_false) = null_checks(expr);
+            (when_false, when_true)
+        }
+        Expr::Grouping(expr, _) => null_checks(expr),
+        _ => (vec![], vec![]),
+    }
+}
+
+/// Whether `statement` always returns, breaks or continues.
+fn leaves(statement: &Statement) -> bool {
+    match statement {
+        Statement::Return(..) | Statement::Break(_) | Statement::Continue(_) => true,
+        Statement::Block(statements, _) => statements.iter().any(leaves),
+        Statement::If { then_branch, else_branch: Some(else_branch), .. } => leaves(then_branch) && leaves(else_branch),
+        _ => false,
+    }
+}
+
+fn is_nullable(ty: &Type) -> bool {
+    matches!(ty, Type::Optional { .. }) || is_primitive(ty, "null")
+}
+
+/// `ty?`, which is just `ty` if that already allows `null`.
+fn optional(ty: Type, span: &Span) -> Type {
+    match ty {
+        Type::Optional { .. } => ty,
+        ty if is_primitive(&ty, "null") => ty,
+        ty => Type::Optional { inner: Box::new(ty), span: span.clone() },
+    }
+}
+
+/// The type of `a..b`, which has no declaration of its own.
+fn range_type(span: &Span) -> Type {
+    Type::Named {
+        name: Token::new(TokenKind::Identifier, "Range".to_string(), 0, span.clone()),
+        generics: vec![],
+        span: span.clone(),
+    }
+}
+
+/// The type aliases of `table` that expand to themselves, directly or through other aliases.
+fn alias_cycles(table: &MultiStageSymbolTable) -> HashSet<String> {
+    let aliases: HashMap<&str, &Type> = table.types.scopes.iter()
+        .flat_map(|scope| scope.symbols.values())
+        .filter(|symbol| symbol.kind == SymbolKind::Alias)
+        .filter_map(|symbol| Some((symbol.name.as_str(), symbol.ty.as_ref()?)))
+        .collect();
+    aliases.keys()
+        .filter(|&&start| {
+            let mut seen = HashSet::new();
+            let mut pending = vec![aliases[start]];
+            while let Some(ty) = pending.pop() {
+                for name in named_types(ty) {
+                    if name == start {
+                        return true;
+                    }
+                    if let Some(target) = aliases.get(name.as_str()).filter(|_| seen.insert(name.clone())) {
+                        pending.push(target);
+                    }
+                }
+            }
+            false
+        })
+        .map(|name| name.to_string())
+        .collect()
+}
+
+/// The names of the named types `ty` mentions, including itself.
+fn named_types(ty: &Type) -> Vec<String> {
+    match ty {
+        Type::Named { name, generics, .. } => std::iter::once(name.lexeme.clone()).chain(generics.iter().flat_map(named_types)).collect(),
+        Type::Tuple { elements, .. } => elements.iter().flat_map(named_types).collect(),
+        Type::Array { element, .. } | Type::Dyn { trait_: element, .. } | Type::Optional { inner: element, .. } => named_types(element),
+        Type::Function { params, return_type, .. } => params.iter().chain(std::iter::once(&**return_type)).flat_map(named_types).collect(),
+        Type::Primitive { .. } | Type::TypeVar { .. } | Type::Error(_) => vec![],
+    }
+}
+
+/// The `Ok` and `Err` types of a `Result`.
+fn result_types(ty: &Type) -> Option<(Type, Type)> {
+    match ty {
+        Type::Named { name, generics, .. } if name.lexeme == "Result" => match generics.as_slice() {
+            [value, error] => Some((value.clone(), error.clone())),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn is_range(ty: &Type) -> bool {
+    matches!(ty, Type::Named { name, generics, .. } if name.lexeme == "Range" && generics.is_empty())
+}
+
+fn is_primitive_name(name: &str) -> bool {
+    matches!(name, "int" | "float" | "string" | "char" | "bool")
+}
+
+/// A method signature for messages, e.g. "<T>(self, T) -> bool".
+fn signature(generics: &[String], receiver: bool, ty: &str) -> String {
+    let ty = match ty.strip_prefix('(') {
+        Some(rest) if receiver && rest.starts_with(')') => format!("(self{}", rest),
+        Some(rest) if receiver => format!("(self, {}", rest),
+        _ => ty.to_string(),
+    };
+    if generics.is_empty() {
+        ty
+    } else {
+        format!("<{}>{}", generics.join(", "), ty)
+    }
+}
+
+fn is_primitive(ty: &Type, name: &str) -> bool {
+    matches!(ty, Type::Primitive { name: n, .. } if n.lexeme == name)
+}
+
+fn is_numeric(ty: &Type) -> bool {
+    is_primitive(ty, "int") || is_primitive(ty, "float")
+}
+
+fn is_inference_var(name: &Token) -> bool {
+    name.lexeme.starts_with('?')
+}
+
+/// Discards a type that is still an unsolved inference variable, since nothing
+/// can be said about what operations it supports.
+fn known(ty: Option<Type>) -> Option<Type> {
+    ty.filter(|ty| !matches!(ty, Type::TypeVar { name, .. } if is_inference_var(name)))
+}
+
+/// Whether the inference variable `var` appears anywhere in `ty`.
+fn occurs(var: &str, ty: &Type) -> bool {
+    match ty {
+        Type::TypeVar { name, .. } => name.lexeme == var,
+        Type::Named { generics: types, .. } | Type::Tuple { elements: types, .. } => types.iter().any(|ty| occurs(var, ty)),
+        Type::Array { element, .. } | Type::Dyn { trait_: element, .. } | Type::Optional { inner: element, .. } => occurs(var, element),
+        Type::Function { params, return_type, .. } => params.iter().any(|ty| occurs(var, ty)) || occurs(var, return_type),
+        Type::Primitive { .. } | Type::Error(_) => false,
+    }
+}
+
+/// Whether `ty` refers to `Self`, directly or through one of its associated types.
+fn mentions_self(ty: &Type) -> bool {
+    match ty {
+        Type::Named { name, generics, .. } => {
+            name.lexeme == "Self" || name.lexeme.starts_with("Self.") || generics.iter().any(mentions_self)
+        }
+        Type::Tuple { elements, .. } => elements.iter().any(mentions_self),
+        Type::Array { element, .. } | Type::Dyn { trait_: element, .. } | Type::Optional { inner: element, .. } => mentions_self(element),
+        Type::Function { params, return_type, .. } => params.iter().any(mentions_self) || mentions_self(return_type),
+        Type::Primitive { .. } | Type::TypeVar { .. } | Type::Error(_) => false,
+    }
+}
+
+pub fn has_inference_vars(ty: &Type) -> bool {
+    match ty {
+        Type::TypeVar { name, .. } => is_inference_var(name),
+        Type::Named { generics: types, .. } | Type::Tuple { elements: types, .. } => types.iter().any(has_inference_vars),
+        Type::Array { element, .. } | Type::Dyn { trait_: element, .. } | Type::Optional { inner: element, .. } => has_inference_vars(element),
+        Type::Function { params, return_type, .. } => params.iter().any(has_inference_vars) || has_inference_vars(return_type),
+        Type::Primitive { .. } | Type::Error(_) => false,
+    }
+}
+
+/// Whether the interpreter can convert a value of type `from` with `as to`.
+fn can_cast(from: &Type, to: &Type) -> bool {
+    let (Type::Primitive { name: from, .. }, Type::Primitive { name: to, .. }) = (from, to) else {
+        return matches!((from, to), (Type::Named { name: a, .. }, Type::Named { name: b, .. }) if a.lexeme == b.lexeme);
+    };
+
+    matches!(
+        (from.lexeme.as_str(), to.lexeme.as_str()),
+        ("int" | "float" | "bool" | "string", "int")
+            | ("int" | "float" | "string", "float")
+            | ("bool" | "int" | "string", "bool")
+            | (_, "string")
+            | ("string" | "char", "char")
+    )
+}
+
+/// The names of a struct's generic parameters.
+fn generic_params(symbol: &Symbol) -> Vec<String> {
+    match &symbol.ty {
+        Some(Type::Named { generics, .. }) => generics.iter().map(|param| param.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+/// The name methods and trait implementations of `ty` are recorded under,
+/// or `None` for a type that isn't known yet.
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Named { name, .. } | Type::Primitive { name, .. } => Some(name.lexeme.clone()),
+        Type::TypeVar { name, .. } if !is_inference_var(name) => Some(name.lexeme.clone()),
+        Type::Array { .. } => Some("array".to_string()),
+        Type::Tuple { .. } => Some("tuple".to_string()),
+        Type::Function { .. } => Some("function".to_string()),
+        Type::Optional { .. } => Some("optional".to_string()),
+        // A trait object has the methods of its trait
+        Type::Dyn { trait_, .. } => Some(trait_name_of(trait_)),
+        Type::TypeVar { .. } | Type::Error(_) => None,
+    }
+}
+
+/// The name of the generic parameter `ty` stands for inside a generic body, if it is one.
+fn type_param<'a>(ty: &'a Type, table: &MultiStageSymbolTable) -> Option<&'a str> {
+    match ty {
+        Type::TypeVar { name, .. } if !is_inference_var(name) => Some(&name.lexeme),
+        Type::Named { name, generics, .. } if generics.is_empty() && !table.has_type(&name.lexeme) => Some(&name.lexeme),
+        _ => None,
+    }
+}
+
+fn trait_name_of(trait_: &Type) -> String {
+    match trait_ {
+        Type::Named { name, .. } | Type::TypeVar { name, .. } => name.lexeme.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn same_trait(a: &Type, b: &Type) -> bool {
+    trait_name_of(a) == trait_name_of(b)
+}
+
+fn names_of(generics: &[Type]) -> Vec<String> {
+    generics.iter().map(|ty| ty.to_string()).collect()
+}
+
+/// Pairs a struct's generic parameters with the arguments of one of its instances.
+fn generic_bindings(symbol: &Symbol, arguments: &[Type]) -> HashMap<String, Type> {
+    generic_params(symbol).into_iter().zip(arguments.iter().cloned()).collect()
+}
+
+/// Drops the lengths that array literals give their types, so that a binding
+/// inferred from `[1, 2]` can later hold an array of any length.
+fn widen(ty: Type) -> Type {
+    match ty {
+        Type::Array { element, span, .. } => Type::Array { element: Box::new(widen(*element)), size: None, span },
+        Type::Tuple { elements, span } => Type::Tuple { elements: elements.into_iter().map(widen).collect(), span },
+        Type::Optional { inner, span } => optional(widen(*inner), &span),
+        other => other,
+    }
+}
+
+/// Replaces the generic parameters named in `bindings` throughout `ty`.
+pub fn substitute(ty: &Type, bindings: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::TypeVar { name, .. } if bindings.contains_key(&name.lexeme) => bindings[&name.lexeme].clone(),
+        Type::Named { name, generics, .. } if generics.is_empty() && bindings.contains_key(&name.lexeme) => {
+            bindings[&name.lexeme].clone()
+        }
+        Type::Named { name, generics, span } => Type::Named {
+            name: name.clone(),
+            generics: generics.iter().map(|ty| substitute(ty, bindings)).collect(),
+            span: span.clone(),
+        },
+        Type::Array { element, size, span } => Type::Array {
+            element: Box::new(substitute(element, bindings)),
+            size: *size,
+            span: span.clone(),
+        },
+        Type::Tuple { elements, span } => Type::Tuple {
+            elements: elements.iter().map(|ty| substitute(ty, bindings)).collect(),
+            span: span.clone(),
+        },
+        Type::Function { params, return_type, span } => Type::Function {
+            params: params.iter().map(|ty| substitute(ty, bindings)).collect(),
+            return_type: Box::new(substitute(return_type, bindings)),
+            span: span.clone(),
+        },
+        Type::Optional { inner, span } => optional(substitute(inner, bindings), span),
+        Type::Dyn { trait_, span } => Type::Dyn { trait_: Box::new(substitute(trait_, bindings)), span: span.clone() },
+        other => other.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sema::passes::populate_table::FullSymbolTablePass;
+    use crate::Compiler;
+
+    const PRELUDE: &str = "module test;\n\nstruct Point {\n    x: float,\n    y: float,\n}\n\nenum Shape {\n    Circle(float),\n    Empty,\n}\n\nextend Point {\n    func scaled(self, by: float) -> Point {\n        return Point { x: self.x * by, y: self.y * by };\n    }\n}\n";
+
+    /// The errors of a `main` function with `body`, after the items of `PRELUDE`.
+    fn errors(body: &str) -> Vec<String> {
+        module_errors(&format!("{}\nfunc main() {{\n{}\n}}\n", PRELUDE, body))
+    }
+
+    fn module_errors(source: &str) -> Vec<String> {
+        match Compiler::new().check_str("test.y", source) {
+            Ok(_) => vec![],
+            Err(errors) => errors.into_iter().map(|error| error.message).collect(),
+        }
+    }
+
+    /// Runs the type checker alone on a whole module, so its state can be inspected.
+    fn check(source: &str) -> TypeChecker {
+        let module = Compiler::new().parse_str("test.y", source).unwrap();
+        let mut pass = FullSymbolTablePass::new();
+        pass.visit_module(&module).unwrap();
+        let mut checker = TypeChecker::new();
+        checker.table = pass.table;
+        checker.visit_module(&module).unwrap();
+        checker
+    }
+
+    #[test]
+    fn test_infer_expressions() {
+        let valid = errors("    let p: Point = Point { x: 1.0, y: 2.0 }.scaled(2.0);\n    let x: float = -p.x;\n    let ok: bool = !(p.y > x) && x != 0.0;\n    let shapes: [Shape] = [Shape.Circle(1.0), Shape.Empty];\n    let pair: (int, string) = (1, \"one\");\n    let first: int = pair[0];\n    let f: (int) -> int = |n: int| int { return n * 2; };\n    let n: int = f(first) + \"1\" as int;\n    n += 1;");
+        assert_eq!(valid, Vec::<String>::new());
+
+        let invalid = errors("    let p = Point { x: 1, z: 2.0 };\n    let b: bool = -true;\n    p.w;\n    let i: int = 1;\n    i += 1.5;\n    let a = [1, \"two\"];\n    Shape.Square;");
+        assert_eq!(invalid, [
+            "Field 'x' of 'Point' expects 'float', found 'int'",
+            "Struct 'Point' has no field 'z'",
+            "Missing field 'y' in 'Point'",
+            "Cannot apply '-' to a value of type 'bool'",
+            "Type 'Point' has no field or method 'w'",
+            "Cannot assign a value of type 'float' to 'int'",
+            "Array elements must have the same type, expected 'int', found 'string'",
+            "Type 'Shape' has no variant or method 'Square'",
+        ]);
+    }
+
+    #[test]
+    fn test_conditional_expressions() {
+        let valid = errors("    let n = 1;\n    let sign: int = if (n < 0) { -1 } else if (n == 0) { 0 } else { 1 };\n    let area: float = match (Shape.Circle(1.0)) {\n        Shape.Circle(r) -> r * r,\n        Shape.Empty -> {\n            return;\n        },\n    };\n    let xs: [int] = if (true) { [] } else { [1, 2] };");
+        assert_eq!(valid, Vec::<String>::new());
+
+        let invalid = errors("    let a = if (true) { 1 };\n    let b = if (true) { 1 } else { \"one\" };\n    let c = match (1) {\n        1 -> 1,\n        _ -> {\n            let d = 2;\n        },\n    };\n    let e: string = if (true) { 1 } else { 2 };\n    let f = if (3) { 1 } else { 2 };\n    if (\"s\") {}\n    while (1) {}");
+        assert_eq!(invalid, [
+            "'if' without 'else' has no value when its condition is false",
+            "'if' and 'else' have incompatible types",
+            "Match cases have incompatible types",
+            "Type mismatch in let binding",
+            "'if' condition must be a 'bool', found 'int'",
+            "'if' condition must be a 'bool', found 'string'",
+            "'while' condition must be a 'bool', found 'int'",
+        ]);
+    }
+
+    #[test]
+    fn test_destructuring_let() {
+        let valid = errors("    let (a, (b, _)): (int, (string, bool)) = (1, (\"b\", true));\n    let { x, y: py } = Point { x: 1.0, y: 2.0 };\n    let ({ x: qx, y: _ }, n) = (Point { x: 3.0, y: 4.0 }, 5);\n    let total: float = x + py + qx;\n    let count: int = a + n;\n    let name: string = b;");
+        assert_eq!(valid, Vec::<String>::new());
+
+        let invalid = errors("    let (a, true) = (1, false);\n    let Shape.Circle(r) = Shape.Empty;\n    let (c, d, e) = (1, 2);\n    let { x, z } = Point { x: 1.0, y: 2.0 };\n    let (f, g);");
+        assert_eq!(invalid, [
+            "Refutable pattern in 'let': '(_, false)' not covered",
+            "Refutable pattern in 'let': 'Shape.Empty' not covered",
+            "A tuple pattern of 3 elements cannot match a value of type '(int, int)'",
+            "Struct 'Point' has no field 'z'",
+            "A destructuring 'let' needs a value or a type annotation",
+        ]);
+    }
+
+    #[test]
+    fn test_optional_types() {
+        let valid = errors("    let p: Point? = null;\n    let x: float = p?.x ?? 0.0;\n    let q: Point? = p?.scaled(2.0);\n    if (p != null && p.x > 0.0) {\n        let y: float = p.y;\n    }\n    let n: int? = if (x > 1.0) { 1 } else { null };\n    if (n == null) {\n        return;\n    }\n    let m: int = n + 1;");
+        assert_eq!(valid, Vec::<String>::new());
+
+        let invalid = errors("    let p: Point? = Point { x: 1.0, y: 2.0 };\n    let x = p.x;\n    let n: int = null;\n    let m: int? = 1;\n    let k: int = m;\n    let s = m ?? \"none\";\n    if (m != null) {\n        m = null;\n        m + 1;\n    }");
+        assert_eq!(invalid, [
+            "A value of type 'Point?' may be null",
+            "Type mismatch in let binding",
+            "Type mismatch in let binding",
+            "The default of '??' must have type 'int', found 'string'",
+            "A value of type 'int?' may be null",
+        ]);
+    }
+
+    #[test]
+    fn test_try_operator() {
+        let source = "module test;\n\nstruct AppError {\n    message: string,\n}\n\nextend From for AppError {\n    type Source = string;\n\n    func from(error: string) -> AppError {\n        return AppError { message: error };\n    }\n}\n\nstruct Other {}\n\nextend Other {\n    func from(error: string) -> Other {\n        return Other {};\n    }\n}\n\nfunc parse(text: string) -> Result<int, string> {\n    return text as Result<int, string>;\n}\n\nfunc count(n: int) -> Result<int, string> {\n    return n as Result<int, string>;\n}\n\nfunc twice(text: string) -> Result<int, AppError> {\n    let n: int = parse(text)?;\n    return Result.Ok(n * 2);\n}\n\nfunc other() -> Result<int, Other> {\n    return Result.Ok(parse(\"1\")?);\n}\n\nfunc plain() -> int {\n    return parse(\"1\")? + 1?;\n}\n\nfunc main() {\n    match (twice(\"2\")) {\n        Result.Ok(n) -> n + 1,\n        Result.Err(error) -> error.message,\n    }\n}\n";
+        let messages = module_errors(source);
+        assert_eq!(messages, [
+            "Cannot cast a value of type 'int' to 'Result<int, string>'",
+            "'?' cannot convert an error of type 'string' into 'Other'",
+            "'?' can only be used in a function that returns 'Result'",
+            "'?' expects a 'Result', found 'int'",
+        ]);
+    }
+
+    #[test]
+    fn test_type_aliases_and_newtypes() {
+        let source = "module test;\n\ntype Meters = float;\ntype Distance = Meters;\ntype UserId = new int;\ntype A = [B];\ntype B = A;\n\nfunc half(d: Distance) -> Meters {\n    return d / 2.0;\n}\n\nfunc main() {\n    let m: Meters = half(3.0) + 1.0;\n    let id = 7 as UserId;\n    let raw: int = id as int;\n    let wrong: UserId = 8;\n    id + 1;\n}\n";
+        let messages = module_errors(source);
+        assert_eq!(messages, [
+            "Type alias 'A' refers to itself",
+            "Type alias 'B' refers to itself",
+            "Type mismatch in let binding",
+            "Invalid binary operation: UserId + int",
+        ]);
+    }
+
+    #[test]
+    fn test_for_loops() {
+        let source = "module test;\n\nstruct Countdown {\n    from: int,\n}\n\nextend Iterator for Countdown {\n    type Item = int;\n\n    func next(self) -> int? {\n        if (self.from == 0) {\n            return null;\n        }\n        self.from -= 1;\n        return self.from;\n    }\n}\n\nstruct Launch {}\n\nextend Iterable for Launch {\n    type Iter = Countdown;\n\n    func iter(self) -> Countdown {\n        return Countdown { from: 10 };\n    }\n}\n\nstruct Lookalike {}\n\nextend Lookalike {\n    func next(self) -> int? {\n        return null;\n    }\n}\n\nfunc main() {\n    let total: int = 0;\n    for (i in 0..10) { total += i; }\n    for (i in 1..=total) { total += i; }\n    for (n in Countdown { from: 3 }) { total += n; }\n    for (n in Launch {}) { total += n; }\n    for (c in \"abc\") { let s: string = c; }\n    for (x in 1.0..2) {}\n    for (x in ..5) {}\n    for (x in true) {}\n    for (x in Lookalike {}) {}\n}\n";
+        let messages = module_errors(source);
+        assert_eq!(messages, [
+            "Range bounds must be 'int', found 'float'",
+            "Cannot iterate over a range without a start",
+            "Cannot iterate over a value of type 'bool'",
+            "Cannot iterate over a value of type 'Lookalike'",
+        ]);
+    }
+
+    #[test]
+    fn test_infer_let_bindings() {
+        let source = "module test;\n\nfunc first<T>(items: [T]) -> T {\n    return items[0];\n}\n\nfunc main() {\n    let v = [1, 2];\n    v = [3, 4, 5];\n    let f = |n: int| int { return n * 2; };\n    let x = f(first(v));\n    let empty: [string] = [];\n    let unknown = [];\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, ["Cannot infer the type of 'unknown'"]);
+
+        let mut types: Vec<_> = checker.binding_types.iter()
+            .map(|(span, ty)| (span.start, format!("{}: {}", &source[span.start..span.end], ty)))
+            .collect();
+        types.sort();
+        let types: Vec<_> = types.into_iter().map(|(_, ty)| ty).collect();
+        assert_eq!(types, ["v: [int]", "f: (int) -> int", "x: int", "empty: [string]", "unknown: [_]"]);
+    }
+
+    #[test]
+    fn test_unification() {
+        let source = "module test;\n\nstruct Box<T> {\n    value: T,\n}\n\nfunc first<T>(items: [T]) -> T {\n    return items[0];\n}\n\nfunc main() {\n    let later;\n    later = first([Box { value: \"s\" }]);\n    let b: Box<int> = Box { value: true };\n    let pair = (first([]), 1);\n    let wrong: (string, bool) = pair;\n    let n: int = pair[0];\n    let nested;\n    nested = [nested];\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Type mismatch in let binding",
+            "Type mismatch in let binding",
+            "Cannot infer a type that contains itself",
+            "Cannot infer the type of 'nested'",
+        ]);
+        assert_eq!(checker.errors[0].notes[0].message, "Expected 'int', found 'bool'");
+        assert_eq!(checker.errors[1].notes[0].message, "Expected 'bool', found 'int'");
+        assert_eq!(checker.errors[2].notes[0].message, "The value would have to be part of its own type");
+
+        // The failed match against `(string, bool)` leaves the first element free for `int`
+        let types: Vec<_> = checker.binding_types.iter()
+            .filter(|(span, _)| matches!(&source[span.start..span.end], "later" | "pair"))
+            .map(|(span, ty)| format!("{}: {}", &source[span.start..span.end], ty))
+            .collect::<std::collections::BTreeSet<_>>().into_iter().collect();
+        assert_eq!(types, ["later: Box<string>", "pair: (int, int)"]);
+    }
+
+    #[test]
+    fn test_generic_functions() {
+        let source = "module test;\n\nstruct Box<T> {\n    value: T,\n}\n\nextend Box<T> {\n    func map<U>(self, f: (T) -> U) -> Box<U> {\n        return Box { value: f(self.value) };\n    }\n}\n\nfunc id<T>(x: T) -> T {\n    return x + 1;\n}\n\nfunc main() {\n    let a = 1;\n    let b = 2;\n    let n = id<int>(5);\n    let less = a < b;\n    let s: Box<string> = Box { value: 1 }.map<string>(|n: int| string { return \"n\"; });\n    id<int, bool>(true);\n    a<int>(1);\n    let v: Box<Vec<int>>;\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Invalid binary operation: T + int",
+            "'id' expects 1 type arguments, found 2",
+            "'a' expects 0 type arguments, found 1",
+            "A value of type 'int' is not callable",
+            "Unknown type 'Vec'",
+        ]);
+
+        let mut types: Vec<_> = checker.binding_types.iter()
+            .map(|(span, ty)| (span.start, format!("{}: {}", &source[span.start..span.end], ty)))
+            .collect();
+        types.sort();
+        let types: Vec<_> = types.into_iter().map(|(_, ty)| ty).collect();
+        assert_eq!(types[2..5], ["n: int", "less: bool", "s: Box<string>"]);
+    }
+
+    #[test]
+    fn test_trait_bounds() {
+        let source = "module test;\n\ntrait Ord {\n    func less(self, other: int) -> bool;\n}\n\ntrait Show {\n    func show(self) -> string;\n}\n\nstruct Point {\n    x: int,\n}\n\nextend Ord for int {\n    func less(self, other: int) -> bool {\n        return self < other;\n    }\n}\n\nfunc max<T: Ord>(a: T, b: T) -> T {\n    a.less(1);\n    a.show();\n    return a;\n}\n\nfunc print<T>(value: T) -> string where T: Show + Ord, U: Point {\n    max(value, value);\n    return value.show();\n}\n\nfunc sort<T>(items: [T]) -> [T] {\n    max(items[0], items[1]);\n    return items;\n}\n\nfunc main() {\n    let n = max(1, 2);\n    max(Point { x: 1 }, Point { x: 2 });\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Type 'T' has no field or method 'show'",
+            "'U' is not a generic parameter of 'print'",
+            "'Point' is not a trait",
+            "Type 'T' does not implement trait 'Ord'",
+            "Type 'Point' does not implement trait 'Ord'",
+        ]);
+        assert_eq!(checker.errors[3].notes[0].message, "Required by the bound 'T: Ord' of 'max'");
+        assert_eq!(checker.errors[3].helps[0].message, "Add the bound 'T: Ord'");
+        assert_eq!(checker.errors[4].helps[0].message, "Implement it with 'extend Ord for Point'");
+    }
+
+    #[test]
+    fn test_trait_implementations() {
+        let source = "module test;\n\ntrait Show {\n    func show(self) -> string;\n    func width(self, pad: int) -> int;\n}\n\ntrait Into<T> {\n    func into(self) -> T;\n}\n\nstruct Point {\n    x: int,\n}\n\nextend Show for Point {\n    func show(self) -> string {\n        return \"p\";\n    }\n    func width(self, pad: float) -> int {\n        return 1;\n    }\n    func extra(self) {}\n}\n\nextend Show for Point {\n    func show(self) -> string {\n        return \"q\";\n    }\n}\n\nextend Into<U> for Point {\n    func into(self) -> U {\n        return self.into();\n    }\n}\n\nextend Into for Pointt {}\n\nextend Point for int {}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Method 'width' doesn't match its declaration in trait 'Show'",
+            "Method 'extra' is not a member of trait 'Show'",
+            "Conflicting implementations of trait 'Show' for 'Point'",
+            "Missing method 'width' of trait 'Show' in the implementation for 'Point'",
+            "Cannot extend unknown type 'Pointt'",
+            "'Into' expects 1 type arguments, found 0",
+            "Missing method 'into' of trait 'Into' in the implementation for 'Pointt'",
+            "'Point' is not a trait",
+        ]);
+        assert_eq!(checker.errors[0].notes[0].message, "Expected 'func width(self, int) -> int', found 'func width(self, float) -> int'");
+    }
+
+    #[test]
+    fn test_trait_associated_items() {
+        let source = "module test;\n\ntrait Source {\n    type Item;\n    const SIZE: int;\n    const NAME: string = \"source\";\n\n    func get(self) -> Self.Item;\n\n    func pair(self) -> (Item, Item) {\n        return (self.get(), self.get());\n    }\n}\n\nstruct Words {}\nstruct Numbers {}\n\nextend Source for Words {\n    type Item = string;\n    const SIZE: int = 2;\n\n    func get(self) -> Item {\n        return \"w\";\n    }\n}\n\nextend Source for Numbers {\n    type Extra = int;\n    const SIZE: float = 1;\n\n    func get(self) -> Item {\n        return 1;\n    }\n}\n\nfunc main() {\n    let pair = Words {}.pair();\n    let name = Words.NAME;\n    let size = Words.SIZE;\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Missing associated type 'Item' of trait 'Source' in the implementation for 'Numbers'",
+            "Type 'Extra' is not a member of trait 'Source'",
+            "Constant 'SIZE' of trait 'Source' has type 'int', found 'float'",
+            "Type mismatch in constant 'SIZE'",
+        ]);
+
+        let mut types: Vec<_> = checker.binding_types.iter()
+            .map(|(span, ty)| (span.start, format!("{}: {}", &source[span.start..span.end], ty)))
+            .collect();
+        types.sort();
+        let types: Vec<_> = types.into_iter().map(|(_, ty)| ty).collect();
+        assert_eq!(types, ["pair: (string, string)", "name: string", "size: int"]);
+    }
+
+    #[test]
+    fn test_method_resolution() {
+        let source = "module test;\n\ntrait A {\n    func m(self) -> int {\n        return 1;\n    }\n}\n\ntrait B {\n    func m(self) -> int {\n        return 2;\n    }\n    func k() -> int;\n}\n\nstruct P {\n    x: int,\n}\n\nextend A for P {}\n\nextend B for P {\n    func k(self) -> int {\n        return self.x;\n    }\n}\n\nextend P {\n    func new(x: int) -> P {\n        return P { x: x };\n    }\n    func get(self) -> int {\n        return self.x;\n    }\n}\n\nfunc main() {\n    let p = P.new(1);\n    let n: int = p.get();\n    p.m();\n    p.new(2);\n    P.get();\n    p.gte();\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Method 'k' doesn't match its declaration in trait 'B'",
+            "Ambiguous method 'm' for type 'P'",
+            "'new' is a static method of 'P'",
+            "Method 'get' of 'P' takes 'self', so it must be called on a value",
+            "Type 'P' has no field or method 'gte'",
+        ]);
+        assert_eq!(checker.errors[0].notes[0].message, "Expected 'func k() -> int', found 'func k(self) -> int'");
+        assert_eq!(checker.errors[1].notes[0].message, "Candidates are 'A.m', 'B.m'");
+        assert_eq!(checker.errors[2].helps[0].message, "Call it as 'P.new(...)'");
+        assert_eq!(checker.errors[4].helps[0].message, "Did you mean 'get'?");
+        assert_eq!(checker.errors[4].notes[0].message, "Methods of 'P': 'get', 'k', 'm'");
+    }
+
+    #[test]
+    fn test_trait_objects() {
+        let source = "module test;\n\ntrait Shape {\n    func area(self) -> float;\n}\n\ntrait Clone {\n    type Item;\n    func new() -> Self;\n    func clone(self) -> Self;\n}\n\nstruct Circle {\n    r: float,\n}\n\nstruct Rock {}\n\nextend Shape for Circle {\n    func area(self) -> float {\n        return self.r;\n    }\n}\n\nfunc area<T: Shape>(shape: T) -> float {\n    return shape.area();\n}\n\nfunc main() {\n    let shapes: [dyn Shape] = [Circle { r: 1.0 }, Rock {}];\n    let c: dyn Clone = Circle { r: 1.0 };\n    let s = Circle { r: 2.0 } as dyn Shape;\n    let a: float = s.area() + area(s) + area(shapes[0]);\n    let r = Rock {} as dyn Shape;\n}\n";
+        let checker = check(source);
+
+        let messages: Vec<_> = checker.errors.iter().map(|error| error.message.as_str()).collect();
+        assert_eq!(messages, [
+            "Array elements must have the same type, expected 'dyn Shape', found 'Rock'",
+            "Trait 'Clone' cannot be used as 'dyn Clone'",
+            "Type mismatch in let binding",
+            "Cannot cast a value of type 'Rock' to 'dyn Shape'",
+        ]);
+        assert_eq!(checker.errors[0].helps[0].message, "Implement it with 'extend Shape for Rock'");
+        let reasons: Vec<_> = checker.errors[1].notes.iter().map(|note| note.message.as_str()).collect();
+        assert_eq!(reasons, [
+            "'new' doesn't take 'self'",
+            "'clone' mentions 'Self' in its signature",
+            "It has the associated type 'Item'",
+        ]);
+    }
+}
@@ -0,0 +1,145 @@
+//! Declarations every module sees without importing them.
+//!
+//! They are added to the module before the passes run, so name resolution, type
+//! checking and the interpreter treat them like the module's own items. Their spans
+//! are empty, which keeps them out of the way of diagnostics and editor lookups.
+
+use crate::frontend::utils::{
+    ast::*,
+    token::{Span, Token, TokenKind},
+};
+
+/// The items of the prelude:
+///
+/// ```text
+/// enum Result<T, E> { Ok(T), Err(E) }
+///
+/// // What `for` steps through: `next` returns `null` once there is nothing left
+/// trait Iterator {
+///     type Item;
+///     func next(self) -> Self.Item?;
+/// }
+///
+/// // What `for` asks for an `Iterator` when given a value that isn't one
+/// trait Iterable {
+///     type Iter;
+///     func iter(self) -> Self.Iter;
+/// }
+///
+/// // What `as` converts a `Source` into a type with, and `?` converts errors with
+/// trait From {
+///     type Source;
+///     func from(value: Self.Source) -> Self;
+/// }
+///
+/// // Parses the text into a `T`, or gives the reason it can't; the runtime provides the body
+/// extend From for Result<T, E> {
+///     type Source = string;
+///     func from(value: string) -> Self;
+/// }
+/// ```
+pub fn items() -> Vec<StatementKind> {
+    let param = |name: &str| Type::Named { name: token(name), generics: vec![], span: Span::default() };
+    let variant = |name: &str, field: &str| EnumVariant { name: token(name), fields: vec![param(field)], span: Span::default() };
+    let type_var = |name: &str| Type::TypeVar { name: token(name), span: Span::default() };
+    let string = Type::Primitive { name: token("string"), span: Span::default() };
+
+    vec![
+        StatementKind::Enum(Enum {
+            attributes: vec![],
+            access: AccessModifier::Public,
+            name: token("Result"),
+            generics: vec![type_var("T"), type_var("E")],
+            variants: vec![variant("Ok", "T"), variant("Err", "E")],
+            span: Span::default(),
+        }),
+        protocol("Iterator", "Item", "next", Type::Optional { inner: Box::new(param("Self.Item")), span: Span::default() }),
+        protocol("Iterable", "Iter", "iter", param("Self.Iter")),
+        StatementKind::Trait(Trait {
+            access: AccessModifier::Public,
+            name: token("From"),
+            methods: vec![conversion(param("Self.Source"))],
+            types: vec![AssociatedType { name: token("Source"), ty: None, span: Span::default() }],
+            constants: vec![],
+            generics: vec![],
+            span: Span::default(),
+        }),
+        StatementKind::Extend(Extend {
+            name: token("Result"),
+            trait_name: Some(token("From")),
+            methods: vec![conversion(string.clone())],
+            types: vec![AssociatedType { name: token("Source"), ty: Some(string), span: Span::default() }],
+            constants: vec![],
+            first_generics: vec![],
+            second_generics: vec![type_var("T"), type_var("E")],
+            span: Span::default(),
+        }),
+    ]
+}
+
+/// `func from(value: source) -> Self;`, the method of `From`.
+fn conversion(source: Type) -> Function {
+    Function {
+        attributes: vec![],
+        access: AccessModifier::None,
+        name: token("from"),
+        generics: vec![],
+        bounds: vec![],
+        params: vec![Parameter { name: token("value"), ty: source, span: Span::default() }],
+        return_type: Type::Named { name: token("Self"), generics: vec![], span: Span::default() },
+        body: None,
+        span: Span::default(),
+        is_method: false,
+    }
+}
+
+/// A trait with one associated type and one method taking only `self`.
+fn protocol(name: &str, associated: &str, method: &str, return_type: Type) -> StatementKind {
+    StatementKind::Trait(Trait {
+        access: AccessModifier::Public,
+        name: token(name),
+        methods: vec![Function {
+            attributes: vec![],
+            access: AccessModifier::None,
+            name: token(method),
+            generics: vec![],
+            bounds: vec![],
+            params: vec![],
+            return_type,
+            body: None,
+            span: Span::default(),
+            is_method: true,
+        }],
+        types: vec![AssociatedType { name: token(associated), ty: None, span: Span::default() }],
+        constants: vec![],
+        generics: vec![],
+        span: Span::default(),
+    })
+}
+
+/// `module` with the prelude's items in front of its own. An item of the module
+/// shadows the prelude item of the same name, along with the prelude's
+/// implementations for it.
+pub fn with_prelude(mut module: Module) -> Module {
+    let declared: Vec<String> = module.stmts.iter().filter_map(item_name).collect();
+    let shadowed = |name: &Token| declared.contains(&name.lexeme);
+    let prelude = items().into_iter().filter(|item| match item {
+        StatementKind::Extend(extend) => !shadowed(&extend.name) && !extend.trait_name.as_ref().is_some_and(shadowed),
+        item => item_name(item).is_none_or(|name| !declared.contains(&name)),
+    });
+    module.stmts = prelude.chain(module.stmts).collect();
+    module
+}
+
+fn item_name(item: &StatementKind) -> Option<String> {
+    match item {
+        StatementKind::Struct(structure) => Some(structure.name.lexeme.clone()),
+        StatementKind::Enum(enumeration) => Some(enumeration.name.lexeme.clone()),
+        StatementKind::Trait(trait_) => Some(trait_.name.lexeme.clone()),
+        _ => None,
+    }
+}
+
+fn token(name: &str) -> Token {
+    Token::new(TokenKind::Identifier, name.to_string(), 0, Span::default())
+}
@@ -1,14 +1,57 @@
 pub mod symbol_table;
 
+use std::collections::{HashMap, HashSet};
+
+use crate::frontend::utils::ast::Type;
+
 use symbol_table::*;
 
 /// MultiStageSymbolTable: supports staged population and lookup of symbols, variants, and fields.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct MultiStageSymbolTable {
     pub types: SymbolTable, // For types (structs, enums, traits, etc.)
     pub values: SymbolTable, // For variables, functions, etc.
     pub enum_variants: SymbolTable, // For enum variants
     pub struct_fields: SymbolTable, // For struct fields
+    /// Methods declared in `extend` blocks and traits, by type name then method name
+    pub methods: HashMap<String, HashMap<String, Symbol>>,
+    /// Where each method of a type is defined, by `Type.method`: the type itself for
+    /// a plain `extend` block, otherwise the trait being implemented
+    pub method_sources: HashMap<String, Vec<String>>,
+    /// Generic parameters of functions (by name) and methods (by `Type.method`)
+    pub generics: HashMap<String, GenericParams>,
+    /// The traits each type implements through `extend Trait for Type`
+    pub implementations: HashMap<String, HashSet<String>>,
+    /// What each trait declares besides its methods
+    pub traits: HashMap<String, TraitMembers>,
+    /// The associated types each type chooses in its trait implementations, by type name
+    pub associated_types: HashMap<String, HashMap<String, Type>>,
+    /// The types of the associated constants each type defines, by type name
+    pub constants: HashMap<String, HashMap<String, Type>>,
+    /// The type each newtype wraps, by newtype name
+    pub newtypes: HashMap<String, Type>,
+}
+
+/// The associated items of a trait
+#[derive(Debug, Default, Clone)]
+pub struct TraitMembers {
+    /// Associated types, which every implementation chooses
+    pub types: Vec<String>,
+    /// Associated constants and their types
+    pub constants: HashMap<String, Type>,
+    /// Methods and constants with a default, which implementations may leave out
+    pub provided: HashSet<String>,
+}
+
+/// The generic parameters a function or method signature may mention
+#[derive(Debug, Default, Clone)]
+pub struct GenericParams {
+    /// Declared by the `extend` block or trait the method belongs to
+    pub outer: Vec<String>,
+    /// Declared by the function itself
+    pub own: Vec<String>,
+    /// The traits each of the function's own parameters must implement
+    pub bounds: HashMap<String, Vec<Type>>,
 }
 
 impl MultiStageSymbolTable {
@@ -18,6 +61,14 @@ impl MultiStageSymbolTable {
             values: SymbolTable::new(),
             enum_variants: SymbolTable::new(),
             struct_fields: SymbolTable::new(),
+            methods: HashMap::new(),
+            method_sources: HashMap::new(),
+            generics: HashMap::new(),
+            implementations: HashMap::new(),
+            traits: HashMap::new(),
+            associated_types: HashMap::new(),
+            constants: HashMap::new(),
+            newtypes: HashMap::new(),
         }
     }
     pub fn has_enum_variant(&self, name: &str) -> bool {
@@ -29,7 +80,25 @@ impl MultiStageSymbolTable {
     pub fn has_type(&self, name: &str) -> bool {
         self.types.get(name).is_some()
     }
+    /// The type the alias `name` stands for, if `name` is an alias.
+    pub fn alias(&self, name: &str) -> Option<&Type> {
+        self.types.get(name).filter(|symbol| symbol.kind == SymbolKind::Alias).and_then(|symbol| symbol.ty.as_ref())
+    }
     pub fn has_value(&self, name: &str) -> bool {
         self.values.get(name).is_some()
     }
+    pub fn method(&self, type_name: &str, name: &str) -> Option<&Symbol> {
+        self.methods.get(type_name).and_then(|methods| methods.get(name))
+    }
+    pub fn implements(&self, type_name: &str, trait_name: &str) -> bool {
+        self.implementations.get(type_name).is_some_and(|traits| traits.contains(trait_name))
+    }
+    /// The generic parameters of a function, or of a method when `type_name` is given.
+    pub fn generic_params(&self, type_name: Option<&str>, name: &str) -> GenericParams {
+        let key = match type_name {
+            Some(type_name) => format!("{}.{}", type_name, name),
+            None => name.to_string(),
+        };
+        self.generics.get(&key).cloned().unwrap_or_default()
+    }
 }
\ No newline at end of file
@@ -6,12 +6,19 @@ use crate::frontend::utils::token::Span;
 pub enum SymbolKind {
     Variable,
     Function,
+    /// A function of an `extend` block or trait that takes `self`
+    Method,
     Struct,
     Enum,
     Trait,
     Type,
+    /// A name declared with "type Name = T;", which stands for `T`
+    Alias,
+    /// A distinct type declared with "type Name = new T;"
+    Newtype,
     Parameter,
     Field,
+    Variant,
     Module,
 }
 
@@ -19,6 +26,8 @@ pub enum SymbolKind {
 pub struct Symbol {
     pub name: String,
     pub kind: SymbolKind,
+    /// The access modifier the item was declared with; `None` for names that can't have one
+    pub access: AccessModifier,
     pub ty: Option<Type>,
     pub span: Option<Span>,
     pub struct_fields: Option<Vec<Field>>, // For struct fields
@@ -42,7 +51,7 @@ impl Scope {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct SymbolTable {
     pub scopes: Vec<Scope>,
 }
@@ -70,6 +79,9 @@ impl SymbolTable {
         }
         None
     }
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut Symbol> {
+        self.scopes.iter_mut().rev().find_map(|scope| scope.symbols.get_mut(name))
+    }
     pub fn current_scope(&self) -> Option<&Scope> {
         self.scopes.last()
     }
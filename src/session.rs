@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::errors::{Error, Warning};
+use crate::frontend::{
+    formatter,
+    lexer::Lexer,
+    parser::Parser,
+    utils::{ast::Module, cst::SyntaxNode, token::{Span, Token}, visitor::Visitor},
+};
+use crate::interpreter::{value::Value, Interpreter, RuntimeError};
+use crate::sema::{
+    imports,
+    passes::{
+        attributes::AttributeChecker, monomorphise::Monomorphiser, populate_table::FullSymbolTablePass,
+        resolver::Resolver, type_checker::TypeChecker,
+    },
+    prelude,
+    utils::MultiStageSymbolTable,
+};
+
+/// Entry point for driving the compiler in-process.
+/// A `Compiler` hands out a `Session` per source file; the convenience methods
+/// run a whole pipeline in one call.
+#[derive(Debug, Default)]
+pub struct Compiler {}
+
+/// How long one pass took.
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    pub pass: &'static str,
+    pub duration: Duration,
+}
+
+/// A module that passed semantic analysis, together with its symbol table.
+#[derive(Debug)]
+pub struct CheckedModule {
+    pub module: Module,
+    pub table: MultiStageSymbolTable,
+    /// The declaration each name refers to, keyed by the span of the use
+    pub resolutions: HashMap<Span, Span>,
+    /// The module with every generic item replaced by the instances the program uses
+    pub monomorphic: Module,
+}
+
+/// The compilation of a single source file.
+/// Every stage reports its failures as located `Error`s instead of printing them.
+#[derive(Debug)]
+pub struct Session {
+    pub name: String,
+    pub source: String,
+    pub timings: Vec<PassTiming>,
+    /// The warnings of the last check, which are kept when it finds errors too
+    pub warnings: Vec<Warning>,
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Compiler {}
+    }
+
+    pub fn session(&self, name: impl Into<String>, source: impl Into<String>) -> Session {
+        Session::new(name, source)
+    }
+
+    /// Lexes and parses `source`.
+    pub fn parse_str(&self, name: &str, source: &str) -> Result<Module, Vec<Error>> {
+        self.session(name, source).parse()
+    }
+
+    /// Lexes, parses and checks `source`.
+    pub fn check_str(&self, name: &str, source: &str) -> Result<CheckedModule, Vec<Error>> {
+        self.session(name, source).check()
+    }
+}
+
+impl Session {
+    pub fn new(name: impl Into<String>, source: impl Into<String>) -> Self {
+        Session {
+            name: name.into(),
+            source: source.into(),
+            timings: vec![],
+            warnings: vec![],
+        }
+    }
+
+    fn time<T>(&mut self, pass: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.timings.push(PassTiming { pass, duration: start.elapsed() });
+        result
+    }
+
+    fn locate(&self, errors: Vec<Error>) -> Vec<Error> {
+        errors.into_iter().map(|error| error.locate(&self.name, &self.source)).collect()
+    }
+
+    pub fn lex(&mut self) -> Result<Vec<Token>, Vec<Error>> {
+        let source = self.source.clone();
+        let mut lexer = Lexer::new(&source, self.name.clone());
+        self.time("Lexing", || lexer.scan_tokens());
+
+        if lexer.had_error {
+            return Err(self.locate(lexer.diagnostics));
+        }
+        Ok(lexer.tokens)
+    }
+
+    pub fn parse(&mut self) -> Result<Module, Vec<Error>> {
+        self.parse_with_tree().map(|(module, _)| module)
+    }
+
+    /// Parses the session's source into its concrete syntax tree.
+    pub fn syntax_tree(&mut self) -> Result<SyntaxNode, Vec<Error>> {
+        self.parse_with_tree().map(|(_, tree)| tree)
+    }
+
+    fn parse_with_tree(&mut self) -> Result<(Module, SyntaxNode), Vec<Error>> {
+        let tokens = self.lex()?;
+        let source = self.source.clone();
+        let mut parser = Parser::new(&tokens, &source, self.name.clone());
+        let module = self.time("Parsing", || parser.parse());
+
+        if parser.errors > 0 {
+            return Err(self.locate(parser.diagnostics));
+        }
+        Ok((module, parser.syntax_tree()))
+    }
+
+    /// Returns the session's source in canonical formatting.
+    /// Files with syntax errors are not formatted.
+    pub fn format(&mut self) -> Result<String, Vec<Error>> {
+        let tree = self.syntax_tree()?;
+        Ok(self.time("Formatting", || formatter::format(&tree)))
+    }
+
+    /// Runs semantic analysis over an already parsed module.
+    pub fn check_module(&mut self, module: Module) -> Result<CheckedModule, Vec<Error>> {
+        self.warnings.clear();
+        let directory = Path::new(&self.name).parent().unwrap_or(Path::new(""));
+        let (module, errors) = imports::with_imports(module, directory);
+        if !errors.is_empty() {
+            return Err(self.locate(errors));
+        }
+        let module = prelude::with_prelude(module);
+        let mut pass = FullSymbolTablePass::new();
+        self.time("Symbol table population", || pass.visit_module(&module))
+            .map_err(|message| self.internal_error(message))?;
+
+        let mut attributes = AttributeChecker::new();
+        self.time("Attribute checking", || attributes.visit_module(&module))
+            .map_err(|message| self.internal_error(message))?;
+
+        let mut resolver = Resolver::new();
+        self.time("Name resolution", || resolver.visit_module(&module))
+            .map_err(|message| self.internal_error(message))?;
+        self.warn(attributes.warnings);
+        self.warn(resolver.warnings);
+        if !attributes.errors.is_empty() || !resolver.errors.is_empty() {
+            return Err(self.locate(attributes.errors.into_iter().chain(resolver.errors).collect()));
+        }
+
+        let mut type_checker = TypeChecker::new();
+        type_checker.table = pass.table;
+        self.time("Type checking", || type_checker.visit_module(&module))
+            .map_err(|message| self.internal_error(message))?;
+        self.warn(type_checker.warnings);
+
+        if !type_checker.errors.is_empty() {
+            return Err(self.locate(type_checker.errors));
+        }
+
+        let mut monomorphiser = Monomorphiser::new(&module, &type_checker.instantiations, &type_checker.binding_types);
+        let monomorphic = self.time("Monomorphisation", || monomorphiser.monomorphise());
+        if !monomorphiser.errors.is_empty() {
+            return Err(self.locate(monomorphiser.errors));
+        }
+        Ok(CheckedModule {
+            module,
+            table: type_checker.table,
+            resolutions: resolver.resolutions,
+            monomorphic,
+        })
+    }
+
+    /// Parses and checks the session's source.
+    pub fn check(&mut self) -> Result<CheckedModule, Vec<Error>> {
+        let module = self.parse()?;
+        self.check_module(module)
+    }
+
+    /// Executes the module's `main` function, writing program output to `out`.
+    pub fn run(&mut self, checked: &CheckedModule, out: Box<dyn Write>) -> Result<Value, Vec<Error>> {
+        let mut interpreter = Interpreter::with_output(out);
+        let result = self.time("Execution", || {
+            interpreter.load_module(&checked.module)?;
+            interpreter.run_main()
+        });
+        result.map_err(|error| self.runtime_error(error))
+    }
+
+    fn warn(&mut self, warnings: Vec<Warning>) {
+        let located: Vec<Warning> = warnings.into_iter().map(|warning| warning.locate(&self.name, &self.source)).collect();
+        self.warnings.extend(located);
+    }
+
+    fn internal_error(&self, message: String) -> Vec<Error> {
+        self.locate(vec![Error::new(message, 1, Default::default(), String::new())])
+    }
+
+    fn runtime_error(&self, error: RuntimeError) -> Vec<Error> {
+        self.locate(vec![Error::new(error.message, 0, error.span, String::new())])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    /// An output sink that can be read back after the program has run.
+    #[derive(Clone, Default)]
+    struct Buffer(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_session_pipeline() {
+        let source = "module test;\nimport \"io\" as io;\n\nfunc main() {\n    let n: int = 6;\n    io.println(\"\\(n * 7)\");\n}\n";
+        let mut session = Compiler::new().session("test.y", source);
+        let checked = session.check().unwrap();
+
+        let out = Buffer::default();
+        let value = session.run(&checked, Box::new(out.clone())).unwrap();
+        assert!(matches!(value, Value::Void));
+        assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "42\n");
+        assert_eq!(session.timings.len(), 8);
+    }
+
+    #[test]
+    fn test_try_returns_converted_errors() {
+        let source = "module test;\nimport \"io\" as io;\n\nstruct Failure {\n    reason: string,\n}\n\nextend From for Failure {\n    type Source = string;\n\n    func from(error: string) -> Failure {\n        return Failure { reason: \"bad input: \" + error };\n    }\n}\n\nfunc check(text: string) -> Result<int, string> {\n    let n: int = (text as Result<int, string>)?;\n    if (n < 0) {\n        return Result.Err(\"\\(n)\");\n    }\n    return Result.Ok(n);\n}\n\nfunc total(a: string, b: string) -> Result<int, Failure> {\n    return Result.Ok(check(a)? + check(b)?);\n}\n\nfunc main() {\n    for (args in [(\"1\", \"2\"), (\"1\", \"-2\"), (\"x\", \"2\")]) {\n        let (a, b) = args;\n        match (total(a, b)) {\n            Result.Ok(n) -> io.println(\"\\(n)\"),\n            Result.Err(failure) -> io.println(failure.reason),\n        }\n    }\n}\n";
+        let mut session = Compiler::new().session("test.y", source);
+        let checked = session.check().unwrap();
+
+        let out = Buffer::default();
+        session.run(&checked, Box::new(out.clone())).unwrap();
+        assert_eq!(String::from_utf8(out.0.borrow().clone()).unwrap(), "3\nbad input: -2\nbad input: 'x' is not a valid 'int'\n");
+    }
+
+    #[test]
+    fn test_runtime_limits() {
+        let run = |source: &'static str| {
+            let run = move || {
+                let mut session = Compiler::new().session("test.y", source);
+                let checked = session.check().unwrap();
+                session.run(&checked, Box::new(Buffer::default())).unwrap_err()[0].message.clone()
+            };
+            std::thread::Builder::new().stack_size(crate::interpreter::STACK_SIZE).spawn(run).unwrap().join().unwrap()
+        };
+
+        let message = run("module test;\n\nfunc f(n: int) -> int {\n    return 1 + f(n - 1);\n}\n\nfunc main() {\n    f(0);\n}\n");
+        assert_eq!(message, "Maximum recursion depth exceeded");
+        let message = run("module test;\n\nfunc main() {\n    let n: int = 9223372036854775807;\n    n += 1;\n}\n");
+        assert_eq!(message, "Integer overflow in 9223372036854775807 + 1");
+        let message = run("module test;\n\nfunc main() {\n    let n: int = 2 ** 4294967296;\n}\n");
+        assert_eq!(message, "Integer overflow in 2 ** 4294967296");
+    }
+
+    #[test]
+    fn test_session_errors_are_located() {
+        let source = "module test;\n\nfunc main() {\n    let x: int = true;\n}\n";
+        let errors = Compiler::new().check_str("test.y", source).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].line, 4);
+        assert_eq!(errors[0].filename, "test.y");
+
+        let errors = Compiler::new().parse_str("test.y", "module test;\nfunc main( {}\n").unwrap_err();
+        assert_eq!(errors[0].line, 2);
+
+        // Warnings are kept alongside the errors
+        let mut session = Compiler::new().session("test.y", "module test;\n\nfunc main() {\n    let x = 1;\n    let x: int = true;\n}\n");
+        assert_eq!(session.check().unwrap_err().len(), 1);
+        assert_eq!(session.warnings[0].message, "'x' shadows an earlier binding");
+    }
+}